@@ -0,0 +1,779 @@
+//! An async facade layer on top of any `HermesProtocolHandler`.
+//!
+//! Subscriptions are exposed as `Stream`s and publications as futures, so that async services don't
+//! have to bridge the `Callback`s (that are run on the transport threads) by hand. Each facade of the
+//! protocol handler has an async counterpart, obtained through the `AsyncHermesProtocolHandler`
+//! extension trait:
+//!
+//! ```ignore
+//! let dialogue = handler.async_dialogue();
+//! let mut intents = dialogue.intents()?;
+//! while let Some(intent) = intents.next().await {
+//!     dialogue.publish_end_session(EndSessionMessage { session_id: intent.session_id, text: None }).await?;
+//! }
+//! ```
+//!
+//! Every stream has a bounded buffer (see `with_buffer` on the facades). The messages are delivered on
+//! the transport thread, which must never wait on a consumer: it would stall the other callbacks of the
+//! handler, or deadlock a deterministic bus pumped on the thread polling the stream. So when the buffer
+//! of a stream is full, the newest message is dropped instead, and counted in `HermesStream::dropped`.
+//! Dropping a stream cancels the underlying subscription.
+//!
+//! The publications are futures for consistency, but they publish synchronously when first polled, and
+//! resolve right away. The transports only queue the message then, so this doesn't block the executor
+//! for long.
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::{Stream, StreamExt};
+use futures_timer::Delay;
+
+use hermes::*;
+
+/// Number of messages a stream buffers when no other size is given with `with_buffer`
+pub const DEFAULT_BUFFER: usize = 64;
+
+/// A stream of the messages received through a subscription. Dropping it cancels the subscription.
+#[must_use = "streams do nothing unless polled"]
+pub struct HermesStream<T> {
+    receiver: mpsc::Receiver<T>,
+    subscription: SubscriptionHandle,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl<T> HermesStream<T> {
+    /// How many messages were dropped so far because the buffer of this stream was full
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Cancel the subscription, reporting the error that may occur while doing it
+    pub fn unsubscribe(self) -> HermesResult<()> {
+        self.subscription.unsubscribe()
+    }
+}
+
+impl<T> Stream for HermesStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.receiver.poll_next_unpin(cx)
+    }
+}
+
+impl<T> std::fmt::Debug for HermesStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("HermesStream")
+            .field("subscription", &self.subscription)
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+fn stream<T, S>(buffer: usize, subscribe: S) -> HermesResult<HermesStream<T>>
+where
+    T: Clone + Send + 'static,
+    S: FnOnce(Callback<T>) -> HermesResult<SubscriptionHandle>,
+{
+    let (sender, receiver) = mpsc::channel(buffer);
+    let sender = Mutex::new(sender);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&dropped);
+    let subscription = subscribe(Callback::new(move |message: &T| {
+        deliver(&sender, &counter, message.clone())
+    }))?;
+    Ok(HermesStream {
+        receiver,
+        subscription,
+        dropped,
+    })
+}
+
+fn stream0<S>(buffer: usize, subscribe: S) -> HermesResult<HermesStream<()>>
+where
+    S: FnOnce(Callback0) -> HermesResult<SubscriptionHandle>,
+{
+    let (sender, receiver) = mpsc::channel(buffer);
+    let sender = Mutex::new(sender);
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&dropped);
+    let subscription = subscribe(Callback0::new(move || deliver(&sender, &counter, ())))?;
+    Ok(HermesStream {
+        receiver,
+        subscription,
+        dropped,
+    })
+}
+
+/// Give a message to a stream without waiting, dropping it if the buffer of the stream is full
+fn deliver<T>(sender: &Mutex<mpsc::Sender<T>>, dropped: &AtomicUsize, message: T) {
+    if let Ok(mut sender) = sender.lock() {
+        match sender.try_send(message) {
+            Ok(()) => {}
+            Err(e) if e.is_full() => {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            // the stream was dropped, there is no one left to give the message to
+            Err(_) => {}
+        }
+    }
+}
+
+/// Resolve with the first item of `responses`, or fail once `timeout` has elapsed
+async fn first_within<T, S: Stream<Item = T>>(responses: S, timeout: Duration) -> HermesResult<T> {
+    futures::pin_mut!(responses);
+    match future::select(responses.next(), Delay::new(timeout)).await {
+        Either::Left((Some(response), _)) => Ok(response),
+        _ => Err(HermesError::Timeout(timeout)),
+    }
+}
+
+/// Ask a component to reload and wait for it to be loaded again, an id is generated for the request
+/// if it doesn't have one. `component_loaded` and `publish` are the ones of the facade of the component
+async fn reload_and_wait<P>(
+    mut component_reload: RequestComponentReloadMessage,
+    timeout: Duration,
+    component_loaded: HermesStream<ComponentLoadedMessage>,
+    publish: P,
+) -> HermesResult<ComponentLoadedMessage>
+where
+    P: FnOnce(RequestComponentReloadMessage) -> HermesResult<()>,
+{
+    if component_reload.id.is_empty() {
+        component_reload.id = new_request_id();
+    }
+    let id = component_reload.id.clone();
+    let responses = component_loaded.filter(move |it| future::ready(it.id.as_ref() == Some(&id)));
+    publish(component_reload)?;
+    first_within(responses, timeout).await
+}
+
+macro_rules! s {
+    ($n:ident<$t:ty> = $s:ident($($a:ident: $ta:ty),*)) => {
+        pub fn $n(&self, $($a: $ta),*) -> HermesResult<HermesStream<$t>> {
+            stream(self.buffer, |handler| self.facade.$s($($a,)* handler))
+        }
+    };
+    ($n:ident = $s:ident($($a:ident: $ta:ty),*)) => {
+        pub fn $n(&self, $($a: $ta),*) -> HermesResult<HermesStream<()>> {
+            stream0(self.buffer, |handler| self.facade.$s($($a,)* handler))
+        }
+    };
+}
+
+macro_rules! p {
+    ($n:ident($($a:ident: $ta:ty),*)) => {
+        /// Publish synchronously when polled, see the crate documentation
+        pub async fn $n(&self, $($a: $ta),*) -> HermesResult<()> {
+            self.facade.$n($($a),*)
+        }
+    };
+}
+
+macro_rules! async_facade {
+    ($(#[$meta:meta])* $name:ident($facade:ident) { $($body:tt)* }) => {
+        $(#[$meta])*
+        pub struct $name {
+            facade: Box<dyn $facade>,
+            buffer: usize,
+        }
+
+        impl $name {
+            pub fn new(facade: Box<dyn $facade>) -> Self {
+                Self {
+                    facade,
+                    buffer: DEFAULT_BUFFER,
+                }
+            }
+
+            /// Set how many messages the streams created from now on buffer before dropping the newest
+            pub fn with_buffer(self, buffer: usize) -> Self {
+                Self { buffer, ..self }
+            }
+
+            $($body)*
+        }
+
+        impl From<Box<dyn $facade>> for $name {
+            fn from(facade: Box<dyn $facade>) -> Self {
+                Self::new(facade)
+            }
+        }
+    };
+}
+
+macro_rules! component {
+    () => {
+        p!(publish_version_request());
+        s!(version<VersionMessage> = subscribe_version());
+        s!(error<ErrorMessage> = subscribe_error());
+        s!(component_loaded<ComponentLoadedMessage> = subscribe_component_loaded());
+    };
+}
+
+macro_rules! identifiable_component {
+    () => {
+        p!(publish_version_request(id: String));
+        s!(version<VersionMessage> = subscribe_version(id: String));
+        s!(error<SiteErrorMessage> = subscribe_error(id: String));
+        s!(all_error<SiteErrorMessage> = subscribe_all_error());
+        s!(component_loaded<ComponentLoadedOnSiteMessage> = subscribe_component_loaded(id: String));
+        s!(all_component_loaded<ComponentLoadedOnSiteMessage> = subscribe_all_component_loaded());
+    };
+}
+
+macro_rules! component_backend {
+    () => {
+        s!(version_request = subscribe_version_request());
+        p!(publish_version(version: VersionMessage));
+        p!(publish_error(error: ErrorMessage));
+        p!(publish_component_loaded(component_loaded: ComponentLoadedMessage));
+    };
+}
+
+macro_rules! identifiable_component_backend {
+    () => {
+        s!(version_request = subscribe_version_request(id: String));
+        p!(publish_version(id: String, version: VersionMessage));
+        p!(publish_error(id: String, error: SiteErrorMessage));
+        p!(publish_component_loaded(id: String, component_loaded: ComponentLoadedOnSiteMessage));
+    };
+}
+
+macro_rules! toggleable {
+    () => {
+        p!(publish_toggle_on());
+        p!(publish_toggle_off());
+    };
+}
+
+macro_rules! toggleable_backend {
+    () => {
+        s!(toggle_on = subscribe_toggle_on());
+        s!(toggle_off = subscribe_toggle_off());
+    };
+}
+
+macro_rules! identifiable_toggleable {
+    () => {
+        p!(publish_toggle_on(site: SiteMessage));
+        p!(publish_toggle_off(site: SiteMessage));
+    };
+}
+
+macro_rules! identifiable_toggleable_backend {
+    () => {
+        s!(toggle_on<SiteMessage> = subscribe_toggle_on());
+        s!(toggle_off<SiteMessage> = subscribe_toggle_off());
+    };
+}
+
+async_facade! {
+    /// The async counterpart of `VoiceActivityFacade`
+    AsyncVoiceActivityFacade(VoiceActivityFacade) {
+        identifiable_component!();
+        s!(vad_up<VadUpMessage> = subscribe_vad_up(site_id: String));
+        s!(vad_down<VadDownMessage> = subscribe_vad_down(site_id: String));
+        s!(all_vad_up<VadUpMessage> = subscribe_all_vad_up());
+        s!(all_vad_down<VadDownMessage> = subscribe_all_vad_down());
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `VoiceActivityBackendFacade`
+    AsyncVoiceActivityBackendFacade(VoiceActivityBackendFacade) {
+        identifiable_component_backend!();
+        p!(publish_vad_up(vad_up: VadUpMessage));
+        p!(publish_vad_down(vad_down: VadDownMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `HotwordFacade`
+    AsyncHotwordFacade(HotwordFacade) {
+        identifiable_component!();
+        identifiable_toggleable!();
+        s!(detected<HotwordDetectedMessage> = subscribe_detected(site_id: String));
+        s!(all_detected<HotwordDetectedMessage> = subscribe_all_detected());
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `HotwordBackendFacade`
+    AsyncHotwordBackendFacade(HotwordBackendFacade) {
+        identifiable_component_backend!();
+        identifiable_toggleable_backend!();
+        p!(publish_detected(site_id: String, message: HotwordDetectedMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `SoundFeedbackFacade`
+    AsyncSoundFeedbackFacade(SoundFeedbackFacade) {
+        identifiable_toggleable!();
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `SoundFeedbackBackendFacade`
+    AsyncSoundFeedbackBackendFacade(SoundFeedbackBackendFacade) {
+        identifiable_toggleable_backend!();
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `AsrFacade`
+    AsyncAsrFacade(AsrFacade) {
+        component!();
+        toggleable!();
+        p!(publish_start_listening(start: AsrStartListeningMessage));
+        p!(publish_stop_listening(site: SiteMessage));
+        p!(publish_component_reload(component_reload: RequestComponentReloadMessage));
+        s!(text_captured<TextCapturedMessage> = subscribe_text_captured());
+        s!(partial_text_captured<TextCapturedMessage> = subscribe_partial_text_captured());
+
+        /// Ask the component to reload and wait for it to be loaded again, an id is generated for the
+        /// request if it doesn't have one
+        pub async fn reload_and_wait(
+            &self,
+            component_reload: RequestComponentReloadMessage,
+            timeout: Duration,
+        ) -> HermesResult<ComponentLoadedMessage> {
+            let component_loaded = self.component_loaded()?;
+            reload_and_wait(component_reload, timeout, component_loaded, |component_reload| {
+                self.facade.publish_component_reload(component_reload)
+            })
+            .await
+        }
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `AsrBackendFacade`
+    AsyncAsrBackendFacade(AsrBackendFacade) {
+        component_backend!();
+        toggleable_backend!();
+        s!(start_listening<AsrStartListeningMessage> = subscribe_start_listening());
+        s!(stop_listening<SiteMessage> = subscribe_stop_listening());
+        s!(component_reload<RequestComponentReloadMessage> = subscribe_component_reload());
+        p!(publish_text_captured(text_captured: TextCapturedMessage));
+        p!(publish_partial_text_captured(text_captured: TextCapturedMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `TtsFacade`
+    AsyncTtsFacade(TtsFacade) {
+        component!();
+        p!(publish_say(to_say: SayMessage));
+        s!(say_finished<SayFinishedMessage> = subscribe_say_finished());
+        p!(publish_register_sound(sound: RegisterSoundMessage));
+
+        /// Say something and wait for the tts to be done saying it, an id is generated for the request
+        /// if it doesn't have one
+        pub async fn say_and_wait(&self, mut to_say: SayMessage, timeout: Duration) -> HermesResult<SayFinishedMessage> {
+            let id = to_say.id.get_or_insert_with(new_request_id).clone();
+            let responses = self
+                .say_finished()?
+                .filter(move |it| future::ready(it.id.as_ref() == Some(&id)));
+            self.facade.publish_say(to_say)?;
+            first_within(responses, timeout).await
+        }
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `TtsBackendFacade`
+    AsyncTtsBackendFacade(TtsBackendFacade) {
+        component_backend!();
+        p!(publish_say_finished(status: SayFinishedMessage));
+        s!(say<SayMessage> = subscribe_say());
+        s!(register_sound<RegisterSoundMessage> = subscribe_register_sound());
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `NluFacade`
+    AsyncNluFacade(NluFacade) {
+        component!();
+        p!(publish_query(query: NluQueryMessage));
+        p!(publish_partial_query(query: NluSlotQueryMessage));
+        p!(publish_component_reload(component_reload: RequestComponentReloadMessage));
+        s!(slot_parsed<NluSlotMessage> = subscribe_slot_parsed());
+        s!(intent_parsed<NluIntentMessage> = subscribe_intent_parsed());
+        s!(intent_not_recognized<NluIntentNotRecognizedMessage> = subscribe_intent_not_recognized());
+
+        /// Run a query and wait for its result, an id is generated for the query if it doesn't have one
+        pub async fn query_and_wait(&self, mut query: NluQueryMessage, timeout: Duration) -> HermesResult<NluQueryResponse> {
+            let id = query.id.get_or_insert_with(new_request_id).clone();
+            let intent_id = id.clone();
+            let intents = self
+                .intent_parsed()?
+                .filter(move |it| future::ready(it.id.as_ref() == Some(&intent_id)))
+                .map(NluQueryResponse::IntentParsed);
+            let not_recognized = self
+                .intent_not_recognized()?
+                .filter(move |it| future::ready(it.id.as_ref() == Some(&id)))
+                .map(NluQueryResponse::IntentNotRecognized);
+            self.facade.publish_query(query)?;
+            first_within(futures::stream::select(intents, not_recognized), timeout).await
+        }
+
+        /// Ask the component to reload and wait for it to be loaded again, an id is generated for the
+        /// request if it doesn't have one
+        pub async fn reload_and_wait(
+            &self,
+            component_reload: RequestComponentReloadMessage,
+            timeout: Duration,
+        ) -> HermesResult<ComponentLoadedMessage> {
+            let component_loaded = self.component_loaded()?;
+            reload_and_wait(component_reload, timeout, component_loaded, |component_reload| {
+                self.facade.publish_component_reload(component_reload)
+            })
+            .await
+        }
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `NluBackendFacade`
+    AsyncNluBackendFacade(NluBackendFacade) {
+        component_backend!();
+        s!(query<NluQueryMessage> = subscribe_query());
+        s!(partial_query<NluSlotQueryMessage> = subscribe_partial_query());
+        s!(component_reload<RequestComponentReloadMessage> = subscribe_component_reload());
+        p!(publish_slot_parsed(slot: NluSlotMessage));
+        p!(publish_intent_parsed(intent: NluIntentMessage));
+        p!(publish_intent_not_recognized(status: NluIntentNotRecognizedMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `AudioServerFacade`
+    AsyncAudioServerFacade(AudioServerFacade) {
+        identifiable_component!();
+        identifiable_toggleable!();
+        p!(publish_play_bytes(bytes: PlayBytesMessage));
+        s!(play_finished<PlayFinishedMessage> = subscribe_play_finished(site_id: String));
+        s!(all_play_finished<PlayFinishedMessage> = subscribe_all_play_finished());
+        s!(audio_frame<AudioFrameMessage> = subscribe_audio_frame(site_id: String));
+        p!(publish_replay_request(request: ReplayRequestMessage));
+        s!(replay_response<AudioFrameMessage> = subscribe_replay_response(site_id: String));
+        p!(publish_stream_bytes(play_bytes_streaming_message: StreamBytesMessage));
+        s!(stream_finished<StreamFinishedMessage> = subscribe_stream_finished(site_id: String));
+        s!(all_stream_finished<StreamFinishedMessage> = subscribe_all_stream_finished());
+
+        /// Play some bytes and wait for them to be played, an id is generated for the request if it is
+        /// empty
+        pub async fn play_and_wait(&self, mut bytes: PlayBytesMessage, timeout: Duration) -> HermesResult<PlayFinishedMessage> {
+            if bytes.id.is_empty() {
+                bytes.id = new_request_id();
+            }
+            let id = bytes.id.clone();
+            let responses = self
+                .play_finished(bytes.site_id.clone())?
+                .filter(move |it| future::ready(it.id == id));
+            self.facade.publish_play_bytes(bytes)?;
+            first_within(responses, timeout).await
+        }
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `AudioServerBackendFacade`
+    AsyncAudioServerBackendFacade(AudioServerBackendFacade) {
+        identifiable_component_backend!();
+        identifiable_toggleable_backend!();
+        s!(play_bytes<PlayBytesMessage> = subscribe_play_bytes(site_id: String));
+        s!(all_play_bytes<PlayBytesMessage> = subscribe_all_play_bytes());
+        p!(publish_play_finished(status: PlayFinishedMessage));
+        p!(publish_audio_frame(frame: AudioFrameMessage));
+        s!(replay_request<ReplayRequestMessage> = subscribe_replay_request(site_id: String));
+        p!(publish_replay_response(frame: AudioFrameMessage));
+        s!(stream_bytes<StreamBytesMessage> = subscribe_stream_bytes(site_id: String));
+        s!(all_stream_bytes<StreamBytesMessage> = subscribe_all_stream_bytes());
+        p!(publish_stream_finished(status: StreamFinishedMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `DialogueFacade`
+    AsyncDialogueFacade(DialogueFacade) {
+        component!();
+        toggleable!();
+        s!(session_queued<SessionQueuedMessage> = subscribe_session_queued());
+        s!(session_started<SessionStartedMessage> = subscribe_session_started());
+        s!(intent<IntentMessage> = subscribe_intent(intent_name: String));
+        s!(intents<IntentMessage> = subscribe_intents());
+        s!(intent_not_recognized<IntentNotRecognizedMessage> = subscribe_intent_not_recognized());
+        s!(session_ended<SessionEndedMessage> = subscribe_session_ended());
+        p!(publish_start_session(start_session: StartSessionMessage));
+        p!(publish_continue_session(continue_session: ContinueSessionMessage));
+        p!(publish_end_session(end_session: EndSessionMessage));
+        p!(publish_configure(config: DialogueConfigureMessage));
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `DialogueBackendFacade`
+    AsyncDialogueBackendFacade(DialogueBackendFacade) {
+        component_backend!();
+        toggleable_backend!();
+        p!(publish_session_queued(status: SessionQueuedMessage));
+        p!(publish_session_started(status: SessionStartedMessage));
+        p!(publish_intent(intent: IntentMessage));
+        p!(publish_intent_not_recognized(intent_not_recognized: IntentNotRecognizedMessage));
+        p!(publish_session_ended(status: SessionEndedMessage));
+        s!(start_session<StartSessionMessage> = subscribe_start_session());
+        s!(continue_session<ContinueSessionMessage> = subscribe_continue_session());
+        s!(end_session<EndSessionMessage> = subscribe_end_session());
+        s!(configure<DialogueConfigureMessage> = subscribe_configure());
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `InjectionFacade`
+    AsyncInjectionFacade(InjectionFacade) {
+        component!();
+        p!(publish_injection_request(request: InjectionRequestMessage));
+        p!(publish_injection_status_request());
+        p!(publish_injection_reset_request(request: InjectionResetRequestMessage));
+        s!(injection_status<InjectionStatusMessage> = subscribe_injection_status());
+        s!(injection_complete<InjectionCompleteMessage> = subscribe_injection_complete());
+        s!(injection_reset_complete<InjectionResetCompleteMessage> = subscribe_injection_reset_complete());
+
+        /// Request an injection and wait for it to complete, an id is generated for the request if it
+        /// doesn't have one
+        pub async fn inject_and_wait(
+            &self,
+            mut request: InjectionRequestMessage,
+            timeout: Duration,
+        ) -> HermesResult<InjectionCompleteMessage> {
+            let id = request.id.get_or_insert_with(new_request_id).clone();
+            let responses = self
+                .injection_complete()?
+                .filter(move |it| future::ready(it.request_id.as_ref() == Some(&id)));
+            self.facade.publish_injection_request(request)?;
+            first_within(responses, timeout).await
+        }
+    }
+}
+
+async_facade! {
+    /// The async counterpart of `InjectionBackendFacade`
+    AsyncInjectionBackendFacade(InjectionBackendFacade) {
+        component_backend!();
+        s!(injection_request<InjectionRequestMessage> = subscribe_injection_request());
+        s!(injection_status_request = subscribe_injection_status_request());
+        s!(injection_reset_request<InjectionResetRequestMessage> = subscribe_injection_reset_request());
+        p!(publish_injection_status(status: InjectionStatusMessage));
+        p!(publish_injection_complete(message: InjectionCompleteMessage));
+        p!(publish_injection_reset_complete(message: InjectionResetCompleteMessage));
+    }
+}
+
+/// Gives access to the async facades of any `HermesProtocolHandler`
+pub trait AsyncHermesProtocolHandler {
+    fn async_voice_activity(&self) -> AsyncVoiceActivityFacade;
+    fn async_hotword(&self) -> AsyncHotwordFacade;
+    fn async_sound_feedback(&self) -> AsyncSoundFeedbackFacade;
+    fn async_asr(&self) -> AsyncAsrFacade;
+    fn async_tts(&self) -> AsyncTtsFacade;
+    fn async_nlu(&self) -> AsyncNluFacade;
+    fn async_audio_server(&self) -> AsyncAudioServerFacade;
+    fn async_dialogue(&self) -> AsyncDialogueFacade;
+    fn async_injection(&self) -> AsyncInjectionFacade;
+    fn async_voice_activity_backend(&self) -> AsyncVoiceActivityBackendFacade;
+    fn async_hotword_backend(&self) -> AsyncHotwordBackendFacade;
+    fn async_sound_feedback_backend(&self) -> AsyncSoundFeedbackBackendFacade;
+    fn async_asr_backend(&self) -> AsyncAsrBackendFacade;
+    fn async_tts_backend(&self) -> AsyncTtsBackendFacade;
+    fn async_nlu_backend(&self) -> AsyncNluBackendFacade;
+    fn async_audio_server_backend(&self) -> AsyncAudioServerBackendFacade;
+    fn async_dialogue_backend(&self) -> AsyncDialogueBackendFacade;
+    fn async_injection_backend(&self) -> AsyncInjectionBackendFacade;
+}
+
+impl<H: HermesProtocolHandler + ?Sized> AsyncHermesProtocolHandler for H {
+    fn async_voice_activity(&self) -> AsyncVoiceActivityFacade {
+        self.voice_activity().into()
+    }
+
+    fn async_hotword(&self) -> AsyncHotwordFacade {
+        self.hotword().into()
+    }
+
+    fn async_sound_feedback(&self) -> AsyncSoundFeedbackFacade {
+        self.sound_feedback().into()
+    }
+
+    fn async_asr(&self) -> AsyncAsrFacade {
+        self.asr().into()
+    }
+
+    fn async_tts(&self) -> AsyncTtsFacade {
+        self.tts().into()
+    }
+
+    fn async_nlu(&self) -> AsyncNluFacade {
+        self.nlu().into()
+    }
+
+    fn async_audio_server(&self) -> AsyncAudioServerFacade {
+        self.audio_server().into()
+    }
+
+    fn async_dialogue(&self) -> AsyncDialogueFacade {
+        self.dialogue().into()
+    }
+
+    fn async_injection(&self) -> AsyncInjectionFacade {
+        self.injection().into()
+    }
+
+    fn async_voice_activity_backend(&self) -> AsyncVoiceActivityBackendFacade {
+        self.voice_activity_backend().into()
+    }
+
+    fn async_hotword_backend(&self) -> AsyncHotwordBackendFacade {
+        self.hotword_backend().into()
+    }
+
+    fn async_sound_feedback_backend(&self) -> AsyncSoundFeedbackBackendFacade {
+        self.sound_feedback_backend().into()
+    }
+
+    fn async_asr_backend(&self) -> AsyncAsrBackendFacade {
+        self.asr_backend().into()
+    }
+
+    fn async_tts_backend(&self) -> AsyncTtsBackendFacade {
+        self.tts_backend().into()
+    }
+
+    fn async_nlu_backend(&self) -> AsyncNluBackendFacade {
+        self.nlu_backend().into()
+    }
+
+    fn async_audio_server_backend(&self) -> AsyncAudioServerBackendFacade {
+        self.audio_server_backend().into()
+    }
+
+    fn async_dialogue_backend(&self) -> AsyncDialogueBackendFacade {
+        self.dialogue_backend().into()
+    }
+
+    fn async_injection_backend(&self) -> AsyncInjectionBackendFacade {
+        self.injection_backend().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc as std_mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor;
+    use hermes::hermes_utils::Example;
+    use hermes_inprocess::InProcessHermesProtocolHandler;
+
+    use super::*;
+
+    fn next_within<T: Send + 'static>(mut stream: HermesStream<T>, timeout: Duration) -> Option<T> {
+        let (sender, receiver) = std_mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(executor::block_on(stream.next()));
+        });
+        receiver.recv_timeout(timeout).ok().flatten()
+    }
+
+    #[test]
+    fn stream_receives_published_messages() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let intents = handler.async_dialogue().intents().unwrap();
+        let intent = IntentMessage::full_example();
+
+        executor::block_on(handler.async_dialogue_backend().publish_intent(intent.clone())).unwrap();
+
+        assert_eq!(next_within(intents, Duration::from_secs(1)), Some(intent));
+    }
+
+    #[test]
+    fn stream0_receives_published_messages() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let toggle_on = handler.async_dialogue_backend().toggle_on().unwrap();
+
+        executor::block_on(handler.async_dialogue().publish_toggle_on()).unwrap();
+
+        assert_eq!(next_within(toggle_on, Duration::from_secs(1)), Some(()));
+    }
+
+    #[test]
+    fn say_and_wait_resolves_with_the_matching_response() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let tts_backend = Arc::new(handler.tts_backend());
+        let responder = Arc::clone(&tts_backend);
+        let _subscription = tts_backend
+            .subscribe_say(Callback::new(move |say: &SayMessage| {
+                responder
+                    .publish_say_finished(SayFinishedMessage::full_example())
+                    .unwrap();
+                responder
+                    .publish_say_finished(SayFinishedMessage {
+                        id: say.id.clone(),
+                        session_id: None,
+                    })
+                    .unwrap();
+            }))
+            .unwrap();
+
+        let finished = executor::block_on(
+            handler
+                .async_tts()
+                .say_and_wait(SayMessage::minimal_example(), Duration::from_secs(1)),
+        )
+        .unwrap();
+
+        assert_ne!(finished, SayFinishedMessage::full_example());
+    }
+
+    #[test]
+    fn say_and_wait_times_out() {
+        let handler = InProcessHermesProtocolHandler::new();
+
+        let result = executor::block_on(
+            handler
+                .async_tts()
+                .say_and_wait(SayMessage::minimal_example(), Duration::from_millis(100)),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn full_streams_drop_the_newest_messages_without_stalling_the_others() {
+        // the messages are delivered on the thread pumping the bus, the one polling the streams
+        let handler = InProcessHermesProtocolHandler::new_deterministic();
+        let backend = handler.async_dialogue_backend();
+        let stalled = handler.async_dialogue().with_buffer(0).intents().unwrap();
+        let mut intents = handler.async_dialogue().intents().unwrap();
+        let intent = IntentMessage::full_example();
+
+        for _ in 0..3 {
+            executor::block_on(backend.publish_intent(intent.clone())).unwrap();
+        }
+        handler.run_until_idle().unwrap();
+
+        let received = executor::block_on(intents.by_ref().take(3).collect::<Vec<_>>());
+        assert_eq!(received, vec![intent.clone(), intent.clone(), intent]);
+        assert_eq!(intents.dropped(), 0);
+        assert_eq!(stalled.dropped(), 2);
+    }
+}
@@ -0,0 +1,442 @@
+//! The subcommands driving the platform through the facades, with the typed messages of the
+//! ontology so that they follow it as it changes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use hermes::compatibility::{Compatibility, ComponentVersionReport};
+use hermes::*;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct SayOpt {
+    /// What to say
+    text: String,
+    #[structopt(long = "site", short = "s", default_value = "default")]
+    site_id: String,
+    #[structopt(long)]
+    lang: Option<String>,
+    /// Wait for the tts to be done saying it
+    #[structopt(long)]
+    wait: bool,
+    /// How long to wait, in seconds
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SessionOpt {
+    /// Start a session, that expects an answer from the user unless it is a notification
+    Start {
+        /// What to say to the user
+        #[structopt(long)]
+        text: Option<String>,
+        /// The intents the user may answer with, can be repeated or separated by commas
+        #[structopt(long = "intent-filter", use_delimiter = true)]
+        intent_filter: Vec<String>,
+        /// Only say the text, without listening to the user
+        #[structopt(long)]
+        notification: bool,
+        #[structopt(long = "site", short = "s")]
+        site_id: Option<String>,
+        #[structopt(long = "custom-data")]
+        custom_data: Option<String>,
+    },
+    /// End a session
+    End {
+        session_id: String,
+        /// What to say to the user before ending the session
+        #[structopt(long)]
+        text: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub struct InjectOpt {
+    /// Values to inject for an entity, as `entity=value,other value`, can be repeated
+    #[structopt(long = "entity", short = "e", parse(try_from_str = parse_entity), required = true)]
+    entities: Vec<(String, Vec<String>)>,
+    /// Add the values to those downloaded with the assistant rather than to the current ones
+    #[structopt(long = "from-vanilla")]
+    from_vanilla: bool,
+    /// Wait for the injection to be complete
+    #[structopt(long)]
+    wait: bool,
+    /// How long to wait, in seconds
+    #[structopt(long, default_value = "300")]
+    timeout: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct PlayOpt {
+    /// The wav file to play
+    file: std::path::PathBuf,
+    #[structopt(long = "site", short = "s", default_value = "default")]
+    site_id: String,
+    /// Wait for the audio server to be done playing it
+    #[structopt(long)]
+    wait: bool,
+    /// How long to wait, in seconds
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ToggleOpt {
+    /// The component to toggle: hotword, sound-feedback, asr, dialogue or audio-server
+    component: Toggleable,
+    /// on or off
+    state: ToggleState,
+    /// The site to toggle the component on, for the components running on each site
+    #[structopt(long = "site", short = "s", default_value = "default")]
+    site_id: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct VersionOpt {
+    /// The components to ask: asr, tts, nlu, dialogue, injection, hotword, audio-server or
+    /// voice-activity
+    components: Vec<Versioned>,
+    /// Ask all the components, which is what happens when none is given
+    #[structopt(long)]
+    all: bool,
+    /// The site of the components running on each site
+    #[structopt(long = "site", short = "s", default_value = "default")]
+    site_id: String,
+    /// How long to wait for the versions, in seconds
+    #[structopt(long, default_value = "2")]
+    timeout: u64,
+}
+
+/// Parse the values of an entity, as `entity=value,other value`
+fn parse_entity(entity: &str) -> Result<(String, Vec<String>), String> {
+    let mut parts = entity.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(values)) if !name.is_empty() && !values.is_empty() => Ok((
+            name.to_string(),
+            values.split(',').map(|it| it.trim().to_string()).collect(),
+        )),
+        _ => Err(format!("expected entity=value,other value, got '{}'", entity)),
+    }
+}
+
+/// Parses the names of a list of variants, as given on the command line
+macro_rules! named {
+    ($t:ident { $($name:expr => $variant:ident,)* }) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum $t {
+            $($variant,)*
+        }
+
+        impl $t {
+            const ALL: &'static [$t] = &[$($t::$variant,)*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    $($t::$variant => $name,)*
+                }
+            }
+        }
+
+        impl FromStr for $t {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, String> {
+                Self::ALL.iter().cloned().find(|it| it.name() == s).ok_or_else(|| {
+                    let names: Vec<&str> = Self::ALL.iter().map(|it| it.name()).collect();
+                    format!("expected one of {}, got '{}'", names.join(", "), s)
+                })
+            }
+        }
+
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+    };
+}
+
+named!(Toggleable {
+    "hotword" => Hotword,
+    "sound-feedback" => SoundFeedback,
+    "asr" => Asr,
+    "dialogue" => Dialogue,
+    "audio-server" => AudioServer,
+});
+
+named!(ToggleState {
+    "on" => On,
+    "off" => Off,
+});
+
+named!(Versioned {
+    "asr" => Asr,
+    "tts" => Tts,
+    "nlu" => Nlu,
+    "dialogue" => Dialogue,
+    "injection" => Injection,
+    "hotword" => Hotword,
+    "audio-server" => AudioServer,
+    "voice-activity" => VoiceActivity,
+});
+
+pub fn say(handler: &dyn HermesProtocolHandler, opt: SayOpt) -> HermesResult<()> {
+    let say = SayMessage {
+        text: opt.text,
+        lang: opt.lang,
+        id: Some(new_request_id()),
+        site_id: opt.site_id,
+        session_id: None,
+    };
+    if opt.wait {
+        handler.tts().say_and_wait(say, Duration::from_secs(opt.timeout))?;
+    } else {
+        handler.tts().publish_say(say)?;
+    }
+    Ok(())
+}
+
+pub fn session(handler: &dyn HermesProtocolHandler, opt: SessionOpt) -> HermesResult<()> {
+    match opt {
+        SessionOpt::Start {
+            text,
+            intent_filter,
+            notification,
+            site_id,
+            custom_data,
+        } => {
+            let init = if notification {
+                SessionInit::Notification {
+                    text: text.unwrap_or_default(),
+                }
+            } else {
+                SessionInit::Action {
+                    text,
+                    intent_filter: Some(intent_filter).filter(|it| !it.is_empty()),
+                    can_be_enqueued: true,
+                    send_intent_not_recognized: false,
+                }
+            };
+            handler.dialogue().publish_start_session(StartSessionMessage {
+                init,
+                custom_data,
+                site_id,
+            })
+        }
+        SessionOpt::End { session_id, text } => handler
+            .dialogue()
+            .publish_end_session(EndSessionMessage { session_id, text }),
+    }
+}
+
+pub fn inject(handler: &dyn HermesProtocolHandler, opt: InjectOpt) -> HermesResult<()> {
+    let kind = if opt.from_vanilla {
+        InjectionKind::AddFromVanilla
+    } else {
+        InjectionKind::Add
+    };
+    let entities: HashMap<String, Vec<EntityValue>> = opt
+        .entities
+        .into_iter()
+        .map(|(entity, values)| {
+            let values = values
+                .into_iter()
+                .map(|value| EntityValue { value, weight: 1 })
+                .collect();
+            (entity, values)
+        })
+        .collect();
+    let request = InjectionRequestMessage {
+        operations: vec![(kind, entities)],
+        lexicon: HashMap::new(),
+        cross_language: None,
+        id: Some(new_request_id()),
+    };
+    if opt.wait {
+        handler
+            .injection()
+            .inject_and_wait(request, Duration::from_secs(opt.timeout))?;
+    } else {
+        handler.injection().publish_injection_request(request)?;
+    }
+    Ok(())
+}
+
+pub fn play(handler: &dyn HermesProtocolHandler, opt: PlayOpt) -> HermesResult<()> {
+    let bytes = PlayBytesMessage {
+        id: new_request_id(),
+        wav_bytes: std::fs::read(&opt.file)?,
+        site_id: opt.site_id,
+    };
+    if opt.wait {
+        handler
+            .audio_server()
+            .play_and_wait(bytes, Duration::from_secs(opt.timeout))?;
+    } else {
+        handler.audio_server().publish_play_bytes(bytes)?;
+    }
+    Ok(())
+}
+
+fn toggle_site<F: IdentifiableToggleableFacade + ?Sized>(
+    facade: &F,
+    state: ToggleState,
+    site_id: String,
+) -> HermesResult<()> {
+    let site = SiteMessage {
+        site_id,
+        session_id: None,
+    };
+    match state {
+        ToggleState::On => facade.publish_toggle_on(site),
+        ToggleState::Off => facade.publish_toggle_off(site),
+    }
+}
+
+fn toggle_everywhere<F: ToggleableFacade + ?Sized>(facade: &F, state: ToggleState) -> HermesResult<()> {
+    match state {
+        ToggleState::On => facade.publish_toggle_on(),
+        ToggleState::Off => facade.publish_toggle_off(),
+    }
+}
+
+pub fn toggle(handler: &dyn HermesProtocolHandler, opt: ToggleOpt) -> HermesResult<()> {
+    match opt.component {
+        Toggleable::Hotword => toggle_site(&*handler.hotword(), opt.state, opt.site_id),
+        Toggleable::SoundFeedback => toggle_site(&*handler.sound_feedback(), opt.state, opt.site_id),
+        Toggleable::AudioServer => toggle_site(&*handler.audio_server(), opt.state, opt.site_id),
+        Toggleable::Asr => toggle_everywhere(&*handler.asr(), opt.state),
+        Toggleable::Dialogue => toggle_everywhere(&*handler.dialogue(), opt.state),
+    }
+}
+
+/// The version of a component, along with the protocol it speaks and what it supports of it
+fn describe(report: &CompatibilityReport, reported: &ComponentVersionReport) -> String {
+    let version = match &reported.version {
+        Some(version) => version,
+        None => return "no answer".to_string(),
+    };
+    let protocol = match (&reported.compatibility, &version.protocol_version) {
+        (Compatibility::Incompatible(theirs), _) => {
+            format!("protocol {}, INCOMPATIBLE with {}", theirs, report.protocol_version)
+        }
+        (_, Some(theirs)) => format!("protocol {}", theirs),
+        (_, None) => "unknown protocol".to_string(),
+    };
+    let capabilities = if version.capabilities.is_empty() {
+        "no capabilities".to_string()
+    } else {
+        format!("capabilities: {}", version.capabilities.join(", "))
+    };
+    format!("{}, {}, {}", version.version, protocol, capabilities)
+}
+
+pub fn version(handler: &dyn HermesProtocolHandler, opt: VersionOpt) -> HermesResult<()> {
+    let components = if opt.all || opt.components.is_empty() {
+        Versioned::ALL.to_vec()
+    } else {
+        opt.components
+    };
+    let report = check_compatibility(handler, &[opt.site_id], Duration::from_secs(opt.timeout))?;
+    for reported in &report.components {
+        // the components are named with underscores in the report, with dashes on the command line
+        let component = Versioned::ALL
+            .iter()
+            .find(|it| it.name().replace('-', "_") == reported.component);
+        match component {
+            Some(component) if components.contains(component) => {
+                println!("{}: {}", component, describe(&report, reported))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hermes::compatibility::{protocol_version, CAPABILITIES};
+    use hermes::mock::MockTts;
+    use hermes_inprocess::InProcessHermesProtocolHandler;
+
+    use super::*;
+
+    #[test]
+    fn entities_are_parsed() {
+        assert_eq!(
+            parse_entity("city=Paris, New York"),
+            Ok(("city".to_string(), vec!["Paris".to_string(), "New York".to_string()]))
+        );
+        assert!(parse_entity("city").is_err());
+        assert!(parse_entity("city=").is_err());
+    }
+
+    #[test]
+    fn versions_tell_their_protocol() {
+        let ours = protocol_version();
+        let mut theirs = protocol_version();
+        theirs.major += 1;
+        let reported = |version: Option<VersionMessage>, compatibility| ComponentVersionReport {
+            component: "tts",
+            site_id: None,
+            version,
+            compatibility,
+        };
+        let report = CompatibilityReport {
+            protocol_version: ours.clone(),
+            components: vec![],
+        };
+
+        let version = VersionMessage::new(ours.clone());
+        assert_eq!(
+            describe(&report, &reported(Some(version.clone()), Compatibility::Compatible)),
+            format!("{}, protocol {}, capabilities: {}", ours, ours, CAPABILITIES.join(", "))
+        );
+
+        let version = VersionMessage {
+            protocol_version: Some(theirs.clone()),
+            capabilities: vec![],
+            ..version
+        };
+        assert_eq!(
+            describe(
+                &report,
+                &reported(Some(version.clone()), Compatibility::Incompatible(theirs.clone()))
+            ),
+            format!(
+                "{}, protocol {}, INCOMPATIBLE with {}, no capabilities",
+                ours, theirs, ours
+            )
+        );
+
+        let version = VersionMessage {
+            protocol_version: None,
+            ..version
+        };
+        assert_eq!(
+            describe(&report, &reported(Some(version), Compatibility::Unknown)),
+            format!("{}, unknown protocol, no capabilities", ours)
+        );
+        assert_eq!(describe(&report, &reported(None, Compatibility::NoReply)), "no answer");
+    }
+
+    #[test]
+    fn say_waits_for_the_tts() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let tts = MockTts::new(&handler).unwrap();
+
+        say(
+            &handler,
+            SayOpt::from_iter(&["say", "hello", "--site", "kitchen", "--wait", "--timeout", "1"]),
+        )
+        .unwrap();
+
+        let said = tts.received().unwrap();
+        assert_eq!(said.len(), 1);
+        assert_eq!(said[0].text, "hello");
+        assert_eq!(said[0].site_id, "kitchen");
+    }
+}
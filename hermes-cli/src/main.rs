@@ -0,0 +1,89 @@
+//! The `hermes` command line tool, to see what goes through the MQTT broker of a platform and to
+//! drive it
+
+mod interact;
+mod watch;
+
+use std::thread;
+use std::time::Duration;
+
+use hermes::HermesResult;
+use hermes_mqtt::MqttHermesProtocolHandler;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "hermes")]
+struct Opt {
+    /// The address of the MQTT broker
+    #[structopt(long, short = "b", default_value = "localhost:1883", global = true)]
+    broker: String,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Print the messages going through the broker, decoded into their type of the ontology
+    Watch(watch::WatchOpt),
+    /// Have the tts say something
+    Say(interact::SayOpt),
+    /// Start or end a dialogue session
+    Session(interact::SessionOpt),
+    /// Inject entity values in the asr and the nlu
+    Inject(interact::InjectOpt),
+    /// Have the audio server play a wav file
+    Play(interact::PlayOpt),
+    /// Toggle a component on or off
+    Toggle(interact::ToggleOpt),
+    /// Print the versions of the components, with the protocol they speak and its capabilities
+    Version(interact::VersionOpt),
+}
+
+/// How long the MQTT client is given to send what was published before exiting, as it sends in the
+/// background
+const FLUSH_DURATION: Duration = Duration::from_millis(200);
+
+fn run(opt: Opt) -> HermesResult<()> {
+    let handler = MqttHermesProtocolHandler::new(&opt.broker)?;
+    match opt.command {
+        Command::Watch(watch) => watch::run(&handler, watch),
+        Command::Say(say) => interact::say(&handler, say),
+        Command::Session(session) => interact::session(&handler, session),
+        Command::Inject(inject) => interact::inject(&handler, inject),
+        Command::Play(play) => interact::play(&handler, play),
+        Command::Toggle(toggle) => interact::toggle(&handler, toggle),
+        Command::Version(version) => interact::version(&handler, version),
+    }?;
+    thread::sleep(FLUSH_DURATION);
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Opt::from_args()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subcommands_are_parsed() {
+        let opt = Opt::from_iter(&["hermes", "toggle", "hotword", "off", "--site", "kitchen"]);
+        assert!(matches!(opt.command, Command::Toggle(_)));
+        assert_eq!(opt.broker, "localhost:1883");
+
+        let opt = Opt::from_iter_safe(&["hermes", "toggle", "tts", "off"]);
+        assert!(opt.is_err());
+
+        let opt = Opt::from_iter(&["hermes", "session", "start", "--intent-filter", "lights,weather"]);
+        match opt.command {
+            Command::Session(interact::SessionOpt::Start { intent_filter, .. }) => {
+                assert_eq!(intent_filter, vec!["lights", "weather"])
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+}
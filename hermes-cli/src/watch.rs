@@ -0,0 +1,211 @@
+//! `hermes watch`: the messages going through the broker, decoded into the types of the ontology
+//! their topics carry. Binary payloads are summed up rather than dumped, and the payloads that
+//! don't decode are flagged.
+
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use hermes::*;
+use hermes_mqtt::topics::*;
+use hermes_mqtt::{decode_typed, MqttHermesProtocolHandler, TrafficFilter, TypedPayload};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct WatchOpt {
+    /// Only the messages of this component, as named in the topics (asr, tts, dialogueManager,
+    /// intent...), can be repeated
+    #[structopt(long = "component", short = "c")]
+    components: Vec<String>,
+    /// Only the messages of this site
+    #[structopt(long = "site", short = "s")]
+    site_id: Option<String>,
+    /// Only the messages of this session
+    #[structopt(long = "session")]
+    session_id: Option<String>,
+}
+
+/// What a message turned out to be
+#[derive(Debug, PartialEq)]
+enum Decoded {
+    /// A message without payload
+    Empty,
+    Message {
+        type_name: &'static str,
+        /// The message as its type encodes it
+        json: serde_json::Value,
+    },
+    Binary {
+        size: usize,
+        /// How long the audio lasts, when the payload is a wav file
+        duration: Option<Duration>,
+    },
+    Invalid {
+        type_name: &'static str,
+        error: String,
+    },
+}
+
+impl Decoded {
+    fn json(&self) -> Option<&serde_json::Value> {
+        match self {
+            Decoded::Message { json, .. } => Some(json),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a payload into the type of the ontology its topic carries
+fn decode(topic: &HermesTopic, payload: &[u8]) -> Decoded {
+    if topic.is_binary() {
+        return Decoded::Binary {
+            size: payload.len(),
+            duration: wav_duration(payload),
+        };
+    }
+    match decode_typed(topic, payload) {
+        Some(TypedPayload {
+            type_name,
+            json: Ok(json),
+        }) => Decoded::Message { type_name, json },
+        Some(TypedPayload {
+            type_name,
+            json: Err(error),
+        }) => Decoded::Invalid { type_name, error },
+        None => Decoded::Empty,
+    }
+}
+
+/// How long the audio of a wav file lasts, going by its header
+fn wav_duration(bytes: &[u8]) -> Option<Duration> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut byte_rate = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+        let size = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().ok()?) as usize;
+        let body = position + 8;
+        match &bytes[position..position + 4] {
+            b"fmt " if body + 12 <= bytes.len() => {
+                byte_rate = Some(u32::from_le_bytes(bytes[body + 8..body + 12].try_into().ok()?))
+            }
+            b"data" => {
+                let byte_rate = byte_rate.filter(|it| *it > 0)?;
+                // the size of a streamed wav isn't known when its header is written
+                let length = size.min(bytes.len() - body);
+                return Some(Duration::from_secs_f64(length as f64 / f64::from(byte_rate)));
+            }
+            _ => {}
+        }
+        position = body.checked_add(size)?.checked_add(size % 2)?;
+    }
+    None
+}
+
+/// The component of a topic, as named in its path
+fn component(topic: &HermesTopic) -> String {
+    topic.as_path().split('/').nth(1).unwrap_or_default().to_string()
+}
+
+fn print(topic: &HermesTopic, decoded: &Decoded) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "{} {} ", Local::now().format("%H:%M:%S%.3f"), topic.as_path())?;
+    match decoded {
+        Decoded::Empty => writeln!(out, "(no payload)"),
+        Decoded::Message { type_name, json } => writeln!(
+            out,
+            "{}\n{}",
+            type_name,
+            serde_json::to_string_pretty(json).unwrap_or_default()
+        ),
+        Decoded::Binary {
+            size,
+            duration: Some(duration),
+        } => writeln!(out, "{} bytes of audio, {} ms", size, duration.as_millis()),
+        Decoded::Binary { size, duration: None } => writeln!(out, "{} bytes", size),
+        Decoded::Invalid { type_name, error } => writeln!(out, "INVALID {}: {}", type_name, error),
+    }
+}
+
+pub fn run(handler: &MqttHermesProtocolHandler, opt: WatchOpt) -> HermesResult<()> {
+    let filter = TrafficFilter {
+        site_id: opt.site_id,
+        session_id: opt.session_id,
+    };
+    let components = opt.components;
+    let _subscription = handler.subscribe_traffic(move |topic, payload| {
+        if !components.is_empty() && !components.contains(&component(topic)) {
+            return;
+        }
+        let decoded = decode(topic, payload);
+        if !filter.matches(topic, decoded.json()) {
+            return;
+        }
+        if let Err(e) = print(topic, &decoded) {
+            eprintln!("Could not print the message on {}: {}", topic.as_path(), e)
+        }
+    })?;
+    loop {
+        thread::park()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_are_decoded_into_the_type_of_their_topic() {
+        let decoded = decode(
+            &HermesTopic::Tts(TtsCommand::SayFinished),
+            br#"{"id": "abc", "sessionId": null}"#,
+        );
+        assert_eq!(
+            decoded,
+            Decoded::Message {
+                type_name: "SayFinishedMessage",
+                json: serde_json::json!({ "id": "abc", "sessionId": null }),
+            }
+        );
+
+        let decoded = decode(&HermesTopic::Tts(TtsCommand::Say), br#"{"id": "abc"}"#);
+        assert!(matches!(
+            decoded,
+            Decoded::Invalid {
+                type_name: "SayMessage",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn audio_is_summed_up() {
+        // 16kHz 16 bits mono, 0.1 second of silence
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 1, 0]);
+        wav.extend_from_slice(&16_000u32.to_le_bytes());
+        wav.extend_from_slice(&32_000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&3200u32.to_le_bytes());
+        wav.extend_from_slice(&[0; 3200]);
+
+        let decoded = decode(
+            &HermesTopic::AudioServer(Some("default".into()), AudioServerCommand::AudioFrame),
+            &wav,
+        );
+
+        assert_eq!(
+            decoded,
+            Decoded::Binary {
+                size: wav.len(),
+                duration: Some(Duration::from_millis(100)),
+            }
+        );
+    }
+}
@@ -0,0 +1,735 @@
+//! The state of the sessions of every site. Events go in, the messages to publish in response come
+//! out, nothing is published from here so that the state can be kept locked while it changes.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use log::{debug, warn};
+
+use hermes::*;
+
+use crate::DialogueConfig;
+
+/// A message the dialogue manager must publish
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Output {
+    SessionQueued(SessionQueuedMessage),
+    SessionStarted(SessionStartedMessage),
+    SessionEnded(SessionEndedMessage),
+    Intent(IntentMessage),
+    IntentNotRecognized(IntentNotRecognizedMessage),
+    Say(SayMessage),
+    StartListening(AsrStartListeningMessage),
+    StopListening(SiteMessage),
+    Query(NluQueryMessage),
+    PartialQuery(NluSlotQueryMessage),
+    HotwordOn(SiteMessage),
+    HotwordOff(SiteMessage),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Then {
+    Listen,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// Waiting for the session before it on its site to end
+    Queued(SessionInit),
+    /// Waiting for the tts to say something
+    Saying { say_id: String, then: Then },
+    /// Waiting for the asr to capture what the user says
+    Listening,
+    /// Waiting for the nlu to understand what the user said
+    Understanding { query_id: String },
+    /// Waiting for the client to continue or end the session
+    WaitingForClient,
+}
+
+#[derive(Debug)]
+struct Session {
+    id: String,
+    site_id: String,
+    custom_data: Option<String>,
+    intent_filter: Option<Vec<String>>,
+    send_intent_not_recognized: bool,
+    slot: Option<String>,
+    step: Step,
+    /// When the component we are waiting for times out
+    deadline: Option<Instant>,
+    /// What the asr captured in the current turn
+    captured: Option<TextCapturedMessage>,
+}
+
+impl Session {
+    fn new(site_id: String, custom_data: Option<String>, init: SessionInit) -> Self {
+        Self {
+            id: new_request_id(),
+            site_id,
+            custom_data,
+            intent_filter: None,
+            send_intent_not_recognized: false,
+            slot: None,
+            step: Step::Queued(init),
+            deadline: None,
+            captured: None,
+        }
+    }
+
+    fn site(&self) -> SiteMessage {
+        SiteMessage {
+            site_id: self.site_id.clone(),
+            session_id: Some(self.id.clone()),
+        }
+    }
+
+    fn say(&mut self, config: &DialogueConfig, text: String, then: Then, now: Instant, out: &mut Vec<Output>) {
+        let say_id = new_request_id();
+        out.push(Output::Say(SayMessage {
+            text,
+            lang: None,
+            id: Some(say_id.clone()),
+            site_id: self.site_id.clone(),
+            session_id: Some(self.id.clone()),
+        }));
+        self.step = Step::Saying { say_id, then };
+        self.deadline = Some(now + config.tts_timeout);
+    }
+
+    fn listen(&mut self, config: &DialogueConfig, now: Instant, out: &mut Vec<Output>) {
+        out.push(Output::StartListening(AsrStartListeningMessage {
+            site_id: self.site_id.clone(),
+            session_id: Some(self.id.clone()),
+            start_signal_ms: None,
+        }));
+        self.step = Step::Listening;
+        self.deadline = Some(now + config.asr_timeout);
+        self.captured = None;
+    }
+
+    /// Say `text` if there is something to say, and go on
+    fn say_then(
+        &mut self,
+        config: &DialogueConfig,
+        text: Option<String>,
+        then: Then,
+        now: Instant,
+        out: &mut Vec<Output>,
+    ) {
+        match text.filter(|it| !it.trim().is_empty()) {
+            Some(text) => self.say(config, text, then, now, out),
+            None if then == Then::Listen => self.listen(config, now, out),
+            None => {}
+        }
+    }
+
+    fn wait_for_client(&mut self, config: &DialogueConfig, now: Instant) {
+        self.step = Step::WaitingForClient;
+        self.deadline = Some(now + config.client_timeout);
+    }
+
+    fn is_understanding(&self, id: &Option<String>) -> bool {
+        matches!(&self.step, Step::Understanding { query_id } if Some(query_id) == id.as_ref())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Site {
+    active: Option<Session>,
+    queue: VecDeque<Session>,
+}
+
+fn active_mut<'a>(sites: &'a mut HashMap<String, Site>, session_id: &str) -> Option<&'a mut Session> {
+    sites
+        .values_mut()
+        .filter_map(|it| it.active.as_mut())
+        .find(|it| it.id == session_id)
+}
+
+/// The session waiting for the result of a query
+fn understood<'a>(sites: &'a mut HashMap<String, Site>, query_id: &Option<String>) -> Option<&'a mut Session> {
+    sites
+        .values_mut()
+        .filter_map(|it| it.active.as_mut())
+        .find(|it| it.is_understanding(query_id))
+}
+
+/// The sessions of every site
+#[derive(Debug)]
+pub(crate) struct Dialogue {
+    config: DialogueConfig,
+    sites: HashMap<String, Site>,
+    /// The intents enabled or disabled with a `DialogueConfigureMessage`, for every site under
+    /// `None`, and per site
+    intents: HashMap<Option<String>, HashMap<String, bool>>,
+}
+
+impl Dialogue {
+    pub fn new(config: DialogueConfig) -> Self {
+        Self {
+            config,
+            sites: HashMap::new(),
+            intents: HashMap::new(),
+        }
+    }
+
+    fn is_enabled(&self, site_id: &str, intent_name: &str) -> bool {
+        let configured = |site: Option<String>| self.intents.get(&site).and_then(|it| it.get(intent_name)).cloned();
+        configured(Some(site_id.to_string()))
+            .or_else(|| configured(None))
+            .unwrap_or(true)
+    }
+
+    /// Start a session that was queued, or created right now
+    fn start(&mut self, mut session: Session, now: Instant, out: &mut Vec<Output>) {
+        let init = match std::mem::replace(&mut session.step, Step::WaitingForClient) {
+            Step::Queued(init) => init,
+            _ => unreachable!("only sessions that didn't start yet can be started"),
+        };
+        out.push(Output::SessionStarted(SessionStartedMessage {
+            session_id: session.id.clone(),
+            custom_data: session.custom_data.clone(),
+            site_id: session.site_id.clone(),
+            reactivated_from_session_id: None,
+        }));
+        out.push(Output::HotwordOff(session.site()));
+        match init {
+            SessionInit::Action {
+                text,
+                intent_filter,
+                send_intent_not_recognized,
+                ..
+            } => {
+                session.intent_filter = intent_filter;
+                session.send_intent_not_recognized = send_intent_not_recognized;
+                session.say_then(&self.config, text, Then::Listen, now, out);
+            }
+            SessionInit::Notification { text } => session.say(&self.config, text, Then::End, now, out),
+        }
+        self.sites.entry(session.site_id.clone()).or_default().active = Some(session);
+    }
+
+    /// End the active session of a site, and start the next one queued there
+    fn end(&mut self, site_id: &str, termination: SessionTerminationType, now: Instant, out: &mut Vec<Output>) {
+        let site = self.sites.entry(site_id.to_string()).or_default();
+        let session = match site.active.take() {
+            Some(session) => session,
+            None => return,
+        };
+        debug!("Ending session {} on site {}: {:?}", session.id, site_id, termination);
+        out.push(Output::SessionEnded(SessionEndedMessage {
+            session_id: session.id.clone(),
+            custom_data: session.custom_data.clone(),
+            termination,
+            site_id: site_id.to_string(),
+        }));
+        out.push(Output::HotwordOn(SiteMessage {
+            site_id: site_id.to_string(),
+            session_id: None,
+        }));
+        if let Some(next) = site.queue.pop_front() {
+            self.start(next, now, out)
+        }
+    }
+
+    pub fn hotword_detected(&mut self, message: &HotwordDetectedMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        if self.sites.get(&message.site_id).map_or(false, |it| it.active.is_some()) {
+            debug!(
+                "Ignoring hotword on site {}, a session is running there",
+                message.site_id
+            );
+            return out;
+        }
+        let init = SessionInit::Action {
+            text: None,
+            intent_filter: None,
+            can_be_enqueued: false,
+            send_intent_not_recognized: false,
+        };
+        self.start(Session::new(message.site_id.clone(), None, init), now, &mut out);
+        out
+    }
+
+    pub fn start_session(&mut self, message: &StartSessionMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let site_id = message
+            .site_id
+            .clone()
+            .unwrap_or_else(|| self.config.default_site_id.clone());
+        let session = Session::new(site_id.clone(), message.custom_data.clone(), message.init.clone());
+        let site = self.sites.entry(site_id.clone()).or_default();
+        if site.active.is_none() {
+            self.start(session, now, &mut out);
+            return out;
+        }
+        let can_be_enqueued = match message.init {
+            SessionInit::Action { can_be_enqueued, .. } => can_be_enqueued,
+            SessionInit::Notification { .. } => true,
+        };
+        if can_be_enqueued {
+            out.push(Output::SessionQueued(SessionQueuedMessage {
+                session_id: session.id.clone(),
+                custom_data: session.custom_data.clone(),
+                site_id,
+            }));
+            site.queue.push_back(session);
+        } else {
+            out.push(Output::SessionEnded(SessionEndedMessage {
+                session_id: session.id,
+                custom_data: session.custom_data,
+                termination: SessionTerminationType::SiteUnavailable,
+                site_id,
+            }));
+        }
+        out
+    }
+
+    pub fn continue_session(&mut self, message: &ContinueSessionMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        match active_mut(&mut self.sites, &message.session_id).filter(|it| it.step == Step::WaitingForClient) {
+            Some(session) => {
+                if message.custom_data.is_some() {
+                    session.custom_data = message.custom_data.clone();
+                }
+                session.intent_filter = message.intent_filter.clone();
+                session.send_intent_not_recognized = message.send_intent_not_recognized;
+                session.slot = message.slot.clone();
+                session.say_then(config, Some(message.text.clone()), Then::Listen, now, &mut out);
+            }
+            None => warn!("Can't continue session {}, it isn't waiting for us", message.session_id),
+        }
+        out
+    }
+
+    pub fn end_session(&mut self, message: &EndSessionMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        let site_id = match active_mut(&mut self.sites, &message.session_id) {
+            Some(session) => {
+                session.say_then(config, message.text.clone(), Then::End, now, &mut out);
+                if out.is_empty() {
+                    Some(session.site_id.clone())
+                } else {
+                    None
+                }
+            }
+            None => {
+                // the session may not have started yet
+                for site in self.sites.values_mut() {
+                    if let Some(index) = site.queue.iter().position(|it| it.id == message.session_id) {
+                        let session = site.queue.remove(index).unwrap();
+                        out.push(Output::SessionEnded(SessionEndedMessage {
+                            session_id: session.id,
+                            custom_data: session.custom_data,
+                            termination: SessionTerminationType::Nominal,
+                            site_id: session.site_id,
+                        }));
+                    }
+                }
+                None
+            }
+        };
+        if let Some(site_id) = site_id {
+            self.end(&site_id, SessionTerminationType::Nominal, now, &mut out)
+        }
+        out
+    }
+
+    pub fn configure(&mut self, message: &DialogueConfigureMessage) -> Vec<Output> {
+        let intents = self.intents.entry(message.site_id.clone()).or_default();
+        for intent in message.intents.iter().flatten() {
+            if let Some(enable) = intent.enable {
+                intents.insert(intent.intent_id.clone(), enable);
+            }
+        }
+        vec![]
+    }
+
+    pub fn say_finished(&mut self, message: &SayFinishedMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        let session = self
+            .sites
+            .values_mut()
+            .filter_map(|it| it.active.as_mut())
+            .find(|it| matches!(&it.step, Step::Saying { say_id, .. } if Some(say_id) == message.id.as_ref()));
+        let site_id = match session {
+            Some(session) => match session.step {
+                Step::Saying { then: Then::Listen, .. } => {
+                    session.listen(config, now, &mut out);
+                    None
+                }
+                _ => Some(session.site_id.clone()),
+            },
+            None => None,
+        };
+        if let Some(site_id) = site_id {
+            self.end(&site_id, SessionTerminationType::Nominal, now, &mut out)
+        }
+        out
+    }
+
+    pub fn text_captured(&mut self, message: &TextCapturedMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        let session = match message
+            .session_id
+            .as_ref()
+            .and_then(|id| active_mut(&mut self.sites, id))
+            .filter(|it| it.step == Step::Listening)
+        {
+            Some(session) => session,
+            None => return out,
+        };
+        out.push(Output::StopListening(session.site()));
+        let query_id = new_request_id();
+        match (&session.slot, &session.intent_filter) {
+            (Some(slot_name), Some(intent_filter)) if intent_filter.len() == 1 => {
+                out.push(Output::PartialQuery(NluSlotQueryMessage {
+                    input: message.text.clone(),
+                    asr_tokens: message.tokens.clone(),
+                    intent_name: intent_filter[0].clone(),
+                    slot_name: slot_name.clone(),
+                    id: Some(query_id.clone()),
+                    session_id: Some(session.id.clone()),
+                }))
+            }
+            _ => out.push(Output::Query(NluQueryMessage {
+                input: message.text.clone(),
+                asr_tokens: message.tokens.clone(),
+                intent_filter: session.intent_filter.clone(),
+                id: Some(query_id.clone()),
+                session_id: Some(session.id.clone()),
+            })),
+        }
+        session.step = Step::Understanding { query_id };
+        session.deadline = Some(now + config.nlu_timeout);
+        session.captured = Some(message.clone());
+        out
+    }
+
+    pub fn intent_parsed(&mut self, message: &NluIntentMessage, now: Instant) -> Vec<Output> {
+        let site_id = match understood(&mut self.sites, &message.id) {
+            Some(session) => session.site_id.clone(),
+            None => return vec![],
+        };
+        if !self.is_enabled(&site_id, &message.intent.intent_name) {
+            debug!("Intent {} is disabled on site {}", message.intent.intent_name, site_id);
+            return self.not_understood(&message.id, Some(message.input.clone()), 1., None, now);
+        }
+        let mut out = vec![];
+        let config = &self.config;
+        if let Some(session) = understood(&mut self.sites, &message.id) {
+            let captured = session.captured.take();
+            out.push(Output::Intent(IntentMessage {
+                session_id: session.id.clone(),
+                custom_data: session.custom_data.clone(),
+                site_id: session.site_id.clone(),
+                input: message.input.clone(),
+                asr_tokens: captured.as_ref().and_then(|it| it.tokens.clone()).map(|it| vec![it]),
+                asr_confidence: captured.as_ref().map(|it| it.likelihood),
+                speaker_hypotheses: captured.and_then(|it| it.speaker_hypotheses),
+                intent: message.intent.clone(),
+                slots: message.slots.clone(),
+                alternatives: message.alternatives.clone(),
+            }));
+            session.wait_for_client(config, now);
+        }
+        out
+    }
+
+    pub fn slot_parsed(&mut self, message: &NluSlotMessage, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        if let Some(session) = understood(&mut self.sites, &message.id) {
+            let captured = session.captured.take();
+            out.push(Output::Intent(IntentMessage {
+                session_id: session.id.clone(),
+                custom_data: session.custom_data.clone(),
+                site_id: session.site_id.clone(),
+                input: message.input.clone(),
+                asr_tokens: captured.as_ref().and_then(|it| it.tokens.clone()).map(|it| vec![it]),
+                asr_confidence: captured.as_ref().map(|it| it.likelihood),
+                speaker_hypotheses: captured.and_then(|it| it.speaker_hypotheses),
+                intent: NluIntentClassifierResult {
+                    intent_name: message.intent_name.clone(),
+                    confidence_score: 1.,
+                },
+                slots: message.slot.clone().into_iter().collect(),
+                alternatives: None,
+            }));
+            session.wait_for_client(config, now);
+        }
+        out
+    }
+
+    pub fn intent_not_recognized(&mut self, message: &NluIntentNotRecognizedMessage, now: Instant) -> Vec<Output> {
+        self.not_understood(
+            &message.id,
+            Some(message.input.clone()),
+            message.confidence_score,
+            message.alternatives.clone(),
+            now,
+        )
+    }
+
+    fn not_understood(
+        &mut self,
+        query_id: &Option<String>,
+        input: Option<String>,
+        confidence_score: f32,
+        alternatives: Option<Vec<NluIntentAlternative>>,
+        now: Instant,
+    ) -> Vec<Output> {
+        let mut out = vec![];
+        let config = &self.config;
+        let site_id = match understood(&mut self.sites, query_id) {
+            Some(session) if session.send_intent_not_recognized => {
+                out.push(Output::IntentNotRecognized(IntentNotRecognizedMessage {
+                    session_id: session.id.clone(),
+                    custom_data: session.custom_data.clone(),
+                    site_id: session.site_id.clone(),
+                    input,
+                    speaker_hypotheses: session.captured.take().and_then(|it| it.speaker_hypotheses),
+                    confidence_score,
+                    alternatives,
+                }));
+                session.wait_for_client(config, now);
+                None
+            }
+            Some(session) => Some(session.site_id.clone()),
+            None => None,
+        };
+        if let Some(site_id) = site_id {
+            self.end(&site_id, SessionTerminationType::IntentNotRecognized, now, &mut out)
+        }
+        out
+    }
+
+    /// End the sessions whose component didn't answer in time
+    pub fn tick(&mut self, now: Instant) -> Vec<Output> {
+        let mut out = vec![];
+        let expired: Vec<(String, HermesComponent)> = self
+            .sites
+            .values()
+            .filter_map(|it| it.active.as_ref())
+            .filter(|it| it.deadline.map_or(false, |deadline| deadline <= now))
+            .filter_map(|it| {
+                let component = match it.step {
+                    Step::Saying { .. } => HermesComponent::Tts,
+                    Step::Listening => HermesComponent::Asr,
+                    Step::Understanding { .. } => HermesComponent::Nlu,
+                    Step::WaitingForClient => HermesComponent::ClientApp,
+                    Step::Queued(_) => return None,
+                };
+                Some((it.site_id.clone(), component))
+            })
+            .collect();
+        for (site_id, component) in expired {
+            warn!("{:?} timed out on site {}", component, site_id);
+            if component == HermesComponent::Asr {
+                if let Some(session) = self.sites.get(&site_id).and_then(|it| it.active.as_ref()) {
+                    out.push(Output::StopListening(session.site()))
+                }
+            }
+            let termination = SessionTerminationType::Timeout {
+                component: Some(component),
+            };
+            self.end(&site_id, termination, now, &mut out)
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermes::hermes_utils::Example;
+
+    fn dialogue() -> Dialogue {
+        Dialogue::new(DialogueConfig::default())
+    }
+
+    fn detected(site_id: &str) -> HotwordDetectedMessage {
+        HotwordDetectedMessage {
+            site_id: site_id.into(),
+            ..HotwordDetectedMessage::minimal_example()
+        }
+    }
+
+    fn action(can_be_enqueued: bool) -> StartSessionMessage {
+        StartSessionMessage {
+            init: SessionInit::Action {
+                text: None,
+                intent_filter: None,
+                can_be_enqueued,
+                send_intent_not_recognized: false,
+            },
+            custom_data: Some("data".into()),
+            site_id: Some("kitchen".into()),
+        }
+    }
+
+    fn session_id(outputs: &[Output]) -> String {
+        outputs
+            .iter()
+            .find_map(|it| match it {
+                Output::SessionStarted(started) => Some(started.session_id.clone()),
+                Output::SessionQueued(queued) => Some(queued.session_id.clone()),
+                _ => None,
+            })
+            .expect("no session was started nor queued")
+    }
+
+    fn query_id(outputs: &[Output]) -> Option<String> {
+        outputs.iter().find_map(|it| match it {
+            Output::Query(query) => query.id.clone(),
+            _ => None,
+        })
+    }
+
+    fn termination(outputs: &[Output]) -> Option<SessionTerminationType> {
+        outputs.iter().find_map(|it| match it {
+            Output::SessionEnded(ended) => Some(ended.termination.clone()),
+            _ => None,
+        })
+    }
+
+    fn captured(session_id: &str, text: &str) -> TextCapturedMessage {
+        TextCapturedMessage {
+            text: text.into(),
+            site_id: "kitchen".into(),
+            session_id: Some(session_id.into()),
+            ..TextCapturedMessage::minimal_example()
+        }
+    }
+
+    fn parsed(query_id: Option<String>, intent_name: &str) -> NluIntentMessage {
+        let mut intent = NluIntentMessage::minimal_example();
+        intent.id = query_id;
+        intent.input = "turn on the lights".into();
+        intent.intent.intent_name = intent_name.into();
+        intent
+    }
+
+    #[test]
+    fn hotword_leads_to_an_intent() {
+        let mut dialogue = dialogue();
+        let now = Instant::now();
+
+        let out = dialogue.hotword_detected(&detected("kitchen"), now);
+        let session_id = session_id(&out);
+        assert!(out.iter().any(|it| matches!(it, Output::HotwordOff(_))));
+        assert!(out.iter().any(|it| matches!(it, Output::StartListening(_))));
+
+        let out = dialogue.text_captured(&captured(&session_id, "turn on the lights"), now);
+        assert!(matches!(out[0], Output::StopListening(_)));
+        let query_id = query_id(&out);
+
+        let out = dialogue.intent_parsed(&parsed(query_id, "lights"), now);
+        match &out[..] {
+            [Output::Intent(intent)] => {
+                assert_eq!(intent.session_id, session_id);
+                assert_eq!(intent.site_id, "kitchen");
+                assert_eq!(intent.intent.intent_name, "lights");
+            }
+            other => panic!("expected an intent, got {:?}", other),
+        }
+
+        let end = EndSessionMessage { session_id, text: None };
+        let out = dialogue.end_session(&end, now);
+        assert_eq!(termination(&out), Some(SessionTerminationType::Nominal));
+        assert!(out.iter().any(|it| matches!(it, Output::HotwordOn(_))));
+    }
+
+    #[test]
+    fn sessions_are_queued_on_busy_sites() {
+        let mut dialogue = dialogue();
+        let now = Instant::now();
+        let first = session_id(&dialogue.start_session(&action(true), now));
+
+        let out = dialogue.start_session(&action(true), now);
+        assert!(matches!(out[..], [Output::SessionQueued(_)]));
+        let second = session_id(&out);
+
+        let out = dialogue.start_session(&action(false), now);
+        assert_eq!(termination(&out), Some(SessionTerminationType::SiteUnavailable));
+
+        let end = EndSessionMessage {
+            session_id: first,
+            text: None,
+        };
+        let out = dialogue.end_session(&end, now);
+        assert!(out
+            .iter()
+            .any(|it| matches!(it, Output::SessionStarted(started) if started.session_id == second)));
+    }
+
+    #[test]
+    fn notifications_end_once_said() {
+        let mut dialogue = dialogue();
+        let now = Instant::now();
+        let notification = StartSessionMessage {
+            init: SessionInit::Notification { text: "hello".into() },
+            custom_data: None,
+            site_id: None,
+        };
+
+        let out = dialogue.start_session(&notification, now);
+        let say = out
+            .iter()
+            .find_map(|it| match it {
+                Output::Say(say) => Some(say.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(say.site_id, "default");
+
+        let finished = SayFinishedMessage {
+            id: say.id,
+            session_id: say.session_id,
+        };
+        let out = dialogue.say_finished(&finished, now);
+        assert_eq!(termination(&out), Some(SessionTerminationType::Nominal));
+    }
+
+    #[test]
+    fn disabled_intents_are_not_recognized() {
+        let mut dialogue = dialogue();
+        let now = Instant::now();
+        dialogue.configure(&DialogueConfigureMessage {
+            site_id: None,
+            intents: Some(vec![DialogueConfigureIntent {
+                intent_id: "lights".into(),
+                enable: Some(false),
+            }]),
+        });
+        let session_id = session_id(&dialogue.hotword_detected(&detected("kitchen"), now));
+        let query_id = query_id(&dialogue.text_captured(&captured(&session_id, "turn on the lights"), now));
+
+        let out = dialogue.intent_parsed(&parsed(query_id, "lights"), now);
+
+        assert_eq!(termination(&out), Some(SessionTerminationType::IntentNotRecognized));
+    }
+
+    #[test]
+    fn silent_components_time_out() {
+        let mut dialogue = dialogue();
+        let now = Instant::now();
+        dialogue.hotword_detected(&detected("kitchen"), now);
+
+        assert!(dialogue.tick(now).is_empty());
+        let out = dialogue.tick(now + DialogueConfig::default().asr_timeout);
+
+        assert!(matches!(out[0], Output::StopListening(_)));
+        assert_eq!(
+            termination(&out),
+            Some(SessionTerminationType::Timeout {
+                component: Some(HermesComponent::Asr)
+            })
+        );
+    }
+}
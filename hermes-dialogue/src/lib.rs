@@ -0,0 +1,264 @@
+//! A reference dialogue manager, running the sessions on top of any `HermesProtocolHandler`.
+//!
+//! A session starts when a hotword is detected or when a client asks for one with a
+//! `StartSessionMessage`. The dialogue manager then has the asr listen to the user, has the nlu
+//! understand what they said and publishes the resulting intent. The client then continues the
+//! session, which loops back to listening, or ends it. Sessions started on a site where another one
+//! is running are queued, if they can be. A component that doesn't answer in time ends the session
+//! with a `SessionTerminationType::Timeout`.
+
+mod dialogue;
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use hermes::*;
+
+use crate::dialogue::{Dialogue, Output};
+
+/// How often the timeouts are checked
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialogueConfig {
+    /// The site of the sessions started without one
+    pub default_site_id: String,
+    /// How long the tts may take to say something
+    pub tts_timeout: Duration,
+    /// How long the asr may take to capture what the user says
+    pub asr_timeout: Duration,
+    /// How long the nlu may take to understand what the user said
+    pub nlu_timeout: Duration,
+    /// How long the client may take to continue or end a session after an intent
+    pub client_timeout: Duration,
+}
+
+impl Default for DialogueConfig {
+    fn default() -> Self {
+        Self {
+            default_site_id: "default".to_string(),
+            tts_timeout: Duration::from_secs(30),
+            asr_timeout: Duration::from_secs(10),
+            nlu_timeout: Duration::from_secs(5),
+            client_timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// The facades the dialogue manager talks through
+struct Facades {
+    dialogue: Box<dyn DialogueBackendFacade>,
+    hotword: Box<dyn HotwordFacade>,
+    asr: Box<dyn AsrFacade>,
+    nlu: Box<dyn NluFacade>,
+    tts: Box<dyn TtsFacade>,
+}
+
+impl Facades {
+    fn publish(&self, output: Output) -> HermesResult<()> {
+        match output {
+            Output::SessionQueued(message) => self.dialogue.publish_session_queued(message),
+            Output::SessionStarted(message) => self.dialogue.publish_session_started(message),
+            Output::SessionEnded(message) => self.dialogue.publish_session_ended(message),
+            Output::Intent(message) => self.dialogue.publish_intent(message),
+            Output::IntentNotRecognized(message) => self.dialogue.publish_intent_not_recognized(message),
+            Output::Say(message) => self.tts.publish_say(message),
+            Output::StartListening(message) => self.asr.publish_start_listening(message),
+            Output::StopListening(message) => self.asr.publish_stop_listening(message),
+            Output::Query(message) => self.nlu.publish_query(message),
+            Output::PartialQuery(message) => self.nlu.publish_partial_query(message),
+            Output::HotwordOn(message) => self.hotword.publish_toggle_on(message),
+            Output::HotwordOff(message) => self.hotword.publish_toggle_off(message),
+        }
+    }
+}
+
+struct Shared {
+    dialogue: Mutex<Dialogue>,
+    facades: Facades,
+}
+
+impl Shared {
+    /// Apply an event to the sessions, and publish what results from it once they are unlocked
+    fn handle<F: FnOnce(&mut Dialogue, Instant) -> Vec<Output>>(&self, event: F) {
+        let outputs = match self.dialogue.lock() {
+            Ok(mut dialogue) => event(&mut dialogue, Instant::now()),
+            Err(_) => {
+                error!("Could not lock the dialogue sessions");
+                return;
+            }
+        };
+        for output in outputs {
+            if let Err(e) = self.facades.publish(output) {
+                error!("Could not publish for the dialogue: {}", e)
+            }
+        }
+    }
+
+    fn callback<T: 'static, F>(shared: &Arc<Self>, event: F) -> Callback<T>
+    where
+        F: Fn(&mut Dialogue, &T, Instant) -> Vec<Output> + Send + Sync + 'static,
+    {
+        let shared = Arc::clone(shared);
+        Callback::new(move |message: &T| shared.handle(|dialogue, now| event(dialogue, message, now)))
+    }
+}
+
+/// A running dialogue manager, it stops when dropped
+pub struct DialogueManager {
+    _subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl DialogueManager {
+    pub fn new<H: HermesProtocolHandler + ?Sized>(handler: &H, config: DialogueConfig) -> HermesResult<Self> {
+        let shared = Arc::new(Shared {
+            dialogue: Mutex::new(Dialogue::new(config)),
+            facades: Facades {
+                dialogue: handler.dialogue_backend(),
+                hotword: handler.hotword(),
+                asr: handler.asr(),
+                nlu: handler.nlu(),
+                tts: handler.tts(),
+            },
+        });
+        let facades = &shared.facades;
+
+        let replier = Arc::clone(&shared);
+        let subscriptions = vec![
+            facades
+                .hotword
+                .subscribe_all_detected(Shared::callback(&shared, Dialogue::hotword_detected))?,
+            facades
+                .dialogue
+                .subscribe_start_session(Shared::callback(&shared, Dialogue::start_session))?,
+            facades
+                .dialogue
+                .subscribe_continue_session(Shared::callback(&shared, Dialogue::continue_session))?,
+            facades
+                .dialogue
+                .subscribe_end_session(Shared::callback(&shared, Dialogue::end_session))?,
+            facades.dialogue.subscribe_configure(Shared::callback(
+                &shared,
+                |dialogue: &mut Dialogue, message: &DialogueConfigureMessage, _| dialogue.configure(message),
+            ))?,
+            facades
+                .tts
+                .subscribe_say_finished(Shared::callback(&shared, Dialogue::say_finished))?,
+            facades
+                .asr
+                .subscribe_text_captured(Shared::callback(&shared, Dialogue::text_captured))?,
+            facades
+                .nlu
+                .subscribe_intent_parsed(Shared::callback(&shared, Dialogue::intent_parsed))?,
+            facades
+                .nlu
+                .subscribe_slot_parsed(Shared::callback(&shared, Dialogue::slot_parsed))?,
+            facades
+                .nlu
+                .subscribe_intent_not_recognized(Shared::callback(&shared, Dialogue::intent_not_recognized))?,
+            facades.dialogue.subscribe_version_request(Callback0::new(move || {
+                let version = semver::Version::parse(env!("CARGO_PKG_VERSION")).expect("the crate version is valid");
+                if let Err(e) = replier.facades.dialogue.publish_version(VersionMessage::new(version)) {
+                    error!("Could not publish the dialogue version: {}", e)
+                }
+            }))?,
+        ];
+
+        // the timeouts are checked as long as the callbacks keep the sessions alive
+        let sessions = Arc::downgrade(&shared);
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            match sessions.upgrade() {
+                Some(shared) => shared.handle(Dialogue::tick),
+                None => break,
+            }
+        });
+
+        Ok(Self {
+            _subscriptions: subscriptions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hermes::mock::{MockAsr, MockNlu, MockTts};
+    use hermes_inprocess::InProcessHermesProtocolHandler;
+
+    use super::*;
+
+    #[test]
+    fn notification_session_goes_through_the_tts() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let _manager = DialogueManager::new(&handler, DialogueConfig::default()).unwrap();
+
+        let tts = Arc::new(handler.tts_backend());
+        let responder = Arc::clone(&tts);
+        let _say = tts
+            .subscribe_say(Callback::new(move |say: &SayMessage| {
+                responder
+                    .publish_say_finished(SayFinishedMessage {
+                        id: say.id.clone(),
+                        session_id: say.session_id.clone(),
+                    })
+                    .unwrap()
+            }))
+            .unwrap();
+        let dialogue = handler.dialogue();
+        let receiver = receiver(1, Overflow::Block, |cb| dialogue.subscribe_session_ended(cb)).unwrap();
+
+        dialogue
+            .publish_start_session(StartSessionMessage {
+                init: SessionInit::Notification { text: "hello".into() },
+                custom_data: Some("data".into()),
+                site_id: None,
+            })
+            .unwrap();
+
+        let ended = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(ended.termination, SessionTerminationType::Nominal);
+        assert_eq!(ended.custom_data, Some("data".into()));
+        assert_eq!(ended.site_id, "default");
+    }
+
+    #[test]
+    fn action_session_is_understood_and_handled() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let _manager = DialogueManager::new(&handler, DialogueConfig::default()).unwrap();
+        let asr = MockAsr::new(&handler).unwrap();
+        let nlu = MockNlu::new(&handler).unwrap();
+        let tts = MockTts::new(&handler).unwrap();
+        asr.will_capture("turn on the lights").unwrap();
+        nlu.understands("turn on the lights", "lights").unwrap();
+        let _app = HermesApp::builder()
+            .on_intent("lights", |_: &IntentMessage, _: &mut ()| Action::end_with("done"))
+            .start(handler.dialogue())
+            .unwrap();
+
+        let dialogue = handler.dialogue();
+        let receiver = receiver(1, Overflow::Block, |cb| dialogue.subscribe_session_ended(cb)).unwrap();
+
+        dialogue
+            .publish_start_session(StartSessionMessage {
+                init: SessionInit::Action {
+                    text: Some("what do you want?".into()),
+                    intent_filter: None,
+                    can_be_enqueued: true,
+                    send_intent_not_recognized: false,
+                },
+                custom_data: None,
+                site_id: None,
+            })
+            .unwrap();
+
+        let ended = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(ended.termination, SessionTerminationType::Nominal);
+        assert_eq!(asr.received().unwrap().len(), 1);
+        assert_eq!(nlu.received().unwrap()[0].input, "turn on the lights");
+        let said: Vec<String> = tts.received().unwrap().into_iter().map(|say| say.text).collect();
+        assert_eq!(said, vec!["what do you want?".to_string(), "done".to_string()]);
+    }
+}
@@ -0,0 +1,128 @@
+#![allow(non_camel_case_types)]
+
+use std::cell::Cell;
+
+use hermes::HermesError;
+
+/// The kind of an error reported by a function returning `SNIPS_RESULT_KO`, it can be retrieved
+/// with `hermes_get_last_error_kind`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SNIPS_HERMES_ERROR_KIND {
+    /// The last call made from this thread succeeded, or none was made yet
+    SNIPS_HERMES_ERROR_KIND_NONE = 0,
+    /// An error that isn't one of the kinds below, a null pointer argument for example
+    SNIPS_HERMES_ERROR_KIND_OTHER = 1,
+    /// The transport failed to carry a message, for example the MQTT broker couldn't be reached
+    SNIPS_HERMES_ERROR_KIND_TRANSPORT = 2,
+    /// A message couldn't be encoded to, or decoded from, its wire format
+    SNIPS_HERMES_ERROR_KIND_CODEC = 3,
+    /// The protocol handler was shut down
+    SNIPS_HERMES_ERROR_KIND_CLOSED = 4,
+    /// A message was rejected because it is not valid
+    SNIPS_HERMES_ERROR_KIND_VALIDATION = 5,
+    /// No response to a request was received in time
+    SNIPS_HERMES_ERROR_KIND_TIMEOUT = 6,
+    /// A lock was poisoned, which means a thread panicked while holding it
+    SNIPS_HERMES_ERROR_KIND_POISON_LOCK = 7,
+    /// Reading or writing a file failed
+    SNIPS_HERMES_ERROR_KIND_IO = 8,
+    /// The configuration of the protocol handler is invalid
+    SNIPS_HERMES_ERROR_KIND_CONFIG = 9,
+}
+
+impl SNIPS_HERMES_ERROR_KIND {
+    pub fn of(error: &failure::Error) -> Self {
+        match error.iter_chain().find_map(|it| it.downcast_ref::<HermesError>()) {
+            Some(HermesError::Transport(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_TRANSPORT,
+            Some(HermesError::Codec(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_CODEC,
+            Some(HermesError::Closed) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_CLOSED,
+            Some(HermesError::Validation(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_VALIDATION,
+            Some(HermesError::Timeout(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_TIMEOUT,
+            Some(HermesError::PoisonLock) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_POISON_LOCK,
+            Some(HermesError::Io(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_IO,
+            Some(HermesError::Config(_)) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_CONFIG,
+            None => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_OTHER,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR_KIND: Cell<SNIPS_HERMES_ERROR_KIND> =
+        Cell::new(SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_NONE);
+}
+
+/// Remember the kind of the error of `result`, `SNIPS_HERMES_ERROR_KIND_NONE` if it is a success,
+/// so that it can be retrieved from C later on
+pub fn record_error_kind<T, E: Into<failure::Error>>(result: Result<T, E>) -> failure::Fallible<T> {
+    let result = result.map_err(Into::into);
+    let kind = match &result {
+        Ok(_) => SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_NONE,
+        Err(e) => SNIPS_HERMES_ERROR_KIND::of(e),
+    };
+    LAST_ERROR_KIND.with(|it| it.set(kind));
+    result
+}
+
+pub fn last_error_kind() -> SNIPS_HERMES_ERROR_KIND {
+    LAST_ERROR_KIND.with(Cell::get)
+}
+
+/// Same as `ffi_utils::wrap!` but also records the kind of the error for
+/// `hermes_get_last_error_kind`
+#[macro_export]
+macro_rules! wrap_hermes {
+    ($e:expr) => {
+        ffi_utils::wrap!($crate::record_error_kind($e))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_kind_is_found_behind_a_context() {
+        use failure::ResultExt;
+
+        let result: Result<(), _> = Err(HermesError::Closed);
+        let error = result.context("could not publish").unwrap_err().into();
+
+        assert_eq!(
+            SNIPS_HERMES_ERROR_KIND::of(&error),
+            SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_CLOSED
+        );
+    }
+
+    #[test]
+    fn record_error_kind_follows_the_last_call() {
+        let _ = record_error_kind::<(), _>(Err(HermesError::transport("broker is gone")));
+        assert_eq!(
+            last_error_kind(),
+            SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_TRANSPORT
+        );
+
+        let _ = record_error_kind::<(), HermesError>(Ok(()));
+        assert_eq!(last_error_kind(), SNIPS_HERMES_ERROR_KIND::SNIPS_HERMES_ERROR_KIND_NONE);
+    }
+
+    #[test]
+    fn every_error_has_its_own_kind() {
+        let errors = vec![
+            HermesError::transport("broker is gone"),
+            HermesError::codec("not json"),
+            HermesError::Closed,
+            HermesError::Validation("confidence_score: 2 is not within [0, 1]".into()),
+            HermesError::Timeout(std::time::Duration::from_secs(1)),
+            HermesError::PoisonLock,
+            HermesError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "no such file")),
+            HermesError::Config("unknown transport".into()),
+        ];
+        let kinds = errors
+            .into_iter()
+            .map(|it| SNIPS_HERMES_ERROR_KIND::of(&it.into()) as i32)
+            .collect::<Vec<_>>();
+
+        assert_eq!(kinds, vec![2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+}
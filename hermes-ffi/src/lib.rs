@@ -1,7 +1,10 @@
+mod errors;
 #[cfg(feature = "structures")]
 pub mod ontology;
 mod protocol_handler;
 
+pub use crate::errors::*;
+
 #[cfg(feature = "structures")]
 pub use crate::ontology::*;
 pub use crate::protocol_handler::*;
@@ -71,18 +71,35 @@ pub struct CVersionMessage {
     pub major: u64,
     pub minor: u64,
     pub patch: u64,
+    /// Nullable, the version of the hermes protocol the component speaks, components predating
+    /// this field don't send it
+    pub protocol_version: *const libc::c_char,
+    /// The optional features of the protocol the component supports
+    pub capabilities: *const CStringArray,
 }
 
+unsafe impl Sync for CVersionMessage {}
+
 impl CVersionMessage {
     pub fn from(input: &hermes::VersionMessage) -> Fallible<Self> {
+        let protocol_version = input.protocol_version.as_ref().map(ToString::to_string);
         Ok(Self {
             major: input.version.major,
             minor: input.version.minor,
             patch: input.version.patch,
+            protocol_version: convert_to_nullable_c_string!(protocol_version),
+            capabilities: CStringArray::c_repr_of(input.capabilities.clone())?.into_raw_pointer(),
         })
     }
 }
 
+impl Drop for CVersionMessage {
+    fn drop(&mut self) {
+        take_back_nullable_c_string!(self.protocol_version);
+        let _ = unsafe { CStringArray::drop_raw_pointer(self.capabilities) };
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct CErrorMessage {
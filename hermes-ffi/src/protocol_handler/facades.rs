@@ -105,7 +105,7 @@ macro_rules! generate_facade_wrapper {
                 Ok(())
             }
 
-            ffi_utils::wrap!(fun(handler, facade))
+            $crate::wrap_hermes!(fun(handler, facade))
         }
     };
 }
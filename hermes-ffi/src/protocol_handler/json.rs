@@ -31,7 +31,7 @@ macro_rules! generate_facade_publish_json {
     ($c_symbol:ident = $facade:ty:$method:ident) => {
         #[no_mangle]
         pub unsafe extern "C" fn $c_symbol(facade: *const $facade) -> ffi_utils::SNIPS_RESULT {
-            ffi_utils::wrap!(unsafe { (*facade).extract() }.$method())
+            $crate::wrap_hermes!(unsafe { (*facade).extract() }.$method())
         }
     };
 
@@ -55,9 +55,10 @@ macro_rules! generate_facade_publish_json {
                 unsafe { (*facade).extract() }.$method(
                     $(unsafe { CStr::from_ptr($filter_name) }.to_string_lossy().into_owned(),)*
                     message,
-                )
+                )?;
+                Ok(())
             }
-            ffi_utils::wrap!(fun(facade, $($filter_name,)* message))
+            $crate::wrap_hermes!(fun(facade, $($filter_name,)* message))
         }
     };
 }
@@ -81,13 +82,15 @@ macro_rules! generate_facade_subscribe_json {
                 let user_data = unsafe { (*facade).user_data().duplicate() };
                 let callback = $crate::json_ptr_to_callback(handler, user_data)?;
 
+                // the C API has no way to cancel a subscription, keep it active
                 unsafe { (*facade).extract() }.$method(
                     $(unsafe { CStr::from_ptr($filter_name) }.to_string_lossy().into_owned(),)*
                     callback,
-                )
+                )?.forget();
+                Ok(())
             }
 
-            ffi_utils::wrap!(fun(facade, $($filter_name,)* handler))
+            $crate::wrap_hermes!(fun(facade, $($filter_name,)* handler))
         }
     };
 }
@@ -15,7 +15,20 @@ macro_rules! generate_hermes_c_symbols {
     () => {
         #[no_mangle]
         pub extern "C" fn hermes_enable_debug_logs() -> ffi_utils::SNIPS_RESULT {
-            ffi_utils::wrap!($crate::init_debug_logs())
+            $crate::wrap_hermes!($crate::init_debug_logs())
+        }
+
+        /// Used to retrieve the kind of the error of the last call made from this thread, it tells
+        /// apart the errors `hermes_get_last_error` describes
+        #[no_mangle]
+        pub unsafe extern "C" fn hermes_get_last_error_kind(
+            kind: *mut $crate::SNIPS_HERMES_ERROR_KIND,
+        ) -> ffi_utils::SNIPS_RESULT {
+            if kind.is_null() {
+                return ffi_utils::SNIPS_RESULT::SNIPS_RESULT_KO;
+            }
+            *kind = $crate::last_error_kind();
+            ffi_utils::SNIPS_RESULT::SNIPS_RESULT_OK
         }
 
         generate_facade_c_symbols!();
@@ -43,10 +43,11 @@ macro_rules! generate_facade_publish {
                 use ffi_utils::{AsRust, RawBorrow};
 
                 let message = unsafe { (*message).as_rust() }?;
-                unsafe {(*facade).extract().$method($(<$qualifier as RawBorrow<$qualifier_raw>>::raw_borrow($qualifier_name)?.as_rust()?,)* message)}
+                unsafe {(*facade).extract().$method($(<$qualifier as RawBorrow<$qualifier_raw>>::raw_borrow($qualifier_name)?.as_rust()?,)* message)}?;
+                Ok(())
             }
 
-            ffi_utils::wrap!(fun(facade, $($qualifier_name,)* message))
+            $crate::wrap_hermes!(fun(facade, $($qualifier_name,)* message))
         }
     };
     ($c_symbol:ident = $facade:ty:$method:ident($( + $qualifier_name:ident : $qualifier:ty as $qualifier_raw:ty,)*)) => {
@@ -55,10 +56,11 @@ macro_rules! generate_facade_publish {
             fn fun(facade : *const $facade, $($qualifier_name : *const $qualifier_raw,)*) -> failure::Fallible<()> {
                 use ffi_utils::{AsRust, RawBorrow};
 
-                unsafe {(*facade).extract().$method($(<$qualifier as RawBorrow<$qualifier_raw>>::raw_borrow($qualifier_name)?.as_rust()?,)*)}
+                unsafe {(*facade).extract().$method($(<$qualifier as RawBorrow<$qualifier_raw>>::raw_borrow($qualifier_name)?.as_rust()?,)*)}?;
+                Ok(())
             }
 
-            ffi_utils::wrap!(fun(facade, $($qualifier_name,)*))
+            $crate::wrap_hermes!(fun(facade, $($qualifier_name,)*))
         }
     };
 }
@@ -73,10 +75,13 @@ macro_rules! generate_facade_subscribe {
 
                 let user_data = unsafe { (*facade).user_data().duplicate() };
                 let callback = $crate::structure_ptr_to_callback(handler, user_data)?;
-                unsafe { (*facade).extract().$method($(<$filter as RawBorrow<$filter_raw>>::raw_borrow($filter_name)?.as_rust()?,)* callback) }
+                // the C API has no way to cancel a subscription, keep it active
+                unsafe { (*facade).extract().$method($(<$filter as RawBorrow<$filter_raw>>::raw_borrow($filter_name)?.as_rust()?,)* callback) }?
+                    .forget();
+                Ok(())
             }
 
-            ffi_utils::wrap!(fun(facade, $($filter_name,)* handler))
+            $crate::wrap_hermes!(fun(facade, $($filter_name,)* handler))
         }
     };
 }
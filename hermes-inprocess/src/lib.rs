@@ -1,30 +1,348 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, Weak};
 
-use failure::Fallible;
+use lazy_static::lazy_static;
 use log::*;
 
+use hermes::trace::{deliver_span, publish_span};
 use hermes::*;
 
+lazy_static! {
+    /// The buses shared by name, they are kept while a handler holds them
+    static ref NAMED_BUSES: Mutex<HashMap<String, Weak<SharedBus>>> = Mutex::new(HashMap::new());
+}
+
+/// The bus of one or more handlers, it dies with the last handler holding it
+struct SharedBus {
+    dispatch: Dispatch,
+    /// The name the bus is shared under, if it is
+    name: Option<String>,
+    /// Told when the bus dies, emptied when their subscription is cancelled
+    closed: Mutex<Vec<Arc<Mutex<Option<Callback0>>>>>,
+}
+
+impl SharedBus {
+    fn new(name: Option<String>) -> Arc<Self> {
+        Self::with_dispatch(name, Dispatch::Threaded(Mutex::new(ripb::Bus::new())))
+    }
+
+    fn deterministic() -> Arc<Self> {
+        Self::with_dispatch(None, Dispatch::Deterministic(Mutex::new(Queue::default())))
+    }
+
+    fn with_dispatch(name: Option<String>, dispatch: Dispatch) -> Arc<Self> {
+        Arc::new(Self {
+            dispatch,
+            name,
+            closed: Mutex::new(vec![]),
+        })
+    }
+
+    fn publish<M: ripb::Message + Send + 'static>(&self, message: Stamped<M>) -> HermesResult<()> {
+        match &self.dispatch {
+            Dispatch::Threaded(bus) => {
+                bus.lock().map_err(PoisonLock::from)?.publish(message);
+            }
+            Dispatch::Deterministic(queue) => {
+                let mut queue = queue.lock().map_err(PoisonLock::from)?;
+                queue
+                    .messages
+                    .push_back((TypeId::of::<Stamped<M>>(), Box::new(message)));
+            }
+        }
+        Ok(())
+    }
+
+    fn create_subscriber(&self) -> HermesResult<Subscriber> {
+        Ok(match &self.dispatch {
+            Dispatch::Threaded(bus) => Subscriber::Threaded {
+                subscriber: bus.lock().map_err(PoisonLock::from)?.create_subscriber(),
+                listeners: Arc::new(Listeners::default()),
+                hooked: Mutex::new(HashSet::new()),
+            },
+            Dispatch::Deterministic(queue) => {
+                let listeners = Arc::new(Listeners::default());
+                let mut queue = queue.lock().map_err(PoisonLock::from)?;
+                queue.listeners.retain(|it| it.upgrade().is_some());
+                queue.listeners.push(Arc::downgrade(&listeners));
+                Subscriber::Deterministic(listeners)
+            }
+        })
+    }
+
+    /// Deliver the oldest message of the queue to the listeners registered for its type, on the
+    /// calling thread. Returns whether there was a message to deliver.
+    fn pump(&self) -> HermesResult<bool> {
+        let queue = match &self.dispatch {
+            Dispatch::Deterministic(queue) => queue,
+            Dispatch::Threaded(_) => return Ok(false),
+        };
+        // neither lock is held while the listeners run, they may publish or subscribe
+        let (type_id, message, listeners) = {
+            let mut queue = queue.lock().map_err(PoisonLock::from)?;
+            let (type_id, message) = match queue.messages.pop_front() {
+                Some(it) => it,
+                None => return Ok(false),
+            };
+            (
+                type_id,
+                message,
+                queue.listeners.iter().filter_map(Weak::upgrade).collect::<Vec<_>>(),
+            )
+        };
+        for listeners in listeners {
+            listeners.deliver(type_id, &*message)?
+        }
+        Ok(true)
+    }
+}
+
+/// How the messages published on a bus reach its subscribers
+enum Dispatch {
+    /// ripb delivers them on its own threads as soon as they are published
+    Threaded(Mutex<ripb::Bus>),
+    /// They wait in a queue until they are pumped
+    Deterministic(Mutex<Queue>),
+}
+
+/// The messages of a deterministic bus waiting to be delivered, in publish order, along with the
+/// listeners of its subscribers
+#[derive(Default)]
+struct Queue {
+    messages: VecDeque<(TypeId, Box<dyn Any + Send>)>,
+    listeners: Vec<Weak<Listeners>>,
+}
+
+type Listener = Arc<dyn Fn(&dyn Any) + Send + Sync>;
+
+/// What a subscriber listens to, by type of message, each listener under the key it was
+/// registered with so that it can be removed
+#[derive(Default)]
+struct Listeners {
+    registered: Mutex<Vec<(usize, TypeId, Listener)>>,
+    next_key: AtomicUsize,
+}
+
+impl Listeners {
+    fn add(&self, type_id: TypeId, listener: Listener) -> HermesResult<usize> {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        self.registered
+            .lock()
+            .map_err(PoisonLock::from)?
+            .push((key, type_id, listener));
+        Ok(key)
+    }
+
+    fn remove(&self, key: usize) -> HermesResult<()> {
+        self.registered
+            .lock()
+            .map_err(PoisonLock::from)?
+            .retain(|(it, _, _)| *it != key);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.registered.lock().map(|it| it.len()).unwrap_or_default()
+    }
+
+    /// Run the listeners of the type of `message`, the lock isn't held while they run, they may
+    /// subscribe or unsubscribe
+    fn deliver(&self, type_id: TypeId, message: &dyn Any) -> HermesResult<()> {
+        let matching = self
+            .registered
+            .lock()
+            .map_err(PoisonLock::from)?
+            .iter()
+            .filter(|(_, it, _)| *it == type_id)
+            .map(|(_, _, listener)| Arc::clone(listener))
+            .collect::<Vec<_>>();
+        for listener in matching {
+            listener(message)
+        }
+        Ok(())
+    }
+}
+
+/// The subscriber of a facade, like the bus it subscribes to, it is kept by the handler and the
+/// facade
+enum Subscriber {
+    /// ripb can't remove a listener, it is given a single one per type of message which runs the
+    /// listeners registered for it
+    Threaded {
+        subscriber: ripb::Subscriber,
+        listeners: Arc<Listeners>,
+        hooked: Mutex<HashSet<TypeId>>,
+    },
+    Deterministic(Arc<Listeners>),
+}
+
+impl Subscriber {
+    fn listeners(&self) -> &Arc<Listeners> {
+        match self {
+            Subscriber::Threaded { listeners, .. } => listeners,
+            Subscriber::Deterministic(listeners) => listeners,
+        }
+    }
+
+    /// Register a listener, returns the key to remove it with
+    fn on_message<M, F>(&self, listener: F) -> HermesResult<usize>
+    where
+        M: ripb::Message + 'static,
+        F: Fn(&M) + Send + Sync + 'static,
+    {
+        let type_id = TypeId::of::<M>();
+        if let Subscriber::Threaded {
+            subscriber,
+            listeners,
+            hooked,
+        } = self
+        {
+            let mut hooked = hooked.lock().map_err(PoisonLock::from)?;
+            if !hooked.contains(&type_id) {
+                let listeners = Arc::clone(listeners);
+                subscriber
+                    .on_message(move |message: &M| {
+                        if let Err(e) = listeners.deliver(type_id, message) {
+                            warn!("Could not deliver a message: {}", e)
+                        }
+                    })
+                    // the subscriber can only fail to register the listener when the bus is gone
+                    .map_err(|_| HermesError::Closed)?;
+                hooked.insert(type_id);
+            }
+        }
+        let listener: Listener = Arc::new(move |message: &dyn Any| {
+            if let Some(message) = message.downcast_ref::<M>() {
+                listener(message)
+            }
+        });
+        self.listeners().add(type_id, listener)
+    }
+}
+
+impl Drop for SharedBus {
+    fn drop(&mut self) {
+        if let Some(name) = &self.name {
+            let mut buses = NAMED_BUSES.lock().unwrap_or_else(PoisonError::into_inner);
+            // the name may already have been given to a new bus
+            if buses.get(name).map_or(false, |bus| bus.upgrade().is_none()) {
+                buses.remove(name);
+            }
+        }
+        let closed = self.closed.get_mut().map(std::mem::take).unwrap_or_default();
+        for slot in closed {
+            if let Some(callback) = slot.lock().ok().and_then(|mut it| it.take()) {
+                callback.call()
+            }
+        }
+    }
+}
+
 pub struct InProcessHermesProtocolHandler {
-    subscribers: Arc<Mutex<Vec<Arc<ripb::Subscriber>>>>,
-    bus: Arc<Mutex<ripb::Bus>>,
+    subscribers: Arc<Mutex<Vec<Arc<Subscriber>>>>,
+    bus: Arc<SharedBus>,
+    origin: Option<MessageOrigin>,
+    validation: Arc<RwLock<ValidationMode>>,
+    /// The chunks of the audio streams published, checked in order when validating
+    stream_sequence: Arc<Mutex<StreamBytesSequence>>,
 }
 
 impl InProcessHermesProtocolHandler {
+    /// Create a handler on a bus of its own
     pub fn new() -> Self {
+        Self::on_bus(SharedBus::new(None))
+    }
+
+    /// Create a handler on the bus shared under `name` by the handlers of the process. The bus is
+    /// created if no handler holds it, and dies with the last handler holding it.
+    pub fn named<N: Into<String>>(name: N) -> Self {
+        let name = name.into();
+        let mut buses = NAMED_BUSES.lock().unwrap_or_else(PoisonError::into_inner);
+        let bus = match buses.get(&name).and_then(Weak::upgrade) {
+            Some(bus) => bus,
+            None => {
+                let bus = SharedBus::new(Some(name.clone()));
+                buses.insert(name, Arc::downgrade(&bus));
+                bus
+            }
+        };
+        Self::on_bus(bus)
+    }
+
+    /// Create a handler on a bus of its own where the messages published aren't delivered right
+    /// away: they are queued, and delivered in publish order on the thread calling `pump` or
+    /// `run_until_idle`. This makes tests independent of thread scheduling.
+    pub fn new_deterministic() -> Self {
+        Self::on_bus(SharedBus::deterministic())
+    }
+
+    /// Deliver the oldest message waiting on the bus of a deterministic handler, if any, to the
+    /// callbacks of all the handlers sharing it. Returns whether a message was delivered, which
+    /// is never the case on a handler that isn't deterministic.
+    pub fn pump(&self) -> HermesResult<bool> {
+        self.bus.pump()
+    }
+
+    /// Deliver the messages waiting on the bus of a deterministic handler, and those published
+    /// by the callbacks in the meantime, until the queue is empty. Returns how many messages were
+    /// delivered. Callbacks answering each other forever make this run forever too.
+    pub fn run_until_idle(&self) -> HermesResult<usize> {
+        let mut delivered = 0;
+        while self.bus.pump()? {
+            delivered += 1;
+        }
+        Ok(delivered)
+    }
+
+    fn on_bus(bus: Arc<SharedBus>) -> Self {
         Self {
             subscribers: Arc::new(Mutex::new(Vec::new())),
-            bus: Arc::new(Mutex::new(ripb::Bus::new())),
+            bus,
+            origin: None,
+            validation: Arc::new(RwLock::new(ValidationMode::default())),
+            stream_sequence: Arc::new(Mutex::new(StreamBytesSequence::new())),
         }
     }
 
+    /// Be told when the bus of this handler dies, that is when the last handler holding it is
+    /// dropped. The facades still around report `HermesError::Closed` from then on.
+    pub fn subscribe_bus_closed(&self, callback: Callback0) -> HermesResult<SubscriptionHandle> {
+        let slot = Arc::new(Mutex::new(Some(callback)));
+        let mut closed = self.bus.closed.lock().map_err(PoisonLock::from)?;
+        closed.retain(|it| it.lock().map(|it| it.is_some()).unwrap_or(false));
+        closed.push(Arc::clone(&slot));
+        Ok(SubscriptionHandle::new(move || {
+            slot.lock().map_err(PoisonLock::from)?.take();
+            Ok(())
+        }))
+    }
+
+    /// Create a handler whose messages carry a `MessageMeta` telling they come from `origin`
+    pub fn new_with_origin(origin: MessageOrigin) -> Self {
+        Self {
+            origin: Some(origin),
+            ..Self::new()
+        }
+    }
+
+    /// Check the messages published and received from now on, see `ValidationMode`
+    pub fn set_validation_mode(&self, mode: ValidationMode) -> HermesResult<()> {
+        *self.validation.write().map_err(PoisonLock::from)? = mode;
+        Ok(())
+    }
+
     fn get_handler<T: Send + Sync + Debug>(&self, component: T) -> Box<InProcessComponent<T>> {
         Box::new(InProcessComponent {
             component,
             bus: Arc::downgrade(&self.bus),
             subscriber: Mutex::new(None),
             subscribers: Arc::clone(&self.subscribers),
+            origin: self.origin.clone(),
+            validation: Arc::clone(&self.validation),
+            stream_sequence: Arc::clone(&self.stream_sequence),
         })
     }
 }
@@ -35,6 +353,18 @@ impl Default for InProcessHermesProtocolHandler {
     }
 }
 
+fn connect_in_process(config: &HandlerConfig) -> HermesResult<Box<dyn HermesProtocolHandler>> {
+    Ok(Box::new(InProcessHermesProtocolHandler::named(
+        config.broker_address.as_str(),
+    )))
+}
+
+/// Make the `inprocess` transport available to `hermes::connect`, the connections to the same name
+/// share their bus, see `InProcessHermesProtocolHandler::named`
+pub fn register_transports() -> HermesResult<()> {
+    hermes::register_transport("inprocess", connect_in_process)
+}
+
 impl HermesProtocolHandler for InProcessHermesProtocolHandler {
     fn voice_activity(&self) -> Box<dyn VoiceActivityFacade> {
         self.get_handler(VoiceActivity)
@@ -111,44 +441,164 @@ impl HermesProtocolHandler for InProcessHermesProtocolHandler {
 
 impl std::fmt::Display for InProcessHermesProtocolHandler {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Snips InProcess Bus")
+        match &self.bus.name {
+            Some(name) => write!(f, "Snips InProcess Bus '{}'", name),
+            None => write!(f, "Snips InProcess Bus"),
+        }
     }
 }
 
 struct InProcessComponent<T: Send + Sync + Debug> {
     component: T,
-    bus: Weak<Mutex<ripb::Bus>>,
-    subscriber: Mutex<Option<Arc<ripb::Subscriber>>>,
-    subscribers: Arc<Mutex<Vec<Arc<ripb::Subscriber>>>>,
+    bus: Weak<SharedBus>,
+    subscriber: Mutex<Option<Arc<Subscriber>>>,
+    subscribers: Arc<Mutex<Vec<Arc<Subscriber>>>>,
+    origin: Option<MessageOrigin>,
+    validation: Arc<RwLock<ValidationMode>>,
+    stream_sequence: Arc<Mutex<StreamBytesSequence>>,
+}
+
+/// The current validation mode, messages aren't checked if the lock is poisoned
+fn validation_mode(validation: &RwLock<ValidationMode>) -> ValidationMode {
+    validation.read().map(|it| *it).unwrap_or_default()
+}
+
+/// A message telling the session and the site it is about through its typed fields
+trait Traced {
+    fn trace_context(&self) -> TraceContext {
+        TraceContext::default()
+    }
+}
+
+/// An id field of a message, some are optional and some aren't
+trait TracedId {
+    fn traced_id(&self) -> Option<String>;
+}
+
+impl TracedId for String {
+    fn traced_id(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl TracedId for Option<String> {
+    fn traced_id(&self) -> Option<String> {
+        self.clone()
+    }
+}
+
+macro_rules! traced {
+    ($($t:ty { $($field:ident),* })*) => {
+        $(
+            impl Traced for $t {
+                #[allow(clippy::needless_update)]
+                fn trace_context(&self) -> TraceContext {
+                    TraceContext {
+                        $($field: self.$field.traced_id(),)*
+                        ..TraceContext::default()
+                    }
+                }
+            }
+        )*
+    };
+}
+
+traced! {
+    SiteMessage { session_id, site_id }
+    SiteErrorMessage { session_id, site_id }
+    ErrorMessage { session_id }
+    ComponentLoadedOnSiteMessage { site_id }
+    VadUpMessage { site_id }
+    VadDownMessage { site_id }
+    HotwordDetectedMessage { site_id }
+    AsrStartListeningMessage { session_id, site_id }
+    TextCapturedMessage { session_id, site_id }
+    SayMessage { session_id, site_id }
+    SayFinishedMessage { session_id }
+    NluQueryMessage { session_id }
+    NluSlotQueryMessage { session_id }
+    NluSlotMessage { session_id }
+    NluIntentMessage { session_id }
+    NluIntentNotRecognizedMessage { session_id }
+    PlayBytesMessage { site_id }
+    StreamBytesMessage { site_id }
+    AudioFrameMessage { site_id }
+    ReplayRequestMessage { site_id }
+    PlayFinishedMessage { site_id }
+    StreamFinishedMessage { site_id }
+    StartSessionMessage { site_id }
+    ContinueSessionMessage { session_id }
+    EndSessionMessage { session_id }
+    DialogueConfigureMessage { site_id }
+    SessionQueuedMessage { session_id, site_id }
+    SessionStartedMessage { session_id, site_id }
+    IntentMessage { session_id, site_id }
+    IntentNotRecognizedMessage { session_id, site_id }
+    SessionEndedMessage { session_id, site_id }
+}
+
+impl Traced for VersionMessage {}
+impl Traced for ComponentLoadedMessage {}
+impl Traced for RequestComponentReloadMessage {}
+impl Traced for RegisterSoundMessage {}
+impl Traced for InjectionRequestMessage {}
+impl Traced for InjectionResetRequestMessage {}
+impl Traced for InjectionStatusMessage {}
+impl Traced for InjectionCompleteMessage {}
+impl Traced for InjectionResetCompleteMessage {}
+
+/// The context of a message about to be published: the one it tells through its session and site
+/// fields, completed with the one of the callback publishing it, if any. Only the typed fields are
+/// read, publishing audio doesn't cost more when tracing
+fn context_of<M: Traced>(message: &M) -> TraceContext {
+    message.trace_context().or(TraceContext::current())
+}
+
+/// What actually travels on the bus: a message along with its metadata, if the publishing handler
+/// attaches some, and the context of the callback that published it, if any
+#[derive(Debug)]
+struct Stamped<M> {
+    message: M,
+    meta: Option<MessageMeta>,
+    context: TraceContext,
 }
 
 impl<T: Send + Sync + Debug> InProcessComponent<T> {
-    fn publish<M: ripb::Message + Debug + 'static>(&self, message: M) -> Fallible<()> {
+    /// Publish a message belonging to `context`, see `context_of`
+    fn publish<M: ripb::Message + Debug + Send + 'static>(
+        &self,
+        context: TraceContext,
+        message: M,
+    ) -> HermesResult<()> {
         debug!("Publishing {:?}/{:#?}", self.component, message);
-        self.publish_quiet(message)
+        self.publish_quiet(context, message)
     }
 
-    fn publish_quiet<M: ripb::Message + Debug + 'static>(&self, message: M) -> Fallible<()> {
-        let bus = self
-            .bus
-            .upgrade()
-            .ok_or_else(|| failure::format_err!("bus was killed"))?;
-        let bus = bus.lock().map_err(PoisonLock::from)?;
-        bus.publish(message);
-        Ok(())
+    fn publish_quiet<M: ripb::Message + Debug + Send + 'static>(
+        &self,
+        context: TraceContext,
+        message: M,
+    ) -> HermesResult<()> {
+        let bus = self.bus.upgrade().ok_or(HermesError::Closed)?;
+        let span = publish_span(type_name::<M>(), || context.clone());
+        let _entered = span.enter();
+        bus.publish(Stamped {
+            message,
+            meta: MessageMeta::published(self.origin.as_ref()),
+            context,
+        })
     }
 
-    fn ensure_has_subscriber(&self) -> Fallible<()> {
+    /// Check a message about to be published, returns the context it belongs to, see `context_of`
+    fn check_outgoing<M: Validate + Debug + Traced>(&self, message: &M) -> HermesResult<TraceContext> {
+        validation_mode(&self.validation).check_outgoing(message)?;
+        Ok(context_of(message))
+    }
+
+    fn ensure_has_subscriber(&self) -> HermesResult<()> {
         let mut subscriber = self.subscriber.lock().map_err(PoisonLock::from)?;
         if subscriber.is_none() {
-            let result = Arc::new(
-                self.bus
-                    .upgrade()
-                    .ok_or_else(|| failure::format_err!("bus was killed"))?
-                    .lock()
-                    .unwrap()
-                    .create_subscriber(),
-            );
+            let result = Arc::new(self.bus.upgrade().ok_or(HermesError::Closed)?.create_subscriber()?);
             self.subscribers
                 .lock()
                 .map_err(PoisonLock::from)?
@@ -158,71 +608,90 @@ impl<T: Send + Sync + Debug> InProcessComponent<T> {
         Ok(())
     }
 
-    fn subscribe0<M: ripb::Message + 'static>(&self, callback: Callback0) -> Fallible<()> {
+    /// Register a handler on the subscriber of this facade. The returned handle removes it (and
+    /// whatever it captured) from the subscriber, and empties the slot it is kept in so that the
+    /// deliveries which already picked it up skip it
+    fn on_message<M, F>(&self, handler: F) -> HermesResult<SubscriptionHandle>
+    where
+        M: ripb::Message + 'static,
+        F: Fn(&M, Option<&MessageMeta>) -> () + Send + Sync + 'static,
+    {
         self.ensure_has_subscriber()?;
-        self.subscriber
-            .lock()
-            .map_err(PoisonLock::from)?
-            .as_ref()
-            .unwrap()
-            .on_message(move |_: &M| callback.call())?;
-        Ok(())
-    }
-
-    fn subscribe<M, P, C>(&self, callback: Callback<P>, converter: C) -> Fallible<()>
+        let slot = Arc::new(Mutex::new(Some(Arc::new(handler))));
+        let registered = Arc::clone(&slot);
+        let subscriber = self.subscriber.lock().map_err(PoisonLock::from)?;
+        let subscriber = subscriber.as_ref().unwrap();
+        let listeners = Arc::downgrade(subscriber.listeners());
+        let key = subscriber.on_message(move |m: &Stamped<M>| {
+            // don't hold the lock while running the handler, it may want to unsubscribe
+            let handler = registered.lock().ok().and_then(|it| it.as_ref().map(Arc::clone));
+            if let Some(handler) = handler {
+                let span = deliver_span(type_name::<M>(), || m.context.clone());
+                let _entered = span.enter();
+                m.context.scope(|| handler(&m.message, m.meta.as_ref()))
+            }
+        })?;
+        Ok(SubscriptionHandle::new(move || {
+            slot.lock().map_err(PoisonLock::from)?.take();
+            if let Some(listeners) = listeners.upgrade() {
+                listeners.remove(key)?
+            }
+            Ok(())
+        }))
+    }
+
+    fn subscribe0<M: ripb::Message + 'static>(&self, callback: Callback0) -> HermesResult<SubscriptionHandle> {
+        self.on_message(move |_: &M, meta: Option<&MessageMeta>| callback.call_with_meta(meta))
+    }
+
+    fn subscribe<M, P, C>(&self, callback: Callback<P>, converter: C) -> HermesResult<SubscriptionHandle>
     where
         M: ripb::Message + Debug + 'static,
-        P: 'static,
-        C: Fn(&M) -> &P + Send + 'static,
+        P: Validate + Debug + 'static,
+        C: Fn(&M) -> &P + Send + Sync + 'static,
     {
-        self.ensure_has_subscriber()?;
-        self.subscriber
-            .lock()
-            .map_err(PoisonLock::from)?
-            .as_ref()
-            .unwrap()
-            .on_message(move |m: &M| callback.call(converter(m)))?;
-        Ok(())
+        let validation = Arc::clone(&self.validation);
+        self.on_message(move |m: &M, meta: Option<&MessageMeta>| {
+            let message = converter(m);
+            if validation_mode(&validation).accepts_incoming(message) {
+                callback.call_with_meta(message, meta)
+            }
+        })
     }
 
-    fn subscribe0_filter<M, F>(&self, callback: Callback0, filter: F) -> Fallible<()>
+    fn subscribe0_filter<M, F>(&self, callback: Callback0, filter: F) -> HermesResult<SubscriptionHandle>
     where
         M: ripb::Message + 'static,
-        F: Fn(&M) -> bool + Send + 'static,
+        F: Fn(&M) -> bool + Send + Sync + 'static,
     {
-        self.ensure_has_subscriber()?;
-        self.subscriber
-            .lock()
-            .map_err(PoisonLock::from)?
-            .as_ref()
-            .unwrap()
-            .on_message(move |m: &M| {
-                if filter(m) {
-                    callback.call()
-                }
-            })?;
-        Ok(())
+        self.on_message(move |m: &M, meta: Option<&MessageMeta>| {
+            if filter(m) {
+                callback.call_with_meta(meta)
+            }
+        })
     }
 
-    fn subscribe_filter<M, P, C, F>(&self, callback: Callback<P>, converter: C, filter: F) -> Fallible<()>
+    fn subscribe_filter<M, P, C, F>(
+        &self,
+        callback: Callback<P>,
+        converter: C,
+        filter: F,
+    ) -> HermesResult<SubscriptionHandle>
     where
         M: ripb::Message + Debug + 'static,
-        P: 'static,
-        C: Fn(&M) -> &P + Send + 'static,
-        F: Fn(&M) -> bool + Send + 'static,
+        P: Validate + Debug + 'static,
+        C: Fn(&M) -> &P + Send + Sync + 'static,
+        F: Fn(&M) -> bool + Send + Sync + 'static,
     {
-        self.ensure_has_subscriber()?;
-        self.subscriber
-            .lock()
-            .map_err(PoisonLock::from)?
-            .as_ref()
-            .unwrap()
-            .on_message(move |m: &M| {
-                if filter(m) {
-                    callback.call(converter(m))
+        let validation = Arc::clone(&self.validation);
+        self.on_message(move |m: &M, meta: Option<&MessageMeta>| {
+            if filter(m) {
+                let message = converter(m);
+                if validation_mode(&validation).accepts_incoming(message) {
+                    callback.call_with_meta(message, meta)
                 }
-            })?;
-        Ok(())
+            }
+        })
     }
 }
 
@@ -287,51 +756,63 @@ struct ComponentLoaded<T: Debug> {
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> ComponentFacade for InProcessComponent<T> {
-    fn publish_version_request(&self) -> Fallible<()> {
-        self.publish(ComponentVersionRequest {
-            component: self.component,
-        } as ComponentVersionRequest<T>)
+    fn publish_version_request(&self) -> HermesResult<()> {
+        self.publish(
+            TraceContext::current(),
+            ComponentVersionRequest {
+                component: self.component,
+            } as ComponentVersionRequest<T>,
+        )
     }
 
-    fn subscribe_version(&self, handler: Callback<VersionMessage>) -> Fallible<()> {
+    fn subscribe_version(&self, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ComponentVersion<T> { version }, handler)
     }
 
-    fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> Fallible<()> {
+    fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ComponentError<T> { error }, handler)
     }
 
-    fn subscribe_component_loaded(&self, handler: Callback<ComponentLoadedMessage>) -> Fallible<()> {
+    fn subscribe_component_loaded(
+        &self,
+        handler: Callback<ComponentLoadedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ComponentLoaded<T> { component_loaded }, handler)
     }
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> ComponentBackendFacade for InProcessComponent<T> {
-    fn subscribe_version_request(&self, handler: Callback0) -> Fallible<()> {
+    fn subscribe_version_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ComponentVersionRequest<T>, handler)
     }
 
-    fn publish_version(&self, version: VersionMessage) -> Fallible<()> {
+    fn publish_version(&self, version: VersionMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&version)?;
         let component_version: ComponentVersion<T> = ComponentVersion {
             version,
             component: self.component,
         };
-        self.publish(component_version)
+        self.publish(context, component_version)
     }
 
-    fn publish_error(&self, error: ErrorMessage) -> Fallible<()> {
+    fn publish_error(&self, error: ErrorMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&error)?;
         let component_error: ComponentError<T> = ComponentError {
             error,
             component: self.component,
         };
-        self.publish(component_error)
+        self.publish(context, component_error)
     }
 
-    fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> Fallible<()> {
-        self.publish(ComponentLoaded {
-            component_loaded,
-            component: self.component,
-        })
+    fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&component_loaded)?;
+        self.publish(
+            context,
+            ComponentLoaded {
+                component_loaded,
+                component: self.component,
+            },
+        )
     }
 }
 
@@ -363,23 +844,31 @@ struct IdentifiableComponentLoaded<T: Debug> {
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> IdentifiableComponentFacade for InProcessComponent<T> {
-    fn publish_version_request(&self, site_id: String) -> Fallible<()> {
+    fn publish_version_request(&self, site_id: String) -> HermesResult<()> {
         let version_request = IdentifiableComponentVersionRequest {
             site_id,
             component: self.component,
         };
-        self.publish(version_request)
+        self.publish(TraceContext::current(), version_request)
     }
 
-    fn subscribe_version(&self, site_id: String, handler: Callback<VersionMessage>) -> Fallible<()> {
+    fn subscribe_version(
+        &self,
+        site_id: String,
+        handler: Callback<VersionMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, IdentifiableComponentVersion<T> { version }, handler, site_id, |it| &it.site_id)
     }
 
-    fn subscribe_error(&self, site_id: String, handler: Callback<SiteErrorMessage>) -> Fallible<()> {
+    fn subscribe_error(
+        &self,
+        site_id: String,
+        handler: Callback<SiteErrorMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, IdentifiableComponentError<T> { error }, handler, site_id, |it| &it.site_id)
     }
 
-    fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> Fallible<()> {
+    fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, IdentifiableComponentError<T> { error }, handler)
     }
 
@@ -387,49 +876,55 @@ impl<T: Send + Sync + Debug + Copy + 'static> IdentifiableComponentFacade for In
         &self,
         site_id: String,
         handler: Callback<ComponentLoadedOnSiteMessage>,
-    ) -> Fallible<()> {
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, IdentifiableComponentLoaded<T> { component_loaded }, handler, site_id, |it| &it.site_id)
     }
 
-    fn subscribe_all_component_loaded(&self, handler: Callback<ComponentLoadedOnSiteMessage>) -> Fallible<()> {
+    fn subscribe_all_component_loaded(
+        &self,
+        handler: Callback<ComponentLoadedOnSiteMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, IdentifiableComponentLoaded<T> { component_loaded }, handler)
     }
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> IdentifiableComponentBackendFacade for InProcessComponent<T> {
-    fn subscribe_version_request(&self, site_id: String, handler: Callback0) -> Fallible<()> {
+    fn subscribe_version_request(&self, site_id: String, handler: Callback0) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, IdentifiableComponentVersionRequest<T>, handler, site_id)
     }
 
-    fn publish_version(&self, site_id: String, version: VersionMessage) -> Fallible<()> {
+    fn publish_version(&self, site_id: String, version: VersionMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&version)?;
         let component_version: IdentifiableComponentVersion<T> = IdentifiableComponentVersion {
             site_id,
             version,
             component: self.component,
         };
-        self.publish(component_version)
+        self.publish(context, component_version)
     }
 
-    fn publish_error(&self, site_id: String, error: SiteErrorMessage) -> Fallible<()> {
+    fn publish_error(&self, site_id: String, error: SiteErrorMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&error)?;
         let component_error: IdentifiableComponentError<T> = IdentifiableComponentError {
             site_id,
             error,
             component: self.component,
         };
-        self.publish(component_error)
+        self.publish(context, component_error)
     }
 
     fn publish_component_loaded(
         &self,
         site_id: String,
         component_loaded: ComponentLoadedOnSiteMessage,
-    ) -> Fallible<()> {
+    ) -> HermesResult<()> {
+        let context = self.check_outgoing(&component_loaded)?;
         let component_loaded = IdentifiableComponentLoaded {
             site_id,
             component_loaded,
             component: self.component,
         };
-        self.publish(component_loaded)
+        self.publish(context, component_loaded)
     }
 }
 
@@ -446,29 +941,31 @@ struct IdentifiableToggleableToggleOff<T> {
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> IdentifiableToggleableFacade for InProcessComponent<T> {
-    fn publish_toggle_on(&self, site: SiteMessage) -> Fallible<()> {
+    fn publish_toggle_on(&self, site: SiteMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&site)?;
         let toggle_on: IdentifiableToggleableToggleOn<T> = IdentifiableToggleableToggleOn {
             site,
             component: self.component,
         };
-        self.publish(toggle_on)
+        self.publish(context, toggle_on)
     }
 
-    fn publish_toggle_off(&self, site: SiteMessage) -> Fallible<()> {
+    fn publish_toggle_off(&self, site: SiteMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&site)?;
         let toggle_off: IdentifiableToggleableToggleOff<T> = IdentifiableToggleableToggleOff {
             site,
             component: self.component,
         };
-        self.publish(toggle_off)
+        self.publish(context, toggle_off)
     }
 }
 
 impl<T: Send + Sync + Debug + 'static> IdentifiableToggleableBackendFacade for InProcessComponent<T> {
-    fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> Fallible<()> {
+    fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, IdentifiableToggleableToggleOn<T> { site }, handler)
     }
 
-    fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> Fallible<()> {
+    fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, IdentifiableToggleableToggleOff<T> { site }, handler)
     }
 }
@@ -507,54 +1004,66 @@ struct NluReload {
 }
 
 impl NluFacade for InProcessComponent<Nlu> {
-    fn publish_query(&self, query: NluQueryMessage) -> Fallible<()> {
-        self.publish(NluQuery { query })
+    fn publish_query(&self, query: NluQueryMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&query)?;
+        self.publish(context, NluQuery { query })
     }
 
-    fn publish_partial_query(&self, query: NluSlotQueryMessage) -> Fallible<()> {
-        self.publish(NluPartialQuery { query })
+    fn publish_partial_query(&self, query: NluSlotQueryMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&query)?;
+        self.publish(context, NluPartialQuery { query })
     }
 
-    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> Fallible<()> {
-        self.publish(NluReload { component_reload })
+    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&component_reload)?;
+        self.publish(context, NluReload { component_reload })
     }
 
-    fn subscribe_slot_parsed(&self, handler: Callback<NluSlotMessage>) -> Fallible<()> {
+    fn subscribe_slot_parsed(&self, handler: Callback<NluSlotMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluSlotParsed { slot }, handler)
     }
 
-    fn subscribe_intent_parsed(&self, handler: Callback<NluIntentMessage>) -> Fallible<()> {
+    fn subscribe_intent_parsed(&self, handler: Callback<NluIntentMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluIntentParsed { intent }, handler)
     }
 
-    fn subscribe_intent_not_recognized(&self, handler: Callback<NluIntentNotRecognizedMessage>) -> Fallible<()> {
+    fn subscribe_intent_not_recognized(
+        &self,
+        handler: Callback<NluIntentNotRecognizedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluIntentNotRecognized { status }, handler)
     }
 }
 
 impl NluBackendFacade for InProcessComponent<Nlu> {
-    fn subscribe_query(&self, handler: Callback<NluQueryMessage>) -> Fallible<()> {
+    fn subscribe_query(&self, handler: Callback<NluQueryMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluQuery { query }, handler)
     }
 
-    fn subscribe_partial_query(&self, handler: Callback<NluSlotQueryMessage>) -> Fallible<()> {
+    fn subscribe_partial_query(&self, handler: Callback<NluSlotQueryMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluPartialQuery { query }, handler)
     }
 
-    fn subscribe_component_reload(&self, handler: Callback<RequestComponentReloadMessage>) -> Fallible<()> {
+    fn subscribe_component_reload(
+        &self,
+        handler: Callback<RequestComponentReloadMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, NluReload { component_reload }, handler)
     }
 
-    fn publish_slot_parsed(&self, slot: NluSlotMessage) -> Fallible<()> {
-        self.publish(NluSlotParsed { slot })
+    fn publish_slot_parsed(&self, slot: NluSlotMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&slot)?;
+        self.publish(context, NluSlotParsed { slot })
     }
 
-    fn publish_intent_parsed(&self, intent: NluIntentMessage) -> Fallible<()> {
-        self.publish(NluIntentParsed { intent })
+    fn publish_intent_parsed(&self, intent: NluIntentMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&intent)?;
+        self.publish(context, NluIntentParsed { intent })
     }
 
-    fn publish_intent_not_recognized(&self, status: NluIntentNotRecognizedMessage) -> Fallible<()> {
-        self.publish(NluIntentNotRecognized { status })
+    fn publish_intent_not_recognized(&self, status: NluIntentNotRecognizedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, NluIntentNotRecognized { status })
     }
 }
 
@@ -569,27 +1078,27 @@ struct ToggleableToggleOff<T> {
 }
 
 impl<T: Send + Sync + Debug + Copy + 'static> ToggleableFacade for InProcessComponent<T> {
-    fn publish_toggle_on(&self) -> Fallible<()> {
+    fn publish_toggle_on(&self) -> HermesResult<()> {
         let toggle_on: ToggleableToggleOn<T> = ToggleableToggleOn {
             component: self.component,
         };
-        self.publish(toggle_on)
+        self.publish(TraceContext::current(), toggle_on)
     }
 
-    fn publish_toggle_off(&self) -> Fallible<()> {
+    fn publish_toggle_off(&self) -> HermesResult<()> {
         let toggle_off: ToggleableToggleOff<T> = ToggleableToggleOff {
             component: self.component,
         };
-        self.publish(toggle_off)
+        self.publish(TraceContext::current(), toggle_off)
     }
 }
 
 impl<T: Send + Sync + Debug + 'static> ToggleableBackendFacade for InProcessComponent<T> {
-    fn subscribe_toggle_on(&self, handler: Callback0) -> Fallible<()> {
+    fn subscribe_toggle_on(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ToggleableToggleOn<T>, handler)
     }
 
-    fn subscribe_toggle_off(&self, handler: Callback0) -> Fallible<()> {
+    fn subscribe_toggle_off(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, ToggleableToggleOff<T>, handler)
     }
 }
@@ -608,34 +1117,40 @@ struct VoiceActivityVadDown {
 }
 
 impl VoiceActivityFacade for InProcessComponent<VoiceActivity> {
-    fn subscribe_vad_up(&self, site_id: String, handler: Callback<VadUpMessage>) -> Fallible<()> {
+    fn subscribe_vad_up(&self, site_id: String, handler: Callback<VadUpMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, VoiceActivityVadUp { vad_up }, handler, site_id, |it| &it
             .vad_up
             .site_id)
     }
 
-    fn subscribe_vad_down(&self, site_id: String, handler: Callback<VadDownMessage>) -> Fallible<()> {
+    fn subscribe_vad_down(
+        &self,
+        site_id: String,
+        handler: Callback<VadDownMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, VoiceActivityVadDown { vad_down }, handler, site_id, |it| &it
             .vad_down
             .site_id)
     }
 
-    fn subscribe_all_vad_up(&self, handler: Callback<VadUpMessage>) -> Fallible<()> {
+    fn subscribe_all_vad_up(&self, handler: Callback<VadUpMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, VoiceActivityVadUp { vad_up }, handler)
     }
 
-    fn subscribe_all_vad_down(&self, handler: Callback<VadDownMessage>) -> Fallible<()> {
+    fn subscribe_all_vad_down(&self, handler: Callback<VadDownMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, VoiceActivityVadDown { vad_down }, handler)
     }
 }
 
 impl VoiceActivityBackendFacade for InProcessComponent<VoiceActivity> {
-    fn publish_vad_up(&self, vad_up: VadUpMessage) -> Fallible<()> {
-        self.publish(VoiceActivityVadUp { vad_up })
+    fn publish_vad_up(&self, vad_up: VadUpMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&vad_up)?;
+        self.publish(context, VoiceActivityVadUp { vad_up })
     }
 
-    fn publish_vad_down(&self, vad_down: VadDownMessage) -> Fallible<()> {
-        self.publish(VoiceActivityVadDown { vad_down })
+    fn publish_vad_down(&self, vad_down: VadDownMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&vad_down)?;
+        self.publish(context, VoiceActivityVadDown { vad_down })
     }
 }
 
@@ -649,18 +1164,35 @@ struct HotwordDetected {
 }
 
 impl HotwordFacade for InProcessComponent<Hotword> {
-    fn subscribe_detected(&self, id: String, handler: Callback<HotwordDetectedMessage>) -> Fallible<()> {
+    fn subscribe_detected(
+        &self,
+        id: String,
+        handler: Callback<HotwordDetectedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, HotwordDetected { message }, handler, id, |it| &it.id)
     }
 
-    fn subscribe_all_detected(&self, handler: Callback<HotwordDetectedMessage>) -> Fallible<()> {
+    fn subscribe_all_detected(&self, handler: Callback<HotwordDetectedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, HotwordDetected { message }, handler)
     }
+
+    fn subscribe_all_detected_with_id(
+        &self,
+        handler: Callback<(String, HotwordDetectedMessage)>,
+    ) -> HermesResult<SubscriptionHandle> {
+        let validation = Arc::clone(&self.validation);
+        self.on_message(move |detected: &HotwordDetected, meta: Option<&MessageMeta>| {
+            if validation_mode(&validation).accepts_incoming(&detected.message) {
+                handler.call_with_meta(&(detected.id.clone(), detected.message.clone()), meta)
+            }
+        })
+    }
 }
 
 impl HotwordBackendFacade for InProcessComponent<Hotword> {
-    fn publish_detected(&self, id: String, message: HotwordDetectedMessage) -> Fallible<()> {
-        self.publish(HotwordDetected { id, message })
+    fn publish_detected(&self, id: String, message: HotwordDetectedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&message)?;
+        self.publish(context, HotwordDetected { id, message })
     }
 }
 
@@ -700,46 +1232,60 @@ struct AsrPartialTextCaptured {
 }
 
 impl AsrFacade for InProcessComponent<Asr> {
-    fn publish_start_listening(&self, start: AsrStartListeningMessage) -> Fallible<()> {
-        self.publish(AsrStartListening { start })
+    fn publish_start_listening(&self, start: AsrStartListeningMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&start)?;
+        self.publish(context, AsrStartListening { start })
     }
 
-    fn publish_stop_listening(&self, site: SiteMessage) -> Fallible<()> {
-        self.publish(AsrStopListening { site })
+    fn publish_stop_listening(&self, site: SiteMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&site)?;
+        self.publish(context, AsrStopListening { site })
     }
 
-    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> Fallible<()> {
-        self.publish(AsrReload { component_reload })
+    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&component_reload)?;
+        self.publish(context, AsrReload { component_reload })
     }
 
-    fn subscribe_text_captured(&self, handler: Callback<TextCapturedMessage>) -> Fallible<()> {
+    fn subscribe_text_captured(&self, handler: Callback<TextCapturedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AsrTextCaptured { text_captured }, handler)
     }
 
-    fn subscribe_partial_text_captured(&self, handler: Callback<TextCapturedMessage>) -> Fallible<()> {
+    fn subscribe_partial_text_captured(
+        &self,
+        handler: Callback<TextCapturedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AsrPartialTextCaptured { text_captured }, handler)
     }
 }
 
 impl AsrBackendFacade for InProcessComponent<Asr> {
-    fn subscribe_start_listening(&self, handler: Callback<AsrStartListeningMessage>) -> Fallible<()> {
+    fn subscribe_start_listening(
+        &self,
+        handler: Callback<AsrStartListeningMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AsrStartListening { start }, handler)
     }
 
-    fn subscribe_stop_listening(&self, handler: Callback<SiteMessage>) -> Fallible<()> {
+    fn subscribe_stop_listening(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AsrStopListening { site }, handler)
     }
 
-    fn subscribe_component_reload(&self, handler: Callback<RequestComponentReloadMessage>) -> Fallible<()> {
+    fn subscribe_component_reload(
+        &self,
+        handler: Callback<RequestComponentReloadMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AsrReload { component_reload }, handler)
     }
 
-    fn publish_text_captured(&self, text_captured: TextCapturedMessage) -> Fallible<()> {
-        self.publish(AsrTextCaptured { text_captured })
+    fn publish_text_captured(&self, text_captured: TextCapturedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&text_captured)?;
+        self.publish(context, AsrTextCaptured { text_captured })
     }
 
-    fn publish_partial_text_captured(&self, text_captured: TextCapturedMessage) -> Fallible<()> {
-        self.publish(AsrPartialTextCaptured { text_captured })
+    fn publish_partial_text_captured(&self, text_captured: TextCapturedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&text_captured)?;
+        self.publish(context, AsrPartialTextCaptured { text_captured })
     }
 }
 
@@ -762,29 +1308,32 @@ struct TtsRegisterSound {
 }
 
 impl TtsFacade for InProcessComponent<Tts> {
-    fn publish_say(&self, to_say: SayMessage) -> Fallible<()> {
-        self.publish(TtsSay { to_say })
+    fn publish_say(&self, to_say: SayMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&to_say)?;
+        self.publish(context, TtsSay { to_say })
     }
 
-    fn subscribe_say_finished(&self, handler: Callback<SayFinishedMessage>) -> Fallible<()> {
+    fn subscribe_say_finished(&self, handler: Callback<SayFinishedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, TtsSayFinished { status }, handler)
     }
 
-    fn publish_register_sound(&self, sound: RegisterSoundMessage) -> Fallible<()> {
-        self.publish(TtsRegisterSound { sound })
+    fn publish_register_sound(&self, sound: RegisterSoundMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&sound)?;
+        self.publish(context, TtsRegisterSound { sound })
     }
 }
 
 impl TtsBackendFacade for InProcessComponent<Tts> {
-    fn publish_say_finished(&self, status: SayFinishedMessage) -> Fallible<()> {
-        self.publish(TtsSayFinished { status })
+    fn publish_say_finished(&self, status: SayFinishedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, TtsSayFinished { status })
     }
 
-    fn subscribe_say(&self, handler: Callback<SayMessage>) -> Fallible<()> {
+    fn subscribe_say(&self, handler: Callback<SayMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, TtsSay { to_say }, handler)
     }
 
-    fn subscribe_register_sound(&self, handler: Callback<RegisterSoundMessage>) -> Fallible<()> {
+    fn subscribe_register_sound(&self, handler: Callback<RegisterSoundMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, TtsRegisterSound { sound }, handler)
     }
 }
@@ -828,80 +1377,154 @@ struct AudioServerStreamFinished {
 }
 
 impl AudioServerFacade for InProcessComponent<AudioServer> {
-    fn publish_play_bytes(&self, bytes: PlayBytesMessage) -> Fallible<()> {
-        self.publish(AudioServerPlayBytes { bytes })
+    fn publish_play_bytes(&self, bytes: PlayBytesMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&bytes)?;
+        self.publish(context, AudioServerPlayBytes { bytes })
     }
 
-    fn subscribe_play_finished(&self, site_id: String, handler: Callback<PlayFinishedMessage>) -> Fallible<()> {
+    fn subscribe_play_finished(
+        &self,
+        site_id: String,
+        handler: Callback<PlayFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerPlayFinished { status }, handler, site_id)
     }
 
-    fn subscribe_all_play_finished(&self, handler: Callback<PlayFinishedMessage>) -> Fallible<()> {
+    fn subscribe_all_play_finished(&self, handler: Callback<PlayFinishedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AudioServerPlayFinished { status }, handler)
     }
 
-    fn subscribe_audio_frame(&self, site_id: String, handler: Callback<AudioFrameMessage>) -> Fallible<()> {
+    fn subscribe_audio_frame(
+        &self,
+        site_id: String,
+        handler: Callback<AudioFrameMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerAudioFrame { frame }, handler, site_id)
     }
 
-    fn publish_replay_request(&self, request: ReplayRequestMessage) -> Fallible<()> {
-        self.publish(AudioServerReplayRequest { request })
+    fn publish_replay_request(&self, request: ReplayRequestMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&request)?;
+        self.publish(context, AudioServerReplayRequest { request })
     }
 
-    fn subscribe_replay_response(&self, site_id: String, handler: Callback<AudioFrameMessage>) -> Fallible<()> {
+    fn subscribe_replay_response(
+        &self,
+        site_id: String,
+        handler: Callback<AudioFrameMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerReplayResponse { frame }, handler, site_id)
     }
 
-    fn publish_stream_bytes(&self, stream_bytes_message: StreamBytesMessage) -> Fallible<()> {
-        self.publish(AudioServerStreamBytes {
-            bytes: stream_bytes_message,
-        })
+    fn publish_stream_bytes(&self, stream_bytes_message: StreamBytesMessage) -> HermesResult<()> {
+        // held while publishing, so that the chunks are published in the order they were checked
+        let mut sequence = self.stream_sequence.lock().map_err(PoisonLock::from)?;
+        validation_mode(&self.validation).check_outgoing_chunk(&mut sequence, &stream_bytes_message)?;
+        let context = context_of(&stream_bytes_message);
+        self.publish(
+            context,
+            AudioServerStreamBytes {
+                bytes: stream_bytes_message,
+            },
+        )
     }
 
-    fn subscribe_stream_finished(&self, site_id: String, handler: Callback<StreamFinishedMessage>) -> Fallible<()> {
+    fn subscribe_stream_finished(
+        &self,
+        site_id: String,
+        handler: Callback<StreamFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerStreamFinished { status }, handler, site_id)
     }
 
-    fn subscribe_all_stream_finished(&self, handler: Callback<StreamFinishedMessage>) -> Fallible<()> {
+    fn subscribe_all_stream_finished(
+        &self,
+        handler: Callback<StreamFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AudioServerStreamFinished { status }, handler)
     }
 }
 
 impl AudioServerBackendFacade for InProcessComponent<AudioServer> {
-    fn subscribe_play_bytes(&self, site_id: String, handler: Callback<PlayBytesMessage>) -> Fallible<()> {
+    fn subscribe_play_bytes(
+        &self,
+        site_id: String,
+        handler: Callback<PlayBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerPlayBytes { bytes }, handler, site_id)
     }
 
-    fn subscribe_all_play_bytes(&self, handler: Callback<PlayBytesMessage>) -> Fallible<()> {
+    fn subscribe_all_play_bytes(&self, handler: Callback<PlayBytesMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, AudioServerPlayBytes { bytes }, handler)
     }
 
-    fn publish_play_finished(&self, status: PlayFinishedMessage) -> Fallible<()> {
-        self.publish(AudioServerPlayFinished { status })
+    fn publish_play_finished(&self, status: PlayFinishedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, AudioServerPlayFinished { status })
     }
 
-    fn publish_audio_frame(&self, frame: AudioFrameMessage) -> Fallible<()> {
-        self.publish_quiet(AudioServerAudioFrame { frame })
+    fn publish_audio_frame(&self, frame: AudioFrameMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&frame)?;
+        self.publish_quiet(context, AudioServerAudioFrame { frame })
     }
 
-    fn subscribe_replay_request(&self, site_id: String, handler: Callback<ReplayRequestMessage>) -> Fallible<()> {
+    fn subscribe_replay_request(
+        &self,
+        site_id: String,
+        handler: Callback<ReplayRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, AudioServerReplayRequest { request }, handler, site_id)
     }
 
-    fn publish_replay_response(&self, frame: AudioFrameMessage) -> Fallible<()> {
-        self.publish_quiet(AudioServerReplayResponse { frame })
+    fn publish_replay_response(&self, frame: AudioFrameMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&frame)?;
+        self.publish_quiet(context, AudioServerReplayResponse { frame })
     }
 
-    fn subscribe_stream_bytes(&self, site_id: String, handler: Callback<StreamBytesMessage>) -> Fallible<()> {
-        subscribe_filter!(self, AudioServerStreamBytes { bytes }, handler, site_id)
+    fn subscribe_stream_bytes(
+        &self,
+        site_id: String,
+        handler: Callback<StreamBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
+        self.subscribe_stream_bytes_of(Some(site_id), handler)
     }
 
-    fn subscribe_all_stream_bytes(&self, handler: Callback<StreamBytesMessage>) -> Fallible<()> {
-        subscribe!(self, AudioServerStreamBytes { bytes }, handler)
+    fn subscribe_all_stream_bytes(&self, handler: Callback<StreamBytesMessage>) -> HermesResult<SubscriptionHandle> {
+        self.subscribe_stream_bytes_of(None, handler)
     }
 
-    fn publish_stream_finished(&self, status: StreamFinishedMessage) -> Fallible<()> {
-        self.publish(AudioServerStreamFinished { status })
+    fn publish_stream_finished(&self, status: StreamFinishedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, AudioServerStreamFinished { status })
+    }
+}
+
+impl InProcessComponent<AudioServer> {
+    /// Subscribe to the chunks of the audio streams of a site, or of all of them. Every
+    /// subscription gets all the chunks, so each checks their order on its own
+    fn subscribe_stream_bytes_of(
+        &self,
+        site_id: Option<String>,
+        handler: Callback<StreamBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
+        log::debug!("Subscribing on {:?}/AudioServerStreamBytes", self.component);
+        let validation = Arc::clone(&self.validation);
+        let sequence = Mutex::new(StreamBytesSequence::new());
+        self.on_message(move |m: &AudioServerStreamBytes, meta: Option<&MessageMeta>| {
+            let on_site = match &site_id {
+                Some(site_id) => *site_id == m.bytes.site_id,
+                None => true,
+            };
+            if !on_site {
+                return;
+            }
+            let accepted = {
+                let mut sequence = sequence.lock().unwrap_or_else(PoisonError::into_inner);
+                validation_mode(&validation).accepts_incoming_chunk(&mut sequence, &m.bytes)
+            };
+            if accepted {
+                handler.call_with_meta(&m.bytes, meta)
+            }
+        })
     }
 }
 
@@ -954,84 +1577,103 @@ struct DialogueConfigure {
 }
 
 impl DialogueFacade for InProcessComponent<Dialogue> {
-    fn subscribe_session_queued(&self, handler: Callback<SessionQueuedMessage>) -> Fallible<()> {
+    fn subscribe_session_queued(&self, handler: Callback<SessionQueuedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueSessionQueued { status }, handler)
     }
 
-    fn subscribe_session_started(&self, handler: Callback<SessionStartedMessage>) -> Fallible<()> {
+    fn subscribe_session_started(&self, handler: Callback<SessionStartedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueSessionStarted { status }, handler)
     }
 
-    fn subscribe_intent(&self, intent_name: String, handler: Callback<IntentMessage>) -> Fallible<()> {
+    fn subscribe_intent(
+        &self,
+        intent_name: String,
+        handler: Callback<IntentMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe_filter!(self, DialogueIntent { intent }, handler, intent_name, |it| &it
             .intent
             .intent
             .intent_name)
     }
 
-    fn subscribe_intents(&self, handler: Callback<IntentMessage>) -> Fallible<()> {
+    fn subscribe_intents(&self, handler: Callback<IntentMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueIntent { intent }, handler)
     }
 
-    fn subscribe_intent_not_recognized(&self, handler: Callback<IntentNotRecognizedMessage>) -> Fallible<()> {
+    fn subscribe_intent_not_recognized(
+        &self,
+        handler: Callback<IntentNotRecognizedMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueIntentNotRecognized { intent_not_recognized }, handler)
     }
 
-    fn subscribe_session_ended(&self, handler: Callback<SessionEndedMessage>) -> Fallible<()> {
+    fn subscribe_session_ended(&self, handler: Callback<SessionEndedMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueSessionEnded { status }, handler)
     }
 
-    fn publish_start_session(&self, start_session: StartSessionMessage) -> Fallible<()> {
-        self.publish(DialogueStartSession { start_session })
+    fn publish_start_session(&self, start_session: StartSessionMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&start_session)?;
+        self.publish(context, DialogueStartSession { start_session })
     }
 
-    fn publish_continue_session(&self, continue_session: ContinueSessionMessage) -> Fallible<()> {
-        self.publish(DialogueContinueSession { continue_session })
+    fn publish_continue_session(&self, continue_session: ContinueSessionMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&continue_session)?;
+        self.publish(context, DialogueContinueSession { continue_session })
     }
 
-    fn publish_end_session(&self, end_session: EndSessionMessage) -> Fallible<()> {
-        self.publish(DialogueEndSession { end_session })
+    fn publish_end_session(&self, end_session: EndSessionMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&end_session)?;
+        self.publish(context, DialogueEndSession { end_session })
     }
 
-    fn publish_configure(&self, config: DialogueConfigureMessage) -> Fallible<()> {
-        self.publish(DialogueConfigure { config })
+    fn publish_configure(&self, config: DialogueConfigureMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&config)?;
+        self.publish(context, DialogueConfigure { config })
     }
 }
 
 impl DialogueBackendFacade for InProcessComponent<Dialogue> {
-    fn publish_session_queued(&self, status: SessionQueuedMessage) -> Fallible<()> {
-        self.publish(DialogueSessionQueued { status })
+    fn publish_session_queued(&self, status: SessionQueuedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, DialogueSessionQueued { status })
     }
 
-    fn publish_session_started(&self, status: SessionStartedMessage) -> Fallible<()> {
-        self.publish(DialogueSessionStarted { status })
+    fn publish_session_started(&self, status: SessionStartedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, DialogueSessionStarted { status })
     }
 
-    fn publish_intent(&self, intent: IntentMessage) -> Fallible<()> {
-        self.publish(DialogueIntent { intent })
+    fn publish_intent(&self, intent: IntentMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&intent)?;
+        self.publish(context, DialogueIntent { intent })
     }
 
-    fn publish_intent_not_recognized(&self, intent_not_recognized: IntentNotRecognizedMessage) -> Fallible<()> {
-        self.publish(DialogueIntentNotRecognized { intent_not_recognized })
+    fn publish_intent_not_recognized(&self, intent_not_recognized: IntentNotRecognizedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&intent_not_recognized)?;
+        self.publish(context, DialogueIntentNotRecognized { intent_not_recognized })
     }
 
-    fn publish_session_ended(&self, status: SessionEndedMessage) -> Fallible<()> {
-        self.publish(DialogueSessionEnded { status })
+    fn publish_session_ended(&self, status: SessionEndedMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, DialogueSessionEnded { status })
     }
 
-    fn subscribe_start_session(&self, handler: Callback<StartSessionMessage>) -> Fallible<()> {
+    fn subscribe_start_session(&self, handler: Callback<StartSessionMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueStartSession { start_session }, handler)
     }
 
-    fn subscribe_continue_session(&self, handler: Callback<ContinueSessionMessage>) -> Fallible<()> {
+    fn subscribe_continue_session(
+        &self,
+        handler: Callback<ContinueSessionMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueContinueSession { continue_session }, handler)
     }
 
-    fn subscribe_end_session(&self, handler: Callback<EndSessionMessage>) -> Fallible<()> {
+    fn subscribe_end_session(&self, handler: Callback<EndSessionMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueEndSession { end_session }, handler)
     }
 
-    fn subscribe_configure(&self, handler: Callback<DialogueConfigureMessage>) -> Fallible<()> {
+    fn subscribe_configure(&self, handler: Callback<DialogueConfigureMessage>) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, DialogueConfigure { config }, handler)
     }
 }
@@ -1068,54 +1710,74 @@ struct InjectionResetComplete {
 }
 
 impl InjectionFacade for InProcessComponent<Injection> {
-    fn publish_injection_request(&self, request: InjectionRequestMessage) -> Fallible<()> {
-        self.publish(InjectionPerform { request })
+    fn publish_injection_request(&self, request: InjectionRequestMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&request)?;
+        self.publish(context, InjectionPerform { request })
     }
 
-    fn publish_injection_status_request(&self) -> Fallible<()> {
-        self.publish(InjectionStatusRequest {})
+    fn publish_injection_status_request(&self) -> HermesResult<()> {
+        self.publish(TraceContext::current(), InjectionStatusRequest {})
     }
 
-    fn publish_injection_reset_request(&self, request: InjectionResetRequestMessage) -> Fallible<()> {
-        self.publish(InjectionResetPerform { request })
+    fn publish_injection_reset_request(&self, request: InjectionResetRequestMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&request)?;
+        self.publish(context, InjectionResetPerform { request })
     }
 
-    fn subscribe_injection_status(&self, handler: Callback<InjectionStatusMessage>) -> Fallible<()> {
+    fn subscribe_injection_status(
+        &self,
+        handler: Callback<InjectionStatusMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionStatus { status }, handler)
     }
 
-    fn subscribe_injection_complete(&self, handler: Callback<InjectionCompleteMessage>) -> Fallible<()> {
+    fn subscribe_injection_complete(
+        &self,
+        handler: Callback<InjectionCompleteMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionComplete { message }, handler)
     }
 
-    fn subscribe_injection_reset_complete(&self, handler: Callback<InjectionResetCompleteMessage>) -> Fallible<()> {
+    fn subscribe_injection_reset_complete(
+        &self,
+        handler: Callback<InjectionResetCompleteMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionResetComplete { message }, handler)
     }
 }
 
 impl InjectionBackendFacade for InProcessComponent<Injection> {
-    fn subscribe_injection_request(&self, handler: Callback<InjectionRequestMessage>) -> Fallible<()> {
+    fn subscribe_injection_request(
+        &self,
+        handler: Callback<InjectionRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionPerform { request }, handler)
     }
 
-    fn subscribe_injection_status_request(&self, handler: Callback0) -> Fallible<()> {
+    fn subscribe_injection_status_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionStatusRequest, handler)
     }
 
-    fn subscribe_injection_reset_request(&self, handler: Callback<InjectionResetRequestMessage>) -> Fallible<()> {
+    fn subscribe_injection_reset_request(
+        &self,
+        handler: Callback<InjectionResetRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
         subscribe!(self, InjectionResetPerform { request }, handler)
     }
 
-    fn publish_injection_status(&self, status: InjectionStatusMessage) -> Fallible<()> {
-        self.publish(InjectionStatus { status })
+    fn publish_injection_status(&self, status: InjectionStatusMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&status)?;
+        self.publish(context, InjectionStatus { status })
     }
 
-    fn publish_injection_complete(&self, message: InjectionCompleteMessage) -> Fallible<()> {
-        self.publish(InjectionComplete { message })
+    fn publish_injection_complete(&self, message: InjectionCompleteMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&message)?;
+        self.publish(context, InjectionComplete { message })
     }
 
-    fn publish_injection_reset_complete(&self, message: InjectionResetCompleteMessage) -> Fallible<()> {
-        self.publish(InjectionResetComplete { message })
+    fn publish_injection_reset_complete(&self, message: InjectionResetCompleteMessage) -> HermesResult<()> {
+        let context = self.check_outgoing(&message)?;
+        self.publish(context, InjectionResetComplete { message })
     }
 }
 
@@ -1131,4 +1793,500 @@ mod tests {
     }
 
     hermes_test_suite::test_suite!();
+
+    mod deterministic {
+        use super::*;
+
+        fn create_handlers() -> (Rc<InProcessHermesProtocolHandler>, Rc<InProcessHermesProtocolHandler>) {
+            let handler = Rc::new(InProcessHermesProtocolHandler::new_deterministic());
+            (Rc::clone(&handler), handler)
+        }
+
+        hermes_test_suite::test_suite!(
+            DELIVER = |handler: &InProcessHermesProtocolHandler| handler.run_until_idle().unwrap()
+        );
+    }
+
+    #[test]
+    fn deterministic_handlers_deliver_in_publish_order_when_pumped() {
+        let handler = InProcessHermesProtocolHandler::new_deterministic();
+        let tts = handler.tts();
+        let backend = handler.tts_backend();
+        let heard = Arc::new(Mutex::new(vec![]));
+        let said = Arc::clone(&heard);
+        let responder = handler.tts_backend();
+        let _say = backend
+            .subscribe_say(Callback::new(move |say: &SayMessage| {
+                said.lock().unwrap().push(format!("say {}", say.text));
+                responder
+                    .publish_say_finished(SayFinishedMessage {
+                        id: say.id.clone(),
+                        session_id: None,
+                    })
+                    .unwrap()
+            }))
+            .unwrap();
+        let finished = Arc::clone(&heard);
+        let _finished = tts
+            .subscribe_say_finished(Callback::new(move |message: &SayFinishedMessage| {
+                finished.lock().unwrap().push(format!("finished {:?}", message.id))
+            }))
+            .unwrap();
+        let say = |id: &str| SayMessage {
+            id: Some(id.into()),
+            text: id.into(),
+            lang: None,
+            site_id: "default".into(),
+            session_id: None,
+        };
+
+        tts.publish_say(say("first")).unwrap();
+        tts.publish_say(say("second")).unwrap();
+        assert!(heard.lock().unwrap().is_empty());
+
+        assert!(handler.pump().unwrap());
+        assert_eq!(*heard.lock().unwrap(), vec!["say first"]);
+
+        assert_eq!(handler.run_until_idle().unwrap(), 3);
+        assert_eq!(
+            *heard.lock().unwrap(),
+            vec![
+                "say first",
+                "say second",
+                "finished Some(\"first\")",
+                "finished Some(\"second\")"
+            ]
+        );
+        assert!(!handler.pump().unwrap());
+    }
+
+    fn listener_count(handler: &InProcessHermesProtocolHandler) -> usize {
+        let subscribers = handler.subscribers.lock().unwrap();
+        subscribers.iter().map(|it| it.listeners().len()).sum()
+    }
+
+    #[test]
+    fn unsubscribing_removes_the_listeners() {
+        for handler in vec![
+            InProcessHermesProtocolHandler::new(),
+            InProcessHermesProtocolHandler::new_deterministic(),
+        ] {
+            let dialogue = handler.dialogue();
+            let _ended = dialogue
+                .subscribe_session_ended(Callback::new(|_: &SessionEndedMessage| {}))
+                .unwrap();
+            assert_eq!(listener_count(&handler), 1);
+
+            for _ in 0..10 {
+                let intents = dialogue
+                    .subscribe_intents(Callback::new(|_: &IntentMessage| {}))
+                    .unwrap();
+                assert_eq!(listener_count(&handler), 2);
+                drop(intents);
+            }
+            assert_eq!(listener_count(&handler), 1);
+
+            let intents = dialogue
+                .subscribe_intents(Callback::new(|_: &IntentMessage| {}))
+                .unwrap();
+            intents.unsubscribe().unwrap();
+            assert_eq!(listener_count(&handler), 1);
+        }
+    }
+
+    #[test]
+    fn facade_of_a_dropped_handler_reports_closed() {
+        let dialogue = InProcessHermesProtocolHandler::new().dialogue();
+
+        match dialogue.publish_toggle_on() {
+            Err(HermesError::Closed) => {}
+            other => panic!("expected a closed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn messages_carry_the_meta_of_their_origin() {
+        let handler = InProcessHermesProtocolHandler::new_with_origin(MessageOrigin::new("snips-tts", "kitchen"));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _subscription = handler
+            .tts()
+            .subscribe_say_finished(Callback::with_meta(move |_: &SayFinishedMessage, meta| {
+                sender.lock().unwrap().send(meta.cloned()).unwrap()
+            }))
+            .unwrap();
+
+        handler
+            .tts_backend()
+            .publish_say_finished(SayFinishedMessage {
+                id: None,
+                session_id: None,
+            })
+            .unwrap();
+
+        let meta = receiver
+            .recv_timeout(std::time::Duration::from_secs(1))
+            .unwrap()
+            .expect("no meta received");
+        assert_eq!(meta.origin, Some(MessageOrigin::new("snips-tts", "kitchen")));
+    }
+
+    fn detected(current_sensitivity: f32) -> HotwordDetectedMessage {
+        HotwordDetectedMessage {
+            site_id: "kitchen".into(),
+            model_id: "hey_snips".into(),
+            model_version: None,
+            model_type: None,
+            current_sensitivity: Some(current_sensitivity),
+            detection_signal_ms: None,
+            end_signal_ms: None,
+        }
+    }
+
+    #[test]
+    fn invalid_messages_are_rejected_when_asked() {
+        let handler = InProcessHermesProtocolHandler::new();
+        let backend = handler.hotword_backend();
+
+        backend.publish_detected("hey_snips".into(), detected(2.)).unwrap();
+
+        handler.set_validation_mode(ValidationMode::Reject).unwrap();
+        match backend.publish_detected("hey_snips".into(), detected(2.)) {
+            Err(HermesError::Validation(reason)) => assert!(reason.contains("current_sensitivity")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        backend.publish_detected("hey_snips".into(), detected(0.5)).unwrap();
+    }
+
+    #[test]
+    fn stream_chunks_out_of_order_are_rejected_when_asked() {
+        let publisher = InProcessHermesProtocolHandler::named("streams");
+        let receiver = InProcessHermesProtocolHandler::named("streams");
+        receiver.set_validation_mode(ValidationMode::Reject).unwrap();
+        let backend = receiver.audio_server_backend();
+        let chunks = hermes::receiver(4, Overflow::Block, |cb| backend.subscribe_all_stream_bytes(cb)).unwrap();
+        let chunk = |chunk_number| StreamBytesMessage {
+            bytes: vec![0; 4],
+            site_id: "default".into(),
+            stream_id: "stream".into(),
+            chunk_number,
+            is_last_chunk: false,
+        };
+        let audio_server = publisher.audio_server();
+
+        for chunk_number in vec![0, 2, 1, 3] {
+            audio_server.publish_stream_bytes(chunk(chunk_number)).unwrap();
+        }
+        let received = (0..3)
+            .map(|_| {
+                chunks
+                    .recv_timeout(std::time::Duration::from_secs(1))
+                    .unwrap()
+                    .chunk_number
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(received, vec![0, 2, 3]);
+
+        publisher.set_validation_mode(ValidationMode::Reject).unwrap();
+        audio_server.publish_stream_bytes(chunk(5)).unwrap();
+        match audio_server.publish_stream_bytes(chunk(4)) {
+            Err(HermesError::Validation(reason)) => assert!(reason.contains("chunk_number")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn handlers_are_created_from_urls() {
+        register_transports().unwrap();
+
+        let handler = hermes::connect("inprocess://main").unwrap();
+
+        handler.dialogue().publish_toggle_on().unwrap();
+        assert_eq!(handler.to_string(), "Snips InProcess Bus 'main'");
+    }
+
+    #[test]
+    fn named_handlers_share_their_bus() {
+        let publisher = InProcessHermesProtocolHandler::named("shared");
+        let same = InProcessHermesProtocolHandler::named("shared");
+        let other = InProcessHermesProtocolHandler::named("other");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let toggled = |handler: &InProcessHermesProtocolHandler, name: &'static str| {
+            let sender = Mutex::new(sender.clone());
+            handler
+                .dialogue_backend()
+                .subscribe_toggle_on(Callback0::new(move || sender.lock().unwrap().send(name).unwrap()))
+                .unwrap()
+        };
+        let _same = toggled(&same, "same");
+        let _other = toggled(&other, "other");
+
+        publisher.dialogue().publish_toggle_on().unwrap();
+
+        let timeout = std::time::Duration::from_millis(500);
+        assert_eq!(receiver.recv_timeout(timeout).unwrap(), "same");
+        assert!(receiver.recv_timeout(timeout).is_err());
+    }
+
+    #[test]
+    fn named_bus_dies_with_its_last_handler() {
+        let first = InProcessHermesProtocolHandler::named("short-lived");
+        let second = InProcessHermesProtocolHandler::named("short-lived");
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _closed = first
+            .subscribe_bus_closed(Callback0::new(move || sender.lock().unwrap().send(()).unwrap()))
+            .unwrap();
+        let dialogue = first.dialogue();
+
+        drop(first);
+        dialogue.publish_toggle_on().unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        drop(second);
+        receiver.try_recv().unwrap();
+        match dialogue.publish_toggle_on() {
+            Err(HermesError::Closed) => {}
+            other => panic!("expected a closed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn receivers_are_closed_with_the_bus() {
+        use hermes::hermes_utils::Example;
+
+        let handler = InProcessHermesProtocolHandler::new();
+        let dialogue = handler.dialogue();
+        let intents = receiver(4, Overflow::Block, |callback| dialogue.subscribe_intents(callback))
+            .unwrap()
+            .closed_by(|callback| handler.subscribe_bus_closed(callback))
+            .unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+
+        handler
+            .dialogue_backend()
+            .publish_intent(IntentMessage::full_example())
+            .unwrap();
+        assert_eq!(intents.recv_timeout(timeout).unwrap(), IntentMessage::full_example());
+
+        drop(handler);
+        // the facade still holds the subscription, only the bus tells that nothing will come
+        match intents.recv_timeout(timeout) {
+            Err(HermesError::Closed) => {}
+            other => panic!("expected a closed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bridge_forwards_the_routed_components_and_sites() {
+        let satellite = InProcessHermesProtocolHandler::new();
+        let base = InProcessHermesProtocolHandler::new();
+        let _bridge = Bridge::builder()
+            .route_sites(HermesComponent::Hotword, Side::Left, vec!["kitchen"])
+            .route(HermesComponent::Tts, Side::Left)
+            .start(&satellite, &base)
+            .unwrap();
+        let hotword = base.hotword();
+        let detections = receiver(4, Overflow::Block, |cb| hotword.subscribe_all_detected_with_id(cb)).unwrap();
+        let tts = satellite.tts_backend();
+        let says = receiver(4, Overflow::Block, |cb| tts.subscribe_say(cb)).unwrap();
+
+        let mut bedroom = detected(0.5);
+        bedroom.site_id = "bedroom".into();
+        let hotword = satellite.hotword_backend();
+        hotword.publish_detected("hey_snips".into(), bedroom).unwrap();
+        hotword.publish_detected("hey_snips".into(), detected(0.5)).unwrap();
+        base.tts()
+            .publish_say(SayMessage {
+                text: "hello".into(),
+                lang: None,
+                id: None,
+                site_id: "kitchen".into(),
+                session_id: None,
+            })
+            .unwrap();
+
+        let timeout = std::time::Duration::from_secs(1);
+        let (id, detected) = detections.recv_timeout(timeout).unwrap();
+        assert_eq!((id.as_str(), detected.site_id.as_str()), ("hey_snips", "kitchen"));
+        assert_eq!(says.recv_timeout(timeout).unwrap().text, "hello");
+        assert!(detections.recv_timeout(timeout).is_err());
+    }
+
+    #[test]
+    fn bridge_does_not_forward_back_what_it_published() {
+        let left = InProcessHermesProtocolHandler::new();
+        let right = InProcessHermesProtocolHandler::new();
+        let _bridge = Bridge::builder()
+            .route(HermesComponent::Asr, Side::Left)
+            .route(HermesComponent::Asr, Side::Right)
+            .route(HermesComponent::Nlu, Side::Left)
+            .route(HermesComponent::Nlu, Side::Right)
+            .start(&left, &right)
+            .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _toggled = left
+            .asr_backend()
+            .subscribe_toggle_on(Callback0::new(move || sender.lock().unwrap().send(()).unwrap()))
+            .unwrap();
+        let nlu = left.nlu_backend();
+        let queries = hermes::receiver(4, Overflow::Block, |cb| nlu.subscribe_query(cb)).unwrap();
+
+        left.asr().publish_toggle_on().unwrap();
+        left.nlu()
+            .publish_query(NluQueryMessage {
+                input: "hello".into(),
+                asr_tokens: None,
+                intent_filter: None,
+                id: None,
+                session_id: None,
+            })
+            .unwrap();
+
+        let timeout = std::time::Duration::from_millis(500);
+        receiver.recv_timeout(timeout).unwrap();
+        assert!(receiver.recv_timeout(timeout).is_err());
+        assert_eq!(queries.recv_timeout(timeout).unwrap().input, "hello");
+        assert!(queries.recv_timeout(timeout).is_err());
+    }
+
+    fn say(text: &str, site_id: &str) -> SayMessage {
+        SayMessage {
+            text: text.into(),
+            lang: None,
+            id: None,
+            site_id: site_id.into(),
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn interceptors_see_rewrite_and_reject_messages() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let log = Arc::clone(&seen);
+        let handler = InterceptedHandler::new(InProcessHermesProtocolHandler::new_deterministic())
+            .with(move |message: &mut Intercepted| {
+                log.lock()
+                    .unwrap()
+                    .push(format!("{:?} {}", message.direction(), message.topic()));
+                Ok(())
+            })
+            .with(|message: &mut Intercepted| {
+                if let Some(say) = message.message_mut::<SayMessage>() {
+                    if say.site_id == "kitchen" {
+                        say.site_id = "default".into()
+                    }
+                    if say.text.is_empty() {
+                        return Err(HermesError::Validation("nothing to say".into()));
+                    }
+                }
+                Ok(())
+            });
+        let backend = handler.tts_backend();
+        let says = receiver(4, Overflow::Block, |cb| backend.subscribe_say(cb)).unwrap();
+
+        let tts = handler.tts();
+        tts.publish_say(say("hello", "kitchen")).unwrap();
+        assert!(tts.publish_say(say("", "kitchen")).is_err());
+        handler.handler().run_until_idle().unwrap();
+
+        assert_eq!(says.try_recv().unwrap().site_id, "default");
+        assert!(says.try_recv().is_none());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                "Publish hermes/tts/say",
+                "Publish hermes/tts/say",
+                "Deliver hermes/tts/say"
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_context_follows_the_messages_published_by_callbacks() {
+        let handler = InProcessHermesProtocolHandler::new_deterministic();
+        let responder = handler.tts_backend();
+        let _say = handler
+            .tts_backend()
+            .subscribe_say(Callback::new(move |say: &SayMessage| {
+                responder
+                    .publish_say_finished(SayFinishedMessage {
+                        id: say.id.clone(),
+                        session_id: None,
+                    })
+                    .unwrap()
+            }))
+            .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _finished = handler
+            .tts()
+            .subscribe_say_finished(Callback::new(move |_: &SayFinishedMessage| {
+                sender.lock().unwrap().send(TraceContext::current()).unwrap()
+            }))
+            .unwrap();
+        let context = TraceContext {
+            session_id: Some("turn".into()),
+            site_id: Some("kitchen".into()),
+        };
+
+        context
+            .scope(|| handler.tts().publish_say(say("hello", "kitchen")))
+            .unwrap();
+        handler.run_until_idle().unwrap();
+
+        assert_eq!(receiver.try_recv().unwrap(), context);
+        assert!(TraceContext::current().is_empty());
+    }
+
+    #[test]
+    fn trace_context_is_inferred_from_the_messages() {
+        use hermes::hermes_utils::Example;
+
+        let handler = InProcessHermesProtocolHandler::new_deterministic();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _intents = handler
+            .dialogue()
+            .subscribe_intents(Callback::new(move |_: &IntentMessage| {
+                sender.lock().unwrap().send(TraceContext::current()).unwrap()
+            }))
+            .unwrap();
+        let intent = IntentMessage::full_example();
+
+        handler.dialogue_backend().publish_intent(intent.clone()).unwrap();
+        handler.run_until_idle().unwrap();
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            TraceContext {
+                session_id: Some(intent.session_id),
+                site_id: Some(intent.site_id),
+            }
+        );
+    }
+
+    #[test]
+    fn app_ends_the_session_of_a_panicking_handler() {
+        use hermes::hermes_utils::Example;
+
+        let handler = InProcessHermesProtocolHandler::new_deterministic();
+        let backend = handler.dialogue_backend();
+        let ends = receiver(4, Overflow::Block, |cb| backend.subscribe_end_session(cb)).unwrap();
+        let _app = HermesApp::builder()
+            .on_intent("crash", |_: &IntentMessage, _: &mut ()| -> Action {
+                panic!("handler failure")
+            })
+            .start(handler.dialogue())
+            .unwrap();
+        let mut intent = IntentMessage::minimal_example();
+        intent.session_id = "session".into();
+        intent.intent.intent_name = "crash".into();
+
+        handler.dialogue_backend().publish_intent(intent).unwrap();
+        handler.run_until_idle().unwrap();
+
+        assert_eq!(ends.try_recv().unwrap().session_id, "session");
+    }
 }
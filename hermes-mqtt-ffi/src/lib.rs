@@ -10,7 +10,6 @@ use failure::Fallible;
 use failure::ResultExt;
 use ffi_utils::*;
 use hermes_ffi::*;
-use log::*;
 
 generate_error_handling!(hermes_get_last_error);
 
@@ -38,35 +37,26 @@ pub struct CMqttOptions {
     tls_disable_root_store: libc::c_uchar,
 }
 
-impl AsRust<hermes_mqtt::MqttOptions> for CMqttOptions {
-    fn as_rust(&self) -> std::result::Result<hermes_mqtt::MqttOptions, failure::Error> {
-        let id = hermes_mqtt::get_mqtt_id();
-        let mut options = ::hermes_mqtt::MqttOptions::new(id, create_rust_string_from!(self.broker_address));
-        options.username = create_optional_rust_string_from!(self.username);
-        options.password = create_optional_rust_string_from!(self.password);
-        if let Some(hostname) = create_optional_rust_string_from!(self.tls_hostname) {
-            let mut tls = ::hermes_mqtt::TlsOptions::new(hostname);
-            tls.disable_root_store = self.tls_disable_root_store != 0;
-            tls.cafile = create_optional_rust_vec_string_from!(self.tls_ca_file)
-                .unwrap_or_else(|| vec![])
+impl AsRust<hermes::HandlerConfig> for CMqttOptions {
+    fn as_rust(&self) -> std::result::Result<hermes::HandlerConfig, failure::Error> {
+        let paths = |it: Option<Vec<String>>| {
+            it.unwrap_or_else(|| vec![])
                 .iter()
                 .map(::std::path::PathBuf::from)
-                .collect();
-            tls.capath = create_optional_rust_vec_string_from!(self.tls_ca_path)
-                .unwrap_or_else(|| vec![])
-                .iter()
-                .map(::std::path::PathBuf::from)
-                .collect();
-            if let (Some(ref k), Some(ref c)) = (
-                create_optional_rust_string_from!(self.tls_client_key),
-                create_optional_rust_string_from!(self.tls_client_cert),
-            ) {
-                tls.client_certs_key = Some((c.into(), k.into()));
-            }
-            debug!("TLS options: {:?}", tls);
-            options.tls = Some(tls)
-        }
-        Ok(options)
+                .collect()
+        };
+        Ok(hermes::HandlerConfig {
+            broker_address: create_rust_string_from!(self.broker_address),
+            username: create_optional_rust_string_from!(self.username),
+            password: create_optional_rust_string_from!(self.password),
+            tls_hostname: create_optional_rust_string_from!(self.tls_hostname),
+            tls_ca_file: paths(create_optional_rust_vec_string_from!(self.tls_ca_file)),
+            tls_ca_path: paths(create_optional_rust_vec_string_from!(self.tls_ca_path)),
+            tls_client_key: create_optional_rust_string_from!(self.tls_client_key).map(Into::into),
+            tls_client_cert: create_optional_rust_string_from!(self.tls_client_cert).map(Into::into),
+            tls_disable_root_store: self.tls_disable_root_store != 0,
+            ..hermes::HandlerConfig::default()
+        })
     }
 }
 
@@ -95,7 +85,7 @@ pub extern "C" fn hermes_protocol_handler_new_mqtt(
         }
         Ok(())
     }
-    wrap!(new_mqtt_handler(handler, broker_address, user_data))
+    wrap_hermes!(new_mqtt_handler(handler, broker_address, user_data))
 }
 
 #[no_mangle]
@@ -109,7 +99,7 @@ pub extern "C" fn hermes_protocol_handler_new_mqtt_with_options(
         mqtt_options: *const CMqttOptions,
         user_data: *mut libc::c_void,
     ) -> Result<(), failure::Error> {
-        let options = unsafe { (&*mqtt_options).as_rust() }?;
+        let options = hermes_mqtt::mqtt_options(&unsafe { (&*mqtt_options).as_rust() }?);
         let cph = CProtocolHandler::new(
             Box::new(
                 hermes_mqtt::MqttHermesProtocolHandler::new_with_options(options)
@@ -123,7 +113,7 @@ pub extern "C" fn hermes_protocol_handler_new_mqtt_with_options(
         }
         Ok(())
     }
-    wrap!(new_mqtt_handler(handler, mqtt_options, user_data))
+    wrap_hermes!(new_mqtt_handler(handler, mqtt_options, user_data))
 }
 
 #[no_mangle]
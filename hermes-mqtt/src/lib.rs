@@ -1,10 +1,14 @@
-mod topics;
+mod recorder;
+pub mod topics;
 
+use std::collections::HashMap;
+use std::fmt::Debug;
 use std::string::ToString;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError, RwLock, Weak};
+use std::time::Instant;
 
-use failure::{format_err, Fallible, ResultExt, SyncFailure};
+use hermes::trace::{deliver_span, publish_span};
 use hermes::*;
 use lazy_static::lazy_static;
 use log::*;
@@ -14,6 +18,8 @@ use crate::topics::*;
 use rumqtt::PublishBuilder;
 pub use rumqtt::{MqttOptions, TlsOptions};
 
+pub use crate::recorder::{decode_typed, replay, AudioSpan, TrafficEntry, TrafficFilter, TrafficRecorder, TypedPayload};
+
 lazy_static! {
     static ref MQTT_ID_COUNTER: AtomicUsize = AtomicUsize::from(0);
 }
@@ -31,27 +37,201 @@ pub fn get_mqtt_id() -> String {
     )
 }
 
+/// The options of the MQTT client described by a config. TLS is enabled by a TLS hostname, or by
+/// the `mqtts` transport in which case the host of the broker is the default hostname
+pub fn mqtt_options(config: &HandlerConfig) -> MqttOptions {
+    let (host, port) = config.broker_host_and_port();
+    let port = port.unwrap_or(if config.transport == "mqtts" { 8883 } else { 1883 });
+    let broker_address = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut options = MqttOptions::new(get_mqtt_id(), broker_address);
+    options.username = config.username.clone();
+    options.password = config.password.clone();
+    let tls_hostname = match &config.tls_hostname {
+        Some(hostname) => Some(hostname.clone()),
+        None if config.transport == "mqtts" => Some(host.to_string()),
+        None => None,
+    };
+    if let Some(hostname) = tls_hostname {
+        let mut tls = TlsOptions::new(hostname);
+        tls.disable_root_store = config.tls_disable_root_store;
+        tls.cafile = config.tls_ca_file.clone();
+        tls.capath = config.tls_ca_path.clone();
+        if let (Some(key), Some(cert)) = (&config.tls_client_key, &config.tls_client_cert) {
+            tls.client_certs_key = Some((cert.clone(), key.clone()));
+        }
+        debug!("TLS options: {:?}", tls);
+        options.tls = Some(tls)
+    }
+    options
+}
+
+fn connect_mqtt(config: &HandlerConfig) -> HermesResult<Box<dyn HermesProtocolHandler>> {
+    let handler = MqttHermesProtocolHandler::new_with_options(mqtt_options(config))?;
+    Ok(Box::new(handler))
+}
+
+/// Make the `mqtt` and `mqtts` transports available to `hermes::connect`
+pub fn register_transports() -> HermesResult<()> {
+    register_transport("mqtt", connect_mqtt)?;
+    register_transport("mqtts", connect_mqtt)
+}
+
+/// The errors of the rumqtt client aren't `Sync`, so only their description is kept
+fn transport_error<E: std::fmt::Display>(e: E) -> HermesError {
+    HermesError::transport(e.to_string())
+}
+
+type MqttCallback = Arc<dyn Fn(&rumqtt::Publish) -> () + Send + Sync>;
+
+type DecodeErrorCallbacks = Mutex<Vec<(usize, Arc<Callback<DecodeError>>)>>;
+
+/// The current validation mode, messages aren't checked if the lock is poisoned
+fn validation_mode(validation: &RwLock<ValidationMode>) -> ValidationMode {
+    validation.read().map(|it| *it).unwrap_or_default()
+}
+
+/// The current decode policy, the standard one if the lock is poisoned
+fn decode_policy(policy: &RwLock<DecodePolicy>) -> DecodePolicy {
+    policy.read().map(|it| *it).unwrap_or_default()
+}
+
+/// The current metrics, none are recorded if the lock is poisoned
+fn current_metrics(metrics: &RwLock<Option<Arc<Metrics>>>) -> Option<Arc<Metrics>> {
+    metrics.read().ok().and_then(|it| it.clone())
+}
+
+/// Run a callback on a message received on `topic`, in the span of the message and with its
+/// context as the current one, measuring how long it takes when there are metrics
+fn deliver<R, F: FnOnce() -> R>(
+    metrics: Option<&Metrics>,
+    pattern: &str,
+    topic: &str,
+    context: TraceContext,
+    callback: F,
+) -> R {
+    let span = deliver_span(topic, || context.clone());
+    let _entered = span.enter();
+    context.scope(|| match metrics {
+        Some(metrics) => {
+            let start = Instant::now();
+            let result = callback();
+            metrics.callback_ran(pattern, start.elapsed());
+            result
+        }
+        None => callback(),
+    })
+}
+
+/// The number of a chunk of an audio stream, from the topic it was published on
+fn parse_chunk_number(chunk_number: &str) -> Result<u32, String> {
+    chunk_number
+        .parse()
+        .map_err(|e| format!("invalid chunk number '{}': {}", chunk_number, e))
+}
+
+/// Report a message that could not be decoded to the metrics and to the callbacks subscribed to
+/// the decode errors
+fn report_decode_error(
+    decode_errors: &DecodeErrorCallbacks,
+    metrics: Option<&Metrics>,
+    pattern: &str,
+    message: &rumqtt::Publish,
+    error: String,
+) {
+    warn!("Error while decoding object on topic {:?}: {}", message.topic_name, error);
+    if let Some(metrics) = metrics {
+        metrics.decode_failed(pattern)
+    }
+    // don't hold the lock while running the callbacks, they may want to unsubscribe
+    let callbacks: Vec<Arc<Callback<DecodeError>>> = match decode_errors.lock() {
+        Ok(callbacks) => callbacks.iter().map(|(_, it)| Arc::clone(it)).collect(),
+        Err(_) => {
+            error!("could not lock the callbacks of the decode errors");
+            return;
+        }
+    };
+    if callbacks.is_empty() {
+        return;
+    }
+    let decode_error = DecodeError {
+        topic: message.topic_name.clone(),
+        payload: message.payload.to_vec(),
+        error,
+    };
+    for callback in callbacks {
+        callback.call(&decode_error)
+    }
+}
+
+/// The context of a binary message, which only tells the site in its topic
+fn topic_context(topic: &HermesTopic) -> TraceContext {
+    TraceContext {
+        session_id: None,
+        site_id: topic.site_id().map(str::to_string),
+    }
+}
+
+/// The local callbacks registered on a given MQTT topic filter, the broker subscription is shared
+/// by all of them and only cancelled when the last one is removed
+#[derive(Default)]
+struct TopicSubscription {
+    callbacks: Arc<Mutex<Vec<(usize, MqttCallback)>>>,
+}
+
 struct MqttHandler {
-    mqtt_client: rumqtt::MqttClient,
+    mqtt_client: Arc<rumqtt::MqttClient>,
+    subscriptions: Arc<Mutex<HashMap<String, TopicSubscription>>>,
+    subscription_counter: AtomicUsize,
+    /// When set, the JSON messages published carry a `MessageMeta` in their `_meta` field, as do the
+    /// ones a bridge forwards. The messages without payload are left empty, as the subscribers of
+    /// other clients expect them
+    origin: Option<MessageOrigin>,
+    /// What to do with the invalid messages published or received
+    validation: Arc<RwLock<ValidationMode>>,
+    /// How to decode the JSON messages received
+    decode_policy: Arc<RwLock<DecodePolicy>>,
+    /// Where the metrics of the traffic go, if anywhere
+    metrics: Arc<RwLock<Option<Arc<Metrics>>>>,
+    /// The callbacks given the messages that could not be decoded
+    decode_errors: Arc<DecodeErrorCallbacks>,
+    /// The chunks of the audio streams published, checked in order when validating
+    stream_sequence: Mutex<StreamBytesSequence>,
 }
 
 impl MqttHandler {
-    pub fn publish(&self, topic: &HermesTopic) -> Fallible<()> {
-        let topic = &*topic.as_path();
-        debug!("Publishing on MQTT topic '{}'", topic);
+    pub fn publish(&self, topic: &HermesTopic) -> HermesResult<()> {
+        let path = &*topic.as_path();
+        let span = publish_span(path, TraceContext::current);
+        let _entered = span.enter();
+        debug!("Publishing on MQTT topic '{}'", path);
         self.mqtt_client
-            .publish(topic)
+            .publish(path)
             .and_then(PublishBuilder::send)
-            .map_err(SyncFailure::new)?;
+            .map_err(transport_error)?;
+        self.published(topic, &[]);
         Ok(())
     }
 
-    pub fn publish_payload<P: serde::Serialize>(&self, topic: &HermesTopic, payload: P) -> Fallible<()> {
-        serde_json::to_vec(&payload).map(|p| {
-            let topic = &*topic.as_path();
+    pub fn publish_payload<P>(&self, topic: &HermesTopic, payload: P) -> HermesResult<()>
+    where
+        P: serde::Serialize + Validate + Debug,
+    {
+        validation_mode(&self.validation).check_outgoing(&payload)?;
+        self.send_payload(topic, payload)
+    }
+
+    fn send_payload<P: serde::Serialize>(&self, topic: &HermesTopic, payload: P) -> HermesResult<()> {
+        self.encode(payload).map(|p| {
+            let path = &*topic.as_path();
+            let span = publish_span(path, || TraceContext::of_json(&p).or(TraceContext::current()));
+            let _entered = span.enter();
             debug!(
                 "Publishing on MQTT topic '{}', payload: {}",
-                topic,
+                path,
                 if p.len() < 2048 {
                     String::from_utf8_lossy(&p).to_string()
                 } else {
@@ -59,48 +239,116 @@ impl MqttHandler {
                 }
             );
             trace!("Payload: {}", String::from_utf8_lossy(&p));
-            self.mqtt_client
-                .publish(topic)
-                .map(|m| m.payload(p))
-                .and_then(PublishBuilder::send)
-                .map_err(SyncFailure::new)
+            self.send(topic, path, p)
         })??;
         Ok(())
     }
 
-    pub fn publish_binary_payload(&self, topic: &HermesTopic, payload: Vec<u8>) -> Fallible<()> {
-        let topic = &*topic.as_path();
-        debug!(
-            "Publishing as binary on MQTT topic '{}', with size {}",
-            topic,
-            payload.len()
-        );
+    /// Send a payload on `topic`, whose path is `path`, and count it once sent when there are
+    /// metrics
+    fn send(&self, topic: &HermesTopic, path: &str, payload: Vec<u8>) -> HermesResult<()> {
+        let metrics = current_metrics(&self.metrics);
+        // the client takes the payload, the metrics need it once it is sent
+        let counted = metrics.as_ref().map(|_| payload.clone());
         self.mqtt_client
-            .publish(topic)
+            .publish(path)
             .map(|m| m.payload(payload))
             .and_then(PublishBuilder::send)
-            .map_err(SyncFailure::new)?;
-
+            .map_err(transport_error)?;
+        if let (Some(metrics), Some(payload)) = (metrics, counted) {
+            metrics.published(&topic.pattern(), &payload)
+        }
         Ok(())
     }
 
-    pub fn subscribe<F>(&self, topic: &HermesTopic, handler: F) -> Fallible<()>
+    /// Count a message published on `topic`, when there are metrics
+    fn published(&self, topic: &HermesTopic, payload: &[u8]) {
+        if let Some(metrics) = current_metrics(&self.metrics) {
+            metrics.published(&topic.pattern(), payload)
+        }
+    }
+
+    fn meta(&self) -> Option<MessageMeta> {
+        MessageMeta::published(self.origin.as_ref())
+    }
+
+    /// Encode a payload to JSON, adding its metadata when we have some
+    fn encode<P: serde::Serialize>(&self, payload: P) -> HermesResult<Vec<u8>> {
+        match self.meta() {
+            Some(mut meta) => {
+                let mut payload = serde_json::to_value(payload)?;
+                meta.context = TraceContext::of_value(&payload).or(meta.context);
+                if let serde_json::Value::Object(ref mut fields) = payload {
+                    fields.insert(hermes::meta::META_FIELD.to_string(), serde_json::to_value(meta)?);
+                }
+                Ok(serde_json::to_vec(&payload)?)
+            }
+            None => Ok(serde_json::to_vec(&payload)?),
+        }
+    }
+
+    /// The metadata of a JSON message, if it has some
+    fn decode_meta(payload: &[u8]) -> Option<MessageMeta> {
+        if payload.is_empty() {
+            return None;
+        }
+        serde_json::from_slice::<hermes::meta::MetaEnvelope>(payload)
+            .ok()
+            .and_then(|envelope| envelope.meta)
+    }
+
+    pub fn publish_binary_payload(&self, topic: &HermesTopic, payload: Vec<u8>) -> HermesResult<()> {
+        let path = &*topic.as_path();
+        let span = publish_span(path, || topic_context(topic).or(TraceContext::current()));
+        let _entered = span.enter();
+        debug!(
+            "Publishing as binary on MQTT topic '{}', with size {}",
+            path,
+            payload.len()
+        );
+        self.send(topic, path, payload)
+    }
+
+    pub fn subscribe<F>(&self, topic: &HermesTopic, handler: F) -> HermesResult<SubscriptionHandle>
     where
-        F: Fn() -> () + Send + Sync + 'static,
+        F: Fn(Option<&MessageMeta>) -> () + Send + Sync + 'static,
     {
         let log_level = Self::log_level(topic);
+        let metrics = Arc::clone(&self.metrics);
+        let pattern = topic.pattern();
         self.inner_subscribe(topic, move |m| {
             log!(log_level, "Received a message on MQTT topic '{:?}'", m.topic_name);
-            handler()
+            let metrics = current_metrics(&metrics);
+            if let Some(metrics) = &metrics {
+                metrics.received(&pattern, &m.payload)
+            }
+            let meta = Self::decode_meta(&m.payload);
+            let context = TraceContext::of_json(&m.payload);
+            deliver(metrics.as_deref(), &pattern, &m.topic_name, context, || handler(meta.as_ref()))
         })
     }
 
-    pub fn subscribe_payload<F, P>(&self, topic: &HermesTopic, handler: F) -> Fallible<()>
+    pub fn subscribe_payload<F, P>(&self, topic: &HermesTopic, handler: F) -> HermesResult<SubscriptionHandle>
+    where
+        F: Fn(&P, Option<&MessageMeta>) -> () + Send + Sync + 'static,
+        P: serde::de::DeserializeOwned + serde::Serialize + Validate + Debug,
+    {
+        self.subscribe_payload_from(topic, move |_, p, meta| handler(p, meta))
+    }
+
+    /// Subscribe to the JSON messages of `topic`, the handler is also given the path of the topic
+    /// each one came from
+    pub fn subscribe_payload_from<F, P>(&self, topic: &HermesTopic, handler: F) -> HermesResult<SubscriptionHandle>
     where
-        F: Fn(&P) -> () + Send + Sync + 'static,
-        P: serde::de::DeserializeOwned,
+        F: Fn(&str, &P, Option<&MessageMeta>) -> () + Send + Sync + 'static,
+        P: serde::de::DeserializeOwned + serde::Serialize + Validate + Debug,
     {
         let log_level = Self::log_level(topic);
+        let validation = Arc::clone(&self.validation);
+        let policy = Arc::clone(&self.decode_policy);
+        let metrics = Arc::clone(&self.metrics);
+        let decode_errors = Arc::clone(&self.decode_errors);
+        let pattern = topic.pattern();
         self.inner_subscribe(topic, move |m| {
             log!(
                 log_level,
@@ -117,19 +365,46 @@ impl MqttHandler {
                 }
             );
             trace!("Payload: {}", String::from_utf8_lossy(&m.payload));
-            let r = serde_json::from_slice(m.payload.as_slice());
+            let metrics = current_metrics(&metrics);
+            if let Some(metrics) = &metrics {
+                metrics.received(&pattern, &m.payload)
+            }
+            let r = decode_policy(&policy).decode::<P>(m.payload.as_slice());
             match r {
-                Ok(p) => handler(&p),
-                Err(e) => warn!("Error while decoding object on topic {:?}: {}", m.topic_name, e),
+                Ok(Decoded { message: p, defaulted }) => {
+                    if !defaulted.is_empty() {
+                        warn!(
+                            "Message on topic {:?} lacks the fields {}, its publisher may need an upgrade",
+                            m.topic_name,
+                            defaulted.join(", ")
+                        );
+                        if let Some(metrics) = &metrics {
+                            for field in &defaulted {
+                                metrics.defaulted(&pattern, field)
+                            }
+                        }
+                    }
+                    if validation_mode(&validation).accepts_incoming(&p) {
+                        let meta = Self::decode_meta(&m.payload);
+                        let context = TraceContext::of_json(&m.payload);
+                        deliver(metrics.as_deref(), &pattern, &m.topic_name, context, || {
+                            handler(&m.topic_name, &p, meta.as_ref())
+                        })
+                    }
+                }
+                Err(e) => report_decode_error(&decode_errors, metrics.as_deref(), &pattern, m, e.to_string()),
             }
         })
     }
 
-    pub fn subscribe_binary_payload<F>(&self, topic: &HermesTopic, handler: F) -> Fallible<()>
+    pub fn subscribe_binary_payload<F>(&self, topic: &HermesTopic, handler: F) -> HermesResult<SubscriptionHandle>
     where
-        F: Fn(&HermesTopic, &[u8]) -> () + Send + Sync + 'static,
+        F: Fn(&HermesTopic, &[u8]) -> Result<(), String> + Send + Sync + 'static,
     {
         let log_level = Self::log_level(topic);
+        let metrics = Arc::clone(&self.metrics);
+        let decode_errors = Arc::clone(&self.decode_errors);
+        let pattern = topic.pattern();
         self.inner_subscribe(topic, move |m| {
             log!(
                 log_level,
@@ -146,24 +421,109 @@ impl MqttHandler {
                 }
             );
             trace!("Payload: {}", String::from_utf8_lossy(&m.payload));
+            let metrics = current_metrics(&metrics);
+            if let Some(metrics) = &metrics {
+                metrics.received(&pattern, &m.payload)
+            }
             let topic = HermesTopic::from_path(&m.topic_name);
-            if let Some(topic) = topic {
-                handler(&topic, &m.payload)
-            } else {
-                error!("could not parse topic: {:?}", m.topic_name)
+            let decoded = match topic {
+                Some(topic) => {
+                    let context = topic_context(&topic);
+                    deliver(metrics.as_deref(), &pattern, &m.topic_name, context, || handler(&topic, &m.payload))
+                }
+                None => Err("could not parse the topic".to_string()),
+            };
+            if let Err(e) = decoded {
+                report_decode_error(&decode_errors, metrics.as_deref(), &pattern, m, e)
             }
         })
     }
 
-    fn inner_subscribe<F>(&self, topic: &HermesTopic, callback: F) -> Fallible<()>
+    fn inner_subscribe<F>(&self, topic: &HermesTopic, callback: F) -> HermesResult<SubscriptionHandle>
     where
         F: Fn(&::rumqtt::Publish) -> () + Send + Sync + 'static,
     {
-        self.mqtt_client
-            .subscribe(topic.to_string(), Box::new(callback))
-            .map_err(SyncFailure::new)?
-            .send()
-            .map_err(SyncFailure::new)?;
+        self.subscribe_filter(topic.to_string(), callback)
+    }
+
+    /// Subscribe to an MQTT topic filter, which may match more than one hermes topic
+    fn subscribe_filter<F>(&self, topic: String, callback: F) -> HermesResult<SubscriptionHandle>
+    where
+        F: Fn(&::rumqtt::Publish) -> () + Send + Sync + 'static,
+    {
+        let id = self.subscription_counter.fetch_add(1, Ordering::Relaxed);
+        let mut subscriptions = self.subscriptions.lock().map_err(PoisonLock::from)?;
+        if let Some(subscription) = subscriptions.get(&topic) {
+            subscription
+                .callbacks
+                .lock()
+                .map_err(PoisonLock::from)?
+                .push((id, Arc::new(callback)));
+        } else {
+            let subscription = TopicSubscription::default();
+            subscription
+                .callbacks
+                .lock()
+                .map_err(PoisonLock::from)?
+                .push((id, Arc::new(callback)));
+            let callbacks = Arc::clone(&subscription.callbacks);
+            self.mqtt_client
+                .subscribe(
+                    topic.clone(),
+                    Box::new(move |m: &::rumqtt::Publish| {
+                        // don't hold the lock while running the callbacks, they may want to
+                        // (un)subscribe
+                        let callbacks: Vec<MqttCallback> = match callbacks.lock() {
+                            Ok(callbacks) => callbacks.iter().map(|(_, it)| Arc::clone(it)).collect(),
+                            Err(_) => {
+                                error!("could not lock callbacks of topic {:?}", m.topic_name);
+                                return;
+                            }
+                        };
+                        for callback in callbacks {
+                            callback(m)
+                        }
+                    }),
+                )
+                .map_err(transport_error)?
+                .send()
+                .map_err(transport_error)?;
+            subscriptions.insert(topic.clone(), subscription);
+        }
+        drop(subscriptions);
+
+        let mqtt_client = Arc::downgrade(&self.mqtt_client);
+        let subscriptions = Arc::downgrade(&self.subscriptions);
+        Ok(SubscriptionHandle::new(move || {
+            Self::inner_unsubscribe(&mqtt_client, &subscriptions, &topic, id)
+        }))
+    }
+
+    fn inner_unsubscribe(
+        mqtt_client: &Weak<rumqtt::MqttClient>,
+        subscriptions: &Weak<Mutex<HashMap<String, TopicSubscription>>>,
+        topic: &str,
+        id: usize,
+    ) -> HermesResult<()> {
+        let (mqtt_client, subscriptions) = match (mqtt_client.upgrade(), subscriptions.upgrade()) {
+            (Some(mqtt_client), Some(subscriptions)) => (mqtt_client, subscriptions),
+            // the handler is gone and took all its subscriptions with it
+            _ => return Ok(()),
+        };
+        let mut subscriptions = subscriptions.lock().map_err(PoisonLock::from)?;
+        let is_unused = match subscriptions.get(topic) {
+            Some(subscription) => {
+                let mut callbacks = subscription.callbacks.lock().map_err(PoisonLock::from)?;
+                callbacks.retain(|(callback_id, _)| *callback_id != id);
+                callbacks.is_empty()
+            }
+            None => false,
+        };
+        if is_unused {
+            debug!("No more callbacks on MQTT topic '{}', unsubscribing", topic);
+            subscriptions.remove(topic);
+            mqtt_client.unsubscribe(topic.to_string()).map_err(transport_error)?;
+        }
         Ok(())
     }
 
@@ -182,80 +542,149 @@ pub struct MqttHermesProtocolHandler {
 
 impl MqttHermesProtocolHandler {
     #[allow(clippy::new_ret_no_self)]
-    pub fn new(broker_address: &str) -> Fallible<MqttHermesProtocolHandler> {
+    pub fn new(broker_address: &str) -> HermesResult<MqttHermesProtocolHandler> {
         let id = get_mqtt_id();
         let client_options = rumqtt::MqttOptions::new(id, broker_address);
         Self::new_with_options(client_options)
     }
 
-    pub fn new_with_options(mut options: rumqtt::MqttOptions) -> Fallible<MqttHermesProtocolHandler> {
+    pub fn new_with_options(options: rumqtt::MqttOptions) -> HermesResult<MqttHermesProtocolHandler> {
+        Self::start(options, None)
+    }
+
+    /// Create a handler whose JSON messages carry a `MessageMeta` telling they come from `origin`
+    pub fn new_with_origin(
+        options: rumqtt::MqttOptions,
+        origin: MessageOrigin,
+    ) -> HermesResult<MqttHermesProtocolHandler> {
+        Self::start(options, Some(origin))
+    }
+
+    fn start(
+        mut options: rumqtt::MqttOptions,
+        origin: Option<MessageOrigin>,
+    ) -> HermesResult<MqttHermesProtocolHandler> {
         let name = options.broker_addr.clone();
         options.max_packet_size = 10_000_000;
         let mqtt_client = rumqtt::MqttClient::start(options)
-            .map_err(SyncFailure::new)
-            .with_context(|_| format_err!("Could not start MQTT client on {}", name))?;
-
-        let mqtt_handler = Arc::new(MqttHandler { mqtt_client });
+            .map_err(|e| HermesError::transport(format!("Could not start MQTT client on {}: {}", name, e)))?;
+
+        let mqtt_handler = Arc::new(MqttHandler {
+            mqtt_client: Arc::new(mqtt_client),
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            subscription_counter: AtomicUsize::new(0),
+            origin,
+            validation: Arc::new(RwLock::new(ValidationMode::default())),
+            decode_policy: Arc::new(RwLock::new(DecodePolicy::default())),
+            stream_sequence: Mutex::new(StreamBytesSequence::new()),
+            metrics: Arc::new(RwLock::new(None)),
+            decode_errors: Arc::new(Mutex::new(vec![])),
+        });
 
         Ok(MqttHermesProtocolHandler { name, mqtt_handler })
     }
+
+    /// Check the messages published and received from now on, see `ValidationMode`
+    pub fn set_validation_mode(&self, mode: ValidationMode) -> HermesResult<()> {
+        *self.mqtt_handler.validation.write().map_err(PoisonLock::from)? = mode;
+        Ok(())
+    }
+
+    /// Decode the JSON messages received from now on following `policy`, see `DecodePolicy`
+    pub fn set_decode_policy(&self, policy: DecodePolicy) -> HermesResult<()> {
+        *self.mqtt_handler.decode_policy.write().map_err(PoisonLock::from)? = policy;
+        Ok(())
+    }
+
+    /// Report the metrics of the traffic of this handler to `sink` from now on, see `Metrics`
+    pub fn set_metrics_sink(&self, sink: Arc<dyn MetricsSink>) -> HermesResult<()> {
+        *self.mqtt_handler.metrics.write().map_err(PoisonLock::from)? = Some(Arc::new(Metrics::new(sink)));
+        Ok(())
+    }
+
+    /// Subscribe to every hermes message going through the broker, with its raw payload. The
+    /// messages are neither decoded nor validated
+    pub fn subscribe_traffic<F>(&self, handler: F) -> HermesResult<SubscriptionHandle>
+    where
+        F: Fn(&HermesTopic, &[u8]) -> () + Send + Sync + 'static,
+    {
+        self.mqtt_handler
+            .subscribe_filter("hermes/#".to_string(), move |m| match HermesTopic::from_path(&m.topic_name) {
+                Some(topic) => handler(&topic, m.payload.as_slice()),
+                None => debug!("Ignoring the message on unknown topic '{}'", m.topic_name),
+            })
+    }
 }
 
 macro_rules! s {
     ($n:ident<$t:ty> $topic:expr; ) => {
-        fn $n(&self, handler: Callback<$t>) -> Fallible<()> {
-            self.mqtt_handler.subscribe_payload($topic, move |p| handler.call(p))
+        fn $n(&self, handler: Callback<$t>) -> HermesResult<SubscriptionHandle> {
+            self.mqtt_handler.subscribe_payload($topic, move |p, meta| handler.call_with_meta(p, meta))
         }
     };
 
     ($n:ident<$t:ty>($($a:ident: $ta:ty),*) $topic:block) => {
-        fn $n(&self, $($a: $ta),*, handler: Callback<$t>) -> Fallible<()> {
-            self.mqtt_handler.subscribe_payload($topic, move |p| handler.call(p))
+        fn $n(&self, $($a: $ta),*, handler: Callback<$t>) -> HermesResult<SubscriptionHandle> {
+            self.mqtt_handler.subscribe_payload($topic, move |p, meta| handler.call_with_meta(p, meta))
         }
     };
 
     ($n:ident $topic:expr; ) => {
-        fn $n(&self, handler: Callback0) -> Fallible<()> {
-            self.mqtt_handler.subscribe($topic, move || handler.call())
+        fn $n(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+            self.mqtt_handler.subscribe($topic, move |meta| handler.call_with_meta(meta))
         }
     };
 }
 
 macro_rules! s_bin {
     ($n:ident<$t:ty> $topic:block |$rt:ident, $p:ident| $decoder:block) => {
-        fn $n(&self, handler: Callback<$t>) -> Fallible<()> {
-            self.mqtt_handler.subscribe_binary_payload($topic, move |$rt, $p| handler.call(&$decoder))
+        fn $n(&self, handler: Callback<$t>) -> HermesResult<SubscriptionHandle> {
+            let validation = Arc::clone(&self.mqtt_handler.validation);
+            self.mqtt_handler.subscribe_binary_payload($topic, move |$rt, $p| {
+                let message = $decoder?;
+                if validation_mode(&validation).accepts_incoming(&message) {
+                    handler.call(&message)
+                }
+                Ok(())
+            })
         }
     };
 
     ($n:ident<$t:ty>($($a:ident: $ta:ty),*) $topic:block |$rt:ident, $p:ident| $decoder:block) => {
-        fn $n(&self, $($a: $ta),*, handler: Callback<$t>) -> Fallible<()> {
-            self.mqtt_handler.subscribe_binary_payload($topic, move |$rt, $p| handler.call(&$decoder))
+        fn $n(&self, $($a: $ta),*, handler: Callback<$t>) -> HermesResult<SubscriptionHandle> {
+            let validation = Arc::clone(&self.mqtt_handler.validation);
+            self.mqtt_handler.subscribe_binary_payload($topic, move |$rt, $p| {
+                let message = $decoder?;
+                if validation_mode(&validation).accepts_incoming(&message) {
+                    handler.call(&message)
+                }
+                Ok(())
+            })
         }
     };
 }
 
 macro_rules! p {
     ($n:ident<$t:ty> $topic:expr; ) => {
-        fn $n(&self, payload: $t) -> Fallible<()> {
+        fn $n(&self, payload: $t) -> HermesResult<()> {
             self.mqtt_handler.publish_payload($topic, payload)
         }
     };
 
     ($n:ident<$t:ty>($param1:ident: $t1:ty) $topic:block ) => {
-        fn $n(&self, $param1: $t1, payload: $t) -> Fallible<()> {
+        fn $n(&self, $param1: $t1, payload: $t) -> HermesResult<()> {
             self.mqtt_handler.publish_payload($topic, payload)
         }
     };
 
     ($n:ident($payload:ident: $t:ty) $topic:block ) => {
-        fn $n(&self, $payload: $t) -> Fallible<()> {
+        fn $n(&self, $payload: $t) -> HermesResult<()> {
             self.mqtt_handler.publish_payload($topic, $payload)
         }
     };
 
     ($n:ident $topic:expr; ) => {
-        fn $n(&self) -> Fallible<()> {
+        fn $n(&self) -> HermesResult<()> {
             self.mqtt_handler.publish($topic)
         }
     };
@@ -263,7 +692,8 @@ macro_rules! p {
 
 macro_rules! p_bin {
     ($n:ident($payload:ident: $t:ty) $topic:block $bytes:block ) => {
-        fn $n(&self, $payload: $t) -> Fallible<()> {
+        fn $n(&self, $payload: $t) -> HermesResult<()> {
+            validation_mode(&self.mqtt_handler.validation).check_outgoing(&$payload)?;
             self.mqtt_handler.publish_binary_payload($topic, $bytes)
         }
     };
@@ -274,7 +704,7 @@ macro_rules! impl_component_facades_for {
     // to get the component... I'm sad...
     ($t:ty) => {
         impl ComponentFacade for $t {
-            fn publish_version_request(&self) -> Fallible<()> {
+            fn publish_version_request(&self) -> HermesResult<()> {
                 self.mqtt_handler.publish(&HermesTopic::Component(
                     None,
                     self.component,
@@ -282,51 +712,51 @@ macro_rules! impl_component_facades_for {
                 ))
             }
 
-            fn subscribe_version(&self, handler: Callback<VersionMessage>) -> Fallible<()> {
+            fn subscribe_version(&self, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Version),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
-            fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> Fallible<()> {
+            fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Error),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
-            fn subscribe_component_loaded(&self, handler: Callback<ComponentLoadedMessage>) -> Fallible<()> {
+            fn subscribe_component_loaded(&self, handler: Callback<ComponentLoadedMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Loaded),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
         }
 
         impl ComponentBackendFacade for $t {
-            fn subscribe_version_request(&self, handler: Callback0) -> Fallible<()> {
+            fn subscribe_version_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe(
                     &HermesTopic::Component(None, self.component, ComponentCommand::VersionRequest),
-                    move || handler.call(),
+                    move |meta| handler.call_with_meta(meta),
                 )
             }
 
-            fn publish_version(&self, version: VersionMessage) -> Fallible<()> {
+            fn publish_version(&self, version: VersionMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Version),
                     version,
                 )
             }
 
-            fn publish_error(&self, error: ErrorMessage) -> Fallible<()> {
+            fn publish_error(&self, error: ErrorMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Error),
                     error,
                 )
             }
 
-            fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> Fallible<()> {
+            fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(None, self.component, ComponentCommand::Loaded),
                     component_loaded,
@@ -341,24 +771,24 @@ macro_rules! impl_toggleable_facades_for {
     // to get the toggle on/off topics... I'm sad...
     ($t:ty) => {
         impl ToggleableFacade for $t {
-            fn publish_toggle_on(&self) -> Fallible<()> {
+            fn publish_toggle_on(&self) -> HermesResult<()> {
                 self.mqtt_handler.publish(&self.toggle_on_topic)
             }
 
-            fn publish_toggle_off(&self) -> Fallible<()> {
+            fn publish_toggle_off(&self) -> HermesResult<()> {
                 self.mqtt_handler.publish(&self.toggle_off_topic)
             }
         }
 
         impl ToggleableBackendFacade for $t {
-            fn subscribe_toggle_on(&self, handler: Callback0) -> Fallible<()> {
+            fn subscribe_toggle_on(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler
-                    .subscribe(&self.toggle_on_topic, move || handler.call())
+                    .subscribe(&self.toggle_on_topic, move |meta| handler.call_with_meta(meta))
             }
 
-            fn subscribe_toggle_off(&self, handler: Callback0) -> Fallible<()> {
+            fn subscribe_toggle_off(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler
-                    .subscribe(&self.toggle_off_topic, move || handler.call())
+                    .subscribe(&self.toggle_off_topic, move |meta| handler.call_with_meta(meta))
             }
         }
     };
@@ -367,24 +797,24 @@ macro_rules! impl_toggleable_facades_for {
 macro_rules! impl_identifiable_toggleable_facades_for {
     ($t:ty) => {
         impl IdentifiableToggleableFacade for $t {
-            fn publish_toggle_on(&self, site: SiteMessage) -> Fallible<()> {
+            fn publish_toggle_on(&self, site: SiteMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(&self.toggle_on_topic, site)
             }
 
-            fn publish_toggle_off(&self, site: SiteMessage) -> Fallible<()> {
+            fn publish_toggle_off(&self, site: SiteMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(&self.toggle_off_topic, site)
             }
         }
 
         impl IdentifiableToggleableBackendFacade for $t {
-            fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> Fallible<()> {
+            fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler
-                    .subscribe_payload(&self.toggle_on_topic, move |p| handler.call(p))
+                    .subscribe_payload(&self.toggle_on_topic, move |p, meta| handler.call_with_meta(p, meta))
             }
 
-            fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> Fallible<()> {
+            fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler
-                    .subscribe_payload(&self.toggle_off_topic, move |p| handler.call(p))
+                    .subscribe_payload(&self.toggle_off_topic, move |p, meta| handler.call_with_meta(p, meta))
             }
         }
     };
@@ -393,7 +823,7 @@ macro_rules! impl_identifiable_toggleable_facades_for {
 macro_rules! impl_identifiable_component_facades_for {
     ($t:ty) => {
         impl IdentifiableComponentFacade for $t {
-            fn publish_version_request(&self, site_id: String) -> Fallible<()> {
+            fn publish_version_request(&self, site_id: String) -> HermesResult<()> {
                 self.mqtt_handler.publish(&HermesTopic::Component(
                     Some(site_id),
                     self.component,
@@ -401,24 +831,24 @@ macro_rules! impl_identifiable_component_facades_for {
                 ))
             }
 
-            fn subscribe_version(&self, site_id: String, handler: Callback<VersionMessage>) -> Fallible<()> {
+            fn subscribe_version(&self, site_id: String, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Version),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
-            fn subscribe_error(&self, site_id: String, handler: Callback<SiteErrorMessage>) -> Fallible<()> {
+            fn subscribe_error(&self, site_id: String, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Error),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
-            fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> Fallible<()> {
+            fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(Some("+".to_string()), self.component, ComponentCommand::Error),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
@@ -426,44 +856,44 @@ macro_rules! impl_identifiable_component_facades_for {
                 &self,
                 site_id: String,
                 handler: Callback<ComponentLoadedOnSiteMessage>,
-            ) -> Fallible<()> {
+            ) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Loaded),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
 
-            fn subscribe_all_component_loaded(&self, handler: Callback<ComponentLoadedOnSiteMessage>) -> Fallible<()> {
+            fn subscribe_all_component_loaded(&self, handler: Callback<ComponentLoadedOnSiteMessage>) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe_payload(
                     &HermesTopic::Component(Some("+".to_string()), self.component, ComponentCommand::Loaded),
-                    move |p| handler.call(p),
+                    move |p, meta| handler.call_with_meta(p, meta),
                 )
             }
         }
 
         impl IdentifiableComponentBackendFacade for $t {
-            fn subscribe_version_request(&self, site_id: String, handler: Callback0) -> Fallible<()> {
+            fn subscribe_version_request(&self, site_id: String, handler: Callback0) -> HermesResult<SubscriptionHandle> {
                 self.mqtt_handler.subscribe(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::VersionRequest),
-                    move || handler.call(),
+                    move |meta| handler.call_with_meta(meta),
                 )
             }
 
-            fn publish_version(&self, site_id: String, version: VersionMessage) -> Fallible<()> {
+            fn publish_version(&self, site_id: String, version: VersionMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Version),
                     version,
                 )
             }
 
-            fn publish_error(&self, site_id: String, error: SiteErrorMessage) -> Fallible<()> {
+            fn publish_error(&self, site_id: String, error: SiteErrorMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Error),
                     error,
                 )
             }
 
-            fn publish_component_loaded(&self, site_id: String, loaded: ComponentLoadedOnSiteMessage) -> Fallible<()> {
+            fn publish_component_loaded(&self, site_id: String, loaded: ComponentLoadedOnSiteMessage) -> HermesResult<()> {
                 self.mqtt_handler.publish_payload(
                     &HermesTopic::Component(Some(site_id), self.component, ComponentCommand::Loaded),
                     loaded,
@@ -516,6 +946,19 @@ impl VoiceActivityBackendFacade for MqttComponentFacade {
 impl HotwordFacade for MqttToggleableComponentFacade {
     s!(subscribe_detected<HotwordDetectedMessage>(site_id: String) { &HermesTopic::Hotword(Some(site_id), HotwordCommand::Detected) });
     s!(subscribe_all_detected<HotwordDetectedMessage> &HermesTopic::Hotword(Some("+".into()), HotwordCommand::Detected););
+
+    fn subscribe_all_detected_with_id(
+        &self,
+        handler: Callback<(String, HotwordDetectedMessage)>,
+    ) -> HermesResult<SubscriptionHandle> {
+        let topic = HermesTopic::Hotword(Some("+".into()), HotwordCommand::Detected);
+        self.mqtt_handler
+            .subscribe_payload_from(&topic, move |path, detected: &HotwordDetectedMessage, meta| {
+                if let Some(HermesTopic::Hotword(Some(id), _)) = HermesTopic::from_path(path) {
+                    handler.call_with_meta(&(id, detected.clone()), meta)
+                }
+            })
+    }
 }
 
 impl HotwordBackendFacade for MqttToggleableComponentFacade {
@@ -557,7 +1000,7 @@ impl TtsBackendFacade for MqttComponentFacade {
     s_bin!(subscribe_register_sound<RegisterSoundMessage> { &HermesTopic::Tts(TtsCommand::RegisterSound("#".into())) }
         |topic, bytes| {
             if let HermesTopic::Tts(TtsCommand::RegisterSound(ref sound_id)) = *topic {
-                RegisterSoundMessage { sound_id: sound_id.to_owned(), wav_sound: bytes.into() }
+                Ok(RegisterSoundMessage { sound_id: sound_id.to_owned(), wav_sound: bytes.into() })
             } else {
                 unreachable!();
             }
@@ -587,7 +1030,7 @@ impl AudioServerFacade for MqttToggleableComponentFacade {
     s_bin!(subscribe_audio_frame<AudioFrameMessage>(site_id: String) { &HermesTopic::AudioServer(Some(site_id), AudioServerCommand::AudioFrame) }
             |topic, bytes| {
                 if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::AudioFrame) = *topic {
-                    AudioFrameMessage { site_id: site_id.to_owned(), wav_frame: bytes.into() }
+                    Ok(AudioFrameMessage { site_id: site_id.to_owned(), wav_frame: bytes.into() })
                 } else {
                     unreachable!()
                 }
@@ -596,7 +1039,7 @@ impl AudioServerFacade for MqttToggleableComponentFacade {
     s_bin!(subscribe_replay_response<AudioFrameMessage>(site_id: String) { &HermesTopic::AudioServer(Some(site_id), AudioServerCommand::ReplayResponse) }
             |topic, bytes| {
                 if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::ReplayResponse) = *topic {
-                    AudioFrameMessage { site_id: site_id.to_owned(), wav_frame: bytes.into() }
+                    Ok(AudioFrameMessage { site_id: site_id.to_owned(), wav_frame: bytes.into() })
                 } else {
                     unreachable!()
                 }
@@ -606,20 +1049,75 @@ impl AudioServerFacade for MqttToggleableComponentFacade {
         { bytes.wav_bytes });
     s!(subscribe_play_finished<PlayFinishedMessage>(site_id: String) { &HermesTopic::AudioServer(Some(site_id), AudioServerCommand::PlayFinished) });
     s!(subscribe_all_play_finished<PlayFinishedMessage> &HermesTopic::AudioServer(Some("+".into()), AudioServerCommand::PlayFinished););
-    p_bin!(publish_stream_bytes(stream_bytes_message: StreamBytesMessage)
-       {
-           &HermesTopic::AudioServer(
-               Some(stream_bytes_message.site_id.clone()),
-               AudioServerCommand::StreamBytes {
-                   stream_id: stream_bytes_message.stream_id.clone(),
-                   chunk_number: stream_bytes_message.chunk_number.to_string(),
-                   is_last_chunk: if stream_bytes_message.is_last_chunk { "1".to_string() } else { "0".to_string() }})
-       }
-       { stream_bytes_message.bytes });
+    fn publish_stream_bytes(&self, stream_bytes_message: StreamBytesMessage) -> HermesResult<()> {
+        // held while publishing, so that the chunks are published in the order they were checked
+        let mut sequence = self.mqtt_handler.stream_sequence.lock().map_err(PoisonLock::from)?;
+        validation_mode(&self.mqtt_handler.validation).check_outgoing_chunk(&mut sequence, &stream_bytes_message)?;
+        let topic = HermesTopic::AudioServer(
+            Some(stream_bytes_message.site_id.clone()),
+            AudioServerCommand::StreamBytes {
+                stream_id: stream_bytes_message.stream_id.clone(),
+                chunk_number: stream_bytes_message.chunk_number.to_string(),
+                is_last_chunk: if stream_bytes_message.is_last_chunk { "1" } else { "0" }.to_string(),
+            },
+        );
+        self.mqtt_handler
+            .publish_binary_payload(&topic, stream_bytes_message.bytes)
+    }
     s!(subscribe_stream_finished<StreamFinishedMessage>(site_id: String) { &HermesTopic::AudioServer(Some(site_id), AudioServerCommand::StreamFinished) });
     s!(subscribe_all_stream_finished<StreamFinishedMessage> &HermesTopic::AudioServer(Some("+".into()), AudioServerCommand::StreamFinished););
 }
 
+impl MqttToggleableComponentFacade {
+    /// Subscribe to the chunks of the audio streams of a site, `+` for all of them. Every
+    /// subscription gets all the chunks, so each checks their order on its own
+    fn subscribe_stream_bytes_of(
+        &self,
+        site_id: String,
+        handler: Callback<StreamBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
+        let validation = Arc::clone(&self.mqtt_handler.validation);
+        let sequence = Mutex::new(StreamBytesSequence::new());
+        let topic = HermesTopic::AudioServer(
+            Some(site_id),
+            AudioServerCommand::StreamBytes {
+                stream_id: "+".into(),
+                chunk_number: "+".into(),
+                is_last_chunk: "+".into(),
+            },
+        );
+        self.mqtt_handler.subscribe_binary_payload(&topic, move |topic, bytes| {
+            let message = if let HermesTopic::AudioServer(
+                Some(ref site_id),
+                AudioServerCommand::StreamBytes {
+                    ref stream_id,
+                    ref chunk_number,
+                    ref is_last_chunk,
+                },
+            ) = *topic
+            {
+                StreamBytesMessage {
+                    site_id: site_id.to_owned(),
+                    stream_id: stream_id.to_owned(),
+                    chunk_number: parse_chunk_number(chunk_number)?,
+                    is_last_chunk: is_last_chunk != "0",
+                    bytes: bytes.into(),
+                }
+            } else {
+                unreachable!()
+            };
+            let accepted = {
+                let mut sequence = sequence.lock().unwrap_or_else(PoisonError::into_inner);
+                validation_mode(&validation).accepts_incoming_chunk(&mut sequence, &message)
+            };
+            if accepted {
+                handler.call(&message)
+            }
+            Ok(())
+        })
+    }
+}
+
 impl AudioServerBackendFacade for MqttToggleableComponentFacade {
     p_bin!(publish_audio_frame(frame: AudioFrameMessage)
         { &HermesTopic::AudioServer(Some(frame.site_id), AudioServerCommand::AudioFrame) }
@@ -631,7 +1129,7 @@ impl AudioServerBackendFacade for MqttToggleableComponentFacade {
     s_bin!(subscribe_all_play_bytes<PlayBytesMessage> { &HermesTopic::AudioServer(Some("+".into()), AudioServerCommand::PlayBytes("#".into())) }
             |topic, bytes| {
                 if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::PlayBytes(ref request_id)) = *topic {
-                    PlayBytesMessage { site_id: site_id.to_owned(), id: request_id.to_owned(), wav_bytes: bytes.into() }
+                    Ok(PlayBytesMessage { site_id: site_id.to_owned(), id: request_id.to_owned(), wav_bytes: bytes.into() })
                 } else {
                     unreachable!()
                 }
@@ -639,52 +1137,23 @@ impl AudioServerBackendFacade for MqttToggleableComponentFacade {
     s_bin!(subscribe_play_bytes<PlayBytesMessage>(site_id: String) { &HermesTopic::AudioServer(Some(site_id), AudioServerCommand::PlayBytes("#".into())) }
             |topic, bytes| {
                 if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::PlayBytes(ref request_id)) = *topic {
-                    PlayBytesMessage { site_id: site_id.to_owned(), id: request_id.to_owned(), wav_bytes: bytes.into() }
+                    Ok(PlayBytesMessage { site_id: site_id.to_owned(), id: request_id.to_owned(), wav_bytes: bytes.into() })
                 } else {
                     unreachable!()
                 }
             });
     p!(publish_play_finished(message: PlayFinishedMessage) { &HermesTopic::AudioServer(Some(message.site_id.clone()), AudioServerCommand::PlayFinished) });
-    s_bin!(subscribe_stream_bytes<StreamBytesMessage>(site_id: String)
-        {
-            &HermesTopic::AudioServer(
-                Some(site_id),
-                AudioServerCommand::StreamBytes {
-                    stream_id:"+".into(),
-                    chunk_number:"+".into(),
-                    is_last_chunk:"+".into()
-                }
-            )
-        }
-        |topic, bytes| {
-            if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::StreamBytes {ref stream_id, ref chunk_number, ref is_last_chunk}) = *topic {
-                StreamBytesMessage {
-                    site_id: site_id.to_owned(),
-                    stream_id: stream_id.to_owned(),
-                    chunk_number: chunk_number.parse()
-                        .expect("chunk_nbr is supposed to be properly formatted"),
-                    is_last_chunk: is_last_chunk == "1",
-                    bytes: bytes.into()
-                }
-            } else {
-                unreachable!()
-            }
-            });
-    s_bin!(subscribe_all_stream_bytes<StreamBytesMessage> { &HermesTopic::AudioServer(Some("+".into()), AudioServerCommand::StreamBytes{stream_id:"+".into(), chunk_number:"+".into(), is_last_chunk:"+".into()}) }
-           |topic, bytes| {
-                if let HermesTopic::AudioServer(Some(ref site_id), AudioServerCommand::StreamBytes{ref stream_id, ref chunk_number, ref is_last_chunk}) = *topic {
-                    StreamBytesMessage {
-                        site_id: site_id.to_owned(),
-                        stream_id: stream_id.to_owned(),
-                        chunk_number: chunk_number.parse()
-                            .expect("chunk_nbr is supposed to be properly formatted"),
-                        is_last_chunk: is_last_chunk != "0",
-                        bytes: bytes.into()
-                    }
-                } else {
-                    unreachable!()
-                }
-           });
+    fn subscribe_stream_bytes(
+        &self,
+        site_id: String,
+        handler: Callback<StreamBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle> {
+        self.subscribe_stream_bytes_of(site_id, handler)
+    }
+
+    fn subscribe_all_stream_bytes(&self, handler: Callback<StreamBytesMessage>) -> HermesResult<SubscriptionHandle> {
+        self.subscribe_stream_bytes_of("+".into(), handler)
+    }
     p!(publish_stream_finished(message: StreamFinishedMessage) { &HermesTopic::AudioServer(Some(message.site_id.clone()), AudioServerCommand::StreamFinished) });
 }
 
@@ -856,6 +1325,25 @@ impl HermesProtocolHandler for MqttHermesProtocolHandler {
     fn injection_backend(&self) -> Box<dyn InjectionBackendFacade> {
         self.component(Component::Injection)
     }
+
+    fn subscribe_decode_errors(&self, handler: Callback<DecodeError>) -> HermesResult<SubscriptionHandle> {
+        let id = self.mqtt_handler.subscription_counter.fetch_add(1, Ordering::Relaxed);
+        self.mqtt_handler
+            .decode_errors
+            .lock()
+            .map_err(PoisonLock::from)?
+            .push((id, Arc::new(handler)));
+        let decode_errors = Arc::downgrade(&self.mqtt_handler.decode_errors);
+        Ok(SubscriptionHandle::new(move || {
+            if let Some(decode_errors) = decode_errors.upgrade() {
+                decode_errors
+                    .lock()
+                    .map_err(PoisonLock::from)?
+                    .retain(|(callback_id, _)| *callback_id != id);
+            }
+            Ok(())
+        }))
+    }
 }
 
 impl std::fmt::Display for MqttHermesProtocolHandler {
@@ -952,4 +1440,154 @@ mod tests {
     // sleep 50ms between registering the callback and sending the message to be "sure" the event
     // arrive in the right order to the mosquitto server
     hermes_test_suite::test_suite!(WAIT_DURATION = 200);
+
+    #[test]
+    fn undecodable_messages_are_reported() {
+        let (handler_source, handler_receiver) = create_handlers();
+        let errors = receiver(4, Overflow::Block, |cb| handler_receiver.subscribe_decode_errors(cb)).unwrap();
+        let _say = handler_receiver
+            .tts_backend()
+            .subscribe_say(Callback::new(|_: &SayMessage| {}))
+            .unwrap();
+        let _stream = handler_receiver
+            .audio_server_backend()
+            .subscribe_all_stream_bytes(Callback::new(|_: &StreamBytesMessage| {}))
+            .unwrap();
+        sleep(Duration::from_millis(200));
+
+        let stream_bytes = HermesTopic::AudioServer(
+            Some("default".into()),
+            AudioServerCommand::StreamBytes {
+                stream_id: "stream".into(),
+                chunk_number: "first".into(),
+                is_last_chunk: "0".into(),
+            },
+        );
+        handler_source
+            .mqtt_handler
+            .publish_binary_payload(&stream_bytes, vec![1, 2, 3])
+            .unwrap();
+        let error = errors.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(error.topic, "hermes/audioServer/default/playBytesStreaming/stream/first/0");
+        assert_eq!(error.payload, vec![1, 2, 3]);
+        assert!(error.error.contains("chunk number"));
+
+        handler_source
+            .mqtt_handler
+            .send_payload(&HermesTopic::Tts(TtsCommand::Say), "hello")
+            .unwrap();
+        let error = errors.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(error.topic, "hermes/tts/say");
+        assert_eq!(error.payload, b"\"hello\"".to_vec());
+    }
+
+    #[test]
+    fn stream_chunks_out_of_order_are_rejected_when_asked() {
+        let (handler_source, handler_receiver) = create_handlers();
+        handler_receiver.set_validation_mode(ValidationMode::Reject).unwrap();
+        let backend = handler_receiver.audio_server_backend();
+        let chunks = receiver(4, Overflow::Block, |cb| backend.subscribe_all_stream_bytes(cb)).unwrap();
+        sleep(Duration::from_millis(200));
+        let chunk = |chunk_number| StreamBytesMessage {
+            bytes: vec![0; 4],
+            site_id: "default".into(),
+            stream_id: "stream".into(),
+            chunk_number,
+            is_last_chunk: false,
+        };
+        let audio_server = handler_source.audio_server();
+
+        for chunk_number in vec![0, 2, 1, 3] {
+            audio_server.publish_stream_bytes(chunk(chunk_number)).unwrap();
+        }
+        let received = (0..3)
+            .map(|_| chunks.recv_timeout(Duration::from_secs(1)).unwrap().chunk_number)
+            .collect::<Vec<_>>();
+        assert_eq!(received, vec![0, 2, 3]);
+
+        handler_source.set_validation_mode(ValidationMode::Reject).unwrap();
+        audio_server.publish_stream_bytes(chunk(5)).unwrap();
+        match audio_server.publish_stream_bytes(chunk(4)) {
+            Err(HermesError::Validation(reason)) => assert!(reason.contains("chunk_number")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn metadata_is_only_attached_to_json_objects() {
+        let (handler_source, handler_receiver) = create_handlers();
+        let options = rumqtt::MqttOptions::new(get_mqtt_id(), handler_source.name.as_str());
+        let source =
+            MqttHermesProtocolHandler::new_with_origin(options, MessageOrigin::new("test", "localhost")).unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = Mutex::new(sender);
+        let _traffic = handler_receiver
+            .subscribe_traffic(move |topic, payload| {
+                sender
+                    .lock()
+                    .unwrap()
+                    .send((topic.as_path(), payload.to_vec()))
+                    .unwrap()
+            })
+            .unwrap();
+        sleep(Duration::from_millis(200));
+
+        source
+            .mqtt_handler
+            .publish(&HermesTopic::Asr(AsrCommand::ToggleOn))
+            .unwrap();
+        let (topic, payload) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(topic, "hermes/asr/toggleOn");
+        assert!(payload.is_empty());
+
+        source
+            .mqtt_handler
+            .send_payload(
+                &HermesTopic::Tts(TtsCommand::SayFinished),
+                serde_json::json!({"id": "abc"}),
+            )
+            .unwrap();
+        let (topic, payload) = receiver.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(topic, "hermes/tts/sayFinished");
+        let meta = MqttHandler::decode_meta(&payload).unwrap();
+        assert_eq!(meta.origin, Some(MessageOrigin::new("test", "localhost")));
+    }
+
+    #[test]
+    fn lenient_policy_fills_the_missing_fields() {
+        let (handler_source, handler_receiver) = create_handlers();
+        handler_receiver.set_decode_policy(DecodePolicy::Lenient).unwrap();
+        let asr = handler_receiver.asr();
+        let captured = receiver(1, Overflow::Block, |cb| asr.subscribe_text_captured(cb)).unwrap();
+        sleep(Duration::from_millis(200));
+
+        let text = serde_json::json!({"text": "hello", "siteId": "default", "volume": 0.5});
+        handler_source
+            .mqtt_handler
+            .send_payload(&HermesTopic::Asr(AsrCommand::TextCaptured), text)
+            .unwrap();
+        let message = captured.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(message.text, "hello");
+        assert_eq!(message.likelihood, 0.0);
+    }
+
+    #[test]
+    fn options_follow_the_config() {
+        let options = mqtt_options(&HandlerConfig::from_url("mqtt://snips@localhost").unwrap());
+        assert_eq!(options.broker_addr, "localhost:1883");
+        assert_eq!(options.username, Some("snips".into()));
+        assert!(options.tls.is_none());
+
+        let options = mqtt_options(&HandlerConfig::from_url("mqtts://broker.local?ca=/etc/ca.pem").unwrap());
+        assert_eq!(options.broker_addr, "broker.local:8883");
+        assert_eq!(options.tls.unwrap().cafile, vec![std::path::PathBuf::from("/etc/ca.pem")]);
+
+        let options = mqtt_options(&HandlerConfig::from_url("mqtt://[::1]").unwrap());
+        assert_eq!(options.broker_addr, "[::1]:1883");
+
+        let options = mqtt_options(&HandlerConfig::from_url("mqtts://[::1]:8884").unwrap());
+        assert_eq!(options.broker_addr, "[::1]:8884");
+        // the hostname is the host without its brackets
+        assert!(format!("{:?}", options.tls.unwrap()).contains("\"::1\""));
+    }
 }
@@ -0,0 +1,455 @@
+//! Recording of the hermes traffic going through a broker, and its replay, to reproduce what
+//! happened on a site against our own components.
+//!
+//! A recording is made of two logs: a JSON lines log of the messages, one `TrafficEntry` per line,
+//! and an audio log where the binary payloads (audio frames, sounds to play...) are written one
+//! after the other. The entries of the binary messages tell where their payload is in the audio log.
+//! The JSON messages are decoded into the type of the ontology their topic carries when recorded,
+//! and checked again when replayed.
+//!
+//! This is MQTT only: the recorder taps the broker, which carries the traffic of all the clients
+//! of a site and not only the one of the protocol handler, and the replayer publishes on it.
+
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::{DateTime, Utc};
+use hermes::*;
+use log::*;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::topics::*;
+use crate::MqttHermesProtocolHandler;
+
+/// A message of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficEntry {
+    /// When the message was received
+    pub time: DateTime<Utc>,
+    pub topic: String,
+    /// The type of the ontology of the payload, `IntentMessage` for example
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub message_type: Option<String>,
+    /// The JSON payload of the message as its type encodes it, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// Where the binary payload of the message is in the audio log, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioSpan>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioSpan {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Which messages to record or replay, all of them by default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficFilter {
+    /// Only the messages of this site, going by their topic or their `siteId` field
+    pub site_id: Option<String>,
+    /// Only the messages of this session, going by their `sessionId` field. The messages that are
+    /// not about a session, audio frames included, are left out
+    pub session_id: Option<String>,
+}
+
+impl TrafficFilter {
+    /// Whether a message is kept, given its JSON payload if it has one
+    pub fn matches(&self, topic: &HermesTopic, payload: Option<&serde_json::Value>) -> bool {
+        let site_id = match topic {
+            HermesTopic::AudioServer(Some(site_id), _) | HermesTopic::VoiceActivity(site_id, _) => {
+                Some(site_id.as_str())
+            }
+            _ => field(payload, "siteId"),
+        };
+        self.site_id
+            .as_ref()
+            .map_or(true, |wanted| site_id == Some(wanted.as_str()))
+            && self
+                .session_id
+                .as_ref()
+                .map_or(true, |wanted| field(payload, "sessionId") == Some(wanted.as_str()))
+    }
+}
+
+fn field<'a>(payload: Option<&'a serde_json::Value>, name: &str) -> Option<&'a str> {
+    payload.and_then(|it| it.get(name)).and_then(serde_json::Value::as_str)
+}
+
+/// A JSON payload decoded into the type of the ontology its topic carries
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedPayload {
+    /// The name of the type, `IntentMessage` for example
+    pub type_name: &'static str,
+    /// The payload as its type encodes it, or why it doesn't decode into it
+    pub json: Result<serde_json::Value, String>,
+}
+
+fn typed<T: DeserializeOwned + serde::Serialize>(payload: &[u8]) -> Option<TypedPayload> {
+    let type_name = std::any::type_name::<T>().rsplit("::").next().unwrap_or_default();
+    let json = serde_json::from_slice::<T>(payload)
+        .and_then(serde_json::to_value)
+        .map_err(|e| e.to_string());
+    Some(TypedPayload { type_name, json })
+}
+
+/// Decode a JSON payload into the type of the ontology its topic carries. There is nothing to
+/// decode for the topics whose payload is binary, nor for those without payload (the requests
+/// without payload may still carry metadata, which doesn't make them a message)
+pub fn decode_typed(topic: &HermesTopic, payload: &[u8]) -> Option<TypedPayload> {
+    match topic {
+        _ if payload.is_empty() || topic.is_binary() => None,
+        HermesTopic::Feedback(_)
+        | HermesTopic::Hotword(_, HotwordCommand::ToggleOn)
+        | HermesTopic::Hotword(_, HotwordCommand::ToggleOff)
+        | HermesTopic::Asr(AsrCommand::ToggleOn)
+        | HermesTopic::Asr(AsrCommand::ToggleOff)
+        | HermesTopic::Asr(AsrCommand::StopListening)
+        | HermesTopic::AudioServer(_, AudioServerCommand::ToggleOn)
+        | HermesTopic::AudioServer(_, AudioServerCommand::ToggleOff) => typed::<SiteMessage>(payload),
+        HermesTopic::VoiceActivity(_, VoiceActivityCommand::VadUp) => typed::<VadUpMessage>(payload),
+        HermesTopic::VoiceActivity(_, VoiceActivityCommand::VadDown) => typed::<VadDownMessage>(payload),
+        HermesTopic::Hotword(_, HotwordCommand::Detected) => typed::<HotwordDetectedMessage>(payload),
+        HermesTopic::Asr(AsrCommand::StartListening) => typed::<AsrStartListeningMessage>(payload),
+        HermesTopic::Asr(AsrCommand::TextCaptured) | HermesTopic::Asr(AsrCommand::PartialTextCaptured) => {
+            typed::<TextCapturedMessage>(payload)
+        }
+        HermesTopic::Asr(AsrCommand::Reload) | HermesTopic::Nlu(NluCommand::Reload) => {
+            typed::<RequestComponentReloadMessage>(payload)
+        }
+        HermesTopic::Tts(TtsCommand::Say) => typed::<SayMessage>(payload),
+        HermesTopic::Tts(TtsCommand::SayFinished) => typed::<SayFinishedMessage>(payload),
+        HermesTopic::Nlu(NluCommand::Query) => typed::<NluQueryMessage>(payload),
+        HermesTopic::Nlu(NluCommand::PartialQuery) => typed::<NluSlotQueryMessage>(payload),
+        HermesTopic::Nlu(NluCommand::SlotParsed) => typed::<NluSlotMessage>(payload),
+        HermesTopic::Nlu(NluCommand::IntentParsed) => typed::<NluIntentMessage>(payload),
+        HermesTopic::Nlu(NluCommand::IntentNotRecognized) => typed::<NluIntentNotRecognizedMessage>(payload),
+        HermesTopic::Intent(_) => typed::<IntentMessage>(payload),
+        HermesTopic::DialogueManager(command) => match command {
+            DialogueManagerCommand::StartSession => typed::<StartSessionMessage>(payload),
+            DialogueManagerCommand::ContinueSession => typed::<ContinueSessionMessage>(payload),
+            DialogueManagerCommand::EndSession => typed::<EndSessionMessage>(payload),
+            DialogueManagerCommand::SessionQueued => typed::<SessionQueuedMessage>(payload),
+            DialogueManagerCommand::SessionStarted => typed::<SessionStartedMessage>(payload),
+            DialogueManagerCommand::SessionEnded => typed::<SessionEndedMessage>(payload),
+            DialogueManagerCommand::IntentNotRecognized => typed::<IntentNotRecognizedMessage>(payload),
+            DialogueManagerCommand::Configure => typed::<DialogueConfigureMessage>(payload),
+            DialogueManagerCommand::ToggleOn | DialogueManagerCommand::ToggleOff => typed::<SiteMessage>(payload),
+        },
+        HermesTopic::AudioServer(_, AudioServerCommand::ReplayRequest) => typed::<ReplayRequestMessage>(payload),
+        HermesTopic::AudioServer(_, AudioServerCommand::PlayFinished) => typed::<PlayFinishedMessage>(payload),
+        HermesTopic::AudioServer(_, AudioServerCommand::StreamFinished) => typed::<StreamFinishedMessage>(payload),
+        HermesTopic::Injection(command) => match command {
+            InjectionCommand::Perform => typed::<InjectionRequestMessage>(payload),
+            InjectionCommand::Status => typed::<InjectionStatusMessage>(payload),
+            InjectionCommand::Complete => typed::<InjectionCompleteMessage>(payload),
+            InjectionCommand::ResetRequest => typed::<InjectionResetRequestMessage>(payload),
+            InjectionCommand::ResetComplete => typed::<InjectionResetCompleteMessage>(payload),
+            InjectionCommand::StatusRequest => None,
+        },
+        HermesTopic::Component(_, _, command) => match command {
+            ComponentCommand::Version => typed::<VersionMessage>(payload),
+            ComponentCommand::Error => typed::<ErrorMessage>(payload),
+            ComponentCommand::Loaded => typed::<ComponentLoadedMessage>(payload),
+            ComponentCommand::VersionRequest => None,
+        },
+        // the binary topics, handled above
+        HermesTopic::AudioServer(_, _) | HermesTopic::Tts(TtsCommand::RegisterSound(_)) => None,
+    }
+}
+
+struct Logs {
+    log: Box<dyn Write + Send>,
+    audio: Box<dyn Write + Send>,
+    audio_length: u64,
+}
+
+impl Logs {
+    fn record(&mut self, topic_name: &str, payload: &[u8], filter: &TrafficFilter) -> HermesResult<()> {
+        let topic = match HermesTopic::from_path(topic_name) {
+            Some(topic) => topic,
+            None => {
+                debug!("Not recording the message on unknown topic '{}'", topic_name);
+                return Ok(());
+            }
+        };
+        let mut entry = TrafficEntry {
+            time: Utc::now(),
+            topic: topic_name.to_string(),
+            message_type: None,
+            payload: None,
+            audio: None,
+        };
+        if topic.is_binary() {
+            if !filter.matches(&topic, None) {
+                return Ok(());
+            }
+            self.audio.write_all(payload)?;
+            entry.audio = Some(AudioSpan {
+                offset: self.audio_length,
+                length: payload.len() as u64,
+            });
+            self.audio_length += payload.len() as u64;
+        } else {
+            match decode_typed(&topic, payload) {
+                Some(TypedPayload {
+                    type_name,
+                    json: Ok(json),
+                }) => {
+                    entry.message_type = Some(type_name.to_string());
+                    entry.payload = Some(json);
+                }
+                Some(TypedPayload {
+                    type_name,
+                    json: Err(e),
+                }) => {
+                    warn!(
+                        "Not recording the message on '{}', it isn't a {}: {}",
+                        topic_name, type_name, e
+                    );
+                    return Ok(());
+                }
+                None => {}
+            }
+            if !filter.matches(&topic, entry.payload.as_ref()) {
+                return Ok(());
+            }
+        }
+        serde_json::to_writer(&mut self.log, &entry)?;
+        self.log.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Records every hermes message going through the broker of a handler, until it is dropped. The
+/// messages that don't decode into the type of their topic are left out, with a warning
+pub struct TrafficRecorder {
+    logs: Arc<Mutex<Logs>>,
+    _subscription: SubscriptionHandle,
+}
+
+impl TrafficRecorder {
+    pub fn start<L, A>(
+        handler: &MqttHermesProtocolHandler,
+        log: L,
+        audio: A,
+        filter: TrafficFilter,
+    ) -> HermesResult<Self>
+    where
+        L: Write + Send + 'static,
+        A: Write + Send + 'static,
+    {
+        let logs = Arc::new(Mutex::new(Logs {
+            log: Box::new(log),
+            audio: Box::new(audio),
+            audio_length: 0,
+        }));
+        let recorder = Arc::clone(&logs);
+        let subscription = handler
+            .mqtt_handler
+            .subscribe_filter("hermes/#".to_string(), move |m| {
+                let recorded = recorder
+                    .lock()
+                    .map_err(PoisonLock::from)
+                    .map_err(HermesError::from)
+                    .and_then(|mut logs| logs.record(&m.topic_name, m.payload.as_slice(), &filter));
+                if let Err(e) = recorded {
+                    error!("Could not record the message on topic '{}': {}", m.topic_name, e)
+                }
+            })?;
+
+        Ok(Self {
+            logs,
+            _subscription: subscription,
+        })
+    }
+
+    /// Write what was recorded so far to the logs
+    pub fn flush(&self) -> HermesResult<()> {
+        let mut logs = self.logs.lock().map_err(PoisonLock::from)?;
+        logs.log.flush()?;
+        logs.audio.flush()?;
+        Ok(())
+    }
+}
+
+/// Publish a recording back through the broker of a handler, waiting between the messages as long
+/// as they were apart divided by `speed`, which must be positive and finite: 1.0 keeps the original
+/// timing, 10.0 replays ten times faster. Fails on the first message that doesn't decode into the
+/// type of its topic. Returns how many messages were replayed.
+pub fn replay<L, A>(
+    handler: &MqttHermesProtocolHandler,
+    log: L,
+    audio: A,
+    speed: f64,
+    filter: &TrafficFilter,
+) -> HermesResult<usize>
+where
+    L: BufRead,
+    A: Read + Seek,
+{
+    replay_with(log, audio, speed, filter, |topic, payload| {
+        handler.mqtt_handler.publish_binary_payload(topic, payload)
+    })
+}
+
+fn replay_with<L, A, P>(log: L, mut audio: A, speed: f64, filter: &TrafficFilter, mut publish: P) -> HermesResult<usize>
+where
+    L: BufRead,
+    A: Read + Seek,
+    P: FnMut(&HermesTopic, Vec<u8>) -> HermesResult<()>,
+{
+    if !(speed.is_finite() && speed > 0.) {
+        return Err(HermesError::Config(format!(
+            "the replay speed must be positive and finite, not {}",
+            speed
+        )));
+    }
+    let mut previous: Option<DateTime<Utc>> = None;
+    let mut replayed = 0;
+    for (index, line) in log.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: TrafficEntry = serde_json::from_str(&line)?;
+        let topic = match HermesTopic::from_path(&entry.topic) {
+            Some(topic) => topic,
+            None => {
+                warn!("Not replaying the message on unknown topic '{}'", entry.topic);
+                continue;
+            }
+        };
+        if !filter.matches(&topic, entry.payload.as_ref()) {
+            continue;
+        }
+
+        let payload = match (&entry.payload, entry.audio) {
+            (Some(payload), _) => match decode_typed(&topic, &serde_json::to_vec(payload)?) {
+                Some(TypedPayload { json: Ok(json), .. }) => serde_json::to_vec(&json)?,
+                Some(TypedPayload {
+                    type_name,
+                    json: Err(e),
+                }) => {
+                    return Err(HermesError::codec(format!(
+                        "the payload on line {} isn't a {}: {}",
+                        index + 1,
+                        type_name,
+                        e
+                    )))
+                }
+                None => serde_json::to_vec(payload)?,
+            },
+            (None, Some(span)) => {
+                let mut bytes = vec![0u8; span.length as usize];
+                audio.seek(SeekFrom::Start(span.offset))?;
+                audio.read_exact(&mut bytes)?;
+                bytes
+            }
+            (None, None) => vec![],
+        };
+        if let Some(previous) = previous {
+            let gap = (entry.time - previous).to_std().unwrap_or_default();
+            thread::sleep(gap.div_f64(speed));
+        }
+        previous = Some(entry.time);
+
+        publish(&topic, payload)?;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn messages_are_filtered_by_site_and_session() {
+        let filter = TrafficFilter {
+            site_id: Some("kitchen".into()),
+            session_id: None,
+        };
+        let ended = HermesTopic::DialogueManager(DialogueManagerCommand::SessionEnded);
+        let kitchen = serde_json::json!({ "siteId": "kitchen", "sessionId": "abc" });
+        let bedroom = serde_json::json!({ "siteId": "bedroom", "sessionId": "def" });
+        assert!(filter.matches(&ended, Some(&kitchen)));
+        assert!(!filter.matches(&ended, Some(&bedroom)));
+        assert!(filter.matches(
+            &HermesTopic::AudioServer(Some("kitchen".into()), AudioServerCommand::AudioFrame),
+            None
+        ));
+
+        let filter = TrafficFilter {
+            site_id: None,
+            session_id: Some("def".into()),
+        };
+        assert!(!filter.matches(&ended, Some(&kitchen)));
+        assert!(filter.matches(&ended, Some(&bedroom)));
+        assert!(!filter.matches(
+            &HermesTopic::AudioServer(Some("bedroom".into()), AudioServerCommand::AudioFrame),
+            None
+        ));
+    }
+
+    #[test]
+    fn binary_payloads_go_to_the_audio_log() {
+        let mut logs = Logs {
+            log: Box::new(Vec::<u8>::new()),
+            audio: Box::new(Vec::<u8>::new()),
+            audio_length: 0,
+        };
+        let filter = TrafficFilter::default();
+
+        logs.record("hermes/audioServer/default/audioFrame", &[1, 2, 3], &filter)
+            .unwrap();
+        logs.record("hermes/audioServer/default/audioFrame", &[4, 5], &filter)
+            .unwrap();
+        logs.record("not/hermes", b"{}", &filter).unwrap();
+
+        assert_eq!(logs.audio_length, 5);
+    }
+
+    #[test]
+    fn replay_checks_its_speed_and_the_payloads() {
+        let log = r#"{"time":"2019-05-03T10:00:00Z","topic":"hermes/tts/sayFinished","payload":{"id":"abc"}}
+{"time":"2019-05-03T10:00:01Z","topic":"hermes/tts/say","payload":{"id":"abc"}}"#;
+        let filter = TrafficFilter::default();
+        let mut published = vec![];
+
+        for speed in vec![0., -1., f64::NAN, f64::INFINITY] {
+            let result = replay_with(log.as_bytes(), Cursor::new(vec![]), speed, &filter, |topic, _| {
+                published.push(topic.as_path());
+                Ok(())
+            });
+            assert!(result.is_err());
+        }
+        assert!(published.is_empty());
+
+        let result = replay_with(log.as_bytes(), Cursor::new(vec![]), 1000., &filter, |topic, _| {
+            published.push(topic.as_path());
+            Ok(())
+        });
+        match result {
+            Err(HermesError::Codec(e)) => assert!(e.to_string().contains("line 2")),
+            other => panic!("expected a codec error, got {:?}", other),
+        }
+        assert_eq!(published, vec!["hermes/tts/sayFinished"]);
+    }
+
+    #[test]
+    fn entries_are_read_back() {
+        let line = r#"{"time":"2019-05-03T10:00:00Z","topic":"hermes/audioServer/default/audioFrame","audio":{"offset":3,"length":2}}"#;
+        let entry: TrafficEntry = serde_json::from_str(line).unwrap();
+
+        assert_eq!(entry.payload, None);
+        assert_eq!(entry.audio, Some(AudioSpan { offset: 3, length: 2 }));
+        assert_eq!(serde_json::to_string(&entry).unwrap(), line);
+    }
+}
@@ -40,6 +40,56 @@ pub enum HermesTopic {
 impl ToPath for HermesTopic {}
 
 impl HermesTopic {
+    /// The path of this topic with the parts that vary (site ids, request ids, intent names...)
+    /// replaced by `+`, the same for all the topics of a kind
+    pub fn pattern(&self) -> String {
+        let any = || "+".to_string();
+        let topic = match self.clone() {
+            HermesTopic::VoiceActivity(_, command) => HermesTopic::VoiceActivity(any(), command),
+            HermesTopic::Hotword(id, command) => HermesTopic::Hotword(id.map(|_| any()), command),
+            HermesTopic::Tts(TtsCommand::RegisterSound(_)) => HermesTopic::Tts(TtsCommand::RegisterSound(any())),
+            HermesTopic::Intent(_) => HermesTopic::Intent(any()),
+            HermesTopic::AudioServer(site_id, command) => HermesTopic::AudioServer(
+                site_id.map(|_| any()),
+                match command {
+                    AudioServerCommand::PlayBytes(_) => AudioServerCommand::PlayBytes(any()),
+                    AudioServerCommand::StreamBytes { .. } => AudioServerCommand::StreamBytes {
+                        stream_id: any(),
+                        chunk_number: any(),
+                        is_last_chunk: any(),
+                    },
+                    command => command,
+                },
+            ),
+            HermesTopic::Component(id, component, command) => {
+                HermesTopic::Component(id.map(|_| any()), component, command)
+            }
+            topic => topic,
+        };
+        topic.as_path()
+    }
+
+    /// Whether the payload of this topic is raw bytes rather than JSON
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            HermesTopic::AudioServer(_, AudioServerCommand::AudioFrame)
+                | HermesTopic::AudioServer(_, AudioServerCommand::ReplayResponse)
+                | HermesTopic::AudioServer(_, AudioServerCommand::PlayBytes(_))
+                | HermesTopic::AudioServer(_, AudioServerCommand::StreamBytes { .. })
+                | HermesTopic::Tts(TtsCommand::RegisterSound(_))
+        )
+    }
+
+    /// The site in the path of this topic, if it has one
+    pub fn site_id(&self) -> Option<&str> {
+        match self {
+            HermesTopic::VoiceActivity(site_id, _) => Some(site_id),
+            HermesTopic::AudioServer(site_id, _) | HermesTopic::Component(site_id, _, _) => site_id.as_deref(),
+            _ => None,
+        }
+    }
+
     fn parse_asr<'a, It: Iterator<Item = &'a str>>(mut comps: It) -> Option<HermesTopic> {
         use self::AsrCommand::*;
         use self::HermesTopic::Asr;
@@ -857,6 +907,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn patterns_hide_what_varies() {
+        let play_bytes = HermesTopic::AudioServer(Some("kitchen".into()), AudioServerCommand::PlayBytes("42".into()));
+        assert_eq!(play_bytes.pattern(), "hermes/audioServer/+/playBytes/+");
+        assert_eq!(HermesTopic::Intent("lights".into()).pattern(), "hermes/intent/+");
+        assert_eq!(
+            HermesTopic::Component(Some("default".into()), Component::Hotword, ComponentCommand::Error).pattern(),
+            "hermes/hotword/+/error"
+        );
+        assert_eq!(HermesTopic::Tts(TtsCommand::Say).pattern(), "hermes/tts/say");
+        assert_eq!(play_bytes.site_id(), Some("kitchen"));
+    }
+
     #[test]
     fn enum_to_string_conversion_works() {
         for (expected_route, path) in routes() {
@@ -21,7 +21,7 @@ macro_rules! t {
             let receiver = handler_receiver.$s_facade();
             let (tx, rx) = std::sync::mpsc::channel();
             let tx = std::sync::Mutex::new(tx);
-            receiver
+            let _subscription = receiver
                 .$s($(message.$($field).*.clone(),)?
                     $($subscribe_arg,)?
                     hermes::Callback0::new(move || {
@@ -30,6 +30,7 @@ macro_rules! t {
                 .unwrap();
             std::thread::sleep(WAIT_DURATION);
             source.$p($($publish_arg,)? $($subscribe_arg,)?).unwrap();
+            deliver(&*handler_source);
             let result = rx.recv_timeout(std::time::Duration::from_secs(1));
             assert!(result.is_ok(), "didn't receive message after one second");
         }
@@ -52,7 +53,7 @@ macro_rules! t {
             let tx = std::sync::Mutex::new(tx);
             use hermes::hermes_utils::Example;
             let message = <$t>::full_example();
-            receiver
+            let _subscription = receiver
                 .$s($(message.$($field).*.clone(),)?
                     $($subscribe_arg,)?
                     hermes::Callback::new(move |o: &$t| {
@@ -61,11 +62,13 @@ macro_rules! t {
                 .unwrap();
             std::thread::sleep(WAIT_DURATION);
             source.$p($($publish_arg,)? $($subscribe_arg,)? message.clone()).unwrap();
+            deliver(&*handler_source);
             let result = rx.recv_timeout(std::time::Duration::from_secs(1));
             assert!(result.is_ok(), "didn't receive message after one second (full example)");
             assert_eq!(result.unwrap(), message);
             let message2 = <$t>::minimal_example();
             source.$p($($publish_arg,)? $($subscribe_arg,)? message2.clone()).unwrap();
+            deliver(&*handler_source);
             let result2 = rx.recv_timeout(std::time::Duration::from_secs(1));
             assert!(result2.is_ok(), "didn't receive message after one second (minimal example)");
             assert_eq!(result2.unwrap(), message2);
@@ -73,6 +76,142 @@ macro_rules! t {
     };
 }
 
+#[macro_export]
+macro_rules! t_unsubscribe {
+    (
+        $name:ident :
+        $s_facade:ident.
+        $s:ident $($subscribe_arg:block)? <=
+        $p_facade:ident.
+        $p:ident
+        $($publish_arg:block)?
+    ) => {
+        #[test]
+        fn $name() {
+            let (handler_source, handler_receiver) = create_handlers();
+            let source = handler_source.$p_facade();
+            let receiver = handler_receiver.$s_facade();
+            let (tx, rx) = std::sync::mpsc::channel();
+            let tx = std::sync::Mutex::new(tx);
+            {
+                let _subscription = receiver
+                    .$s($($subscribe_arg,)?
+                        hermes::Callback0::new(move || {
+                        tx.lock().map(|it| it.send(())).unwrap().unwrap()
+                    }))
+                    .unwrap();
+                std::thread::sleep(WAIT_DURATION);
+                source.$p($($publish_arg,)? $($subscribe_arg,)?).unwrap();
+                deliver(&*handler_source);
+                let result = rx.recv_timeout(std::time::Duration::from_secs(1));
+                assert!(result.is_ok(), "didn't receive message after one second");
+                // dropping the handle cancels the subscription
+            }
+            std::thread::sleep(WAIT_DURATION);
+            source.$p($($publish_arg,)? $($subscribe_arg,)?).unwrap();
+            deliver(&*handler_source);
+            let result = rx.recv_timeout(SILENCE_DURATION);
+            assert!(result.is_err(), "received a message after dropping the subscription");
+        }
+    };
+    (
+        $name:ident :
+        $s_facade:ident.
+        $s:ident $(($($field:ident).+))? $($subscribe_arg:block)? <=
+        $t:ty |
+        $p_facade:ident.
+        $p:ident
+        $($publish_arg:block)?
+    ) => {
+        #[test]
+        fn $name() {
+            let (handler_source, handler_receiver) = create_handlers();
+            let source = handler_source.$p_facade();
+            let receiver = handler_receiver.$s_facade();
+            let (tx, rx) = std::sync::mpsc::channel();
+            let tx = std::sync::Mutex::new(tx);
+            let (other_tx, other_rx) = std::sync::mpsc::channel();
+            let other_tx = std::sync::Mutex::new(other_tx);
+            use hermes::hermes_utils::Example;
+            let message = <$t>::full_example();
+            let subscription = receiver
+                .$s($(message.$($field).*.clone(),)?
+                    $($subscribe_arg,)?
+                    hermes::Callback::new(move |o: &$t| {
+                    tx.lock().map(|it| it.send(o.clone())).unwrap().unwrap()
+                }))
+                .unwrap();
+            // a second subscription on the same messages must not be affected by the first one going
+            let _other_subscription = receiver
+                .$s($(message.$($field).*.clone(),)?
+                    $($subscribe_arg,)?
+                    hermes::Callback::new(move |o: &$t| {
+                    other_tx.lock().map(|it| it.send(o.clone())).unwrap().unwrap()
+                }))
+                .unwrap();
+            std::thread::sleep(WAIT_DURATION);
+            source.$p($($publish_arg,)? $($subscribe_arg,)? message.clone()).unwrap();
+            deliver(&*handler_source);
+            let result = rx.recv_timeout(std::time::Duration::from_secs(1));
+            assert!(result.is_ok(), "didn't receive message after one second");
+            assert!(other_rx.recv_timeout(std::time::Duration::from_secs(1)).is_ok());
+            subscription.unsubscribe().unwrap();
+            std::thread::sleep(WAIT_DURATION);
+            source.$p($($publish_arg,)? $($subscribe_arg,)? message.clone()).unwrap();
+            deliver(&*handler_source);
+            assert!(other_rx.recv_timeout(std::time::Duration::from_secs(1)).is_ok());
+            let result = rx.recv_timeout(SILENCE_DURATION);
+            assert!(result.is_err(), "received a message after unsubscribing");
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! t_request {
+    (
+        $name:ident :
+        $c_facade:ident.
+        $wait:ident $request_arg:block <=
+        $b_facade:ident.
+        $s:ident =>
+        $r:ty $(as $wrap:path)? |
+        $p:ident
+        |$request:ident: $t:ty| $response:expr
+    ) => {
+        #[test]
+        fn $name() {
+            use hermes::hermes_utils::Example;
+            let (handler_client, handler_backend) = create_handlers();
+            let client = handler_client.$c_facade();
+            let backend = std::sync::Arc::new(handler_backend.$b_facade());
+            let responder = std::sync::Arc::clone(&backend);
+            let _subscription = backend
+                .$s(hermes::Callback::new(move |$request: &$t| {
+                    // a response to another request must not be mistaken for the one to ours
+                    responder.$p(<$r>::full_example()).unwrap();
+                    responder.$p($response).unwrap();
+                }))
+                .unwrap();
+            std::thread::sleep(WAIT_DURATION);
+            // the client waits on a thread of its own, so that this one can deliver the request and
+            // the responses when the handlers need it
+            let (tx, rx) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                tx.send(client.$wait($request_arg, std::time::Duration::from_secs(1))).unwrap()
+            });
+            let response = loop {
+                deliver(&*handler_backend);
+                match rx.recv_timeout(std::time::Duration::from_millis(10)) {
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    result => break result.expect("the client panicked"),
+                }
+            };
+            assert!(response.is_ok(), "didn't receive a response after one second");
+            assert_ne!(response.unwrap(), $($wrap)?(<$r>::full_example()));
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! t_toggleable {
     ($name:ident : $f_back:ident | $f:ident) => {
@@ -137,6 +276,19 @@ macro_rules! t_identifiable_component {
         };
     }
 
+/// The tests every protocol handler must pass, run against the handlers returned by a
+/// `create_handlers` function of the calling module.
+///
+/// By default the suite expects the handlers to deliver the messages on their own: it gives them
+/// `WAIT_DURATION` milliseconds (0 by default) to set up a subscription before publishing, and
+/// waits a little to be sure a message doesn't come. With `DELIVER`, the handlers only deliver
+/// when told to, the suite then calls it after each publish and never sleeps:
+///
+/// ```ignore
+/// hermes_test_suite::test_suite!(DELIVER = |handler: &InProcessHermesProtocolHandler| {
+///     handler.run_until_idle().unwrap()
+/// });
+/// ```
 #[macro_export]
 macro_rules! test_suite {
     () => {
@@ -144,10 +296,35 @@ macro_rules! test_suite {
     };
 
     (WAIT_DURATION = $wait_duration:expr) => {
-        use $crate::{t, t_identifiable_component, t_identifiable_toggleable, t_component, t_toggleable};
+        $crate::test_suite!(
+            @suite WAIT_DURATION = $wait_duration,
+            SILENCE_DURATION = 200,
+            fn deliver<H: ?Sized>(_handler: &H) {}
+        );
+    };
+
+    (DELIVER = |$handler:ident: $handler_type:ty| $deliver:expr) => {
+        $crate::test_suite!(
+            @suite WAIT_DURATION = 0,
+            SILENCE_DURATION = 0,
+            fn deliver($handler: $handler_type) {
+                $deliver;
+            }
+        );
+    };
+
+    (@suite WAIT_DURATION = $wait_duration:expr, SILENCE_DURATION = $silence_duration:expr, $deliver:item) => {
+        use $crate::{
+            t, t_component, t_identifiable_component, t_identifiable_toggleable, t_request, t_toggleable, t_unsubscribe,
+        };
         use snips_nlu_ontology::Slot;
 
         const WAIT_DURATION: std::time::Duration = std::time::Duration::from_millis($wait_duration);
+        /// How long to wait for a message to be sure it doesn't come
+        const SILENCE_DURATION: std::time::Duration = std::time::Duration::from_millis($silence_duration);
+
+        // tells the handlers to deliver the messages published, when they don't on their own
+        $deliver
 
         t_identifiable_component!(voice_activity_identifiable_component: voice_activity_backend | voice_activity);
         t!(voice_activity_vad_up_works:
@@ -257,5 +434,41 @@ macro_rules! test_suite {
                     injection_backend.subscribe_injection_reset_request <= InjectionResetRequestMessage | injection.publish_injection_reset_request);
         t!(injection_reset_complete:
                     injection.subscribe_injection_reset_complete <= InjectionResetCompleteMessage | injection_backend.publish_injection_reset_complete);
+
+        t_unsubscribe!(unsubscribe_intent_works:
+                    dialogue.subscribe_intent(intent.intent_name) <= IntentMessage | dialogue_backend.publish_intent);
+        t_unsubscribe!(unsubscribe_say_finished_works:
+                    tts.subscribe_say_finished <= SayFinishedMessage | tts_backend.publish_say_finished);
+        t_unsubscribe!(unsubscribe_play_bytes_works:
+                    audio_server_backend.subscribe_play_bytes(site_id) <= PlayBytesMessage | audio_server.publish_play_bytes);
+        t_unsubscribe!(unsubscribe_version_works:
+                    hotword.subscribe_version { "identifier".to_string() } <= VersionMessage | hotword_backend.publish_version);
+        t_unsubscribe!(drop_toggle_on_subscription_works:
+                    asr_backend.subscribe_toggle_on <= asr.publish_toggle_on);
+
+        t_request!(tts_say_and_wait_works:
+                    tts.say_and_wait { SayMessage::minimal_example() } <=
+                    tts_backend.subscribe_say => SayFinishedMessage | publish_say_finished
+                    |request: SayMessage| SayFinishedMessage { id: request.id.clone(), session_id: request.session_id.clone() });
+        t_request!(audio_server_play_and_wait_works:
+                    audio_server.play_and_wait { PlayBytesMessage { id: String::new(), ..PlayBytesMessage::minimal_example() } } <=
+                    audio_server_backend.subscribe_all_play_bytes => PlayFinishedMessage | publish_play_finished
+                    |request: PlayBytesMessage| PlayFinishedMessage { id: request.id.clone(), site_id: request.site_id.clone() });
+        t_request!(injection_inject_and_wait_works:
+                    injection.inject_and_wait { InjectionRequestMessage::minimal_example() } <=
+                    injection_backend.subscribe_injection_request => InjectionCompleteMessage | publish_injection_complete
+                    |request: InjectionRequestMessage| InjectionCompleteMessage { request_id: request.id.clone() });
+        t_request!(nlu_reload_and_wait_works:
+                    nlu.reload_and_wait { RequestComponentReloadMessage { id: String::new() } } <=
+                    nlu_backend.subscribe_component_reload => ComponentLoadedMessage | publish_component_loaded
+                    |request: RequestComponentReloadMessage| ComponentLoadedMessage::from(request));
+        t_request!(nlu_query_and_wait_works:
+                    nlu.query_and_wait { NluQueryMessage::minimal_example() } <=
+                    nlu_backend.subscribe_query => NluIntentMessage as NluQueryResponse::IntentParsed | publish_intent_parsed
+                    |request: NluQueryMessage| NluIntentMessage {
+                        id: request.id.clone(),
+                        input: request.input.clone(),
+                        ..NluIntentMessage::full_example()
+                    });
     };
 }
@@ -0,0 +1,18 @@
+//! Write the JSON Schema of every message of the ontology in a directory, one `<Message>.json` file
+//! per message.
+//!
+//! `cargo run -p hermes --features schema --example ontology_schemas -- <output dir>`
+
+use std::fs;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let output = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| "schemas".to_string()));
+    fs::create_dir_all(&output)?;
+    for (name, schema) in hermes::schema::ontology_schemas() {
+        let path = output.join(format!("{}.json", name));
+        fs::write(&path, serde_json::to_vec_pretty(&schema)?)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
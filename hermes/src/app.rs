@@ -0,0 +1,305 @@
+//! A small framework to write skills on top of the `DialogueFacade`: handlers are registered per
+//! intent, and the `Action` they return is turned into the dialogue message that makes the session
+//! go on.
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use log::{debug, error, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::HermesResult;
+use crate::ontology::*;
+use crate::{Callback, DialogueFacade, SubscriptionHandle};
+
+/// What to do with a session once one of its intents was handled
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Say `text` and listen to the user again, optionally restricting what they can say
+    Continue {
+        text: String,
+        intent_filter: Option<Vec<String>>,
+        slot: Option<String>,
+    },
+    /// End the session, saying `text` first if there is one
+    End { text: Option<String> },
+    /// Leave the session as is, the handler takes care of it
+    Nothing,
+}
+
+impl Action {
+    pub fn continue_with<T: Into<String>>(text: T) -> Self {
+        Action::Continue {
+            text: text.into(),
+            intent_filter: None,
+            slot: None,
+        }
+    }
+
+    pub fn end_with<T: Into<String>>(text: T) -> Self {
+        Action::End {
+            text: Some(text.into()),
+        }
+    }
+}
+
+type Handler<M, S> = Box<dyn Fn(&M, &mut S) -> Action + Send + Sync>;
+
+/// The handlers of an app, along with how they share the state of a session
+struct Handlers<S> {
+    intents: HashMap<String, Handler<IntentMessage, S>>,
+    intent_not_recognized: Option<Handler<IntentNotRecognizedMessage, S>>,
+    /// Whether the session state is kept in the custom data of the sessions
+    stateful: bool,
+}
+
+impl<S: Serialize + DeserializeOwned + Default> Handlers<S> {
+    fn decode_state(&self, custom_data: &Option<String>) -> S {
+        match custom_data {
+            Some(custom_data) if self.stateful => serde_json::from_str(custom_data).unwrap_or_else(|e| {
+                debug!(
+                    "Custom data doesn't hold a session state, starting from the default one: {}",
+                    e
+                );
+                S::default()
+            }),
+            _ => S::default(),
+        }
+    }
+
+    fn encode_state(&self, state: &S, custom_data: &Option<String>) -> Option<String> {
+        if !self.stateful {
+            return custom_data.clone();
+        }
+        match serde_json::to_string(state) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                warn!(
+                    "Could not encode the session state, keeping the custom data as is: {}",
+                    e
+                );
+                custom_data.clone()
+            }
+        }
+    }
+
+    /// Run a handler with the state of its session, the session is ended if the handler panics.
+    /// Returns the action to take and the custom data of the session from then on.
+    fn run<F>(&self, session_id: &str, custom_data: &Option<String>, handler: F) -> (Action, Option<String>)
+    where
+        F: FnOnce(&mut S) -> Action,
+    {
+        let mut state = self.decode_state(custom_data);
+        let action = match panic::catch_unwind(AssertUnwindSafe(|| handler(&mut state))) {
+            Ok(action) => action,
+            Err(_) => {
+                error!("Handler panicked, ending session {}", session_id);
+                Action::End { text: None }
+            }
+        };
+        (action, self.encode_state(&state, custom_data))
+    }
+
+    fn handle_intent(&self, intent: &IntentMessage) -> Option<(Action, Option<String>)> {
+        let handler = self.intents.get(&intent.intent.intent_name)?;
+        Some(self.run(&intent.session_id, &intent.custom_data, |state| handler(intent, state)))
+    }
+
+    fn handle_intent_not_recognized(&self, message: &IntentNotRecognizedMessage) -> Option<(Action, Option<String>)> {
+        let handler = self.intent_not_recognized.as_ref()?;
+        Some(self.run(&message.session_id, &message.custom_data, |state| {
+            handler(message, state)
+        }))
+    }
+}
+
+struct App<S> {
+    dialogue: Box<dyn DialogueFacade>,
+    handlers: Handlers<S>,
+}
+
+impl<S> App<S> {
+    fn act(&self, session_id: &str, action: Action, custom_data: Option<String>) {
+        let result = match action {
+            Action::Continue {
+                text,
+                intent_filter,
+                slot,
+            } => self.dialogue.publish_continue_session(ContinueSessionMessage {
+                session_id: session_id.to_string(),
+                text,
+                intent_filter,
+                custom_data,
+                // we only hear of the user not being understood if we can handle it
+                send_intent_not_recognized: self.handlers.intent_not_recognized.is_some(),
+                slot,
+            }),
+            Action::End { text } => self.dialogue.publish_end_session(EndSessionMessage {
+                session_id: session_id.to_string(),
+                text,
+            }),
+            Action::Nothing => Ok(()),
+        };
+        if let Err(e) = result {
+            error!("Could not make session {} go on: {}", session_id, e)
+        }
+    }
+}
+
+/// Builds a `HermesApp`, create one with `HermesApp::builder` or `HermesApp::with_session_state`
+pub struct HermesAppBuilder<S> {
+    handlers: Handlers<S>,
+}
+
+impl<S> HermesAppBuilder<S>
+where
+    S: Serialize + DeserializeOwned + Default + 'static,
+{
+    fn new(stateful: bool) -> Self {
+        Self {
+            handlers: Handlers {
+                intents: HashMap::new(),
+                intent_not_recognized: None,
+                stateful,
+            },
+        }
+    }
+
+    /// Handle an intent, by its full name
+    pub fn on_intent<N, F>(mut self, intent_name: N, handler: F) -> Self
+    where
+        N: Into<String>,
+        F: Fn(&IntentMessage, &mut S) -> Action + Send + Sync + 'static,
+    {
+        self.handlers.intents.insert(intent_name.into(), Box::new(handler));
+        self
+    }
+
+    /// Handle the user not being understood in the sessions we continued, instead of letting the
+    /// dialogue manager do it
+    pub fn on_intent_not_recognized<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&IntentNotRecognizedMessage, &mut S) -> Action + Send + Sync + 'static,
+    {
+        self.handlers.intent_not_recognized = Some(Box::new(handler));
+        self
+    }
+
+    /// Subscribe to the intents of the handlers, they are handled until the app is dropped
+    pub fn start(self, dialogue: Box<dyn DialogueFacade>) -> HermesResult<HermesApp> {
+        let intent_names: Vec<String> = self.handlers.intents.keys().cloned().collect();
+        let handles_intent_not_recognized = self.handlers.intent_not_recognized.is_some();
+        let app = Arc::new(App {
+            dialogue,
+            handlers: self.handlers,
+        });
+
+        let mut subscriptions = vec![];
+        for intent_name in intent_names {
+            let handler = Arc::clone(&app);
+            subscriptions.push(app.dialogue.subscribe_intent(
+                intent_name,
+                Callback::new(move |intent: &IntentMessage| {
+                    if let Some((action, custom_data)) = handler.handlers.handle_intent(intent) {
+                        handler.act(&intent.session_id, action, custom_data)
+                    }
+                }),
+            )?);
+        }
+        if handles_intent_not_recognized {
+            let handler = Arc::clone(&app);
+            subscriptions.push(app.dialogue.subscribe_intent_not_recognized(Callback::new(
+                move |message: &IntentNotRecognizedMessage| {
+                    if let Some((action, custom_data)) = handler.handlers.handle_intent_not_recognized(message) {
+                        handler.act(&message.session_id, action, custom_data)
+                    }
+                },
+            ))?);
+        }
+
+        Ok(HermesApp {
+            _subscriptions: subscriptions,
+        })
+    }
+}
+
+/// A running skill, its handlers are called until it is dropped
+pub struct HermesApp {
+    _subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl HermesApp {
+    /// Build an app without session state, the custom data of the sessions is left untouched
+    pub fn builder() -> HermesAppBuilder<()> {
+        HermesAppBuilder::new(false)
+    }
+
+    /// Build an app whose handlers share a state for each session. The state is kept as JSON in
+    /// the custom data of the session, it starts from `S::default()` when the custom data doesn't
+    /// hold one. Only continuing a session keeps its state.
+    pub fn with_session_state<S>() -> HermesAppBuilder<S>
+    where
+        S: Serialize + DeserializeOwned + Default + 'static,
+    {
+        HermesAppBuilder::new(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermes_utils::Example;
+
+    fn intent(intent_name: &str, custom_data: Option<&str>) -> IntentMessage {
+        let mut intent = IntentMessage::minimal_example();
+        intent.intent.intent_name = intent_name.into();
+        intent.custom_data = custom_data.map(Into::into);
+        intent
+    }
+
+    #[test]
+    fn intents_are_routed_by_name() {
+        let handlers = HermesApp::builder()
+            .on_intent("lights", |_: &IntentMessage, _: &mut ()| Action::end_with("done"))
+            .handlers;
+
+        let (action, custom_data) = handlers.handle_intent(&intent("lights", Some("data"))).unwrap();
+
+        assert_eq!(action, Action::end_with("done"));
+        assert_eq!(custom_data, Some("data".into()));
+        assert!(handlers.handle_intent(&intent("weather", None)).is_none());
+    }
+
+    #[test]
+    fn session_state_travels_in_custom_data() {
+        let handlers = HermesApp::with_session_state::<u32>()
+            .on_intent("count", |_: &IntentMessage, count: &mut u32| {
+                *count += 1;
+                Action::continue_with("again?")
+            })
+            .handlers;
+
+        let (_, custom_data) = handlers.handle_intent(&intent("count", Some("not a count"))).unwrap();
+        assert_eq!(custom_data, Some("1".into()));
+
+        let (_, custom_data) = handlers
+            .handle_intent(&intent("count", custom_data.as_deref()))
+            .unwrap();
+        assert_eq!(custom_data, Some("2".into()));
+    }
+
+    #[test]
+    fn panicking_handlers_end_the_session() {
+        let handlers = HermesApp::builder()
+            .on_intent("lights", |_: &IntentMessage, _: &mut ()| -> Action {
+                panic!("no lights")
+            })
+            .handlers;
+
+        let (action, _) = handlers.handle_intent(&intent("lights", None)).unwrap();
+
+        assert_eq!(action, Action::End { text: None });
+    }
+}
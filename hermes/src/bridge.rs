@@ -0,0 +1,648 @@
+//! Forwarding of messages between two protocol handlers, to run some components in-process and
+//! others over MQTT for example. Each route of a bridge tells on which handler a component runs:
+//! the messages it publishes there are forwarded to the other handler, and the orders it is given
+//! on the other handler are forwarded to it.
+//!
+//! A component routed on both handlers would have its messages bounce from one to the other
+//! forever, so the bridge marks the messages it forwards with its id, in their `MessageMeta`, and
+//! doesn't forward back the ones it marked. MQTT leaves the messages without payload empty, so the
+//! bridge counts the ones it forwards instead, and recognizes as many of them when they come back.
+//! The audio goes over MQTT as bare binary payloads, without meta either: it is only told apart by
+//! its site, an audio server can't be routed on both handlers for the same site.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::{error, trace, warn};
+
+use crate::meta::forwarding;
+use crate::*;
+
+/// One of the two handlers of a bridge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+struct Route {
+    component: HermesComponent,
+    side: Side,
+    site_ids: Option<Vec<String>>,
+}
+
+/// Builds a `Bridge`, create one with `Bridge::builder`
+pub struct BridgeBuilder {
+    routes: Vec<Route>,
+}
+
+impl BridgeBuilder {
+    /// The component runs on the handler of `side`, for every site. The voice activity is routed
+    /// along with the hotword, and the client apps have no messages of their own to route.
+    pub fn route(mut self, component: HermesComponent, side: Side) -> Self {
+        self.routes.push(Route {
+            component,
+            side,
+            site_ids: None,
+        });
+        self
+    }
+
+    /// The component runs on the handler of `side` for the given sites. The messages that are not
+    /// about a site, nlu queries or injections for example, are forwarded all the same.
+    pub fn route_sites<I, S>(mut self, component: HermesComponent, side: Side, site_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.routes.push(Route {
+            component,
+            side,
+            site_ids: Some(site_ids.into_iter().map(Into::into).collect()),
+        });
+        self
+    }
+
+    /// Subscribe to the messages of the routes, they are forwarded until the bridge is dropped
+    pub fn start(self, left: &dyn HermesProtocolHandler, right: &dyn HermesProtocolHandler) -> HermesResult<Bridge> {
+        let id: Arc<str> = new_request_id().into();
+        let left_echoes = Arc::new(Mutex::new(Echoes::default()));
+        let right_echoes = Arc::new(Mutex::new(Echoes::default()));
+
+        let mut subscriptions = vec![];
+        for route in self.routes {
+            let (from, to, from_echoes, to_echoes) = match route.side {
+                Side::Left => (left, right, &left_echoes, &right_echoes),
+                Side::Right => (right, left, &right_echoes, &left_echoes),
+            };
+            let site_ids = route.site_ids.map(Arc::new);
+            let wiring = Wiring {
+                from,
+                to,
+                outputs: Link {
+                    bridge_id: Arc::clone(&id),
+                    heard_on: Arc::clone(from_echoes),
+                    forwarded_to: Arc::clone(to_echoes),
+                    site_ids: site_ids.clone(),
+                },
+                orders: Link {
+                    bridge_id: Arc::clone(&id),
+                    heard_on: Arc::clone(to_echoes),
+                    forwarded_to: Arc::clone(from_echoes),
+                    site_ids,
+                },
+            };
+            subscriptions.extend(match route.component {
+                HermesComponent::AudioServer => wiring.audio_server()?,
+                HermesComponent::Hotword => wiring.hotword()?,
+                HermesComponent::Asr => wiring.asr()?,
+                HermesComponent::Nlu => wiring.nlu()?,
+                HermesComponent::Dialogue => wiring.dialogue()?,
+                HermesComponent::Tts => wiring.tts()?,
+                HermesComponent::Injection => wiring.injection()?,
+                HermesComponent::ClientApp => vec![],
+            });
+        }
+
+        Ok(Bridge {
+            _subscriptions: subscriptions,
+        })
+    }
+}
+
+/// A running bridge, messages are forwarded between its two handlers until it is dropped
+pub struct Bridge {
+    _subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl Bridge {
+    pub fn builder() -> BridgeBuilder {
+        BridgeBuilder { routes: vec![] }
+    }
+}
+
+/// The messages that can be routed per site
+trait OnSite {
+    fn site_id(&self) -> Option<&str> {
+        None
+    }
+}
+
+macro_rules! on_site {
+    ($($message:ty),*) => {
+        $(
+            impl OnSite for $message {
+                fn site_id(&self) -> Option<&str> {
+                    Some(&self.site_id)
+                }
+            }
+        )*
+    };
+}
+
+on_site!(
+    SiteMessage,
+    VadUpMessage,
+    VadDownMessage,
+    AsrStartListeningMessage,
+    TextCapturedMessage,
+    SayMessage,
+    PlayBytesMessage,
+    StreamBytesMessage,
+    AudioFrameMessage,
+    ReplayRequestMessage,
+    PlayFinishedMessage,
+    StreamFinishedMessage,
+    SessionQueuedMessage,
+    SessionStartedMessage,
+    IntentMessage,
+    IntentNotRecognizedMessage,
+    SessionEndedMessage
+);
+
+/// A hotword detected, along with its id
+impl OnSite for (String, HotwordDetectedMessage) {
+    fn site_id(&self) -> Option<&str> {
+        Some(&self.1.site_id)
+    }
+}
+
+impl OnSite for StartSessionMessage {
+    fn site_id(&self) -> Option<&str> {
+        self.site_id.as_deref()
+    }
+}
+
+impl OnSite for DialogueConfigureMessage {
+    fn site_id(&self) -> Option<&str> {
+        self.site_id.as_deref()
+    }
+}
+
+impl OnSite for RequestComponentReloadMessage {}
+impl OnSite for SayFinishedMessage {}
+impl OnSite for RegisterSoundMessage {}
+impl OnSite for NluQueryMessage {}
+impl OnSite for NluSlotQueryMessage {}
+impl OnSite for NluSlotMessage {}
+impl OnSite for NluIntentMessage {}
+impl OnSite for NluIntentNotRecognizedMessage {}
+impl OnSite for ContinueSessionMessage {}
+impl OnSite for EndSessionMessage {}
+impl OnSite for InjectionRequestMessage {}
+impl OnSite for InjectionStatusMessage {}
+impl OnSite for InjectionCompleteMessage {}
+impl OnSite for InjectionResetRequestMessage {}
+impl OnSite for InjectionResetCompleteMessage {}
+
+/// The messages without payload a bridge forwarded to a handler and hasn't heard back yet, by name
+#[derive(Default)]
+struct Echoes {
+    pending: HashMap<&'static str, usize>,
+}
+
+impl Echoes {
+    fn expect(&mut self, name: &'static str) {
+        *self.pending.entry(name).or_insert(0) += 1;
+    }
+
+    /// Whether a message heard on the handler is one the bridge forwarded, `marked` telling whether
+    /// it carries the mark of the bridge. It is forgotten then.
+    fn is_echo(&mut self, name: &'static str, marked: bool) -> bool {
+        match self.pending.get_mut(name) {
+            Some(pending) if *pending > 0 => {
+                *pending -= 1;
+                true
+            }
+            _ => marked,
+        }
+    }
+}
+
+/// Whether a message was forwarded by the bridge `bridge_id`
+fn marked_by(meta: Option<&MessageMeta>, bridge_id: &str) -> bool {
+    meta.and_then(|it| it.forwarded_by.as_deref()) == Some(bridge_id)
+}
+
+/// The way messages go, from the handler they are heard on to the other one
+struct Link {
+    bridge_id: Arc<str>,
+    heard_on: Arc<Mutex<Echoes>>,
+    forwarded_to: Arc<Mutex<Echoes>>,
+    site_ids: Option<Arc<Vec<String>>>,
+}
+
+impl Link {
+    fn forward<M, F, S, P>(&self, subscribe: S, to: &Arc<F>, publish: P) -> HermesResult<SubscriptionHandle>
+    where
+        M: OnSite + Clone + 'static,
+        F: ?Sized + Send + Sync + 'static,
+        S: FnOnce(Callback<M>) -> HermesResult<SubscriptionHandle>,
+        P: Fn(&F, M) -> HermesResult<()> + Send + Sync + 'static,
+    {
+        let bridge_id = Arc::clone(&self.bridge_id);
+        let site_ids = self.site_ids.clone();
+        let to = Arc::clone(to);
+        subscribe(Callback::with_meta(move |message: &M, meta: Option<&MessageMeta>| {
+            let routed = match (&site_ids, message.site_id()) {
+                (Some(site_ids), Some(site_id)) => site_ids.iter().any(|it| it == site_id),
+                _ => true,
+            };
+            if !routed {
+                return;
+            }
+            if marked_by(meta, &bridge_id) {
+                trace!("Not forwarding back a message the bridge published");
+                return;
+            }
+            if let Err(e) = forwarding(&bridge_id, || publish(&to, message.clone())) {
+                error!("Bridge could not forward a {}: {}", std::any::type_name::<M>(), e)
+            }
+        }))
+    }
+
+    /// Forward the messages without payload, `name` tells them apart
+    fn forward0<F, S, P>(
+        &self,
+        name: &'static str,
+        subscribe: S,
+        to: &Arc<F>,
+        publish: P,
+    ) -> HermesResult<SubscriptionHandle>
+    where
+        F: ?Sized + Send + Sync + 'static,
+        S: FnOnce(Callback0) -> HermesResult<SubscriptionHandle>,
+        P: Fn(&F) -> HermesResult<()> + Send + Sync + 'static,
+    {
+        let bridge_id = Arc::clone(&self.bridge_id);
+        let heard_on = Arc::clone(&self.heard_on);
+        let forwarded_to = Arc::clone(&self.forwarded_to);
+        let to = Arc::clone(to);
+        subscribe(Callback0::with_meta(move |meta: Option<&MessageMeta>| {
+            if pass(&heard_on, &forwarded_to, name, marked_by(meta, &bridge_id)) {
+                if let Err(e) = forwarding(&bridge_id, || publish(&to)) {
+                    error!("Bridge could not forward a {}: {}", name, e)
+                }
+            }
+        }))
+    }
+}
+
+/// Whether a message without payload goes through the bridge, it is then expected back on the
+/// handler it is forwarded to
+fn pass(heard_on: &Mutex<Echoes>, forwarded_to: &Mutex<Echoes>, name: &'static str, marked: bool) -> bool {
+    let echo = match heard_on.lock() {
+        Ok(mut echoes) => echoes.is_echo(name, marked),
+        Err(_) => {
+            error!("Could not lock the bridge to check a message");
+            return false;
+        }
+    };
+    if echo {
+        trace!("Not forwarding back a message the bridge published");
+        return false;
+    }
+    match forwarded_to.lock() {
+        Ok(mut echoes) => echoes.expect(name),
+        Err(_) => error!("Could not lock the bridge to remember a message"),
+    }
+    true
+}
+
+/// The subscriptions of a route, from the handler its component runs on to the other one
+struct Wiring<'a> {
+    from: &'a dyn HermesProtocolHandler,
+    to: &'a dyn HermesProtocolHandler,
+    /// The way of the messages the component publishes
+    outputs: Link,
+    /// The way of the orders it is given
+    orders: Link,
+}
+
+impl<'a> Wiring<'a> {
+    fn audio_server(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn AudioServerFacade> = self.from.audio_server().into();
+        let backend: Arc<dyn AudioServerBackendFacade> = self.to.audio_server_backend().into();
+        let mut subscriptions = vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_all_play_finished(callback),
+                &backend,
+                |it, m| it.publish_play_finished(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_all_stream_finished(callback),
+                &backend,
+                |it, m| it.publish_stream_finished(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_all_play_bytes(callback),
+                &facade,
+                |it, m| it.publish_play_bytes(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_all_stream_bytes(callback),
+                &facade,
+                |it, m| it.publish_stream_bytes(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_toggle_on(callback),
+                &facade,
+                |it, m| it.publish_toggle_on(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_toggle_off(callback),
+                &facade,
+                |it, m| it.publish_toggle_off(m),
+            )?,
+        ];
+        // the audio frames and the replays can only be subscribed to site by site
+        match &self.outputs.site_ids {
+            Some(site_ids) => {
+                for site_id in site_ids.iter() {
+                    subscriptions.push(self.outputs.forward(
+                        |callback| facade.subscribe_audio_frame(site_id.clone(), callback),
+                        &backend,
+                        |it, m| it.publish_audio_frame(m),
+                    )?);
+                    subscriptions.push(self.outputs.forward(
+                        |callback| facade.subscribe_replay_response(site_id.clone(), callback),
+                        &backend,
+                        |it, m| it.publish_replay_response(m),
+                    )?);
+                    subscriptions.push(self.orders.forward(
+                        |callback| backend.subscribe_replay_request(site_id.clone(), callback),
+                        &facade,
+                        |it, m| it.publish_replay_request(m),
+                    )?);
+                }
+            }
+            None => warn!("The audio frames are only bridged for the sites of the audio server routes"),
+        }
+        Ok(subscriptions)
+    }
+
+    fn hotword(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn HotwordFacade> = self.from.hotword().into();
+        let backend: Arc<dyn HotwordBackendFacade> = self.to.hotword_backend().into();
+        let voice_activity = self.from.voice_activity();
+        let voice_activity_backend: Arc<dyn VoiceActivityBackendFacade> = self.to.voice_activity_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_all_detected_with_id(callback),
+                &backend,
+                |it, (id, m)| it.publish_detected(id, m),
+            )?,
+            self.outputs.forward(
+                |callback| voice_activity.subscribe_all_vad_up(callback),
+                &voice_activity_backend,
+                |it, m| it.publish_vad_up(m),
+            )?,
+            self.outputs.forward(
+                |callback| voice_activity.subscribe_all_vad_down(callback),
+                &voice_activity_backend,
+                |it, m| it.publish_vad_down(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_toggle_on(callback),
+                &facade,
+                |it, m| it.publish_toggle_on(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_toggle_off(callback),
+                &facade,
+                |it, m| it.publish_toggle_off(m),
+            )?,
+        ])
+    }
+
+    fn asr(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn AsrFacade> = self.from.asr().into();
+        let backend: Arc<dyn AsrBackendFacade> = self.to.asr_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_text_captured(callback),
+                &backend,
+                |it, m| it.publish_text_captured(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_partial_text_captured(callback),
+                &backend,
+                |it, m| it.publish_partial_text_captured(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_start_listening(callback),
+                &facade,
+                |it, m| it.publish_start_listening(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_stop_listening(callback),
+                &facade,
+                |it, m| it.publish_stop_listening(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_component_reload(callback),
+                &facade,
+                |it, m| it.publish_component_reload(m),
+            )?,
+            self.orders.forward0(
+                "asr toggle on",
+                |callback| backend.subscribe_toggle_on(callback),
+                &facade,
+                |it| it.publish_toggle_on(),
+            )?,
+            self.orders.forward0(
+                "asr toggle off",
+                |callback| backend.subscribe_toggle_off(callback),
+                &facade,
+                |it| it.publish_toggle_off(),
+            )?,
+        ])
+    }
+
+    fn nlu(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn NluFacade> = self.from.nlu().into();
+        let backend: Arc<dyn NluBackendFacade> = self.to.nlu_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_slot_parsed(callback),
+                &backend,
+                |it, m| it.publish_slot_parsed(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_intent_parsed(callback),
+                &backend,
+                |it, m| it.publish_intent_parsed(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_intent_not_recognized(callback),
+                &backend,
+                |it, m| it.publish_intent_not_recognized(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_query(callback),
+                &facade,
+                |it, m| it.publish_query(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_partial_query(callback),
+                &facade,
+                |it, m| it.publish_partial_query(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_component_reload(callback),
+                &facade,
+                |it, m| it.publish_component_reload(m),
+            )?,
+        ])
+    }
+
+    fn dialogue(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn DialogueFacade> = self.from.dialogue().into();
+        let backend: Arc<dyn DialogueBackendFacade> = self.to.dialogue_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_session_queued(callback),
+                &backend,
+                |it, m| it.publish_session_queued(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_session_started(callback),
+                &backend,
+                |it, m| it.publish_session_started(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_intents(callback),
+                &backend,
+                |it, m| it.publish_intent(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_intent_not_recognized(callback),
+                &backend,
+                |it, m| it.publish_intent_not_recognized(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_session_ended(callback),
+                &backend,
+                |it, m| it.publish_session_ended(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_start_session(callback),
+                &facade,
+                |it, m| it.publish_start_session(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_continue_session(callback),
+                &facade,
+                |it, m| it.publish_continue_session(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_end_session(callback),
+                &facade,
+                |it, m| it.publish_end_session(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_configure(callback),
+                &facade,
+                |it, m| it.publish_configure(m),
+            )?,
+            self.orders.forward0(
+                "dialogue toggle on",
+                |callback| backend.subscribe_toggle_on(callback),
+                &facade,
+                |it| it.publish_toggle_on(),
+            )?,
+            self.orders.forward0(
+                "dialogue toggle off",
+                |callback| backend.subscribe_toggle_off(callback),
+                &facade,
+                |it| it.publish_toggle_off(),
+            )?,
+        ])
+    }
+
+    fn tts(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn TtsFacade> = self.from.tts().into();
+        let backend: Arc<dyn TtsBackendFacade> = self.to.tts_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_say_finished(callback),
+                &backend,
+                |it, m| it.publish_say_finished(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_say(callback),
+                &facade,
+                |it, m| it.publish_say(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_register_sound(callback),
+                &facade,
+                |it, m| it.publish_register_sound(m),
+            )?,
+        ])
+    }
+
+    fn injection(&self) -> HermesResult<Vec<SubscriptionHandle>> {
+        let facade: Arc<dyn InjectionFacade> = self.from.injection().into();
+        let backend: Arc<dyn InjectionBackendFacade> = self.to.injection_backend().into();
+        Ok(vec![
+            self.outputs.forward(
+                |callback| facade.subscribe_injection_status(callback),
+                &backend,
+                |it, m| it.publish_injection_status(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_injection_complete(callback),
+                &backend,
+                |it, m| it.publish_injection_complete(m),
+            )?,
+            self.outputs.forward(
+                |callback| facade.subscribe_injection_reset_complete(callback),
+                &backend,
+                |it, m| it.publish_injection_reset_complete(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_injection_request(callback),
+                &facade,
+                |it, m| it.publish_injection_request(m),
+            )?,
+            self.orders.forward(
+                |callback| backend.subscribe_injection_reset_request(callback),
+                &facade,
+                |it, m| it.publish_injection_reset_request(m),
+            )?,
+            self.orders.forward0(
+                "injection status request",
+                |callback| backend.subscribe_injection_status_request(callback),
+                &facade,
+                |it| it.publish_injection_status_request(),
+            )?,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn echoes_are_recognized_once() {
+        let heard_on = Mutex::new(Echoes::default());
+        let forwarded_to = Mutex::new(Echoes::default());
+
+        assert!(pass(&heard_on, &forwarded_to, "asr toggle on", false));
+        // the message comes back, without mark, on the handler it was forwarded to
+        assert!(!pass(&forwarded_to, &heard_on, "asr toggle on", false));
+        assert!(pass(&forwarded_to, &heard_on, "asr toggle on", false));
+        assert!(!pass(&forwarded_to, &heard_on, "asr toggle off", true));
+    }
+
+    #[test]
+    fn marks_tell_bridges_apart() {
+        let meta = forwarding("left", || MessageMeta::published(None));
+
+        assert!(marked_by(meta.as_ref(), "left"));
+        assert!(!marked_by(meta.as_ref(), "right"));
+        assert!(!marked_by(None, "left"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Protocol versions advertised by the components in their `VersionMessage`, and a check of the
+//! components reachable through a protocol handler against the version of this crate.
+
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+use semver::Version;
+
+use crate::errors::{HermesError, HermesResult};
+use crate::ontology::VersionMessage;
+use crate::{Callback, ComponentFacade, HermesProtocolHandler, IdentifiableComponentFacade, SubscriptionHandle};
+
+/// The optional features of the protocol supported by this crate
+pub const CAPABILITIES: &[&str] = &["message-meta"];
+
+/// The version of the protocol spoken by this crate, this is the version of the crate
+pub fn protocol_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("the crate version is a valid semver version")
+}
+
+/// Whether a component speaking the `remote` protocol version can talk with one speaking the
+/// `local` one. Versions are compatible when they share their major version, or their minor version
+/// while the major version is 0.
+pub fn is_compatible(local: &Version, remote: &Version) -> bool {
+    if local.major == 0 {
+        remote.major == 0 && local.minor == remote.minor
+    } else {
+        local.major == remote.major
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Compatibility {
+    Compatible,
+    /// The component speaks a protocol version incompatible with ours
+    Incompatible(Version),
+    /// The component replied but didn't tell which protocol version it speaks
+    Unknown,
+    /// The component didn't reply in time, it may not be running
+    NoReply,
+}
+
+/// The version reply of one component
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentVersionReport {
+    /// The name of the component, as in `HermesProtocolHandler`: `asr`, `dialogue`...
+    pub component: &'static str,
+    /// The site of the component, for the components running on each site
+    pub site_id: Option<String>,
+    pub version: Option<VersionMessage>,
+    pub compatibility: Compatibility,
+}
+
+/// The result of `check_compatibility`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatibilityReport {
+    /// The protocol version the components were checked against
+    pub protocol_version: Version,
+    pub components: Vec<ComponentVersionReport>,
+}
+
+impl CompatibilityReport {
+    /// The components speaking an incompatible protocol version
+    pub fn incompatibilities(&self) -> impl Iterator<Item = &ComponentVersionReport> {
+        self.components
+            .iter()
+            .filter(|it| matches!(it.compatibility, Compatibility::Incompatible(_)))
+    }
+
+    /// Whether no component speaks an incompatible protocol version, components that didn't reply
+    /// or didn't advertise their protocol version aren't held against it
+    pub fn is_compatible(&self) -> bool {
+        self.incompatibilities().next().is_none()
+    }
+
+    /// Fail with a `HermesError::Validation` listing the incompatible components, if there are some
+    pub fn ensure_compatible(&self) -> HermesResult<()> {
+        let incompatibilities = self
+            .incompatibilities()
+            .map(|it| match (&it.site_id, &it.compatibility) {
+                (Some(site_id), Compatibility::Incompatible(version)) => {
+                    format!("{} on site {} speaks {}", it.component, site_id, version)
+                }
+                (None, Compatibility::Incompatible(version)) => format!("{} speaks {}", it.component, version),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        if incompatibilities.is_empty() {
+            Ok(())
+        } else {
+            Err(HermesError::Validation(format!(
+                "incompatible with protocol version {}: {}",
+                self.protocol_version,
+                incompatibilities.join(", ")
+            )))
+        }
+    }
+}
+
+/// Gathers the version replies of the components
+struct VersionRequests {
+    components: Vec<(&'static str, Option<String>)>,
+    subscriptions: Vec<SubscriptionHandle>,
+    sender: mpsc::Sender<(usize, VersionMessage)>,
+}
+
+impl VersionRequests {
+    fn callback(&self) -> Callback<VersionMessage> {
+        let index = self.components.len();
+        let sender = Mutex::new(self.sender.clone());
+        Callback::new(move |version: &VersionMessage| {
+            if let Ok(sender) = sender.lock() {
+                // the check may already be over, nothing to do then
+                let _ = sender.send((index, version.clone()));
+            }
+        })
+    }
+
+    fn request<F: ComponentFacade + ?Sized>(&mut self, component: &'static str, facade: &F) -> HermesResult<()> {
+        let subscription = facade.subscribe_version(self.callback())?;
+        self.subscriptions.push(subscription);
+        self.components.push((component, None));
+        facade.publish_version_request()
+    }
+
+    fn request_on_sites<F: IdentifiableComponentFacade + ?Sized>(
+        &mut self,
+        component: &'static str,
+        facade: &F,
+        site_ids: &[String],
+    ) -> HermesResult<()> {
+        for site_id in site_ids {
+            let subscription = facade.subscribe_version(site_id.clone(), self.callback())?;
+            self.subscriptions.push(subscription);
+            self.components.push((component, Some(site_id.clone())));
+            facade.publish_version_request(site_id.clone())?;
+        }
+        Ok(())
+    }
+}
+
+/// Request the version of every component, the ones running on each site are asked on every site
+/// of `site_ids`, and report how their protocol version compares with ours. Replies are awaited
+/// for at most `timeout`. This blocks, don't call it from a callback of `handler`.
+pub fn check_compatibility<H: HermesProtocolHandler + ?Sized>(
+    handler: &H,
+    site_ids: &[String],
+    timeout: Duration,
+) -> HermesResult<CompatibilityReport> {
+    let (sender, receiver) = mpsc::channel();
+    let mut requests = VersionRequests {
+        components: vec![],
+        subscriptions: vec![],
+        sender,
+    };
+    requests.request("asr", &*handler.asr())?;
+    requests.request("dialogue", &*handler.dialogue())?;
+    requests.request("injection", &*handler.injection())?;
+    requests.request("nlu", &*handler.nlu())?;
+    requests.request("tts", &*handler.tts())?;
+    requests.request_on_sites("audio_server", &*handler.audio_server(), site_ids)?;
+    requests.request_on_sites("hotword", &*handler.hotword(), site_ids)?;
+    requests.request_on_sites("voice_activity", &*handler.voice_activity(), site_ids)?;
+
+    let mut versions = vec![None; requests.components.len()];
+    let mut missing = versions.len();
+    let deadline = Instant::now() + timeout;
+    while missing > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        match receiver.recv_timeout(deadline - now) {
+            Ok((index, version)) => {
+                if versions[index].is_none() {
+                    missing -= 1;
+                }
+                versions[index] = Some(version);
+            }
+            Err(_) => break,
+        }
+    }
+    for subscription in requests.subscriptions {
+        subscription.unsubscribe()?;
+    }
+
+    let protocol_version = protocol_version();
+    let components = requests
+        .components
+        .into_iter()
+        .zip(versions)
+        .map(|((component, site_id), version)| {
+            let compatibility = match version.as_ref().map(|it| it.protocol_version.as_ref()) {
+                None => Compatibility::NoReply,
+                Some(None) => Compatibility::Unknown,
+                Some(Some(remote)) if is_compatible(&protocol_version, remote) => Compatibility::Compatible,
+                Some(Some(remote)) => Compatibility::Incompatible(remote.clone()),
+            };
+            ComponentVersionReport {
+                component,
+                site_id,
+                version,
+                compatibility,
+            }
+        })
+        .collect();
+
+    Ok(CompatibilityReport {
+        protocol_version,
+        components,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn compatibility_follows_semver() {
+        assert!(is_compatible(&version("0.69.0"), &version("0.69.3")));
+        assert!(!is_compatible(&version("0.69.0"), &version("0.70.0")));
+        assert!(is_compatible(&version("1.2.0"), &version("1.0.0")));
+        assert!(!is_compatible(&version("1.2.0"), &version("2.0.0")));
+    }
+
+    #[test]
+    fn report_lists_the_incompatible_components() {
+        let report = CompatibilityReport {
+            protocol_version: version("0.69.0"),
+            components: vec![
+                ComponentVersionReport {
+                    component: "tts",
+                    site_id: None,
+                    version: None,
+                    compatibility: Compatibility::NoReply,
+                },
+                ComponentVersionReport {
+                    component: "hotword",
+                    site_id: Some("kitchen".into()),
+                    version: None,
+                    compatibility: Compatibility::Incompatible(version("0.60.0")),
+                },
+            ],
+        };
+
+        assert!(!report.is_compatible());
+        match report.ensure_compatible() {
+            Err(HermesError::Validation(reason)) => assert!(reason.contains("hotword on site kitchen speaks 0.60.0")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn version_message_advertises_the_protocol_version() {
+        let message = VersionMessage::new(version("1.0.0"));
+
+        assert_eq!(message.protocol_version, Some(protocol_version()));
+        assert!(message.capabilities.contains(&"message-meta".to_string()));
+    }
+
+    #[test]
+    fn version_message_of_older_components_still_decodes() {
+        let message: VersionMessage = serde_json::from_str(r#"{"version": "1.0.0"}"#).unwrap();
+
+        assert_eq!(message.protocol_version, None);
+        assert!(message.capabilities.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Support for the request/response flows of the ontology, where a response is correlated with its
+//! request by an id (a `SayMessage` and its `SayFinishedMessage` for example).
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use crate::errors::{HermesError, HermesResult};
+use crate::ontology::{
+    ComponentLoadedMessage, NluIntentMessage, NluIntentNotRecognizedMessage, RequestComponentReloadMessage,
+};
+use crate::{Callback, SubscriptionHandle};
+
+/// The response to a `NluQueryMessage`
+#[derive(Debug, Clone, PartialEq)]
+pub enum NluQueryResponse {
+    IntentParsed(NluIntentMessage),
+    IntentNotRecognized(NluIntentNotRecognizedMessage),
+}
+
+/// Generate an id suitable to correlate a request with its response, unique across the processes
+/// of a host and very unlikely to collide across hosts.
+pub fn new_request_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let now = chrono::Utc::now();
+    format!(
+        "{:x}-{:x}-{:x}",
+        std::process::id(),
+        // the nanoseconds overflow in 2262, the counter keeps the ids unique with milliseconds
+        now.timestamp_nanos_opt().unwrap_or_else(|| now.timestamp_millis()),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Ask a component to reload and wait for it to be loaded again, an id is generated for the request
+/// if it doesn't have one. `subscribe` and `publish` are the ones of the facade of the component.
+pub(crate) fn reload_and_wait<S, P>(
+    mut component_reload: RequestComponentReloadMessage,
+    timeout: Duration,
+    subscribe: S,
+    publish: P,
+) -> HermesResult<ComponentLoadedMessage>
+where
+    S: FnOnce(Callback<ComponentLoadedMessage>) -> HermesResult<SubscriptionHandle>,
+    P: FnOnce(RequestComponentReloadMessage) -> HermesResult<()>,
+{
+    if component_reload.id.is_empty() {
+        component_reload.id = new_request_id();
+    }
+    let id = component_reload.id.clone();
+    let mut response = PendingResponse::new();
+    let callback = response.callback(move |loaded: &ComponentLoadedMessage| {
+        Some(loaded).filter(|it| it.id.as_ref() == Some(&id)).cloned()
+    });
+    response.keep(subscribe(callback)?);
+    publish(component_reload)?;
+    response.wait(timeout)
+}
+
+/// Collects the first response matching a request from one or more subscriptions.
+pub(crate) struct PendingResponse<T> {
+    sender: mpsc::Sender<T>,
+    receiver: mpsc::Receiver<T>,
+    subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl<T: Send + 'static> PendingResponse<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            subscriptions: vec![],
+        }
+    }
+
+    /// Build a callback for a subscription, `accept` returns the response when the message matches
+    pub fn callback<M, F>(&self, accept: F) -> Callback<M>
+    where
+        F: Fn(&M) -> Option<T> + Send + Sync + 'static,
+    {
+        let sender = Mutex::new(self.sender.clone());
+        Callback::new(move |message: &M| {
+            if let Some(response) = accept(message) {
+                if let Ok(sender) = sender.lock() {
+                    // the request may have already been resolved or timed out, nothing to do then
+                    let _ = sender.send(response);
+                }
+            }
+        })
+    }
+
+    /// Keep a subscription alive until the response is received
+    pub fn keep(&mut self, subscription: SubscriptionHandle) {
+        self.subscriptions.push(subscription)
+    }
+
+    pub fn wait(self, timeout: Duration) -> HermesResult<T> {
+        // we hold a sender, so the only way for this to fail is to time out
+        let response = self
+            .receiver
+            .recv_timeout(timeout)
+            .map_err(|_| HermesError::Timeout(timeout))?;
+        for subscription in self.subscriptions {
+            subscription.unsubscribe()?;
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,177 @@
+//! Decoding of the JSON messages when the components on the bus don't all run the same version of
+//! the ontology: an older component may leave out a field that is now required, a newer one may
+//! send fields unknown to this one.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::{HermesError, HermesResult};
+use crate::meta::META_FIELD;
+
+/// How the protocol handlers decode the JSON messages they receive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodePolicy {
+    /// Unknown fields are ignored, messages missing a required field are rejected
+    Standard,
+    /// Unknown fields are ignored, and the few required fields that older components may leave out
+    /// (scores, durations and lists of slots, see `lenient_defaults`) are given an empty value
+    /// when they are missing. Only the fields at the top of a message are filled, a message missing
+    /// a field of one of its parts, or a field identifying it, its session or its site, is rejected
+    Lenient,
+    /// Messages with unknown fields are rejected too, for conformance testing
+    Strict,
+}
+
+impl Default for DecodePolicy {
+    fn default() -> Self {
+        DecodePolicy::Standard
+    }
+}
+
+/// A decoded message, with the fields it lacked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded<M> {
+    pub message: M,
+    /// The required fields that were missing from the message and were given an empty value, only
+    /// the lenient policy fills them
+    pub defaulted: Vec<String>,
+}
+
+impl DecodePolicy {
+    /// Decode a JSON message following this policy
+    pub fn decode<M: DeserializeOwned + Serialize>(self, payload: &[u8]) -> HermesResult<Decoded<M>> {
+        match self {
+            DecodePolicy::Standard => Ok(Decoded {
+                message: serde_json::from_slice(payload)?,
+                defaulted: vec![],
+            }),
+            DecodePolicy::Strict => {
+                let value: Value = serde_json::from_slice(payload)?;
+                let message = M::deserialize(&value)?;
+                let mut unknown = vec![];
+                unknown_fields(&value, &serde_json::to_value(&message)?, "", &mut unknown);
+                if !unknown.is_empty() {
+                    return Err(HermesError::codec(format!("unknown fields {}", unknown.join(", "))));
+                }
+                Ok(Decoded {
+                    message,
+                    defaulted: vec![],
+                })
+            }
+            DecodePolicy::Lenient => {
+                let mut value: Value = serde_json::from_slice(payload)?;
+                let error = match M::deserialize(&value) {
+                    Ok(message) => {
+                        return Ok(Decoded {
+                            message,
+                            defaulted: vec![],
+                        })
+                    }
+                    Err(e) => e,
+                };
+                let fields = match &mut value {
+                    Value::Object(fields) => fields,
+                    _ => return Err(error.into()),
+                };
+                let mut filled = vec![];
+                for (field, default) in lenient_defaults() {
+                    if !fields.contains_key(field) {
+                        fields.insert(field.to_string(), default);
+                        filled.push(field);
+                    }
+                }
+                let message = match M::deserialize(&value) {
+                    Ok(message) => message,
+                    Err(_) => return Err(error.into()),
+                };
+                // the fields the message doesn't have were ignored rather than defaulted
+                let decoded = serde_json::to_value(&message)?;
+                let defaulted = filled
+                    .into_iter()
+                    .filter(|field| decoded.get(field).is_some())
+                    .map(str::to_string)
+                    .collect();
+                Ok(Decoded { message, defaulted })
+            }
+        }
+    }
+}
+
+/// The fields that older components may leave out, with the value the lenient policy gives them.
+/// They are measures or lists of slots, which a consumer can do without
+fn lenient_defaults() -> Vec<(&'static str, Value)> {
+    vec![
+        ("confidenceScore", Value::from(0.0)),
+        ("likelihood", Value::from(0.0)),
+        ("seconds", Value::from(0.0)),
+        ("slots", Value::Array(vec![])),
+    ]
+}
+
+/// Collect the fields of a JSON message that didn't make it to the message decoded from it,
+/// `decoded` being that message serialized back
+fn unknown_fields(input: &Value, decoded: &Value, path: &str, unknown: &mut Vec<String>) {
+    match (input, decoded) {
+        (Value::Object(input), Value::Object(decoded)) => {
+            for (name, value) in input.iter().filter(|(name, _)| *name != META_FIELD) {
+                let field = if path.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                match decoded.get(name) {
+                    Some(decoded) => unknown_fields(value, decoded, &field, unknown),
+                    // the fields left out when serializing back are the ones without a value
+                    None if !value.is_null() => unknown.push(field),
+                    None => {}
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(decoded)) => {
+            for (index, (value, decoded)) in input.iter().zip(decoded).enumerate() {
+                unknown_fields(value, decoded, &format!("{}[{}]", path, index), unknown)
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ontology::{SayMessage, TextCapturedMessage};
+
+    #[test]
+    fn lenient_decoding_fills_the_missing_fields() {
+        let payload = br#"{"text": "hello", "siteId": "default", "volume": 0.5}"#;
+
+        assert!(DecodePolicy::Standard.decode::<TextCapturedMessage>(payload).is_err());
+        let decoded = DecodePolicy::Lenient.decode::<TextCapturedMessage>(payload).unwrap();
+        assert_eq!(decoded.message.text, "hello");
+        assert_eq!(decoded.message.likelihood, 0.0);
+        assert_eq!(decoded.message.seconds, 0.0);
+        assert_eq!(decoded.defaulted, vec!["likelihood", "seconds"]);
+
+        // what identifies a message, its session or its site is never made up
+        assert!(DecodePolicy::Lenient
+            .decode::<SayMessage>(br#"{"lang": "en"}"#)
+            .is_err());
+        assert!(DecodePolicy::Lenient.decode::<SayMessage>(br#"{"text": 42}"#).is_err());
+    }
+
+    #[test]
+    fn strict_decoding_rejects_the_unknown_fields() {
+        let payload = br#"{"text": "hello", "siteId": "default", "lang": null, "_meta": {"id": "1"}}"#;
+        let decoded = DecodePolicy::Strict.decode::<SayMessage>(payload).unwrap();
+        assert_eq!(decoded.message.text, "hello");
+        assert!(decoded.defaulted.is_empty());
+
+        let payload = br#"{"text": "hello", "siteId": "default", "volume": 0.5}"#;
+        assert!(DecodePolicy::Standard.decode::<SayMessage>(payload).is_ok());
+        match DecodePolicy::Strict.decode::<SayMessage>(payload) {
+            Err(HermesError::Codec(e)) => assert!(e.to_string().contains("volume")),
+            other => panic!("expected a codec error, got {:?}", other),
+        }
+    }
+}
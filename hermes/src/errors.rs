@@ -1,4 +1,99 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::sync::PoisonError;
+use std::time::Duration;
+
+/// The result of the operations of hermes
+pub type HermesResult<T> = Result<T, HermesError>;
+
+/// The errors hermes can report, whatever the protocol handler in use
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HermesError {
+    /// The transport failed to carry a message, for example the MQTT broker couldn't be reached
+    Transport(Box<dyn Error + Send + Sync>),
+    /// A message couldn't be encoded to, or decoded from, its wire format
+    Codec(Box<dyn Error + Send + Sync>),
+    /// The protocol handler behind the facade (or the in-process bus) was shut down
+    Closed,
+    /// A message was rejected because it is not valid
+    Validation(String),
+    /// No response to a request was received in time
+    Timeout(Duration),
+    /// A lock was poisoned, which means a thread panicked while holding it
+    PoisonLock,
+    /// Reading or writing a file failed, for example a recording of the traffic
+    Io(io::Error),
+    /// The configuration of a protocol handler is invalid, or names a transport that isn't
+    /// registered
+    Config(String),
+}
+
+impl HermesError {
+    pub fn transport<E: Into<Box<dyn Error + Send + Sync>>>(error: E) -> Self {
+        HermesError::Transport(error.into())
+    }
+
+    pub fn codec<E: Into<Box<dyn Error + Send + Sync>>>(error: E) -> Self {
+        HermesError::Codec(error.into())
+    }
+}
+
+impl fmt::Display for HermesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HermesError::Transport(e) => write!(f, "Transport error: {}", e),
+            HermesError::Codec(e) => write!(f, "Codec error: {}", e),
+            HermesError::Closed => write!(f, "The protocol handler was closed"),
+            HermesError::Validation(reason) => write!(f, "Invalid message: {}", reason),
+            HermesError::Timeout(timeout) => write!(f, "No response received within {:?}", timeout),
+            HermesError::PoisonLock => write!(f, "Can't lock thread"),
+            HermesError::Io(e) => write!(f, "I/O error: {}", e),
+            HermesError::Config(reason) => write!(f, "Invalid configuration: {}", reason),
+        }
+    }
+}
+
+impl Error for HermesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HermesError::Transport(e) | HermesError::Codec(e) => Some(&**e),
+            HermesError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for HermesError {
+    fn from(e: serde_json::Error) -> Self {
+        HermesError::codec(e)
+    }
+}
+
+impl From<io::Error> for HermesError {
+    fn from(e: io::Error) -> Self {
+        HermesError::Io(e)
+    }
+}
+
+impl From<PoisonLock> for HermesError {
+    fn from(_: PoisonLock) -> Self {
+        HermesError::PoisonLock
+    }
+}
+
+/// A message a protocol handler received but could not decode, see
+/// `HermesProtocolHandler::subscribe_decode_errors`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    /// The topic the message was received on
+    pub topic: String,
+    /// The raw payload of the message
+    pub payload: Vec<u8>,
+    /// Why it could not be decoded
+    pub error: String,
+}
 
 #[derive(Debug, Fail)]
 #[fail(display = "Can't lock thread")]
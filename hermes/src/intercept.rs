@@ -0,0 +1,865 @@
+//! Interception of the messages going through a protocol handler, whatever its transport. An
+//! `InterceptedHandler` wraps a handler so that a stack of interceptors sees every message
+//! published through its facades before the transport does, and every message delivered before
+//! the callback does. Interceptors can log or measure the messages, rewrite them (to alias a site
+//! for example), or stop them by returning an error.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::*;
+
+/// Which way an intercepted message is going
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Published through a facade, on its way to the transport
+    Publish,
+    /// Received from the transport, on its way to a callback
+    Deliver,
+}
+
+/// What the interceptors can do with a message, whatever its type
+trait Payload {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn to_json(&self) -> serde_json::Result<serde_json::Value>;
+    fn set_json(&mut self, json: serde_json::Value) -> serde_json::Result<()>;
+}
+
+impl<T: Serialize + DeserializeOwned + 'static> Payload for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn to_json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+
+    fn set_json(&mut self, json: serde_json::Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(json)?;
+        Ok(())
+    }
+}
+
+/// A message going through an `InterceptedHandler`, along with where it goes
+pub struct Intercepted<'a> {
+    direction: Direction,
+    component: HermesComponent,
+    topic: String,
+    payload: Option<&'a mut dyn Payload>,
+}
+
+impl<'a> Intercepted<'a> {
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The component the message is about, the voice activity and the sound feedback being handled
+    /// by the hotword component
+    pub fn component(&self) -> &HermesComponent {
+        &self.component
+    }
+
+    /// The topic the message travels on with MQTT, whatever the transport. The parts of the topic
+    /// that neither the message nor the subscription tell, like the id of a hotword detected, are
+    /// `+`. The topic is computed before the message goes through the interceptors.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// The message, if it is a `T`. Toggles and other messages without payload are nothing.
+    pub fn message<T: 'static>(&self) -> Option<&T> {
+        self.payload.as_ref().and_then(|it| it.as_any().downcast_ref())
+    }
+
+    /// The message to rewrite, if it is a `T`
+    pub fn message_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.payload.as_mut().and_then(|it| it.as_any_mut().downcast_mut())
+    }
+
+    /// The message encoded in JSON, `None` for the messages without payload
+    pub fn to_json(&self) -> HermesResult<Option<serde_json::Value>> {
+        Ok(self.payload.as_ref().map(|it| it.to_json()).transpose()?)
+    }
+
+    /// Replace the message with the one encoded in `json`, which must be of the same type. This
+    /// does nothing for the messages without payload.
+    pub fn set_json(&mut self, json: serde_json::Value) -> HermesResult<()> {
+        if let Some(payload) = self.payload.as_mut() {
+            payload.set_json(json)?
+        }
+        Ok(())
+    }
+}
+
+/// Sees the messages going through an `InterceptedHandler`. A closure taking an `Intercepted`
+/// is an interceptor seeing the messages going both ways.
+pub trait Interceptor: Send + Sync {
+    /// Called before a message is given to the transport, an error cancels the publish and is
+    /// returned to the publisher
+    fn on_publish(&self, _message: &mut Intercepted) -> HermesResult<()> {
+        Ok(())
+    }
+
+    /// Called before a message is given to a callback, an error drops the message
+    fn on_deliver(&self, _message: &mut Intercepted) -> HermesResult<()> {
+        Ok(())
+    }
+}
+
+impl<F> Interceptor for F
+where
+    F: Fn(&mut Intercepted) -> HermesResult<()> + Send + Sync,
+{
+    fn on_publish(&self, message: &mut Intercepted) -> HermesResult<()> {
+        self(message)
+    }
+
+    fn on_deliver(&self, message: &mut Intercepted) -> HermesResult<()> {
+        self(message)
+    }
+}
+
+type Interceptors = Arc<Vec<Arc<dyn Interceptor>>>;
+
+/// Run the interceptors, in the order they were added, until one of them fails
+fn intercept(interceptors: &[Arc<dyn Interceptor>], message: &mut Intercepted) -> HermesResult<()> {
+    for interceptor in interceptors {
+        match message.direction {
+            Direction::Publish => interceptor.on_publish(message)?,
+            Direction::Deliver => interceptor.on_deliver(message)?,
+        }
+    }
+    Ok(())
+}
+
+/// A protocol handler whose messages go through a stack of interceptors, see `Interceptor`
+pub struct InterceptedHandler<H> {
+    handler: H,
+    interceptors: Interceptors,
+}
+
+impl<H: HermesProtocolHandler> InterceptedHandler<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            interceptors: Arc::new(vec![]),
+        }
+    }
+
+    /// Add an interceptor on top of the stack: it sees the messages after the interceptors added
+    /// before it, whichever way they go. The facades already created keep the interceptors they
+    /// were created with.
+    pub fn with<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+        let mut interceptors = self.interceptors.to_vec();
+        interceptors.push(Arc::new(interceptor));
+        self.interceptors = Arc::new(interceptors);
+        self
+    }
+
+    /// The handler the messages go through once intercepted
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn into_inner(self) -> H {
+        self.handler
+    }
+
+    fn wrap<F: ?Sized>(&self, inner: Box<F>, component: HermesComponent, path: &'static str) -> Box<Facade<F>> {
+        Box::new(Facade {
+            inner,
+            component,
+            path,
+            interceptors: Arc::clone(&self.interceptors),
+        })
+    }
+}
+
+/// A facade of the wrapped handler, along with the interceptors its messages go through
+struct Facade<F: ?Sized> {
+    component: HermesComponent,
+    /// What the topics of the facade start with, like `hermes/tts`
+    path: &'static str,
+    interceptors: Interceptors,
+    inner: Box<F>,
+}
+
+impl<F: ?Sized> Facade<F> {
+    fn topic(&self, command: &str) -> String {
+        format!("{}/{}", self.path, command)
+    }
+
+    fn topic_of(&self, id: &str, command: &str) -> String {
+        format!("{}/{}/{}", self.path, id, command)
+    }
+
+    fn publish<M: Payload>(&self, topic: String, mut message: M) -> HermesResult<M> {
+        intercept(
+            &self.interceptors,
+            &mut Intercepted {
+                direction: Direction::Publish,
+                component: self.component.clone(),
+                topic,
+                payload: Some(&mut message),
+            },
+        )?;
+        Ok(message)
+    }
+
+    fn publish0(&self, topic: String) -> HermesResult<()> {
+        intercept(
+            &self.interceptors,
+            &mut Intercepted {
+                direction: Direction::Publish,
+                component: self.component.clone(),
+                topic,
+                payload: None,
+            },
+        )
+    }
+
+    /// The callback to give the wrapped facade, it intercepts a copy of the messages before giving
+    /// them to `callback`
+    fn deliver<M, T>(&self, callback: Callback<M>, topic: T) -> Callback<M>
+    where
+        M: Payload + Clone + 'static,
+        T: Fn(&M) -> String + Send + Sync + 'static,
+    {
+        if self.interceptors.is_empty() {
+            return callback;
+        }
+        let interceptors = Arc::clone(&self.interceptors);
+        let component = self.component.clone();
+        Callback::with_meta(move |message: &M, meta| {
+            let mut message = message.clone();
+            let mut intercepted = Intercepted {
+                direction: Direction::Deliver,
+                component: component.clone(),
+                topic: topic(&message),
+                payload: Some(&mut message),
+            };
+            match intercept(&interceptors, &mut intercepted) {
+                Ok(()) => callback.call_with_meta(&message, meta),
+                Err(e) => debug!("Message on {} dropped by an interceptor: {}", intercepted.topic, e),
+            }
+        })
+    }
+
+    fn deliver0(&self, callback: Callback0, topic: String) -> Callback0 {
+        if self.interceptors.is_empty() {
+            return callback;
+        }
+        let interceptors = Arc::clone(&self.interceptors);
+        let component = self.component.clone();
+        Callback0::with_meta(move |meta| {
+            let mut intercepted = Intercepted {
+                direction: Direction::Deliver,
+                component: component.clone(),
+                topic: topic.clone(),
+                payload: None,
+            };
+            match intercept(&interceptors, &mut intercepted) {
+                Ok(()) => callback.call_with_meta(meta),
+                Err(e) => debug!("Message on {} dropped by an interceptor: {}", topic, e),
+            }
+        })
+    }
+}
+
+/// A publish with a payload: the topic is computed from the arguments, then the message goes
+/// through the interceptors before being published by the wrapped facade
+macro_rules! p {
+    ($name:ident($($arg:ident: $arg_t:ty),*) $message:ident: $t:ty => $topic:expr) => {
+        fn $name(&self, $($arg: $arg_t,)* $message: $t) -> HermesResult<()> {
+            let topic: String = ($topic).into();
+            let $message = self.publish(topic, $message)?;
+            self.inner.$name($($arg,)* $message)
+        }
+    };
+}
+
+/// A subscription with a payload: the topic is computed from each message delivered, with the
+/// arguments of the subscription
+macro_rules! s {
+    ($name:ident($($arg:ident: $arg_t:ty),*) $t:ty => |$m:pat| $topic:expr) => {
+        fn $name(&self, $($arg: $arg_t,)* handler: Callback<$t>) -> HermesResult<SubscriptionHandle> {
+            let handler = {
+                $(
+                    #[allow(unused_variables)]
+                    let $arg = $arg.clone();
+                )*
+                self.deliver(handler, move |$m: &$t| ($topic).into())
+            };
+            self.inner.$name($($arg,)* handler)
+        }
+    };
+}
+
+macro_rules! impl_component_facades {
+    ($($t:ty),*) => {
+        $(
+            impl ComponentFacade for Facade<$t> {
+                fn publish_version_request(&self) -> HermesResult<()> {
+                    self.publish0(self.topic("versionRequest"))?;
+                    self.inner.publish_version_request()
+                }
+
+                fn subscribe_version(&self, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic("version");
+                    self.inner.subscribe_version(self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic("error");
+                    self.inner.subscribe_error(self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_component_loaded(
+                    &self,
+                    handler: Callback<ComponentLoadedMessage>,
+                ) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic("loaded");
+                    self.inner.subscribe_component_loaded(self.deliver(handler, move |_| topic.clone()))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_component_backend_facades {
+    ($($t:ty),*) => {
+        $(
+            impl ComponentBackendFacade for Facade<$t> {
+                fn subscribe_version_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+                    self.inner.subscribe_version_request(self.deliver0(handler, self.topic("versionRequest")))
+                }
+
+                fn publish_version(&self, version: VersionMessage) -> HermesResult<()> {
+                    let version = self.publish(self.topic("version"), version)?;
+                    self.inner.publish_version(version)
+                }
+
+                fn publish_error(&self, error: ErrorMessage) -> HermesResult<()> {
+                    let error = self.publish(self.topic("error"), error)?;
+                    self.inner.publish_error(error)
+                }
+
+                fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> HermesResult<()> {
+                    let component_loaded = self.publish(self.topic("loaded"), component_loaded)?;
+                    self.inner.publish_component_loaded(component_loaded)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_identifiable_component_facades {
+    ($($t:ty),*) => {
+        $(
+            impl IdentifiableComponentFacade for Facade<$t> {
+                fn publish_version_request(&self, id: String) -> HermesResult<()> {
+                    self.publish0(self.topic_of(&id, "versionRequest"))?;
+                    self.inner.publish_version_request(id)
+                }
+
+                fn subscribe_version(
+                    &self,
+                    id: String,
+                    handler: Callback<VersionMessage>,
+                ) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic_of(&id, "version");
+                    self.inner.subscribe_version(id, self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_error(
+                    &self,
+                    id: String,
+                    handler: Callback<SiteErrorMessage>,
+                ) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic_of(&id, "error");
+                    self.inner.subscribe_error(id, self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle> {
+                    let path = self.path;
+                    self.inner.subscribe_all_error(
+                        self.deliver(handler, move |error| format!("{}/{}/error", path, error.site_id)),
+                    )
+                }
+
+                fn subscribe_component_loaded(
+                    &self,
+                    id: String,
+                    handler: Callback<ComponentLoadedOnSiteMessage>,
+                ) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic_of(&id, "loaded");
+                    self.inner.subscribe_component_loaded(id, self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_all_component_loaded(
+                    &self,
+                    handler: Callback<ComponentLoadedOnSiteMessage>,
+                ) -> HermesResult<SubscriptionHandle> {
+                    let path = self.path;
+                    self.inner.subscribe_all_component_loaded(
+                        self.deliver(handler, move |loaded| format!("{}/{}/loaded", path, loaded.site_id)),
+                    )
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_identifiable_component_backend_facades {
+    ($($t:ty),*) => {
+        $(
+            impl IdentifiableComponentBackendFacade for Facade<$t> {
+                fn subscribe_version_request(&self, id: String, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+                    let handler = self.deliver0(handler, self.topic_of(&id, "versionRequest"));
+                    self.inner.subscribe_version_request(id, handler)
+                }
+
+                fn publish_version(&self, id: String, version: VersionMessage) -> HermesResult<()> {
+                    let version = self.publish(self.topic_of(&id, "version"), version)?;
+                    self.inner.publish_version(id, version)
+                }
+
+                fn publish_error(&self, id: String, error: SiteErrorMessage) -> HermesResult<()> {
+                    let error = self.publish(self.topic_of(&id, "error"), error)?;
+                    self.inner.publish_error(id, error)
+                }
+
+                fn publish_component_loaded(
+                    &self,
+                    id: String,
+                    component_loaded: ComponentLoadedOnSiteMessage,
+                ) -> HermesResult<()> {
+                    let component_loaded = self.publish(self.topic_of(&id, "loaded"), component_loaded)?;
+                    self.inner.publish_component_loaded(id, component_loaded)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_toggleable_facades {
+    ($($t:ty),*) => {
+        $(
+            impl ToggleableFacade for Facade<$t> {
+                fn publish_toggle_on(&self) -> HermesResult<()> {
+                    self.publish0(self.topic("toggleOn"))?;
+                    self.inner.publish_toggle_on()
+                }
+
+                fn publish_toggle_off(&self) -> HermesResult<()> {
+                    self.publish0(self.topic("toggleOff"))?;
+                    self.inner.publish_toggle_off()
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_toggleable_backend_facades {
+    ($($t:ty),*) => {
+        $(
+            impl ToggleableBackendFacade for Facade<$t> {
+                fn subscribe_toggle_on(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+                    self.inner.subscribe_toggle_on(self.deliver0(handler, self.topic("toggleOn")))
+                }
+
+                fn subscribe_toggle_off(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+                    self.inner.subscribe_toggle_off(self.deliver0(handler, self.topic("toggleOff")))
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_identifiable_toggleable_facades {
+    ($($t:ty),*) => {
+        $(
+            impl IdentifiableToggleableFacade for Facade<$t> {
+                fn publish_toggle_on(&self, site: SiteMessage) -> HermesResult<()> {
+                    let site = self.publish(self.topic("toggleOn"), site)?;
+                    self.inner.publish_toggle_on(site)
+                }
+
+                fn publish_toggle_off(&self, site: SiteMessage) -> HermesResult<()> {
+                    let site = self.publish(self.topic("toggleOff"), site)?;
+                    self.inner.publish_toggle_off(site)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_identifiable_toggleable_backend_facades {
+    ($($t:ty),*) => {
+        $(
+            impl IdentifiableToggleableBackendFacade for Facade<$t> {
+                fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic("toggleOn");
+                    self.inner.subscribe_toggle_on(self.deliver(handler, move |_| topic.clone()))
+                }
+
+                fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle> {
+                    let topic = self.topic("toggleOff");
+                    self.inner.subscribe_toggle_off(self.deliver(handler, move |_| topic.clone()))
+                }
+            }
+        )*
+    };
+}
+
+impl_component_facades!(
+    dyn AsrFacade,
+    dyn TtsFacade,
+    dyn NluFacade,
+    dyn DialogueFacade,
+    dyn InjectionFacade
+);
+impl_component_backend_facades!(
+    dyn AsrBackendFacade,
+    dyn TtsBackendFacade,
+    dyn NluBackendFacade,
+    dyn DialogueBackendFacade,
+    dyn InjectionBackendFacade
+);
+impl_identifiable_component_facades!(dyn VoiceActivityFacade, dyn HotwordFacade, dyn AudioServerFacade);
+impl_identifiable_component_backend_facades!(
+    dyn VoiceActivityBackendFacade,
+    dyn HotwordBackendFacade,
+    dyn AudioServerBackendFacade
+);
+impl_toggleable_facades!(dyn AsrFacade, dyn DialogueFacade);
+impl_toggleable_backend_facades!(dyn AsrBackendFacade, dyn DialogueBackendFacade);
+impl_identifiable_toggleable_facades!(dyn HotwordFacade, dyn SoundFeedbackFacade, dyn AudioServerFacade);
+impl_identifiable_toggleable_backend_facades!(
+    dyn HotwordBackendFacade,
+    dyn SoundFeedbackBackendFacade,
+    dyn AudioServerBackendFacade
+);
+
+impl VoiceActivityFacade for Facade<dyn VoiceActivityFacade> {
+    s!(subscribe_vad_up(site_id: String) VadUpMessage => |vad_up| format!("hermes/voiceActivity/{}/vadUp", vad_up.site_id));
+    s!(subscribe_vad_down(site_id: String) VadDownMessage => |vad_down| format!("hermes/voiceActivity/{}/vadDown", vad_down.site_id));
+    s!(subscribe_all_vad_up() VadUpMessage => |vad_up| format!("hermes/voiceActivity/{}/vadUp", vad_up.site_id));
+    s!(subscribe_all_vad_down() VadDownMessage => |vad_down| format!("hermes/voiceActivity/{}/vadDown", vad_down.site_id));
+}
+
+impl VoiceActivityBackendFacade for Facade<dyn VoiceActivityBackendFacade> {
+    p!(publish_vad_up() vad_up: VadUpMessage => format!("hermes/voiceActivity/{}/vadUp", vad_up.site_id));
+    p!(publish_vad_down() vad_down: VadDownMessage => format!("hermes/voiceActivity/{}/vadDown", vad_down.site_id));
+}
+
+impl HotwordFacade for Facade<dyn HotwordFacade> {
+    s!(subscribe_detected(site_id: String) HotwordDetectedMessage => |_| format!("hermes/hotword/{}/detected", site_id));
+    s!(subscribe_all_detected() HotwordDetectedMessage => |_| "hermes/hotword/+/detected");
+    s!(subscribe_all_detected_with_id() (String, HotwordDetectedMessage) => |(id, _)| format!("hermes/hotword/{}/detected", id));
+}
+
+impl HotwordBackendFacade for Facade<dyn HotwordBackendFacade> {
+    p!(publish_detected(site_id: String) message: HotwordDetectedMessage => format!("hermes/hotword/{}/detected", site_id));
+}
+
+impl SoundFeedbackFacade for Facade<dyn SoundFeedbackFacade> {}
+
+impl SoundFeedbackBackendFacade for Facade<dyn SoundFeedbackBackendFacade> {}
+
+impl AsrFacade for Facade<dyn AsrFacade> {
+    p!(publish_start_listening() start: AsrStartListeningMessage => "hermes/asr/startListening");
+    p!(publish_stop_listening() site: SiteMessage => "hermes/asr/stopListening");
+    p!(publish_component_reload() component_reload: RequestComponentReloadMessage => "hermes/asr/reload");
+    s!(subscribe_text_captured() TextCapturedMessage => |_| "hermes/asr/textCaptured");
+    s!(subscribe_partial_text_captured() TextCapturedMessage => |_| "hermes/asr/partialTextCaptured");
+}
+
+impl AsrBackendFacade for Facade<dyn AsrBackendFacade> {
+    s!(subscribe_start_listening() AsrStartListeningMessage => |_| "hermes/asr/startListening");
+    s!(subscribe_stop_listening() SiteMessage => |_| "hermes/asr/stopListening");
+    s!(subscribe_component_reload() RequestComponentReloadMessage => |_| "hermes/asr/reload");
+    p!(publish_text_captured() text_captured: TextCapturedMessage => "hermes/asr/textCaptured");
+    p!(publish_partial_text_captured() text_captured: TextCapturedMessage => "hermes/asr/partialTextCaptured");
+}
+
+impl TtsFacade for Facade<dyn TtsFacade> {
+    p!(publish_say() to_say: SayMessage => "hermes/tts/say");
+    s!(subscribe_say_finished() SayFinishedMessage => |_| "hermes/tts/sayFinished");
+    p!(publish_register_sound() sound: RegisterSoundMessage => format!("hermes/tts/registerSound/{}", sound.sound_id));
+}
+
+impl TtsBackendFacade for Facade<dyn TtsBackendFacade> {
+    p!(publish_say_finished() status: SayFinishedMessage => "hermes/tts/sayFinished");
+    s!(subscribe_say() SayMessage => |_| "hermes/tts/say");
+    s!(subscribe_register_sound() RegisterSoundMessage => |sound| format!("hermes/tts/registerSound/{}", sound.sound_id));
+}
+
+impl NluFacade for Facade<dyn NluFacade> {
+    p!(publish_query() query: NluQueryMessage => "hermes/nlu/query");
+    p!(publish_partial_query() query: NluSlotQueryMessage => "hermes/nlu/partialQuery");
+    p!(publish_component_reload() component_reload: RequestComponentReloadMessage => "hermes/nlu/reload");
+    s!(subscribe_slot_parsed() NluSlotMessage => |_| "hermes/nlu/slotParsed");
+    s!(subscribe_intent_parsed() NluIntentMessage => |_| "hermes/nlu/intentParsed");
+    s!(subscribe_intent_not_recognized() NluIntentNotRecognizedMessage => |_| "hermes/nlu/intentNotRecognized");
+}
+
+impl NluBackendFacade for Facade<dyn NluBackendFacade> {
+    s!(subscribe_query() NluQueryMessage => |_| "hermes/nlu/query");
+    s!(subscribe_partial_query() NluSlotQueryMessage => |_| "hermes/nlu/partialQuery");
+    s!(subscribe_component_reload() RequestComponentReloadMessage => |_| "hermes/nlu/reload");
+    p!(publish_slot_parsed() slot: NluSlotMessage => "hermes/nlu/slotParsed");
+    p!(publish_intent_parsed() intent: NluIntentMessage => "hermes/nlu/intentParsed");
+    p!(publish_intent_not_recognized() status: NluIntentNotRecognizedMessage => "hermes/nlu/intentNotRecognized");
+}
+
+/// The topic of a chunk of a stream, its parts are those of the message
+fn stream_bytes_topic(bytes: &StreamBytesMessage) -> String {
+    format!(
+        "hermes/audioServer/{}/playBytesStreaming/{}/{}/{}",
+        bytes.site_id,
+        bytes.stream_id,
+        bytes.chunk_number,
+        if bytes.is_last_chunk { 1 } else { 0 }
+    )
+}
+
+impl AudioServerFacade for Facade<dyn AudioServerFacade> {
+    p!(publish_play_bytes() bytes: PlayBytesMessage => format!("hermes/audioServer/{}/playBytes/{}", bytes.site_id, bytes.id));
+    s!(subscribe_play_finished(site_id: String) PlayFinishedMessage => |finished| format!("hermes/audioServer/{}/playFinished", finished.site_id));
+    s!(subscribe_all_play_finished() PlayFinishedMessage => |finished| format!("hermes/audioServer/{}/playFinished", finished.site_id));
+    s!(subscribe_audio_frame(site_id: String) AudioFrameMessage => |frame| format!("hermes/audioServer/{}/audioFrame", frame.site_id));
+    p!(publish_replay_request() request: ReplayRequestMessage => format!("hermes/audioServer/{}/replayRequest", request.site_id));
+    s!(subscribe_replay_response(site_id: String) AudioFrameMessage => |frame| format!("hermes/audioServer/{}/replayResponse", frame.site_id));
+    p!(publish_stream_bytes() bytes: StreamBytesMessage => stream_bytes_topic(&bytes));
+    s!(subscribe_stream_finished(site_id: String) StreamFinishedMessage => |finished| format!("hermes/audioServer/{}/streamFinished", finished.site_id));
+    s!(subscribe_all_stream_finished() StreamFinishedMessage => |finished| format!("hermes/audioServer/{}/streamFinished", finished.site_id));
+}
+
+impl AudioServerBackendFacade for Facade<dyn AudioServerBackendFacade> {
+    s!(subscribe_play_bytes(site_id: String) PlayBytesMessage => |bytes| format!("hermes/audioServer/{}/playBytes/{}", bytes.site_id, bytes.id));
+    s!(subscribe_all_play_bytes() PlayBytesMessage => |bytes| format!("hermes/audioServer/{}/playBytes/{}", bytes.site_id, bytes.id));
+    p!(publish_play_finished() status: PlayFinishedMessage => format!("hermes/audioServer/{}/playFinished", status.site_id));
+    p!(publish_audio_frame() frame: AudioFrameMessage => format!("hermes/audioServer/{}/audioFrame", frame.site_id));
+    s!(subscribe_replay_request(site_id: String) ReplayRequestMessage => |request| format!("hermes/audioServer/{}/replayRequest", request.site_id));
+    p!(publish_replay_response() frame: AudioFrameMessage => format!("hermes/audioServer/{}/replayResponse", frame.site_id));
+    s!(subscribe_stream_bytes(site_id: String) StreamBytesMessage => |bytes| stream_bytes_topic(bytes));
+    s!(subscribe_all_stream_bytes() StreamBytesMessage => |bytes| stream_bytes_topic(bytes));
+    p!(publish_stream_finished() status: StreamFinishedMessage => format!("hermes/audioServer/{}/streamFinished", status.site_id));
+}
+
+impl DialogueFacade for Facade<dyn DialogueFacade> {
+    s!(subscribe_session_queued() SessionQueuedMessage => |_| "hermes/dialogueManager/sessionQueued");
+    s!(subscribe_session_started() SessionStartedMessage => |_| "hermes/dialogueManager/sessionStarted");
+    s!(subscribe_intent(intent_name: String) IntentMessage => |intent| format!("hermes/intent/{}", intent.intent.intent_name));
+    s!(subscribe_intents() IntentMessage => |intent| format!("hermes/intent/{}", intent.intent.intent_name));
+    s!(subscribe_intent_not_recognized() IntentNotRecognizedMessage => |_| "hermes/dialogueManager/intentNotRecognized");
+    s!(subscribe_session_ended() SessionEndedMessage => |_| "hermes/dialogueManager/sessionEnded");
+    p!(publish_start_session() start_session: StartSessionMessage => "hermes/dialogueManager/startSession");
+    p!(publish_continue_session() continue_session: ContinueSessionMessage => "hermes/dialogueManager/continueSession");
+    p!(publish_end_session() end_session: EndSessionMessage => "hermes/dialogueManager/endSession");
+    p!(publish_configure() config: DialogueConfigureMessage => "hermes/dialogueManager/configure");
+}
+
+impl DialogueBackendFacade for Facade<dyn DialogueBackendFacade> {
+    p!(publish_session_queued() status: SessionQueuedMessage => "hermes/dialogueManager/sessionQueued");
+    p!(publish_session_started() status: SessionStartedMessage => "hermes/dialogueManager/sessionStarted");
+    p!(publish_intent() intent: IntentMessage => format!("hermes/intent/{}", intent.intent.intent_name));
+    p!(publish_intent_not_recognized() intent_not_recognized: IntentNotRecognizedMessage => "hermes/dialogueManager/intentNotRecognized");
+    p!(publish_session_ended() status: SessionEndedMessage => "hermes/dialogueManager/sessionEnded");
+    s!(subscribe_start_session() StartSessionMessage => |_| "hermes/dialogueManager/startSession");
+    s!(subscribe_continue_session() ContinueSessionMessage => |_| "hermes/dialogueManager/continueSession");
+    s!(subscribe_end_session() EndSessionMessage => |_| "hermes/dialogueManager/endSession");
+    s!(subscribe_configure() DialogueConfigureMessage => |_| "hermes/dialogueManager/configure");
+}
+
+impl InjectionFacade for Facade<dyn InjectionFacade> {
+    p!(publish_injection_request() request: InjectionRequestMessage => "hermes/injection/perform");
+    p!(publish_injection_reset_request() request: InjectionResetRequestMessage => "hermes/injection/reset/perform");
+    s!(subscribe_injection_status() InjectionStatusMessage => |_| "hermes/injection/status");
+    s!(subscribe_injection_complete() InjectionCompleteMessage => |_| "hermes/injection/complete");
+    s!(subscribe_injection_reset_complete() InjectionResetCompleteMessage => |_| "hermes/injection/reset/complete");
+
+    fn publish_injection_status_request(&self) -> HermesResult<()> {
+        self.publish0(self.topic("statusRequest"))?;
+        self.inner.publish_injection_status_request()
+    }
+}
+
+impl InjectionBackendFacade for Facade<dyn InjectionBackendFacade> {
+    s!(subscribe_injection_request() InjectionRequestMessage => |_| "hermes/injection/perform");
+    s!(subscribe_injection_reset_request() InjectionResetRequestMessage => |_| "hermes/injection/reset/perform");
+    p!(publish_injection_status() status: InjectionStatusMessage => "hermes/injection/status");
+    p!(publish_injection_complete() message: InjectionCompleteMessage => "hermes/injection/complete");
+    p!(publish_injection_reset_complete() message: InjectionResetCompleteMessage => "hermes/injection/reset/complete");
+
+    fn subscribe_injection_status_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle> {
+        self.inner
+            .subscribe_injection_status_request(self.deliver0(handler, self.topic("statusRequest")))
+    }
+}
+
+impl<H: HermesProtocolHandler> HermesProtocolHandler for InterceptedHandler<H> {
+    fn voice_activity(&self) -> Box<dyn VoiceActivityFacade> {
+        self.wrap(
+            self.handler.voice_activity(),
+            HermesComponent::Hotword,
+            "hermes/voiceActivity",
+        )
+    }
+
+    fn hotword(&self) -> Box<dyn HotwordFacade> {
+        self.wrap(self.handler.hotword(), HermesComponent::Hotword, "hermes/hotword")
+    }
+
+    fn sound_feedback(&self) -> Box<dyn SoundFeedbackFacade> {
+        self.wrap(
+            self.handler.sound_feedback(),
+            HermesComponent::Hotword,
+            "hermes/feedback/sound",
+        )
+    }
+
+    fn asr(&self) -> Box<dyn AsrFacade> {
+        self.wrap(self.handler.asr(), HermesComponent::Asr, "hermes/asr")
+    }
+
+    fn tts(&self) -> Box<dyn TtsFacade> {
+        self.wrap(self.handler.tts(), HermesComponent::Tts, "hermes/tts")
+    }
+
+    fn nlu(&self) -> Box<dyn NluFacade> {
+        self.wrap(self.handler.nlu(), HermesComponent::Nlu, "hermes/nlu")
+    }
+
+    fn audio_server(&self) -> Box<dyn AudioServerFacade> {
+        self.wrap(
+            self.handler.audio_server(),
+            HermesComponent::AudioServer,
+            "hermes/audioServer",
+        )
+    }
+
+    fn dialogue(&self) -> Box<dyn DialogueFacade> {
+        self.wrap(
+            self.handler.dialogue(),
+            HermesComponent::Dialogue,
+            "hermes/dialogueManager",
+        )
+    }
+
+    fn injection(&self) -> Box<dyn InjectionFacade> {
+        self.wrap(self.handler.injection(), HermesComponent::Injection, "hermes/injection")
+    }
+
+    fn voice_activity_backend(&self) -> Box<dyn VoiceActivityBackendFacade> {
+        self.wrap(
+            self.handler.voice_activity_backend(),
+            HermesComponent::Hotword,
+            "hermes/voiceActivity",
+        )
+    }
+
+    fn hotword_backend(&self) -> Box<dyn HotwordBackendFacade> {
+        self.wrap(
+            self.handler.hotword_backend(),
+            HermesComponent::Hotword,
+            "hermes/hotword",
+        )
+    }
+
+    fn sound_feedback_backend(&self) -> Box<dyn SoundFeedbackBackendFacade> {
+        self.wrap(
+            self.handler.sound_feedback_backend(),
+            HermesComponent::Hotword,
+            "hermes/feedback/sound",
+        )
+    }
+
+    fn asr_backend(&self) -> Box<dyn AsrBackendFacade> {
+        self.wrap(self.handler.asr_backend(), HermesComponent::Asr, "hermes/asr")
+    }
+
+    fn tts_backend(&self) -> Box<dyn TtsBackendFacade> {
+        self.wrap(self.handler.tts_backend(), HermesComponent::Tts, "hermes/tts")
+    }
+
+    fn nlu_backend(&self) -> Box<dyn NluBackendFacade> {
+        self.wrap(self.handler.nlu_backend(), HermesComponent::Nlu, "hermes/nlu")
+    }
+
+    fn audio_server_backend(&self) -> Box<dyn AudioServerBackendFacade> {
+        self.wrap(
+            self.handler.audio_server_backend(),
+            HermesComponent::AudioServer,
+            "hermes/audioServer",
+        )
+    }
+
+    fn dialogue_backend(&self) -> Box<dyn DialogueBackendFacade> {
+        self.wrap(
+            self.handler.dialogue_backend(),
+            HermesComponent::Dialogue,
+            "hermes/dialogueManager",
+        )
+    }
+
+    fn injection_backend(&self) -> Box<dyn InjectionBackendFacade> {
+        self.wrap(
+            self.handler.injection_backend(),
+            HermesComponent::Injection,
+            "hermes/injection",
+        )
+    }
+
+    fn subscribe_decode_errors(&self, handler: Callback<DecodeError>) -> HermesResult<SubscriptionHandle> {
+        self.handler.subscribe_decode_errors(handler)
+    }
+}
+
+impl<H: HermesProtocolHandler> std::fmt::Display for InterceptedHandler<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.handler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_rewritten_through_json() {
+        let mut say = SayMessage {
+            text: "hello".into(),
+            lang: None,
+            id: None,
+            site_id: "kitchen".into(),
+            session_id: None,
+        };
+        let mut intercepted = Intercepted {
+            direction: Direction::Publish,
+            component: HermesComponent::Tts,
+            topic: "hermes/tts/say".into(),
+            payload: Some(&mut say),
+        };
+
+        assert!(intercepted.message::<SayFinishedMessage>().is_none());
+        let mut json = intercepted.to_json().unwrap().unwrap();
+        json["siteId"] = "living room".into();
+        intercepted.set_json(json).unwrap();
+        assert!(intercepted.set_json(serde_json::json!({ "siteId": 42 })).is_err());
+        intercepted.message_mut::<SayMessage>().unwrap().text = "hi".into();
+
+        assert_eq!(say.site_id, "living room");
+        assert_eq!(say.text, "hi");
+    }
+}
@@ -12,23 +12,65 @@ extern crate snips_nlu_ontology;
 #[macro_use]
 pub extern crate hermes_utils;
 
+pub mod app;
+pub mod bridge;
+pub mod compatibility;
+pub mod correlation;
+pub mod decoding;
 pub mod errors;
+pub mod intercept;
+pub mod meta;
+pub mod metrics;
+pub mod mock;
 pub mod ontology;
-
+pub mod receiver;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod session;
+pub mod trace;
+pub mod transport;
+pub mod validation;
+
+pub use crate::app::{Action, HermesApp};
+pub use crate::bridge::{Bridge, Side};
+pub use crate::compatibility::{check_compatibility, CompatibilityReport};
+pub use crate::correlation::{new_request_id, NluQueryResponse};
+pub use crate::decoding::{DecodePolicy, Decoded};
 pub use crate::errors::*;
+pub use crate::intercept::{Direction, Intercepted, InterceptedHandler, Interceptor};
+pub use crate::meta::{MessageMeta, MessageOrigin};
+pub use crate::metrics::{Metrics, MetricsServer, MetricsSink, PrometheusSink};
 pub use crate::ontology::*;
+pub use crate::receiver::{receiver, Overflow, Receiver, ReceiverExt};
+pub use crate::session::{Session, SessionTracker};
+pub use crate::trace::TraceContext;
+pub use crate::transport::{connect, register_transport, HandlerConfig, ToHandlerConfig, TransportFactory};
+pub use crate::validation::{StreamBytesSequence, Validate, ValidationMode, Violation};
+
+use std::time::Duration;
 
-use failure::Fallible;
+use crate::correlation::PendingResponse;
 
-/// A struct wrapping a callback with one argument, create one with the `new` method
+/// A struct wrapping a callback with one argument, create one with the `new` method, or with the
+/// `with_meta` method to also get the `MessageMeta` of the messages
 pub struct Callback<T> {
-    callback: Box<dyn Fn(&T) -> () + Send + Sync>,
+    callback: Box<dyn Fn(&T, Option<&MessageMeta>) -> () + Send + Sync>,
 }
 
 impl<T> Callback<T> {
     pub fn new<F: 'static>(handler: F) -> Callback<T>
     where
         F: Fn(&T) -> () + Send + Sync,
+    {
+        Callback {
+            callback: Box::new(move |arg: &T, _: Option<&MessageMeta>| handler(arg)),
+        }
+    }
+
+    /// The metadata is `None` when the publisher didn't attach any
+    pub fn with_meta<F: 'static>(handler: F) -> Callback<T>
+    where
+        F: Fn(&T, Option<&MessageMeta>) -> () + Send + Sync,
     {
         Callback {
             callback: Box::new(handler),
@@ -36,19 +78,34 @@ impl<T> Callback<T> {
     }
 
     pub fn call(&self, arg: &T) {
-        (self.callback)(arg)
+        (self.callback)(arg, None)
+    }
+
+    pub fn call_with_meta(&self, arg: &T, meta: Option<&MessageMeta>) {
+        (self.callback)(arg, meta)
     }
 }
 
-/// A struct wrapping a callback with no argument, create one with the `new` method
+/// A struct wrapping a callback with no argument, create one with the `new` method, or with the
+/// `with_meta` method to also get the `MessageMeta` of the messages
 pub struct Callback0 {
-    callback: Box<dyn Fn() -> () + Send + Sync>,
+    callback: Box<dyn Fn(Option<&MessageMeta>) -> () + Send + Sync>,
 }
 
 impl Callback0 {
     pub fn new<F: 'static>(handler: F) -> Callback0
     where
         F: Fn() -> () + Send + Sync,
+    {
+        Callback0 {
+            callback: Box::new(move |_: Option<&MessageMeta>| handler()),
+        }
+    }
+
+    /// The metadata is `None` when the publisher didn't attach any
+    pub fn with_meta<F: 'static>(handler: F) -> Callback0
+    where
+        F: Fn(Option<&MessageMeta>) -> () + Send + Sync,
     {
         Callback0 {
             callback: Box::new(handler),
@@ -56,68 +113,131 @@ impl Callback0 {
     }
 
     pub fn call(&self) {
-        (self.callback)()
+        (self.callback)(None)
+    }
+
+    pub fn call_with_meta(&self, meta: Option<&MessageMeta>) {
+        (self.callback)(meta)
+    }
+}
+
+/// A handle on a subscription made through one of the facades. The subscription is cancelled
+/// when `unsubscribe` is called or when the handle is dropped, use `forget` to keep it active for
+/// the whole lifetime of the protocol handler
+#[must_use = "the subscription is cancelled as soon as its handle is dropped"]
+pub struct SubscriptionHandle {
+    unsubscriber: Option<Box<dyn FnOnce() -> HermesResult<()> + Send + Sync>>,
+}
+
+impl SubscriptionHandle {
+    pub fn new<F: 'static>(unsubscriber: F) -> SubscriptionHandle
+    where
+        F: FnOnce() -> HermesResult<()> + Send + Sync,
+    {
+        SubscriptionHandle {
+            unsubscriber: Some(Box::new(unsubscriber)),
+        }
+    }
+
+    /// Cancel the subscription, the messages received from then on aren't given to the callback.
+    /// A message that was being delivered when this was called may still reach it, on the thread
+    /// of the transport, after this returns
+    pub fn unsubscribe(mut self) -> HermesResult<()> {
+        self.unsubscriber
+            .take()
+            .map(|unsubscriber| unsubscriber())
+            .unwrap_or(Ok(()))
+    }
+
+    /// Give up the handle while keeping the subscription active
+    pub fn forget(mut self) {
+        self.unsubscriber = None;
+    }
+}
+
+impl Drop for SubscriptionHandle {
+    fn drop(&mut self) {
+        if let Some(unsubscriber) = self.unsubscriber.take() {
+            // nowhere to report the error here, use `unsubscribe` to get it
+            let _ = unsubscriber();
+        }
+    }
+}
+
+impl std::fmt::Debug for SubscriptionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SubscriptionHandle")
+            .field("active", &self.unsubscriber.is_some())
+            .finish()
     }
 }
 
 /// A generic facade used to interact with a component
 pub trait ComponentFacade: Send + Sync {
-    fn publish_version_request(&self) -> Fallible<()>;
-    fn subscribe_version(&self, handler: Callback<VersionMessage>) -> Fallible<()>;
-    fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> Fallible<()>;
-    fn subscribe_component_loaded(&self, handler: Callback<ComponentLoadedMessage>) -> Fallible<()>;
+    fn publish_version_request(&self) -> HermesResult<()>;
+    fn subscribe_version(&self, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_error(&self, handler: Callback<ErrorMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_component_loaded(&self, handler: Callback<ComponentLoadedMessage>)
+        -> HermesResult<SubscriptionHandle>;
 }
 
 /// A generic facade used to interact with a component
 pub trait IdentifiableComponentFacade: Send + Sync {
-    fn publish_version_request(&self, id: String) -> Fallible<()>;
-    fn subscribe_version(&self, id: String, handler: Callback<VersionMessage>) -> Fallible<()>;
-    fn subscribe_error(&self, id: String, handler: Callback<SiteErrorMessage>) -> Fallible<()>;
-    fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> Fallible<()>;
-    fn subscribe_component_loaded(&self, id: String, handler: Callback<ComponentLoadedOnSiteMessage>) -> Fallible<()>;
-    fn subscribe_all_component_loaded(&self, handler: Callback<ComponentLoadedOnSiteMessage>) -> Fallible<()>;
+    fn publish_version_request(&self, id: String) -> HermesResult<()>;
+    fn subscribe_version(&self, id: String, handler: Callback<VersionMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_error(&self, id: String, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_error(&self, handler: Callback<SiteErrorMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_component_loaded(
+        &self,
+        id: String,
+        handler: Callback<ComponentLoadedOnSiteMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_component_loaded(
+        &self,
+        handler: Callback<ComponentLoadedOnSiteMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
 }
 
 /// A generic facade all components must use to publish their errors and versions (when requested)
 pub trait ComponentBackendFacade: Send + Sync {
-    fn subscribe_version_request(&self, handler: Callback0) -> Fallible<()>;
-    fn publish_version(&self, version: VersionMessage) -> Fallible<()>;
-    fn publish_error(&self, error: ErrorMessage) -> Fallible<()>;
-    fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> Fallible<()>;
+    fn subscribe_version_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle>;
+    fn publish_version(&self, version: VersionMessage) -> HermesResult<()>;
+    fn publish_error(&self, error: ErrorMessage) -> HermesResult<()>;
+    fn publish_component_loaded(&self, component_loaded: ComponentLoadedMessage) -> HermesResult<()>;
 }
 
 /// A generic facade all components must use to publish their errors and versions (when requested)
 pub trait IdentifiableComponentBackendFacade: Send + Sync {
-    fn subscribe_version_request(&self, id: String, handler: Callback0) -> Fallible<()>;
-    fn publish_version(&self, id: String, version: VersionMessage) -> Fallible<()>;
-    fn publish_error(&self, id: String, error: SiteErrorMessage) -> Fallible<()>;
-    fn publish_component_loaded(&self, id: String, component_loaded: ComponentLoadedOnSiteMessage) -> Fallible<()>;
+    fn subscribe_version_request(&self, id: String, handler: Callback0) -> HermesResult<SubscriptionHandle>;
+    fn publish_version(&self, id: String, version: VersionMessage) -> HermesResult<()>;
+    fn publish_error(&self, id: String, error: SiteErrorMessage) -> HermesResult<()>;
+    fn publish_component_loaded(&self, id: String, component_loaded: ComponentLoadedOnSiteMessage) -> HermesResult<()>;
 }
 
 /// A facade to interact with a component that can be toggled on an off at a specific site
 pub trait ToggleableFacade: Send + Sync {
-    fn publish_toggle_on(&self) -> Fallible<()>;
-    fn publish_toggle_off(&self) -> Fallible<()>;
+    fn publish_toggle_on(&self) -> HermesResult<()>;
+    fn publish_toggle_off(&self) -> HermesResult<()>;
 }
 
 /// The facade a component that can be toggled on an off at a specific site must use to receive
 /// its orders
 pub trait ToggleableBackendFacade: Send + Sync {
-    fn subscribe_toggle_on(&self, handler: Callback0) -> Fallible<()>;
-    fn subscribe_toggle_off(&self, handler: Callback0) -> Fallible<()>;
+    fn subscribe_toggle_on(&self, handler: Callback0) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_toggle_off(&self, handler: Callback0) -> HermesResult<SubscriptionHandle>;
 }
 
 /// A facade to interact with a component that can be toggled on an off at a specific site
 pub trait IdentifiableToggleableFacade: Send + Sync {
-    fn publish_toggle_on(&self, site: SiteMessage) -> Fallible<()>;
-    fn publish_toggle_off(&self, site: SiteMessage) -> Fallible<()>;
+    fn publish_toggle_on(&self, site: SiteMessage) -> HermesResult<()>;
+    fn publish_toggle_off(&self, site: SiteMessage) -> HermesResult<()>;
 }
 
 /// The facade a component that can be toggled on an off at a specific site must use to receive
 /// its orders
 pub trait IdentifiableToggleableBackendFacade: Send + Sync {
-    fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> Fallible<()>;
-    fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> Fallible<()>;
+    fn subscribe_toggle_on(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_toggle_off(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle>;
 }
 
 //
@@ -126,27 +246,40 @@ pub trait IdentifiableToggleableBackendFacade: Send + Sync {
 
 /// Facade used to interact with the voice activity component
 pub trait VoiceActivityFacade: IdentifiableComponentFacade {
-    fn subscribe_vad_up(&self, site_id: String, handler: Callback<VadUpMessage>) -> Fallible<()>;
-    fn subscribe_vad_down(&self, site_id: String, handler: Callback<VadDownMessage>) -> Fallible<()>;
-    fn subscribe_all_vad_up(&self, handler: Callback<VadUpMessage>) -> Fallible<()>;
-    fn subscribe_all_vad_down(&self, handler: Callback<VadDownMessage>) -> Fallible<()>;
+    fn subscribe_vad_up(&self, site_id: String, handler: Callback<VadUpMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_vad_down(
+        &self,
+        site_id: String,
+        handler: Callback<VadDownMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_vad_up(&self, handler: Callback<VadUpMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_vad_down(&self, handler: Callback<VadDownMessage>) -> HermesResult<SubscriptionHandle>;
 }
 
 /// Facade the voice activity component must use to publish its results
 pub trait VoiceActivityBackendFacade: IdentifiableComponentBackendFacade {
-    fn publish_vad_up(&self, vad_up: VadUpMessage) -> Fallible<()>;
-    fn publish_vad_down(&self, vad_down: VadDownMessage) -> Fallible<()>;
+    fn publish_vad_up(&self, vad_up: VadUpMessage) -> HermesResult<()>;
+    fn publish_vad_down(&self, vad_down: VadDownMessage) -> HermesResult<()>;
 }
 
 /// The facade to interact with the hotword component
 pub trait HotwordFacade: IdentifiableComponentFacade + IdentifiableToggleableFacade {
-    fn subscribe_detected(&self, site_id: String, handler: Callback<HotwordDetectedMessage>) -> Fallible<()>;
-    fn subscribe_all_detected(&self, handler: Callback<HotwordDetectedMessage>) -> Fallible<()>;
+    fn subscribe_detected(
+        &self,
+        site_id: String,
+        handler: Callback<HotwordDetectedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_detected(&self, handler: Callback<HotwordDetectedMessage>) -> HermesResult<SubscriptionHandle>;
+    /// Subscribe to the detections of every hotword, along with the id of the hotword detected
+    fn subscribe_all_detected_with_id(
+        &self,
+        handler: Callback<(String, HotwordDetectedMessage)>,
+    ) -> HermesResult<SubscriptionHandle>;
 }
 
 /// The facade the hotword feature must use receive its orders and publish detected hotwords
 pub trait HotwordBackendFacade: IdentifiableComponentBackendFacade + IdentifiableToggleableBackendFacade {
-    fn publish_detected(&self, site_id: String, message: HotwordDetectedMessage) -> Fallible<()>;
+    fn publish_detected(&self, site_id: String, message: HotwordDetectedMessage) -> HermesResult<()>;
 }
 
 /// The facade used to toggle on and of the sound feedback at a specific site
@@ -157,130 +290,294 @@ pub trait SoundFeedbackBackendFacade: IdentifiableToggleableBackendFacade {}
 
 /// The facade to interact with the automatic speech recognition component
 pub trait AsrFacade: ComponentFacade + ToggleableFacade {
-    fn publish_start_listening(&self, start: AsrStartListeningMessage) -> Fallible<()>;
-    fn publish_stop_listening(&self, site: SiteMessage) -> Fallible<()>;
-    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> Fallible<()>;
-    fn subscribe_text_captured(&self, handler: Callback<TextCapturedMessage>) -> Fallible<()>;
-    fn subscribe_partial_text_captured(&self, handler: Callback<TextCapturedMessage>) -> Fallible<()>;
+    fn publish_start_listening(&self, start: AsrStartListeningMessage) -> HermesResult<()>;
+    fn publish_stop_listening(&self, site: SiteMessage) -> HermesResult<()>;
+    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> HermesResult<()>;
+    fn subscribe_text_captured(&self, handler: Callback<TextCapturedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_partial_text_captured(
+        &self,
+        handler: Callback<TextCapturedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+
+    /// Ask the component to reload and wait for it to be loaded again, an id is generated for the
+    /// request if it doesn't have one. This blocks, don't call it from a callback of this facade.
+    fn reload_and_wait(
+        &self,
+        component_reload: RequestComponentReloadMessage,
+        timeout: Duration,
+    ) -> HermesResult<ComponentLoadedMessage> {
+        correlation::reload_and_wait(
+            component_reload,
+            timeout,
+            |callback| self.subscribe_component_loaded(callback),
+            |component_reload| self.publish_component_reload(component_reload),
+        )
+    }
 }
 
 /// The facade the automatic speech recognition must use to receive its orders and publish
 /// recognized text
 pub trait AsrBackendFacade: ComponentBackendFacade + ToggleableBackendFacade {
-    fn subscribe_start_listening(&self, handler: Callback<AsrStartListeningMessage>) -> Fallible<()>;
-    fn subscribe_stop_listening(&self, handler: Callback<SiteMessage>) -> Fallible<()>;
-    fn subscribe_component_reload(&self, handler: Callback<RequestComponentReloadMessage>) -> Fallible<()>;
-    fn publish_text_captured(&self, text_captured: TextCapturedMessage) -> Fallible<()>;
-    fn publish_partial_text_captured(&self, text_captured: TextCapturedMessage) -> Fallible<()>;
+    fn subscribe_start_listening(
+        &self,
+        handler: Callback<AsrStartListeningMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_stop_listening(&self, handler: Callback<SiteMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_component_reload(
+        &self,
+        handler: Callback<RequestComponentReloadMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_text_captured(&self, text_captured: TextCapturedMessage) -> HermesResult<()>;
+    fn publish_partial_text_captured(&self, text_captured: TextCapturedMessage) -> HermesResult<()>;
 }
 
 /// The facade to interact with the text to speech component
 pub trait TtsFacade: ComponentFacade {
-    fn publish_say(&self, to_say: SayMessage) -> Fallible<()>;
-    fn subscribe_say_finished(&self, handler: Callback<SayFinishedMessage>) -> Fallible<()>;
-    fn publish_register_sound(&self, sound: RegisterSoundMessage) -> Fallible<()>;
+    fn publish_say(&self, to_say: SayMessage) -> HermesResult<()>;
+    fn subscribe_say_finished(&self, handler: Callback<SayFinishedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn publish_register_sound(&self, sound: RegisterSoundMessage) -> HermesResult<()>;
+
+    /// Say something and wait for the tts to be done saying it, an id is generated for the request if
+    /// it doesn't have one. This blocks, don't call it from a callback of this facade.
+    fn say_and_wait(&self, mut to_say: SayMessage, timeout: Duration) -> HermesResult<SayFinishedMessage> {
+        let id = to_say.id.get_or_insert_with(new_request_id).clone();
+        let mut response = PendingResponse::new();
+        let callback = response.callback(move |finished: &SayFinishedMessage| {
+            Some(finished).filter(|it| it.id.as_ref() == Some(&id)).cloned()
+        });
+        response.keep(self.subscribe_say_finished(callback)?);
+        self.publish_say(to_say)?;
+        response.wait(timeout)
+    }
 }
 
 /// The facade the text to speech must use to receive its orders and advertise when it has finished
 pub trait TtsBackendFacade: ComponentBackendFacade {
-    fn publish_say_finished(&self, status: SayFinishedMessage) -> Fallible<()>;
-    fn subscribe_say(&self, handler: Callback<SayMessage>) -> Fallible<()>;
-    fn subscribe_register_sound(&self, handler: Callback<RegisterSoundMessage>) -> Fallible<()>;
+    fn publish_say_finished(&self, status: SayFinishedMessage) -> HermesResult<()>;
+    fn subscribe_say(&self, handler: Callback<SayMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_register_sound(&self, handler: Callback<RegisterSoundMessage>) -> HermesResult<SubscriptionHandle>;
 }
 
 /// The facade to interact with the natural language understanding component
 pub trait NluFacade: ComponentFacade {
-    fn publish_query(&self, query: NluQueryMessage) -> Fallible<()>;
-    fn publish_partial_query(&self, query: NluSlotQueryMessage) -> Fallible<()>;
-    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> Fallible<()>;
-    fn subscribe_slot_parsed(&self, handler: Callback<NluSlotMessage>) -> Fallible<()>;
-    fn subscribe_intent_parsed(&self, handler: Callback<NluIntentMessage>) -> Fallible<()>;
-    fn subscribe_intent_not_recognized(&self, handler: Callback<NluIntentNotRecognizedMessage>) -> Fallible<()>;
+    fn publish_query(&self, query: NluQueryMessage) -> HermesResult<()>;
+    fn publish_partial_query(&self, query: NluSlotQueryMessage) -> HermesResult<()>;
+    fn publish_component_reload(&self, component_reload: RequestComponentReloadMessage) -> HermesResult<()>;
+    fn subscribe_slot_parsed(&self, handler: Callback<NluSlotMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_intent_parsed(&self, handler: Callback<NluIntentMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_intent_not_recognized(
+        &self,
+        handler: Callback<NluIntentNotRecognizedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+
+    /// Run a query and wait for its result, an id is generated for the query if it doesn't have one.
+    /// This blocks, don't call it from a callback of this facade.
+    fn query_and_wait(&self, mut query: NluQueryMessage, timeout: Duration) -> HermesResult<NluQueryResponse> {
+        let id = query.id.get_or_insert_with(new_request_id).clone();
+        let mut response = PendingResponse::new();
+        let intent_id = id.clone();
+        let callback = response.callback(move |intent: &NluIntentMessage| {
+            Some(intent)
+                .filter(|it| it.id.as_ref() == Some(&intent_id))
+                .map(|it| NluQueryResponse::IntentParsed(it.clone()))
+        });
+        response.keep(self.subscribe_intent_parsed(callback)?);
+        let callback = response.callback(move |not_recognized: &NluIntentNotRecognizedMessage| {
+            Some(not_recognized)
+                .filter(|it| it.id.as_ref() == Some(&id))
+                .map(|it| NluQueryResponse::IntentNotRecognized(it.clone()))
+        });
+        response.keep(self.subscribe_intent_not_recognized(callback)?);
+        self.publish_query(query)?;
+        response.wait(timeout)
+    }
+
+    /// Ask the component to reload and wait for it to be loaded again, an id is generated for the
+    /// request if it doesn't have one. This blocks, don't call it from a callback of this facade.
+    fn reload_and_wait(
+        &self,
+        component_reload: RequestComponentReloadMessage,
+        timeout: Duration,
+    ) -> HermesResult<ComponentLoadedMessage> {
+        correlation::reload_and_wait(
+            component_reload,
+            timeout,
+            |callback| self.subscribe_component_loaded(callback),
+            |component_reload| self.publish_component_reload(component_reload),
+        )
+    }
 }
 
 /// The facade the natural language understanding must use to receive its orders and publish
 /// its results
 pub trait NluBackendFacade: ComponentBackendFacade {
-    fn subscribe_query(&self, handler: Callback<NluQueryMessage>) -> Fallible<()>;
-    fn subscribe_partial_query(&self, handler: Callback<NluSlotQueryMessage>) -> Fallible<()>;
-    fn subscribe_component_reload(&self, handler: Callback<RequestComponentReloadMessage>) -> Fallible<()>;
-    fn publish_slot_parsed(&self, slot: NluSlotMessage) -> Fallible<()>;
-    fn publish_intent_parsed(&self, intent: NluIntentMessage) -> Fallible<()>;
-    fn publish_intent_not_recognized(&self, status: NluIntentNotRecognizedMessage) -> Fallible<()>;
+    fn subscribe_query(&self, handler: Callback<NluQueryMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_partial_query(&self, handler: Callback<NluSlotQueryMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_component_reload(
+        &self,
+        handler: Callback<RequestComponentReloadMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_slot_parsed(&self, slot: NluSlotMessage) -> HermesResult<()>;
+    fn publish_intent_parsed(&self, intent: NluIntentMessage) -> HermesResult<()>;
+    fn publish_intent_not_recognized(&self, status: NluIntentNotRecognizedMessage) -> HermesResult<()>;
 }
 
 /// The facade to interact with the audio server
 pub trait AudioServerFacade: IdentifiableComponentFacade + IdentifiableToggleableFacade {
-    fn publish_play_bytes(&self, bytes: PlayBytesMessage) -> Fallible<()>;
-    fn subscribe_play_finished(&self, site_id: String, handler: Callback<PlayFinishedMessage>) -> Fallible<()>;
-    fn subscribe_all_play_finished(&self, handler: Callback<PlayFinishedMessage>) -> Fallible<()>;
-    fn subscribe_audio_frame(&self, site_id: String, handler: Callback<AudioFrameMessage>) -> Fallible<()>;
-    fn publish_replay_request(&self, request: ReplayRequestMessage) -> Fallible<()>;
-    fn subscribe_replay_response(&self, site_id: String, handler: Callback<AudioFrameMessage>) -> Fallible<()>;
-    fn publish_stream_bytes(&self, play_bytes_streaming_message: StreamBytesMessage) -> Fallible<()>;
-    fn subscribe_stream_finished(&self, site_id: String, handler: Callback<StreamFinishedMessage>) -> Fallible<()>;
-    fn subscribe_all_stream_finished(&self, handler: Callback<StreamFinishedMessage>) -> Fallible<()>;
+    fn publish_play_bytes(&self, bytes: PlayBytesMessage) -> HermesResult<()>;
+    fn subscribe_play_finished(
+        &self,
+        site_id: String,
+        handler: Callback<PlayFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_play_finished(&self, handler: Callback<PlayFinishedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_audio_frame(
+        &self,
+        site_id: String,
+        handler: Callback<AudioFrameMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_replay_request(&self, request: ReplayRequestMessage) -> HermesResult<()>;
+    fn subscribe_replay_response(
+        &self,
+        site_id: String,
+        handler: Callback<AudioFrameMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_stream_bytes(&self, play_bytes_streaming_message: StreamBytesMessage) -> HermesResult<()>;
+    fn subscribe_stream_finished(
+        &self,
+        site_id: String,
+        handler: Callback<StreamFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_stream_finished(
+        &self,
+        handler: Callback<StreamFinishedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+
+    /// Play some bytes and wait for them to be played, an id is generated for the request if it is
+    /// empty. This blocks, don't call it from a callback of this facade.
+    fn play_and_wait(&self, mut bytes: PlayBytesMessage, timeout: Duration) -> HermesResult<PlayFinishedMessage> {
+        if bytes.id.is_empty() {
+            bytes.id = new_request_id();
+        }
+        let id = bytes.id.clone();
+        let mut response = PendingResponse::new();
+        let callback =
+            response.callback(move |finished: &PlayFinishedMessage| Some(finished).filter(|it| it.id == id).cloned());
+        response.keep(self.subscribe_play_finished(bytes.site_id.clone(), callback)?);
+        self.publish_play_bytes(bytes)?;
+        response.wait(timeout)
+    }
 }
 
 /// The facade the audio server must use to receive its orders and advertise when it has finished
 pub trait AudioServerBackendFacade: IdentifiableComponentBackendFacade + IdentifiableToggleableBackendFacade {
-    fn subscribe_play_bytes(&self, site_id: String, handler: Callback<PlayBytesMessage>) -> Fallible<()>;
-    fn subscribe_all_play_bytes(&self, handler: Callback<PlayBytesMessage>) -> Fallible<()>;
-    fn publish_play_finished(&self, status: PlayFinishedMessage) -> Fallible<()>;
-    fn publish_audio_frame(&self, frame: AudioFrameMessage) -> Fallible<()>;
-    fn subscribe_replay_request(&self, site_id: String, handler: Callback<ReplayRequestMessage>) -> Fallible<()>;
-    fn publish_replay_response(&self, frame: AudioFrameMessage) -> Fallible<()>;
-    fn subscribe_stream_bytes(&self, site_id: String, handler: Callback<StreamBytesMessage>) -> Fallible<()>;
-    fn subscribe_all_stream_bytes(&self, handler: Callback<StreamBytesMessage>) -> Fallible<()>;
-    fn publish_stream_finished(&self, status: StreamFinishedMessage) -> Fallible<()>;
+    fn subscribe_play_bytes(
+        &self,
+        site_id: String,
+        handler: Callback<PlayBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_play_bytes(&self, handler: Callback<PlayBytesMessage>) -> HermesResult<SubscriptionHandle>;
+    fn publish_play_finished(&self, status: PlayFinishedMessage) -> HermesResult<()>;
+    fn publish_audio_frame(&self, frame: AudioFrameMessage) -> HermesResult<()>;
+    fn subscribe_replay_request(
+        &self,
+        site_id: String,
+        handler: Callback<ReplayRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_replay_response(&self, frame: AudioFrameMessage) -> HermesResult<()>;
+    fn subscribe_stream_bytes(
+        &self,
+        site_id: String,
+        handler: Callback<StreamBytesMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_all_stream_bytes(&self, handler: Callback<StreamBytesMessage>) -> HermesResult<SubscriptionHandle>;
+    fn publish_stream_finished(&self, status: StreamFinishedMessage) -> HermesResult<()>;
 }
 
 /// The facade to use to interact with the dialogue manager, this is the principal interface that a
 /// lambda should use
 pub trait DialogueFacade: ComponentFacade + ToggleableFacade {
-    fn subscribe_session_queued(&self, handler: Callback<SessionQueuedMessage>) -> Fallible<()>;
-    fn subscribe_session_started(&self, handler: Callback<SessionStartedMessage>) -> Fallible<()>;
-    fn subscribe_intent(&self, intent_name: String, handler: Callback<IntentMessage>) -> Fallible<()>;
-    fn subscribe_intents(&self, handler: Callback<IntentMessage>) -> Fallible<()>;
-    fn subscribe_intent_not_recognized(&self, handler: Callback<IntentNotRecognizedMessage>) -> Fallible<()>;
-    fn subscribe_session_ended(&self, handler: Callback<SessionEndedMessage>) -> Fallible<()>;
-    fn publish_start_session(&self, start_session: StartSessionMessage) -> Fallible<()>;
-    fn publish_continue_session(&self, continue_session: ContinueSessionMessage) -> Fallible<()>;
-    fn publish_end_session(&self, end_session: EndSessionMessage) -> Fallible<()>;
-    fn publish_configure(&self, config: DialogueConfigureMessage) -> Fallible<()>;
+    fn subscribe_session_queued(&self, handler: Callback<SessionQueuedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_session_started(&self, handler: Callback<SessionStartedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_intent(
+        &self,
+        intent_name: String,
+        handler: Callback<IntentMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_intents(&self, handler: Callback<IntentMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_intent_not_recognized(
+        &self,
+        handler: Callback<IntentNotRecognizedMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_session_ended(&self, handler: Callback<SessionEndedMessage>) -> HermesResult<SubscriptionHandle>;
+    fn publish_start_session(&self, start_session: StartSessionMessage) -> HermesResult<()>;
+    fn publish_continue_session(&self, continue_session: ContinueSessionMessage) -> HermesResult<()>;
+    fn publish_end_session(&self, end_session: EndSessionMessage) -> HermesResult<()>;
+    fn publish_configure(&self, config: DialogueConfigureMessage) -> HermesResult<()>;
 }
 
 /// The facade the dialogue manager must use to interact with the lambdas
 pub trait DialogueBackendFacade: ComponentBackendFacade + ToggleableBackendFacade {
-    fn publish_session_queued(&self, status: SessionQueuedMessage) -> Fallible<()>;
-    fn publish_session_started(&self, status: SessionStartedMessage) -> Fallible<()>;
-    fn publish_intent(&self, intent: IntentMessage) -> Fallible<()>;
-    fn publish_intent_not_recognized(&self, intent_not_recognized: IntentNotRecognizedMessage) -> Fallible<()>;
-    fn publish_session_ended(&self, status: SessionEndedMessage) -> Fallible<()>;
-    fn subscribe_start_session(&self, handler: Callback<StartSessionMessage>) -> Fallible<()>;
-    fn subscribe_continue_session(&self, handler: Callback<ContinueSessionMessage>) -> Fallible<()>;
-    fn subscribe_end_session(&self, handler: Callback<EndSessionMessage>) -> Fallible<()>;
-    fn subscribe_configure(&self, handler: Callback<DialogueConfigureMessage>) -> Fallible<()>;
+    fn publish_session_queued(&self, status: SessionQueuedMessage) -> HermesResult<()>;
+    fn publish_session_started(&self, status: SessionStartedMessage) -> HermesResult<()>;
+    fn publish_intent(&self, intent: IntentMessage) -> HermesResult<()>;
+    fn publish_intent_not_recognized(&self, intent_not_recognized: IntentNotRecognizedMessage) -> HermesResult<()>;
+    fn publish_session_ended(&self, status: SessionEndedMessage) -> HermesResult<()>;
+    fn subscribe_start_session(&self, handler: Callback<StartSessionMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_continue_session(&self, handler: Callback<ContinueSessionMessage>)
+        -> HermesResult<SubscriptionHandle>;
+    fn subscribe_end_session(&self, handler: Callback<EndSessionMessage>) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_configure(&self, handler: Callback<DialogueConfigureMessage>) -> HermesResult<SubscriptionHandle>;
 }
 
 /// The facade to interact with the injection component
 pub trait InjectionFacade: ComponentFacade {
-    fn publish_injection_request(&self, request: InjectionRequestMessage) -> Fallible<()>;
-    fn publish_injection_status_request(&self) -> Fallible<()>;
-    fn publish_injection_reset_request(&self, request: InjectionResetRequestMessage) -> Fallible<()>;
-    fn subscribe_injection_status(&self, handler: Callback<InjectionStatusMessage>) -> Fallible<()>;
-    fn subscribe_injection_complete(&self, handler: Callback<InjectionCompleteMessage>) -> Fallible<()>;
-    fn subscribe_injection_reset_complete(&self, handler: Callback<InjectionResetCompleteMessage>) -> Fallible<()>;
+    fn publish_injection_request(&self, request: InjectionRequestMessage) -> HermesResult<()>;
+    fn publish_injection_status_request(&self) -> HermesResult<()>;
+    fn publish_injection_reset_request(&self, request: InjectionResetRequestMessage) -> HermesResult<()>;
+    fn subscribe_injection_status(&self, handler: Callback<InjectionStatusMessage>)
+        -> HermesResult<SubscriptionHandle>;
+    fn subscribe_injection_complete(
+        &self,
+        handler: Callback<InjectionCompleteMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_injection_reset_complete(
+        &self,
+        handler: Callback<InjectionResetCompleteMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+
+    /// Request an injection and wait for it to complete, an id is generated for the request if it
+    /// doesn't have one. This blocks, don't call it from a callback of this facade.
+    fn inject_and_wait(
+        &self,
+        mut request: InjectionRequestMessage,
+        timeout: Duration,
+    ) -> HermesResult<InjectionCompleteMessage> {
+        let id = request.id.get_or_insert_with(new_request_id).clone();
+        let mut response = PendingResponse::new();
+        let callback = response.callback(move |complete: &InjectionCompleteMessage| {
+            Some(complete).filter(|it| it.request_id.as_ref() == Some(&id)).cloned()
+        });
+        response.keep(self.subscribe_injection_complete(callback)?);
+        self.publish_injection_request(request)?;
+        response.wait(timeout)
+    }
 }
 
 /// The facade the injecter must use to receive its orders and advertise when it has finished
 pub trait InjectionBackendFacade: ComponentBackendFacade {
-    fn subscribe_injection_request(&self, handler: Callback<InjectionRequestMessage>) -> Fallible<()>;
-    fn subscribe_injection_status_request(&self, handler: Callback0) -> Fallible<()>;
-    fn subscribe_injection_reset_request(&self, handler: Callback<InjectionResetRequestMessage>) -> Fallible<()>;
-    fn publish_injection_status(&self, status: InjectionStatusMessage) -> Fallible<()>;
-    fn publish_injection_complete(&self, message: InjectionCompleteMessage) -> Fallible<()>;
-    fn publish_injection_reset_complete(&self, message: InjectionResetCompleteMessage) -> Fallible<()>;
+    fn subscribe_injection_request(
+        &self,
+        handler: Callback<InjectionRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_injection_status_request(&self, handler: Callback0) -> HermesResult<SubscriptionHandle>;
+    fn subscribe_injection_reset_request(
+        &self,
+        handler: Callback<InjectionResetRequestMessage>,
+    ) -> HermesResult<SubscriptionHandle>;
+    fn publish_injection_status(&self, status: InjectionStatusMessage) -> HermesResult<()>;
+    fn publish_injection_complete(&self, message: InjectionCompleteMessage) -> HermesResult<()>;
+    fn publish_injection_reset_complete(&self, message: InjectionResetCompleteMessage) -> HermesResult<()>;
 }
 
 pub trait HermesProtocolHandler: Send + Sync + std::fmt::Display {
@@ -302,4 +599,90 @@ pub trait HermesProtocolHandler: Send + Sync + std::fmt::Display {
     fn audio_server_backend(&self) -> Box<dyn AudioServerBackendFacade>;
     fn dialogue_backend(&self) -> Box<dyn DialogueBackendFacade>;
     fn injection_backend(&self) -> Box<dyn InjectionBackendFacade>;
+
+    /// Subscribe to the messages this handler receives but can't decode, they are dropped after
+    /// being given to the callback. The handlers whose messages are never encoded, like the
+    /// in-process one, never call it
+    fn subscribe_decode_errors(&self, _handler: Callback<DecodeError>) -> HermesResult<SubscriptionHandle> {
+        Ok(SubscriptionHandle::new(|| Ok(())))
+    }
+}
+
+/// A boxed handler, like the ones `connect` gives, is a handler too
+impl<H: HermesProtocolHandler + ?Sized> HermesProtocolHandler for Box<H> {
+    fn voice_activity(&self) -> Box<dyn VoiceActivityFacade> {
+        (**self).voice_activity()
+    }
+
+    fn hotword(&self) -> Box<dyn HotwordFacade> {
+        (**self).hotword()
+    }
+
+    fn sound_feedback(&self) -> Box<dyn SoundFeedbackFacade> {
+        (**self).sound_feedback()
+    }
+
+    fn asr(&self) -> Box<dyn AsrFacade> {
+        (**self).asr()
+    }
+
+    fn tts(&self) -> Box<dyn TtsFacade> {
+        (**self).tts()
+    }
+
+    fn nlu(&self) -> Box<dyn NluFacade> {
+        (**self).nlu()
+    }
+
+    fn audio_server(&self) -> Box<dyn AudioServerFacade> {
+        (**self).audio_server()
+    }
+
+    fn dialogue(&self) -> Box<dyn DialogueFacade> {
+        (**self).dialogue()
+    }
+
+    fn injection(&self) -> Box<dyn InjectionFacade> {
+        (**self).injection()
+    }
+
+    fn voice_activity_backend(&self) -> Box<dyn VoiceActivityBackendFacade> {
+        (**self).voice_activity_backend()
+    }
+
+    fn hotword_backend(&self) -> Box<dyn HotwordBackendFacade> {
+        (**self).hotword_backend()
+    }
+
+    fn sound_feedback_backend(&self) -> Box<dyn SoundFeedbackBackendFacade> {
+        (**self).sound_feedback_backend()
+    }
+
+    fn asr_backend(&self) -> Box<dyn AsrBackendFacade> {
+        (**self).asr_backend()
+    }
+
+    fn tts_backend(&self) -> Box<dyn TtsBackendFacade> {
+        (**self).tts_backend()
+    }
+
+    fn nlu_backend(&self) -> Box<dyn NluBackendFacade> {
+        (**self).nlu_backend()
+    }
+
+    fn audio_server_backend(&self) -> Box<dyn AudioServerBackendFacade> {
+        (**self).audio_server_backend()
+    }
+
+    fn dialogue_backend(&self) -> Box<dyn DialogueBackendFacade> {
+        (**self).dialogue_backend()
+    }
+
+    fn injection_backend(&self) -> Box<dyn InjectionBackendFacade> {
+        (**self).injection_backend()
+    }
+
+    fn subscribe_decode_errors(&self, handler: Callback<DecodeError>) -> HermesResult<SubscriptionHandle> {
+        (**self).subscribe_decode_errors(handler)
+    }
 }
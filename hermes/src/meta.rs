@@ -0,0 +1,143 @@
+//! Metadata a protocol handler can attach to the messages it publishes, to tell when and where
+//! they were emitted. Subscribe with `Callback::with_meta` or `Callback0::with_meta` to get it.
+
+use std::cell::RefCell;
+
+use chrono::{DateTime, Utc};
+
+use crate::correlation::new_request_id;
+use crate::trace::TraceContext;
+
+/// The name of the JSON field holding the `MessageMeta` of a message on the wire
+pub const META_FIELD: &str = "_meta";
+
+thread_local! {
+    static FORWARDER: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Where a message comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageOrigin {
+    /// The component that published the message, `snips-dialogue` for example
+    pub component: String,
+    /// The host the component runs on
+    pub host: String,
+}
+
+impl MessageOrigin {
+    pub fn new<C: Into<String>, H: Into<String>>(component: C, host: H) -> Self {
+        Self {
+            component: component.into(),
+            host: host.into(),
+        }
+    }
+}
+
+/// The metadata of a message, set when it is published
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMeta {
+    /// A unique id for this message
+    pub id: String,
+    /// When the message was published
+    pub timestamp: DateTime<Utc>,
+    /// The component that published the message, if it told
+    pub origin: Option<MessageOrigin>,
+    /// The session and the site the message is about, see the `trace` module
+    #[serde(flatten)]
+    pub context: TraceContext,
+    /// The id of the bridge that forwarded the message from another handler, see the `bridge`
+    /// module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_by: Option<String>,
+}
+
+impl MessageMeta {
+    /// Metadata for a message published right now, in the context of the current thread
+    pub fn new(origin: Option<MessageOrigin>) -> Self {
+        Self {
+            id: new_request_id(),
+            timestamp: Utc::now(),
+            origin,
+            context: TraceContext::current(),
+            forwarded_by: FORWARDER.with(|it| it.borrow().clone()),
+        }
+    }
+
+    /// Metadata for a message published right now by a handler, when there is something to tell:
+    /// the origin of the handler, or the bridge forwarding the message
+    pub fn published(origin: Option<&MessageOrigin>) -> Option<Self> {
+        if origin.is_none() && FORWARDER.with(|it| it.borrow().is_none()) {
+            return None;
+        }
+        Some(Self::new(origin.cloned()))
+    }
+}
+
+/// Run `f` so that the messages it publishes on this thread tell they were forwarded by the bridge
+/// `bridge_id`
+pub fn forwarding<R, F: FnOnce() -> R>(bridge_id: &str, f: F) -> R {
+    let _restore = Restore(FORWARDER.with(|it| it.replace(Some(bridge_id.to_string()))));
+    f()
+}
+
+/// Puts the previous forwarder back when `forwarding` ends, even by a panic
+struct Restore(Option<String>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        let _ = FORWARDER.try_with(|it| *it.borrow_mut() = previous);
+    }
+}
+
+/// Extracts the `MessageMeta` of a JSON message, leaving the rest of it alone
+#[derive(Debug, Default, Deserialize)]
+pub struct MetaEnvelope {
+    #[serde(rename = "_meta", default)]
+    pub meta: Option<MessageMeta>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ontology::SayFinishedMessage;
+
+    #[test]
+    fn meta_is_carried_alongside_the_message_fields() {
+        let meta = MessageMeta::new(Some(MessageOrigin::new("snips-tts", "kitchen")));
+        let mut json = serde_json::to_value(SayFinishedMessage {
+            id: Some("say".into()),
+            session_id: None,
+        })
+        .unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .insert(META_FIELD.into(), serde_json::to_value(&meta).unwrap());
+        let bytes = serde_json::to_vec(&json).unwrap();
+
+        let message: SayFinishedMessage = serde_json::from_slice(&bytes).unwrap();
+        let envelope: MetaEnvelope = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(message.id, Some("say".into()));
+        assert_eq!(envelope.meta, Some(meta));
+    }
+
+    #[test]
+    fn forwarded_messages_tell_their_bridge() {
+        assert_eq!(MessageMeta::published(None), None);
+
+        let meta = forwarding("bridge", || MessageMeta::published(None)).unwrap();
+        assert_eq!(meta.forwarded_by, Some("bridge".into()));
+        assert_eq!(meta.origin, None);
+        assert_eq!(MessageMeta::new(None).forwarded_by, None);
+    }
+
+    #[test]
+    fn messages_without_meta_still_decode() {
+        let envelope: MetaEnvelope = serde_json::from_str(r#"{"id": "say", "sessionId": null}"#).unwrap();
+
+        assert_eq!(envelope.meta, None);
+    }
+}
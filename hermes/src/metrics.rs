@@ -0,0 +1,454 @@
+//! Metrics on the traffic of a protocol handler: how many messages and bytes go through each
+//! topic, how many could not be decoded, how long the callbacks take and how long the requests wait
+//! for their response. A handler reports them to a `MetricsSink`, like the `PrometheusSink` which
+//! keeps them for a Prometheus server to scrape.
+//!
+//! Only the MQTT handler reports metrics, see `MqttHermesProtocolHandler::set_metrics_sink`. The
+//! messages are counted once they are handed to the client, the ones that fail to publish aren't.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::*;
+
+use crate::errors::{HermesResult, PoisonLock};
+
+pub const MESSAGES_PUBLISHED: &str = "hermes_messages_published_total";
+pub const MESSAGES_RECEIVED: &str = "hermes_messages_received_total";
+pub const BYTES_PUBLISHED: &str = "hermes_bytes_published_total";
+pub const BYTES_RECEIVED: &str = "hermes_bytes_received_total";
+pub const DECODE_FAILURES: &str = "hermes_decode_failures_total";
+pub const DEFAULTED_FIELDS: &str = "hermes_defaulted_fields_total";
+pub const CALLBACK_DURATION: &str = "hermes_callback_duration_seconds";
+pub const REQUEST_LATENCY: &str = "hermes_request_latency_seconds";
+
+/// The requests whose latency is measured, with the topics of their responses. A request and its
+/// response are paired on the `id` of their messages
+const REQUESTS: &[(&str, &[&str])] = &[
+    ("hermes/tts/say", &["hermes/tts/sayFinished"]),
+    (
+        "hermes/nlu/query",
+        &["hermes/nlu/intentParsed", "hermes/nlu/intentNotRecognized"],
+    ),
+];
+
+/// How long a request is waited for its response before being forgotten
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The upper bounds of the buckets of the histograms, in seconds
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// How often the metrics endpoint checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where the metrics of a handler go
+pub trait MetricsSink: Send + Sync {
+    /// Add `value` to the counter `name` with these labels
+    fn add_to_counter(&self, name: &str, labels: &[(&str, &str)], value: u64);
+    /// Record `value` in the histogram `name` with these labels
+    fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64);
+}
+
+#[derive(Deserialize)]
+struct IdEnvelope {
+    id: Option<String>,
+}
+
+/// Turns what a handler sees into metrics for a sink. The topics are given as patterns, where the
+/// parts that vary (site ids, request ids...) are replaced by `+`, so that the series stay few
+pub struct Metrics {
+    sink: Arc<dyn MetricsSink>,
+    pending: Mutex<HashMap<(&'static str, String), Instant>>,
+}
+
+impl Metrics {
+    pub fn new(sink: Arc<dyn MetricsSink>) -> Self {
+        Self {
+            sink,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A message was published on `topic`
+    pub fn published(&self, topic: &str, payload: &[u8]) {
+        self.count(MESSAGES_PUBLISHED, BYTES_PUBLISHED, topic, payload)
+    }
+
+    /// A message was received on `topic`, it is counted for every subscription it is delivered to
+    pub fn received(&self, topic: &str, payload: &[u8]) {
+        self.count(MESSAGES_RECEIVED, BYTES_RECEIVED, topic, payload)
+    }
+
+    /// A message received on `topic` could not be decoded
+    pub fn decode_failed(&self, topic: &str) {
+        self.sink.add_to_counter(DECODE_FAILURES, &labels(topic), 1)
+    }
+
+    /// A message received on `topic` lacked `field`, which was given an empty value, see
+    /// `DecodePolicy::Lenient`
+    pub fn defaulted(&self, topic: &str, field: &str) {
+        let labels = labels(topic);
+        self.sink
+            .add_to_counter(DEFAULTED_FIELDS, &[labels[0], labels[1], ("field", field)], 1)
+    }
+
+    /// A callback subscribed to `topic` ran for `duration`
+    pub fn callback_ran(&self, topic: &str, duration: Duration) {
+        self.sink
+            .observe(CALLBACK_DURATION, &labels(topic), duration.as_secs_f64())
+    }
+
+    fn count(&self, messages: &str, bytes: &str, topic: &str, payload: &[u8]) {
+        let labels = labels(topic);
+        self.sink.add_to_counter(messages, &labels, 1);
+        self.sink.add_to_counter(bytes, &labels, payload.len() as u64);
+        self.pair(topic, payload);
+    }
+
+    /// Start the clock when a request goes by, and stop it when its response does
+    fn pair(&self, topic: &str, payload: &[u8]) {
+        let (request, is_request) = match REQUESTS.iter().find_map(|(request, responses)| {
+            if *request == topic {
+                Some((*request, true))
+            } else if responses.iter().any(|it| *it == topic) {
+                Some((*request, false))
+            } else {
+                None
+            }
+        }) {
+            Some(it) => it,
+            None => return,
+        };
+        let id = match serde_json::from_slice::<IdEnvelope>(payload).ok().and_then(|it| it.id) {
+            Some(id) => id,
+            None => return,
+        };
+        let now = Instant::now();
+        let since = {
+            let mut pending = match self.pending.lock() {
+                Ok(pending) => pending,
+                Err(_) => {
+                    debug!(
+                        "Could not lock the pending requests, ignoring the message on '{}'",
+                        topic
+                    );
+                    return;
+                }
+            };
+            if is_request {
+                pending.retain(|_, since| now.duration_since(*since) < PENDING_TIMEOUT);
+                pending.entry((request, id)).or_insert(now);
+                return;
+            }
+            pending.remove(&(request, id))
+        };
+        if let Some(since) = since {
+            self.sink.observe(
+                REQUEST_LATENCY,
+                &[("request", request), ("response", topic)],
+                now.duration_since(since).as_secs_f64(),
+            )
+        }
+    }
+}
+
+/// The labels of the metrics about a topic, whose component is the segment after `hermes/`
+fn labels(topic: &str) -> [(&str, &str); 2] {
+    let component = topic.split('/').nth(1).unwrap_or(topic);
+    [("component", component), ("topic", topic)]
+}
+
+type Labels = Vec<(String, String)>;
+
+struct Histogram {
+    /// How many values fell in each bucket or a lower one
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, value: f64) {
+        for (bound, count) in BUCKETS.iter().zip(&mut self.counts) {
+            if value <= *bound {
+                *count += 1
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    counters: BTreeMap<String, BTreeMap<Labels, u64>>,
+    histograms: BTreeMap<String, BTreeMap<Labels, Histogram>>,
+}
+
+/// A sink keeping the metrics in memory to render them in the Prometheus text exposition format,
+/// on demand or on an HTTP endpoint. Its clones share their metrics
+#[derive(Clone, Default)]
+pub struct PrometheusSink {
+    registry: Arc<Mutex<Registry>>,
+}
+
+fn owned_labels(labels: &[(&str, &str)]) -> Labels {
+    let mut labels: Labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    labels.sort();
+    labels
+}
+
+impl MetricsSink for PrometheusSink {
+    fn add_to_counter(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        match self.registry.lock() {
+            Ok(mut registry) => {
+                *registry
+                    .counters
+                    .entry(name.to_string())
+                    .or_default()
+                    .entry(owned_labels(labels))
+                    .or_insert(0) += value
+            }
+            Err(_) => debug!("Could not lock the metrics to count '{}'", name),
+        }
+    }
+
+    fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        match self.registry.lock() {
+            Ok(mut registry) => registry
+                .histograms
+                .entry(name.to_string())
+                .or_default()
+                .entry(owned_labels(labels))
+                .or_default()
+                .record(value),
+            Err(_) => debug!("Could not lock the metrics to observe '{}'", name),
+        }
+    }
+}
+
+impl PrometheusSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> HermesResult<String> {
+        let registry = self.registry.lock().map_err(PoisonLock::from)?;
+        Ok(Exposition(&registry).to_string())
+    }
+
+    /// Serve the metrics over HTTP on `/metrics` at `address`, `127.0.0.1:9090` for example, until
+    /// the returned server is dropped
+    pub fn serve<A: ToSocketAddrs>(&self, address: A) -> HermesResult<MetricsServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let still_running = Arc::clone(&running);
+        let sink = self.clone();
+        let thread = thread::Builder::new().name("hermes-metrics".into()).spawn(move || {
+            while still_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(e) = sink.respond(stream) {
+                            debug!("Could not serve the metrics: {}", e)
+                        }
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(e) => {
+                        debug!("Could not accept a connection to the metrics endpoint: {}", e);
+                        thread::sleep(POLL_INTERVAL)
+                    }
+                }
+            }
+        })?;
+        Ok(MetricsServer {
+            address,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> HermesResult<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let mut request = [0; 1024];
+        let read = stream.read(&mut request)?;
+        let path = String::from_utf8_lossy(&request[..read])
+            .split_whitespace()
+            .nth(1)
+            .map(str::to_string);
+        let (status, body) = match path.as_deref() {
+            Some("/metrics") | Some("/") => ("200 OK", self.render()?),
+            _ => ("404 Not Found", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )?;
+        Ok(())
+    }
+}
+
+/// An HTTP endpoint serving the metrics of a `PrometheusSink`, stopped when dropped
+pub struct MetricsServer {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// The address the endpoint listens on, useful when it was bound to port 0
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                debug!("The metrics endpoint on {} panicked", self.address)
+            }
+        }
+    }
+}
+
+struct Exposition<'a>(&'a Registry);
+
+impl<'a> fmt::Display for Exposition<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, series) in &self.0.counters {
+            writeln!(f, "# TYPE {} counter", name)?;
+            for (labels, value) in series {
+                writeln!(f, "{}{} {}", name, LabelSet(labels, None), value)?;
+            }
+        }
+        for (name, series) in &self.0.histograms {
+            writeln!(f, "# TYPE {} histogram", name)?;
+            for (labels, histogram) in series {
+                for (bound, count) in BUCKETS.iter().zip(&histogram.counts) {
+                    let bound = bound.to_string();
+                    writeln!(f, "{}_bucket{} {}", name, LabelSet(labels, Some(bound.as_str())), count)?;
+                }
+                writeln!(
+                    f,
+                    "{}_bucket{} {}",
+                    name,
+                    LabelSet(labels, Some("+Inf")),
+                    histogram.count
+                )?;
+                writeln!(f, "{}_sum{} {}", name, LabelSet(labels, None), histogram.sum)?;
+                writeln!(f, "{}_count{} {}", name, LabelSet(labels, None), histogram.count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The labels of a series, with the bound of its bucket for the buckets of a histogram
+struct LabelSet<'a>(&'a Labels, Option<&'a str>);
+
+impl<'a> fmt::Display for LabelSet<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bucket = self.1.map(|bound| ("le", bound));
+        let mut labels = self
+            .0
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(bucket)
+            .peekable();
+        if labels.peek().is_none() {
+            return Ok(());
+        }
+        let labels: Vec<String> = labels
+            .map(|(name, value)| {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                format!("{}=\"{}\"", name, value)
+            })
+            .collect();
+        write!(f, "{{{}}}", labels.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traffic_is_counted_and_requests_paired_with_their_responses() {
+        let sink = PrometheusSink::new();
+        let metrics = Metrics::new(Arc::new(sink.clone()));
+        let say = br#"{"text":"hello","siteId":"default","id":"1"}"#;
+
+        metrics.published("hermes/tts/say", say);
+        metrics.received("hermes/tts/sayFinished", br#"{"id":"2"}"#);
+        metrics.received("hermes/tts/sayFinished", br#"{"id":"1"}"#);
+        metrics.decode_failed("hermes/tts/sayFinished");
+
+        let text = sink.render().unwrap();
+        assert!(text.contains("# TYPE hermes_messages_published_total counter\n"));
+        assert!(text.contains(&format!(
+            "hermes_bytes_published_total{{component=\"tts\",topic=\"hermes/tts/say\"}} {}\n",
+            say.len()
+        )));
+        assert!(text.contains("hermes_messages_received_total{component=\"tts\",topic=\"hermes/tts/sayFinished\"} 2\n"));
+        assert!(text.contains("hermes_decode_failures_total{component=\"tts\",topic=\"hermes/tts/sayFinished\"} 1\n"));
+        assert!(text.contains("# TYPE hermes_request_latency_seconds histogram\n"));
+        assert!(text.contains(
+            "hermes_request_latency_seconds_count{request=\"hermes/tts/say\",response=\"hermes/tts/sayFinished\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn histograms_have_cumulative_buckets() {
+        let sink = PrometheusSink::new();
+        sink.observe("duration_seconds", &[("topic", "say \"hi\"")], 0.02);
+        sink.observe("duration_seconds", &[("topic", "say \"hi\"")], 3.0);
+
+        let text = sink.render().unwrap();
+        assert!(text.contains("duration_seconds_bucket{topic=\"say \\\"hi\\\"\",le=\"0.01\"} 0\n"));
+        assert!(text.contains("duration_seconds_bucket{topic=\"say \\\"hi\\\"\",le=\"0.025\"} 1\n"));
+        assert!(text.contains("duration_seconds_bucket{topic=\"say \\\"hi\\\"\",le=\"5\"} 2\n"));
+        assert!(text.contains("duration_seconds_bucket{topic=\"say \\\"hi\\\"\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("duration_seconds_count{topic=\"say \\\"hi\\\"\"} 2\n"));
+    }
+
+    #[test]
+    fn metrics_are_served_over_http() {
+        let sink = PrometheusSink::new();
+        sink.add_to_counter("hits_total", &[], 3);
+        let server = sink.serve("127.0.0.1:0").unwrap();
+
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n# TYPE hits_total counter\nhits_total 3\n"));
+    }
+}
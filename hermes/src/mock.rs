@@ -0,0 +1,300 @@
+//! Scriptable fake components, to test skills and the dialogue without real audio. A mock plays the
+//! backend of a component on any `HermesProtocolHandler`, answers the orders it receives as it was
+//! scripted to, and records them so that tests can make assertions on them.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use log::error;
+
+use crate::errors::{HermesError, HermesResult, PoisonLock};
+use crate::ontology::*;
+use crate::{Callback, HermesProtocolHandler, SubscriptionHandle};
+
+/// An asr capturing, each time it is asked to listen, the next text it was given
+pub type MockAsr = Mock<VecDeque<String>, AsrStartListeningMessage>;
+/// An nlu understanding the inputs it was taught, and nothing else
+pub type MockNlu = Mock<HashMap<String, NluIntentMessage>, NluQueryMessage>;
+/// A tts that has finished saying as soon as it is asked to
+pub type MockTts = Mock<(), SayMessage>;
+/// An audio server that has finished playing as soon as it is asked to
+pub type MockAudioServer = Mock<(), PlayBytesMessage>;
+
+struct Recorder<S, M> {
+    script: S,
+    received: Vec<M>,
+}
+
+struct Shared<S, M> {
+    recorder: Mutex<Recorder<S, M>>,
+    /// Notified each time an order is received
+    received: Condvar,
+}
+
+/// A fake component, it stops answering when dropped
+pub struct Mock<S, M> {
+    shared: Arc<Shared<S, M>>,
+    _subscription: SubscriptionHandle,
+}
+
+impl<S, M> Mock<S, M>
+where
+    S: Send + 'static,
+    M: Clone + Send + 'static,
+{
+    fn start<R, F, A, P>(script: S, subscribe: F, answer: A, publish: P) -> HermesResult<Self>
+    where
+        F: FnOnce(Callback<M>) -> HermesResult<SubscriptionHandle>,
+        A: Fn(&mut S, &M) -> Option<R> + Send + Sync + 'static,
+        P: Fn(R) -> HermesResult<()> + Send + Sync + 'static,
+    {
+        let shared = Arc::new(Shared {
+            recorder: Mutex::new(Recorder {
+                script,
+                received: vec![],
+            }),
+            received: Condvar::new(),
+        });
+        let recorder = Arc::clone(&shared);
+        let subscription = subscribe(Callback::new(move |order: &M| {
+            // the answer is published once the mock is unlocked, as it may well lead to another order
+            let answer = match recorder.recorder.lock() {
+                Ok(mut state) => {
+                    state.received.push(order.clone());
+                    answer(&mut state.script, order)
+                }
+                Err(_) => {
+                    error!("Could not lock the mock to record an order");
+                    return;
+                }
+            };
+            recorder.received.notify_all();
+            if let Some(answer) = answer {
+                if let Err(e) = publish(answer) {
+                    error!("Mock could not answer an order: {}", e)
+                }
+            }
+        }))?;
+
+        Ok(Self {
+            shared,
+            _subscription: subscription,
+        })
+    }
+
+    fn script<T, F: FnOnce(&mut S) -> T>(&self, f: F) -> HermesResult<T> {
+        let mut state = self.shared.recorder.lock().map_err(PoisonLock::from)?;
+        Ok(f(&mut state.script))
+    }
+
+    /// The orders received so far, in order
+    pub fn received(&self) -> HermesResult<Vec<M>> {
+        Ok(self.shared.recorder.lock().map_err(PoisonLock::from)?.received.clone())
+    }
+
+    /// Wait for the mock to have received at least `count` orders, and return them
+    pub fn wait_for(&self, count: usize, timeout: Duration) -> HermesResult<Vec<M>> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.recorder.lock().map_err(PoisonLock::from)?;
+        while state.received.len() < count {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(HermesError::Timeout(timeout));
+            }
+            state = self
+                .shared
+                .received
+                .wait_timeout(state, deadline - now)
+                .map_err(PoisonLock::from)?
+                .0;
+        }
+        Ok(state.received.clone())
+    }
+
+    /// Forget the orders received so far
+    pub fn clear(&self) -> HermesResult<()> {
+        self.shared.recorder.lock().map_err(PoisonLock::from)?.received.clear();
+        Ok(())
+    }
+}
+
+impl MockAsr {
+    pub fn new<H: HermesProtocolHandler + ?Sized>(handler: &H) -> HermesResult<Self> {
+        let asr: Arc<dyn AsrBackendFacade> = handler.asr_backend().into();
+        let publisher = Arc::clone(&asr);
+        Self::start(
+            VecDeque::new(),
+            |callback| asr.subscribe_start_listening(callback),
+            capture,
+            move |captured| publisher.publish_text_captured(captured),
+        )
+    }
+
+    /// Capture `text` the next time the asr is asked to listen, after the texts given before it
+    pub fn will_capture<T: Into<String>>(&self, text: T) -> HermesResult<()> {
+        self.script(|texts| texts.push_back(text.into()))
+    }
+}
+
+impl MockNlu {
+    pub fn new<H: HermesProtocolHandler + ?Sized>(handler: &H) -> HermesResult<Self> {
+        let nlu: Arc<dyn NluBackendFacade> = handler.nlu_backend().into();
+        let publisher = Arc::clone(&nlu);
+        Self::start(
+            HashMap::new(),
+            |callback| nlu.subscribe_query(callback),
+            |intents, query| Some(parse(intents, query)),
+            move |parsed| match parsed {
+                Ok(intent) => publisher.publish_intent_parsed(intent),
+                Err(not_recognized) => publisher.publish_intent_not_recognized(not_recognized),
+            },
+        )
+    }
+
+    /// Understand `input` as `intent_name`, with full confidence and no slots
+    pub fn understands<I: Into<String>, N: Into<String>>(&self, input: I, intent_name: N) -> HermesResult<()> {
+        let input = input.into();
+        self.understands_with(
+            input.clone(),
+            NluIntentMessage {
+                id: None,
+                input,
+                intent: NluIntentClassifierResult {
+                    intent_name: intent_name.into(),
+                    confidence_score: 1.0,
+                },
+                slots: vec![],
+                session_id: None,
+                alternatives: None,
+            },
+        )
+    }
+
+    /// Understand `input` as `intent`, its id, input and session id are those of the query
+    pub fn understands_with<I: Into<String>>(&self, input: I, intent: NluIntentMessage) -> HermesResult<()> {
+        self.script(|intents| {
+            intents.insert(input.into(), intent);
+        })
+    }
+}
+
+impl MockTts {
+    pub fn new<H: HermesProtocolHandler + ?Sized>(handler: &H) -> HermesResult<Self> {
+        let tts: Arc<dyn TtsBackendFacade> = handler.tts_backend().into();
+        let publisher = Arc::clone(&tts);
+        Self::start(
+            (),
+            |callback| tts.subscribe_say(callback),
+            |_, say: &SayMessage| {
+                Some(SayFinishedMessage {
+                    id: say.id.clone(),
+                    session_id: say.session_id.clone(),
+                })
+            },
+            move |finished| publisher.publish_say_finished(finished),
+        )
+    }
+}
+
+impl MockAudioServer {
+    /// Play the audio server of every site
+    pub fn new<H: HermesProtocolHandler + ?Sized>(handler: &H) -> HermesResult<Self> {
+        let audio_server: Arc<dyn AudioServerBackendFacade> = handler.audio_server_backend().into();
+        let publisher = Arc::clone(&audio_server);
+        Self::start(
+            (),
+            |callback| audio_server.subscribe_all_play_bytes(callback),
+            |_, bytes: &PlayBytesMessage| {
+                Some(PlayFinishedMessage {
+                    id: bytes.id.clone(),
+                    site_id: bytes.site_id.clone(),
+                })
+            },
+            move |finished| publisher.publish_play_finished(finished),
+        )
+    }
+}
+
+fn capture(texts: &mut VecDeque<String>, listening: &AsrStartListeningMessage) -> Option<TextCapturedMessage> {
+    Some(TextCapturedMessage {
+        text: texts.pop_front()?,
+        likelihood: 1.0,
+        tokens: None,
+        seconds: 1.0,
+        site_id: listening.site_id.clone(),
+        session_id: listening.session_id.clone(),
+        speaker_hypotheses: None,
+    })
+}
+
+/// An input is understood if it was taught and its intent isn't filtered out by the query
+fn parse(
+    intents: &HashMap<String, NluIntentMessage>,
+    query: &NluQueryMessage,
+) -> Result<NluIntentMessage, NluIntentNotRecognizedMessage> {
+    let intent = intents.get(&query.input).filter(|intent| {
+        query
+            .intent_filter
+            .as_ref()
+            .map_or(true, |filter| filter.contains(&intent.intent.intent_name))
+    });
+    match intent {
+        Some(intent) => Ok(NluIntentMessage {
+            id: query.id.clone(),
+            input: query.input.clone(),
+            session_id: query.session_id.clone(),
+            ..intent.clone()
+        }),
+        None => Err(NluIntentNotRecognizedMessage {
+            id: query.id.clone(),
+            input: query.input.clone(),
+            confidence_score: 0.0,
+            session_id: query.session_id.clone(),
+            alternatives: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermes_utils::Example;
+
+    fn query(input: &str, intent_filter: Option<Vec<String>>) -> NluQueryMessage {
+        NluQueryMessage {
+            input: input.into(),
+            asr_tokens: None,
+            intent_filter,
+            id: Some("abc".into()),
+            session_id: Some("session".into()),
+        }
+    }
+
+    #[test]
+    fn taught_inputs_are_understood() {
+        let mut intent = NluIntentMessage::full_example();
+        intent.intent.intent_name = "lights".into();
+        let intents = vec![("turn on the lights".to_string(), intent)].into_iter().collect();
+
+        let parsed = parse(&intents, &query("turn on the lights", None)).unwrap();
+        assert_eq!(parsed.intent.intent_name, "lights");
+        assert_eq!(parsed.id, Some("abc".into()));
+        assert_eq!(parsed.session_id, Some("session".into()));
+
+        assert!(parse(&intents, &query("what's the weather", None)).is_err());
+        assert!(parse(&intents, &query("turn on the lights", Some(vec!["weather".into()]))).is_err());
+    }
+
+    #[test]
+    fn texts_are_captured_in_turn() {
+        let mut texts: VecDeque<String> = vec!["hello".to_string(), "goodbye".to_string()].into_iter().collect();
+        let listening = AsrStartListeningMessage::full_example();
+
+        let captured = capture(&mut texts, &listening).unwrap();
+        assert_eq!(captured.text, "hello");
+        assert_eq!(captured.site_id, listening.site_id);
+        assert_eq!(capture(&mut texts, &listening).unwrap().text, "goodbye");
+        assert!(capture(&mut texts, &listening).is_none());
+    }
+}
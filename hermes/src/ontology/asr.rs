@@ -1,6 +1,7 @@
 use super::HermesMessage;
 
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct AsrStartListeningMessage {
     /// The site that must be listened too
@@ -14,6 +15,7 @@ pub struct AsrStartListeningMessage {
 impl<'de> HermesMessage<'de> for AsrStartListeningMessage {}
 
 #[derive(Debug, Clone, Default, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct AsrDecodingDuration {
     pub start: f32,
@@ -21,6 +23,7 @@ pub struct AsrDecodingDuration {
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct AsrToken {
     /// The value of the token
@@ -37,6 +40,7 @@ pub struct AsrToken {
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct TextCapturedMessage {
     /// The text captured
@@ -59,6 +63,7 @@ pub struct TextCapturedMessage {
 impl<'de> HermesMessage<'de> for TextCapturedMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SpeakerId {
     /// The name of the detected speaker, `None` represents unknown speakers
@@ -5,6 +5,7 @@ use crate::{HermesComponent, NluIntentAlternative};
 use hermes_utils::Example;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct IntentMessage {
     /// The session in which this intent was detected
@@ -34,6 +35,7 @@ pub struct IntentMessage {
 impl<'de> HermesMessage<'de> for IntentMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct IntentNotRecognizedMessage {
     /// The session in which no intent was recognized
@@ -57,6 +59,7 @@ pub struct IntentNotRecognizedMessage {
 impl<'de> HermesMessage<'de> for IntentNotRecognizedMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum SessionInit {
     /// The session expects a response from the user. Users responses will
@@ -103,6 +106,7 @@ impl Example for SessionInit {
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct StartSessionMessage {
     /// The way this session should be created
@@ -119,6 +123,7 @@ pub struct StartSessionMessage {
 impl<'de> HermesMessage<'de> for StartSessionMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SessionStartedMessage {
     /// The id of the session that was started
@@ -136,6 +141,7 @@ pub struct SessionStartedMessage {
 impl<'de> HermesMessage<'de> for SessionStartedMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SessionQueuedMessage {
     /// The id of the session that was queued
@@ -149,6 +155,7 @@ pub struct SessionQueuedMessage {
 impl<'de> HermesMessage<'de> for SessionQueuedMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ContinueSessionMessage {
     /// The id of the session this action applies to
@@ -178,6 +185,7 @@ pub struct ContinueSessionMessage {
 impl<'de> HermesMessage<'de> for ContinueSessionMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct EndSessionMessage {
     /// The id of the session to end
@@ -189,6 +197,7 @@ pub struct EndSessionMessage {
 impl<'de> HermesMessage<'de> for EndSessionMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "reason", rename_all = "camelCase")]
 pub enum SessionTerminationType {
     /// The session ended as expected
@@ -206,6 +215,7 @@ pub enum SessionTerminationType {
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SessionEndedMessage {
     /// The id of the session that was terminated
@@ -222,6 +232,7 @@ pub struct SessionEndedMessage {
 impl<'de> HermesMessage<'de> for SessionEndedMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct DialogueConfigureMessage {
     /// The site on which this configuration applies, if None the configuration will be applied to
@@ -234,6 +245,7 @@ pub struct DialogueConfigureMessage {
 impl<'de> HermesMessage<'de> for DialogueConfigureMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct DialogueConfigureIntent {
     /// The name of the intent that should be configured.
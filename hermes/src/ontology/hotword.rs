@@ -1,6 +1,7 @@
 use super::HermesMessage;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub enum HotwordModelType {
     Universal,
@@ -8,6 +9,7 @@ pub enum HotwordModelType {
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct HotwordDetectedMessage {
     /// The site where the hotword was triggered
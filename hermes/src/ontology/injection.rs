@@ -12,6 +12,7 @@ type Entity = String;
 type Pronunciation = String;
 
 #[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub enum InjectionKind {
     /// Add to current assistant
@@ -63,6 +64,7 @@ impl Serialize for EntityValue {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InjectionRequestMessage {
     /// List of operations to execute in the order of the list on a model
@@ -79,6 +81,7 @@ pub struct InjectionRequestMessage {
 impl<'de> HermesMessage<'de> for InjectionRequestMessage {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InjectionStatusMessage {
     /// Date of the latest injection
@@ -89,6 +92,7 @@ pub struct InjectionStatusMessage {
 impl<'de> HermesMessage<'de> for InjectionStatusMessage {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InjectionCompleteMessage {
     /// The id of the `InjectionRequestMessage`
@@ -98,6 +102,7 @@ pub struct InjectionCompleteMessage {
 impl<'de> HermesMessage<'de> for InjectionCompleteMessage {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InjectionResetRequestMessage {
     /// The id of the `InjectionResetRequestMessage`
@@ -107,6 +112,7 @@ pub struct InjectionResetRequestMessage {
 impl<'de> HermesMessage<'de> for InjectionResetRequestMessage {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct InjectionResetCompleteMessage {
     /// The id of the `InjectionResetCompleteMessage`
@@ -4,6 +4,8 @@ use hermes_utils::Example;
 use semver;
 use serde::{Deserialize, Serialize};
 
+use crate::validation::Validate;
+
 pub use self::asr::*;
 pub use self::audio_server::*;
 pub use self::dialogue::*;
@@ -22,9 +24,10 @@ pub mod nlu;
 pub mod tts;
 pub mod vad;
 
-pub trait HermesMessage<'de>: fmt::Debug + Deserialize<'de> + Serialize + Example {}
+pub trait HermesMessage<'de>: fmt::Debug + Deserialize<'de> + Serialize + Example + Validate {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SiteMessage {
     /// The site concerned
@@ -45,16 +48,43 @@ impl Default for SiteMessage {
 impl<'de> HermesMessage<'de> for SiteMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct VersionMessage {
     /// The version of the component
     #[example_value(semver::Version::parse("1.0.0").unwrap())]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub version: semver::Version,
+    /// The version of the hermes protocol the component speaks, components predating this field
+    /// don't send it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[example_value(Some(crate::compatibility::protocol_version()))]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub protocol_version: Option<semver::Version>,
+    /// The optional features of the protocol the component supports
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl VersionMessage {
+    /// The version message of a component using this crate, advertising its protocol version and
+    /// capabilities
+    pub fn new(version: semver::Version) -> Self {
+        Self {
+            version,
+            protocol_version: Some(crate::compatibility::protocol_version()),
+            capabilities: crate::compatibility::CAPABILITIES
+                .iter()
+                .map(|it| it.to_string())
+                .collect(),
+        }
+    }
 }
 
 impl<'de> HermesMessage<'de> for VersionMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorMessage {
     /// An optional session id if there is a related session
@@ -68,6 +98,7 @@ pub struct ErrorMessage {
 impl<'de> HermesMessage<'de> for ErrorMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SiteErrorMessage {
     /// Site on which the error happened.
@@ -83,6 +114,7 @@ pub struct SiteErrorMessage {
 impl<'de> HermesMessage<'de> for SiteErrorMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub enum HermesComponent {
     AudioServer,
@@ -112,6 +144,7 @@ where
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ComponentLoadedOnSiteMessage {
     /// Optional id associated to a load/reload operation for a component
@@ -125,6 +158,7 @@ pub struct ComponentLoadedOnSiteMessage {
 impl<'de> HermesMessage<'de> for ComponentLoadedOnSiteMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct RequestComponentReloadMessage {
     /// Id associated to a reload request operation of a component
@@ -134,6 +168,7 @@ pub struct RequestComponentReloadMessage {
 impl<'de> HermesMessage<'de> for RequestComponentReloadMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct ComponentLoadedMessage {
     /// Optional id associated to a load/reload operation for a component
@@ -4,6 +4,7 @@ use super::asr::AsrToken;
 use super::HermesMessage;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluQueryMessage {
     /// The text to run the NLU on
@@ -22,6 +23,7 @@ pub struct NluQueryMessage {
 impl<'de> HermesMessage<'de> for NluQueryMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluSlotQueryMessage {
     /// The text to run the slot detection on
@@ -42,6 +44,7 @@ pub struct NluSlotQueryMessage {
 impl<'de> HermesMessage<'de> for NluSlotQueryMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluSlotMessage {
     /// The id of the `NluSlotQueryMessage` that was processed
@@ -59,6 +62,7 @@ pub struct NluSlotMessage {
 impl<'de> HermesMessage<'de> for NluSlotMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluIntentNotRecognizedMessage {
     /// The id of the `NluQueryMessage` that was processed
@@ -118,6 +122,7 @@ impl Example for NluSlot {
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluIntentClassifierResult {
     /// Name of the intent that was found
@@ -127,6 +132,7 @@ pub struct NluIntentClassifierResult {
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluIntentMessage {
     /// The id of the `NluQueryMessage` that was processed
@@ -147,6 +153,7 @@ pub struct NluIntentMessage {
 impl<'de> HermesMessage<'de> for NluIntentMessage {}
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct NluIntentAlternative {
     /// Name of the intent that was found, or None if not intent was recognized
@@ -1,6 +1,7 @@
 use super::HermesMessage;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SayMessage {
     /// The text to say
@@ -19,6 +20,7 @@ pub struct SayMessage {
 impl<'de> HermesMessage<'de> for SayMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct SayFinishedMessage {
     /// The id of the `SayMessage` which was has been said
@@ -30,10 +32,12 @@ pub struct SayFinishedMessage {
 impl<'de> HermesMessage<'de> for SayFinishedMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct RegisterSoundMessage {
     /// The sound to register encoded as a wav.
     #[serde(serialize_with = "super::as_base64", deserialize_with = "super::from_base64")]
+    #[cfg_attr(feature = "schema", schemars(schema_with = "crate::schema::base64"))]
     pub wav_sound: Vec<u8>,
     /// The id this sound should be registered under
     pub sound_id: String,
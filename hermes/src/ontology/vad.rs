@@ -1,6 +1,7 @@
 use super::HermesMessage;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct VadUpMessage {
     /// The site concerned
@@ -12,6 +13,7 @@ pub struct VadUpMessage {
 impl<'de> HermesMessage<'de> for VadUpMessage {}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize, Serialize, Example)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "camelCase")]
 pub struct VadDownMessage {
     /// The site concerned
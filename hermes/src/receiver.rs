@@ -0,0 +1,371 @@
+//! Subscriptions consumed through a bounded channel instead of a callback, for synchronous
+//! consumers that want to loop on the messages they receive.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::errors::{HermesError, HermesResult, PoisonLock};
+use crate::{Callback, Callback0, SubscriptionHandle};
+
+/// What to do with a new message when the buffer of a `Receiver` is full
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Discard the oldest buffered message to make room for the new one
+    DropOldest,
+    /// Wait for the consumer to make room, this blocks the thread delivering the messages
+    Block,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    dropped: usize,
+    // the subscription callback was dropped or the handler went away, nothing will be received
+    // anymore
+    disconnected: bool,
+    // the receiver was dropped, nothing will be consumed anymore
+    abandoned: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> HermesResult<MutexGuard<State<T>>> {
+        Ok(self.state.lock().map_err(PoisonLock::from)?)
+    }
+
+    fn push(&self, message: T) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        if state.queue.len() >= self.capacity {
+            match self.overflow {
+                Overflow::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+                Overflow::Block => {
+                    while state.queue.len() >= self.capacity && !state.abandoned {
+                        state = match self.not_full.wait(state) {
+                            Ok(state) => state,
+                            Err(_) => return,
+                        };
+                    }
+                }
+            }
+        }
+        if !state.abandoned {
+            state.queue.push_back(message);
+            self.not_empty.notify_one();
+        }
+    }
+
+    fn disconnect(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.disconnected = true;
+        }
+        self.not_empty.notify_all();
+    }
+}
+
+/// Lives in the subscription callback, marks the receiver as disconnected when the callback is
+/// dropped along with the subscription
+struct Sender<T>(Arc<Shared<T>>);
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.disconnect()
+    }
+}
+
+/// The receiving end of a subscription, create one with `receiver` or `ReceiverExt::receiver`.
+/// Dropping it cancels the subscription.
+///
+/// The subscription of a handler that went away isn't always dropped: the facades may keep it
+/// while they are around. Give the receiver a way to know, with `closed_by`, so that it doesn't
+/// wait forever then.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    _subscription: SubscriptionHandle,
+    _closing: Vec<SubscriptionHandle>,
+}
+
+impl<T: Send + 'static> Receiver<T> {
+    /// Close this receiver when the callback given to `subscribe` is called, like the one of
+    /// `InProcessHermesProtocolHandler::subscribe_bus_closed`
+    ///
+    /// ```ignore
+    /// let intents = hermes::receiver(16, Overflow::Block, |callback| dialogue.subscribe_intents(callback))?
+    ///     .closed_by(|callback| handler.subscribe_bus_closed(callback))?;
+    /// ```
+    pub fn closed_by<S>(mut self, subscribe: S) -> HermesResult<Self>
+    where
+        S: FnOnce(Callback0) -> HermesResult<SubscriptionHandle>,
+    {
+        let shared = Arc::clone(&self.shared);
+        let subscription = subscribe(Callback0::new(move || shared.disconnect()))?;
+        self._closing.push(subscription);
+        Ok(self)
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Wait for the next message, fails with `HermesError::Closed` once the subscription is gone,
+    /// or the handler went away (see `closed_by`), and all the buffered messages have been received
+    pub fn recv(&self) -> HermesResult<T> {
+        let mut state = self.shared.lock()?;
+        loop {
+            if let Some(message) = self.take(&mut state) {
+                return Ok(message);
+            }
+            if state.disconnected {
+                return Err(HermesError::Closed);
+            }
+            state = self.shared.not_empty.wait(state).map_err(PoisonLock::from)?;
+        }
+    }
+
+    /// Wait at most `timeout` for the next message, fails with `HermesError::Timeout` if none came
+    pub fn recv_timeout(&self, timeout: Duration) -> HermesResult<T> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock()?;
+        loop {
+            if let Some(message) = self.take(&mut state) {
+                return Ok(message);
+            }
+            if state.disconnected {
+                return Err(HermesError::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(HermesError::Timeout(timeout));
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .map_err(PoisonLock::from)?
+                .0;
+        }
+    }
+
+    /// Take the next message if there is one already buffered
+    pub fn try_recv(&self) -> Option<T> {
+        self.shared.lock().ok().and_then(|mut state| self.take(&mut state))
+    }
+
+    /// Iterate over the messages, blocking while waiting for them, until the subscription is gone or
+    /// the handler went away (see `closed_by`)
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+
+    /// Iterate over the messages that are already buffered, without blocking
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.try_recv())
+    }
+
+    /// How many messages were discarded so far because the buffer was full
+    pub fn dropped(&self) -> usize {
+        self.shared.lock().map(|state| state.dropped).unwrap_or(0)
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let message = state.queue.pop_front();
+        if message.is_some() {
+            self.shared.not_full.notify_one();
+        }
+        message
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // release a delivery that may be waiting for room, the subscription is cancelled right after
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.abandoned = true;
+        }
+        self.shared.not_full.notify_all();
+    }
+}
+
+impl<T> std::fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Receiver")
+            .field("capacity", &self.shared.capacity)
+            .field("overflow", &self.shared.overflow)
+            .finish()
+    }
+}
+
+/// Subscribe through `subscribe` and receive the messages in a channel buffering at most
+/// `capacity` of them (at least one), `overflow` telling what happens when it's full.
+///
+/// ```ignore
+/// let intents = hermes::receiver(16, Overflow::Block, |callback| dialogue.subscribe_intents(callback))?;
+/// for intent in intents.iter() {
+///     println!("{:?}", intent);
+/// }
+/// ```
+pub fn receiver<T, S>(capacity: usize, overflow: Overflow, subscribe: S) -> HermesResult<Receiver<T>>
+where
+    T: Clone + Send + 'static,
+    S: FnOnce(Callback<T>) -> HermesResult<SubscriptionHandle>,
+{
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::with_capacity(capacity),
+            dropped: 0,
+            disconnected: false,
+            abandoned: false,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity,
+        overflow,
+    });
+    let sender = Sender(Arc::clone(&shared));
+    let subscription = subscribe(Callback::new(move |message: &T| sender.0.push(message.clone())))?;
+    Ok(Receiver {
+        shared,
+        _subscription: subscription,
+        _closing: vec![],
+    })
+}
+
+/// Gives facades a `receiver` method, so that `dialogue.receiver(16, Overflow::Block, |f, cb|
+/// f.subscribe_intents(cb))` gives the intents in a `Receiver`
+pub trait ReceiverExt {
+    fn receiver<T, S>(&self, capacity: usize, overflow: Overflow, subscribe: S) -> HermesResult<Receiver<T>>
+    where
+        T: Clone + Send + 'static,
+        S: FnOnce(&Self, Callback<T>) -> HermesResult<SubscriptionHandle>,
+    {
+        receiver(capacity, overflow, |callback| subscribe(self, callback))
+    }
+}
+
+impl<F: ?Sized> ReceiverExt for F {}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    type Feed = Arc<Mutex<Option<Callback<u32>>>>;
+
+    /// A receiver fed by hand through the callback it subscribed with
+    fn fed_receiver(capacity: usize, overflow: Overflow) -> (Receiver<u32>, Feed) {
+        let feed = Arc::new(Mutex::new(None));
+        let subscribed = Arc::clone(&feed);
+        let receiver = receiver(capacity, overflow, move |callback| {
+            *subscribed.lock().unwrap() = Some(callback);
+            let unsubscribed = Arc::clone(&subscribed);
+            Ok(SubscriptionHandle::new(move || {
+                unsubscribed.lock().unwrap().take();
+                Ok(())
+            }))
+        })
+        .unwrap();
+        (receiver, feed)
+    }
+
+    fn send(feed: &Feed, message: u32) {
+        feed.lock().unwrap().as_ref().unwrap().call(&message)
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_messages() {
+        let (receiver, feed) = fed_receiver(2, Overflow::DropOldest);
+        for message in 0..5 {
+            send(&feed, message);
+        }
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(receiver.dropped(), 3);
+    }
+
+    #[test]
+    fn block_waits_for_room() {
+        let (receiver, feed) = fed_receiver(1, Overflow::Block);
+        let sender = thread::spawn(move || {
+            for message in 0..3 {
+                send(&feed, message);
+            }
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), 0);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), 1);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)).unwrap(), 2);
+        sender.join().unwrap();
+        assert_eq!(receiver.dropped(), 0);
+    }
+
+    #[test]
+    fn recv_timeout_times_out() {
+        let (receiver, _feed) = fed_receiver(1, Overflow::Block);
+
+        match receiver.recv_timeout(Duration::from_millis(10)) {
+            Err(HermesError::Timeout(_)) => {}
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recv_reports_closed_once_the_subscription_is_gone() {
+        let (receiver, feed) = fed_receiver(2, Overflow::Block);
+        send(&feed, 1);
+        feed.lock().unwrap().take();
+
+        assert_eq!(receiver.recv().unwrap(), 1);
+        match receiver.recv() {
+            Err(HermesError::Closed) => {}
+            other => panic!("expected a closed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn recv_reports_closed_once_closed_by_the_handler() {
+        let (receiver, feed) = fed_receiver(2, Overflow::Block);
+        let closing = Arc::new(Mutex::new(None));
+        let close = Arc::clone(&closing);
+        let receiver = receiver
+            .closed_by(move |callback| {
+                *close.lock().unwrap() = Some(callback);
+                Ok(SubscriptionHandle::new(|| Ok(())))
+            })
+            .unwrap();
+        send(&feed, 1);
+        closing.lock().unwrap().as_ref().unwrap().call();
+
+        assert_eq!(receiver.recv().unwrap(), 1);
+        match receiver.recv() {
+            Err(HermesError::Closed) => {}
+            other => panic!("expected a closed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dropping_the_receiver_releases_a_blocked_delivery() {
+        let (receiver, feed) = fed_receiver(1, Overflow::Block);
+        send(&feed, 0);
+        let sender = {
+            let feed = Arc::clone(&feed);
+            thread::spawn(move || send(&feed, 1))
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+
+        sender.join().unwrap();
+        assert!(feed.lock().unwrap().is_none());
+    }
+}
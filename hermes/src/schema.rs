@@ -0,0 +1,261 @@
+//! JSON Schemas of the messages of the ontology, to describe them to the bindings and services
+//! written in other languages. Each schema embeds the full and minimal `Example` of its message.
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{
+    InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject, SubschemaValidation,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::ontology::*;
+use hermes_utils::Example;
+
+/// The schema of the byte fields, which are base64 encoded strings in JSON
+pub fn base64(_: &mut SchemaGenerator) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        ..Default::default()
+    };
+    schema
+        .extensions
+        .insert("contentEncoding".to_string(), serde_json::json!("base64"));
+    schema.into()
+}
+
+/// The JSON Schema of a message, with its full and minimal examples
+pub fn message_schema<T: JsonSchema + Example + Serialize>() -> RootSchema {
+    let mut schema = schemars::schema_for!(T);
+    schema.schema.metadata().examples = vec![T::full_example(), T::minimal_example()]
+        .into_iter()
+        .filter_map(|example| serde_json::to_value(example).ok())
+        .collect();
+    schema
+}
+
+macro_rules! schemas {
+    ($($t:ty),* $(,)*) => {
+        vec![$((stringify!($t), message_schema::<$t>())),*]
+    };
+}
+
+/// The JSON Schemas of all the messages of the ontology, along with the name of the message
+pub fn ontology_schemas() -> Vec<(&'static str, RootSchema)> {
+    schemas!(
+        SiteMessage,
+        VersionMessage,
+        ErrorMessage,
+        SiteErrorMessage,
+        ComponentLoadedMessage,
+        ComponentLoadedOnSiteMessage,
+        RequestComponentReloadMessage,
+        VadUpMessage,
+        VadDownMessage,
+        HotwordDetectedMessage,
+        AsrStartListeningMessage,
+        TextCapturedMessage,
+        SpeakerId,
+        SayMessage,
+        SayFinishedMessage,
+        RegisterSoundMessage,
+        NluQueryMessage,
+        NluSlotQueryMessage,
+        NluSlotMessage,
+        NluIntentMessage,
+        NluIntentNotRecognizedMessage,
+        PlayBytesMessage,
+        StreamBytesMessage,
+        AudioFrameMessage,
+        ReplayRequestMessage,
+        PlayFinishedMessage,
+        IntentMessage,
+        IntentNotRecognizedMessage,
+        StartSessionMessage,
+        SessionStartedMessage,
+        SessionQueuedMessage,
+        ContinueSessionMessage,
+        EndSessionMessage,
+        SessionEndedMessage,
+        DialogueConfigureMessage,
+        InjectionRequestMessage,
+        InjectionStatusMessage,
+        InjectionCompleteMessage,
+        InjectionResetRequestMessage,
+        InjectionResetCompleteMessage,
+    )
+}
+
+fn described(description: &str, schema: SchemaObject) -> Schema {
+    SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            description: Some(description.to_string()),
+            ..Default::default()
+        })),
+        ..schema
+    }
+    .into()
+}
+
+fn object(properties: Vec<(&str, Schema)>, required: &[&str]) -> SchemaObject {
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            properties: properties
+                .into_iter()
+                .map(|(name, schema)| (name.to_string(), schema))
+                .collect(),
+            required: required.iter().map(|it| it.to_string()).collect(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+impl JsonSchema for EntityValue {
+    fn schema_name() -> String {
+        "EntityValue".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // a value alone is accepted, but a value is always sent with its weight
+        described(
+            "A value, or a value and its weight (1 when omitted)",
+            SchemaObject {
+                subschemas: Some(Box::new(SubschemaValidation {
+                    any_of: Some(vec![
+                        gen.subschema_for::<String>(),
+                        gen.subschema_for::<(String, u32)>(),
+                    ]),
+                    ..Default::default()
+                })),
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// The `SlotValue` of `snips_nlu_ontology`, tagged by its `kind`
+struct SlotValueSchema;
+
+impl JsonSchema for SlotValueSchema {
+    fn schema_name() -> String {
+        "SlotValue".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let kinds = [
+            "Custom",
+            "Number",
+            "Ordinal",
+            "InstantTime",
+            "TimeInterval",
+            "AmountOfMoney",
+            "Temperature",
+            "Duration",
+            "Percentage",
+            "MusicAlbum",
+            "MusicArtist",
+            "MusicTrack",
+            "City",
+            "Country",
+            "Region",
+        ];
+        let kind = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            enum_values: Some(kinds.iter().map(|it| serde_json::json!(it)).collect()),
+            ..Default::default()
+        };
+        described(
+            "The resolved value of a slot, its other fields depend on its kind",
+            object(vec![("kind", kind.into())], &["kind"]),
+        )
+    }
+}
+
+impl JsonSchema for NluSlot {
+    fn schema_name() -> String {
+        "NluSlot".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // this is the flattened `snips_nlu_ontology::Slot`
+        let range = object(
+            vec![
+                ("start", gen.subschema_for::<usize>()),
+                ("end", gen.subschema_for::<usize>()),
+            ],
+            &["start", "end"],
+        );
+        let properties = vec![
+            ("rawValue", gen.subschema_for::<String>()),
+            ("value", gen.subschema_for::<SlotValueSchema>()),
+            ("alternatives", gen.subschema_for::<Vec<SlotValueSchema>>()),
+            ("range", range.into()),
+            ("entity", gen.subschema_for::<String>()),
+            ("slotName", gen.subschema_for::<String>()),
+            ("confidenceScore", gen.subschema_for::<Option<f32>>()),
+        ];
+        described(
+            "A slot detected by the NLU",
+            object(properties, &["rawValue", "value", "range", "entity", "slotName"]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_schema<T: JsonSchema + Example + Serialize>() -> serde_json::Value {
+        serde_json::to_value(message_schema::<T>()).unwrap()
+    }
+
+    #[test]
+    fn schemas_embed_the_examples() {
+        for (name, schema) in ontology_schemas() {
+            let schema = serde_json::to_value(schema).unwrap();
+            assert_eq!(schema["examples"].as_array().map(Vec::len), Some(2), "{}", name);
+        }
+    }
+
+    #[test]
+    fn schemas_use_camel_case() {
+        let schema = json_schema::<SessionEndedMessage>();
+
+        assert!(schema["properties"]["sessionId"].is_object());
+        assert!(schema["properties"]["customData"].is_object());
+    }
+
+    #[test]
+    fn tagged_enums_carry_their_tag() {
+        let schema = json_schema::<StartSessionMessage>();
+        let variants = schema["definitions"]["SessionInit"]["oneOf"].as_array().unwrap();
+
+        assert!(variants.iter().all(|it| it["properties"]["type"].is_object()));
+
+        let schema = json_schema::<SessionEndedMessage>();
+        let variants = schema["definitions"]["SessionTerminationType"]["oneOf"]
+            .as_array()
+            .unwrap();
+
+        assert!(variants.iter().all(|it| it["properties"]["reason"].is_object()));
+    }
+
+    #[test]
+    fn bytes_are_base64_strings() {
+        let schema = json_schema::<PlayBytesMessage>();
+
+        assert_eq!(schema["properties"]["wavBytes"]["type"], "string");
+        assert_eq!(schema["properties"]["wavBytes"]["contentEncoding"], "base64");
+    }
+
+    #[test]
+    fn entity_values_accept_their_tuple_form() {
+        let schema = json_schema::<InjectionRequestMessage>();
+
+        assert_eq!(
+            schema["definitions"]["EntityValue"]["anyOf"].as_array().map(Vec::len),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,604 @@
+//! Tracking of the dialogue sessions from the client side, rebuilt from the events published by the
+//! dialogue component.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+
+use crate::errors::{HermesResult, PoisonLock};
+use crate::meta::MessageMeta;
+use crate::ontology::*;
+use crate::{Callback, DialogueFacade, SubscriptionHandle};
+
+/// How many ended sessions are remembered, to be queried and to spot the late events about them
+const ENDED_SESSIONS_KEPT: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionState {
+    /// The session waits for the sessions before it on its site to end
+    Queued,
+    Started,
+    Ended(SessionTerminationType),
+}
+
+/// A dialogue session, as seen from the events of the dialogue component
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub session_id: String,
+    pub site_id: String,
+    /// The custom data of the session, as of its last event
+    pub custom_data: Option<String>,
+    pub state: SessionState,
+    /// The names of the intents recognized during the session, in order
+    pub intents: Vec<String>,
+    /// How many times the user wasn't understood during the session
+    pub intents_not_recognized: usize,
+    pub queued_at: Option<DateTime<Utc>>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    fn new(session_id: &str, site_id: &str) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            site_id: site_id.to_string(),
+            custom_data: None,
+            // a session we hear of for the first time mid-way has at least started
+            state: SessionState::Started,
+            intents: vec![],
+            intents_not_recognized: 0,
+            queued_at: None,
+            started_at: None,
+            ended_at: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state == SessionState::Started
+    }
+
+    pub fn is_ended(&self) -> bool {
+        matches!(self.state, SessionState::Ended(_))
+    }
+}
+
+/// The events of the dialogue component a session goes through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionEvent {
+    Queued,
+    Started,
+    Intent,
+    IntentNotRecognized,
+    Ended,
+}
+
+impl fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SessionEvent::Queued => "session queued",
+            SessionEvent::Started => "session started",
+            SessionEvent::Intent => "intent",
+            SessionEvent::IntentNotRecognized => "intent not recognized",
+            SessionEvent::Ended => "session ended",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A session after one of its events
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionChange {
+    pub event: SessionEvent,
+    /// The state of the session before the event, `None` if the session was unknown
+    pub previous_state: Option<SessionState>,
+    pub session: Session,
+}
+
+/// An event of the dialogue component that doesn't fit the sessions it is about
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionAnomaly {
+    /// An event about a session that was neither queued nor started. This is expected for the
+    /// sessions that were already running when the tracker was created.
+    UnknownSession { session_id: String, event: SessionEvent },
+    /// An event about a session that already ended, a session ending twice for example
+    AlreadyEnded { session_id: String, event: SessionEvent },
+    /// A session started while it had already started
+    StartedTwice { session_id: String },
+    /// A session started on a site where another one is still active
+    SiteBusy {
+        site_id: String,
+        session_id: String,
+        active_session_id: String,
+    },
+}
+
+impl fmt::Display for SessionAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionAnomaly::UnknownSession { session_id, event } => {
+                write!(f, "{} event for unknown session {}", event, session_id)
+            }
+            SessionAnomaly::AlreadyEnded { session_id, event } => {
+                write!(f, "{} event for session {} which already ended", event, session_id)
+            }
+            SessionAnomaly::StartedTwice { session_id } => write!(f, "session {} started twice", session_id),
+            SessionAnomaly::SiteBusy {
+                site_id,
+                session_id,
+                active_session_id,
+            } => write!(
+                f,
+                "session {} started on site {} while session {} is still active there",
+                session_id, site_id, active_session_id
+            ),
+        }
+    }
+}
+
+/// What an event did to the sessions
+#[derive(Debug, Default)]
+struct Outcome {
+    changes: Vec<SessionChange>,
+    anomalies: Vec<SessionAnomaly>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    live: HashMap<String, Session>,
+    ended: VecDeque<Session>,
+}
+
+impl Sessions {
+    fn get(&self, session_id: &str) -> Option<&Session> {
+        self.live
+            .get(session_id)
+            .or_else(|| self.ended.iter().rev().find(|it| it.session_id == session_id))
+    }
+
+    fn active_on(&self, site_id: &str) -> Option<&Session> {
+        self.live.values().find(|it| it.site_id == site_id && it.is_active())
+    }
+
+    fn update<F: FnOnce(&mut Session)>(
+        &mut self,
+        event: SessionEvent,
+        session_id: &str,
+        site_id: &str,
+        custom_data: &Option<String>,
+        apply: F,
+    ) -> Outcome {
+        let mut outcome = Outcome::default();
+        if self.ended.iter().any(|it| it.session_id == session_id) {
+            outcome.anomalies.push(SessionAnomaly::AlreadyEnded {
+                session_id: session_id.to_string(),
+                event,
+            });
+            return outcome;
+        }
+        let previous_state = self.live.get(session_id).map(|it| it.state.clone());
+        if previous_state.is_none() && event != SessionEvent::Queued && event != SessionEvent::Started {
+            outcome.anomalies.push(SessionAnomaly::UnknownSession {
+                session_id: session_id.to_string(),
+                event,
+            });
+        }
+
+        let session = self
+            .live
+            .entry(session_id.to_string())
+            .or_insert_with(|| Session::new(session_id, site_id));
+        session.site_id = site_id.to_string();
+        session.custom_data = custom_data.clone();
+        apply(session);
+        let session = session.clone();
+
+        if session.is_ended() {
+            self.live.remove(session_id);
+            self.ended.push_back(session.clone());
+            if self.ended.len() > ENDED_SESSIONS_KEPT {
+                self.ended.pop_front();
+            }
+        }
+        outcome.changes.push(SessionChange {
+            event,
+            previous_state,
+            session,
+        });
+        outcome
+    }
+
+    fn queued(&mut self, message: &SessionQueuedMessage, at: DateTime<Utc>) -> Outcome {
+        self.update(
+            SessionEvent::Queued,
+            &message.session_id,
+            &message.site_id,
+            &message.custom_data,
+            |session| {
+                session.state = SessionState::Queued;
+                session.queued_at = Some(at);
+            },
+        )
+    }
+
+    fn started(&mut self, message: &SessionStartedMessage, at: DateTime<Utc>) -> Outcome {
+        let mut anomalies = vec![];
+        if let Some(session) = self.live.get(&message.session_id).filter(|it| it.is_active()) {
+            anomalies.push(SessionAnomaly::StartedTwice {
+                session_id: session.session_id.clone(),
+            });
+        }
+        if let Some(active) = self
+            .active_on(&message.site_id)
+            .filter(|it| it.session_id != message.session_id)
+        {
+            anomalies.push(SessionAnomaly::SiteBusy {
+                site_id: message.site_id.clone(),
+                session_id: message.session_id.clone(),
+                active_session_id: active.session_id.clone(),
+            });
+        }
+        let mut outcome = self.update(
+            SessionEvent::Started,
+            &message.session_id,
+            &message.site_id,
+            &message.custom_data,
+            |session| {
+                session.state = SessionState::Started;
+                session.started_at = Some(at);
+            },
+        );
+        anomalies.append(&mut outcome.anomalies);
+        outcome.anomalies = anomalies;
+        outcome
+    }
+
+    fn intent(&mut self, message: &IntentMessage) -> Outcome {
+        self.update(
+            SessionEvent::Intent,
+            &message.session_id,
+            &message.site_id,
+            &message.custom_data,
+            |session| session.intents.push(message.intent.intent_name.clone()),
+        )
+    }
+
+    fn intent_not_recognized(&mut self, message: &IntentNotRecognizedMessage) -> Outcome {
+        self.update(
+            SessionEvent::IntentNotRecognized,
+            &message.session_id,
+            &message.site_id,
+            &message.custom_data,
+            |session| session.intents_not_recognized += 1,
+        )
+    }
+
+    fn ended(&mut self, message: &SessionEndedMessage, at: DateTime<Utc>) -> Outcome {
+        self.update(
+            SessionEvent::Ended,
+            &message.session_id,
+            &message.site_id,
+            &message.custom_data,
+            |session| {
+                session.state = SessionState::Ended(message.termination.clone());
+                session.ended_at = Some(at);
+            },
+        )
+    }
+}
+
+/// The callbacks registered on a `SessionTracker`
+struct Listeners<T> {
+    callbacks: Mutex<Vec<(usize, Arc<Callback<T>>)>>,
+    counter: AtomicUsize,
+}
+
+impl<T: 'static> Listeners<T> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            callbacks: Mutex::new(vec![]),
+            counter: AtomicUsize::new(0),
+        })
+    }
+
+    fn subscribe(listeners: &Arc<Self>, callback: Callback<T>) -> HermesResult<SubscriptionHandle> {
+        let id = listeners.counter.fetch_add(1, Ordering::Relaxed);
+        listeners
+            .callbacks
+            .lock()
+            .map_err(PoisonLock::from)?
+            .push((id, Arc::new(callback)));
+        let listeners = Arc::downgrade(listeners);
+        Ok(SubscriptionHandle::new(move || {
+            if let Some(listeners) = listeners.upgrade() {
+                listeners
+                    .callbacks
+                    .lock()
+                    .map_err(PoisonLock::from)?
+                    .retain(|(it, _)| *it != id);
+            }
+            Ok(())
+        }))
+    }
+
+    fn notify(&self, value: &T) {
+        // don't hold the lock while running the callbacks, they may want to (un)subscribe
+        let callbacks: Vec<Arc<Callback<T>>> = match self.callbacks.lock() {
+            Ok(callbacks) => callbacks.iter().map(|(_, it)| Arc::clone(it)).collect(),
+            Err(_) => return,
+        };
+        for callback in callbacks {
+            callback.call(value)
+        }
+    }
+}
+
+struct Shared {
+    sessions: Mutex<Sessions>,
+    changes: Arc<Listeners<SessionChange>>,
+    anomalies: Arc<Listeners<SessionAnomaly>>,
+}
+
+impl Shared {
+    /// Apply an event to the sessions, it happened when it was published if we know when that was
+    fn handle<F>(&self, meta: Option<&MessageMeta>, update: F)
+    where
+        F: FnOnce(&mut Sessions, DateTime<Utc>) -> Outcome,
+    {
+        let at = meta.map(|it| it.timestamp).unwrap_or_else(Utc::now);
+        let outcome = match self.sessions.lock() {
+            Ok(mut sessions) => update(&mut sessions, at),
+            Err(_) => return,
+        };
+        for anomaly in &outcome.anomalies {
+            warn!("Dialogue protocol anomaly: {}", anomaly);
+            self.anomalies.notify(anomaly)
+        }
+        for change in &outcome.changes {
+            self.changes.notify(change)
+        }
+    }
+}
+
+/// Keeps track of the dialogue sessions, from the events received through a `DialogueFacade`.
+/// The sessions are tracked until the tracker is dropped.
+pub struct SessionTracker {
+    shared: Arc<Shared>,
+    _subscriptions: Vec<SubscriptionHandle>,
+}
+
+impl SessionTracker {
+    pub fn new<D: DialogueFacade + ?Sized>(dialogue: &D) -> HermesResult<Self> {
+        let shared = Arc::new(Shared {
+            sessions: Mutex::new(Sessions::default()),
+            changes: Listeners::new(),
+            anomalies: Listeners::new(),
+        });
+
+        let queued = Arc::clone(&shared);
+        let started = Arc::clone(&shared);
+        let intent = Arc::clone(&shared);
+        let intent_not_recognized = Arc::clone(&shared);
+        let ended = Arc::clone(&shared);
+        let subscriptions = vec![
+            dialogue.subscribe_session_queued(Callback::with_meta(
+                move |message: &SessionQueuedMessage, meta: Option<&MessageMeta>| {
+                    queued.handle(meta, |sessions, at| sessions.queued(message, at))
+                },
+            ))?,
+            dialogue.subscribe_session_started(Callback::with_meta(
+                move |message: &SessionStartedMessage, meta: Option<&MessageMeta>| {
+                    started.handle(meta, |sessions, at| sessions.started(message, at))
+                },
+            ))?,
+            dialogue.subscribe_intents(Callback::with_meta(
+                move |message: &IntentMessage, meta: Option<&MessageMeta>| {
+                    intent.handle(meta, |sessions, _| sessions.intent(message))
+                },
+            ))?,
+            dialogue.subscribe_intent_not_recognized(Callback::with_meta(
+                move |message: &IntentNotRecognizedMessage, meta: Option<&MessageMeta>| {
+                    intent_not_recognized.handle(meta, |sessions, _| sessions.intent_not_recognized(message))
+                },
+            ))?,
+            dialogue.subscribe_session_ended(Callback::with_meta(
+                move |message: &SessionEndedMessage, meta: Option<&MessageMeta>| {
+                    ended.handle(meta, |sessions, at| sessions.ended(message, at))
+                },
+            ))?,
+        ];
+
+        Ok(Self {
+            shared,
+            _subscriptions: subscriptions,
+        })
+    }
+
+    fn sessions(&self) -> HermesResult<MutexGuard<Sessions>> {
+        Ok(self.shared.sessions.lock().map_err(PoisonLock::from)?)
+    }
+
+    /// A session that is running, or that ended recently
+    pub fn session(&self, session_id: &str) -> HermesResult<Option<Session>> {
+        Ok(self.sessions()?.get(session_id).cloned())
+    }
+
+    /// The sessions that are queued or started
+    pub fn live_sessions(&self) -> HermesResult<Vec<Session>> {
+        Ok(self.sessions()?.live.values().cloned().collect())
+    }
+
+    /// The session that is started on a site, if there's one
+    pub fn active_session_on(&self, site_id: &str) -> HermesResult<Option<Session>> {
+        Ok(self.sessions()?.active_on(site_id).cloned())
+    }
+
+    /// The sessions queued on a site, in the order they were queued
+    pub fn queued_sessions_on(&self, site_id: &str) -> HermesResult<Vec<Session>> {
+        let mut queued: Vec<Session> = self
+            .sessions()?
+            .live
+            .values()
+            .filter(|it| it.site_id == site_id && it.state == SessionState::Queued)
+            .cloned()
+            .collect();
+        queued.sort_by_key(|it| it.queued_at);
+        Ok(queued)
+    }
+
+    /// Be notified of every change of the sessions
+    pub fn subscribe_changes(&self, handler: Callback<SessionChange>) -> HermesResult<SubscriptionHandle> {
+        Listeners::subscribe(&self.shared.changes, handler)
+    }
+
+    /// Be notified of the events that don't fit the sessions they are about, they are also logged
+    pub fn subscribe_anomalies(&self, handler: Callback<SessionAnomaly>) -> HermesResult<SubscriptionHandle> {
+        Listeners::subscribe(&self.shared.anomalies, handler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermes_utils::Example;
+
+    fn queued(session_id: &str) -> SessionQueuedMessage {
+        SessionQueuedMessage {
+            session_id: session_id.into(),
+            custom_data: None,
+            site_id: "kitchen".into(),
+        }
+    }
+
+    fn started(session_id: &str) -> SessionStartedMessage {
+        SessionStartedMessage {
+            session_id: session_id.into(),
+            custom_data: Some("data".into()),
+            site_id: "kitchen".into(),
+            reactivated_from_session_id: None,
+        }
+    }
+
+    fn intent(session_id: &str, intent_name: &str) -> IntentMessage {
+        let mut intent = IntentMessage::minimal_example();
+        intent.session_id = session_id.into();
+        intent.site_id = "kitchen".into();
+        intent.intent.intent_name = intent_name.into();
+        intent
+    }
+
+    fn ended(session_id: &str) -> SessionEndedMessage {
+        SessionEndedMessage {
+            session_id: session_id.into(),
+            custom_data: None,
+            termination: SessionTerminationType::Nominal,
+            site_id: "kitchen".into(),
+        }
+    }
+
+    #[test]
+    fn sessions_go_through_their_states() {
+        let mut sessions = Sessions::default();
+        let now = Utc::now();
+
+        sessions.queued(&queued("first"), now);
+        let outcome = sessions.started(&started("first"), now);
+        assert_eq!(outcome.changes[0].previous_state, Some(SessionState::Queued));
+        sessions.intent(&intent("first", "turnOnLights"));
+        sessions.queued(&queued("second"), now);
+
+        let active = sessions.active_on("kitchen").unwrap();
+        assert_eq!(active.session_id, "first");
+        assert_eq!(active.custom_data, Some("data".into()));
+        assert_eq!(active.intents, vec!["turnOnLights".to_string()]);
+        assert_eq!(active.started_at, Some(now));
+
+        let outcome = sessions.ended(&ended("first"), now);
+        assert!(outcome.anomalies.is_empty());
+        assert_eq!(
+            outcome.changes[0].session.state,
+            SessionState::Ended(SessionTerminationType::Nominal)
+        );
+        assert!(sessions.active_on("kitchen").is_none());
+        assert_eq!(sessions.get("first").map(|it| it.ended_at), Some(Some(now)));
+        assert_eq!(sessions.live.len(), 1);
+    }
+
+    #[test]
+    fn intents_for_unknown_sessions_are_flagged() {
+        let mut sessions = Sessions::default();
+
+        let outcome = sessions.intent(&intent("unknown", "turnOnLights"));
+
+        assert_eq!(
+            outcome.anomalies,
+            vec![SessionAnomaly::UnknownSession {
+                session_id: "unknown".into(),
+                event: SessionEvent::Intent,
+            }]
+        );
+        // the session was likely running before we started tracking it
+        assert!(sessions.active_on("kitchen").is_some());
+    }
+
+    #[test]
+    fn sessions_ending_twice_are_flagged() {
+        let mut sessions = Sessions::default();
+        let now = Utc::now();
+        sessions.started(&started("session"), now);
+        sessions.ended(&ended("session"), now);
+
+        let outcome = sessions.ended(&ended("session"), now);
+
+        assert!(outcome.changes.is_empty());
+        assert_eq!(
+            outcome.anomalies,
+            vec![SessionAnomaly::AlreadyEnded {
+                session_id: "session".into(),
+                event: SessionEvent::Ended,
+            }]
+        );
+    }
+
+    #[test]
+    fn sessions_starting_on_a_busy_site_are_flagged() {
+        let mut sessions = Sessions::default();
+        let now = Utc::now();
+        sessions.started(&started("first"), now);
+
+        let outcome = sessions.started(&started("second"), now);
+
+        assert_eq!(
+            outcome.anomalies,
+            vec![SessionAnomaly::SiteBusy {
+                site_id: "kitchen".into(),
+                session_id: "second".into(),
+                active_session_id: "first".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn listeners_are_notified_until_they_unsubscribe() {
+        let listeners = Listeners::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&count);
+        let subscription = Listeners::subscribe(
+            &listeners,
+            Callback::new(move |_: &usize| {
+                counted.fetch_add(1, Ordering::Relaxed);
+            }),
+        )
+        .unwrap();
+
+        listeners.notify(&1);
+        subscription.unsubscribe().unwrap();
+        listeners.notify(&2);
+
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+}
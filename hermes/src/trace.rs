@@ -0,0 +1,160 @@
+//! Tracing of the messages going through a handler. The handlers enter a `tracing` span around
+//! every message they publish or deliver, with the session and the site the message is about, so
+//! that a dialogue turn can be followed across the components in a trace viewer.
+//!
+//! The session and the site of a JSON message are read from its `MessageMeta` when it carries
+//! them, from its `sessionId` and `siteId` fields otherwise. A message published by a callback
+//! belongs to the session and the site of the message delivered to the callback, unless it tells
+//! otherwise.
+
+use std::cell::RefCell;
+use std::mem;
+
+use tracing::{field, info_span, Span};
+
+thread_local! {
+    static CURRENT: RefCell<TraceContext> = RefCell::new(TraceContext::default());
+}
+
+/// The session and the site a message is about
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_id: Option<String>,
+}
+
+/// Reads the context of a JSON message, from its meta and from its fields
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Traced {
+    #[serde(rename = "_meta", default)]
+    meta: Option<TraceContext>,
+    session_id: Option<String>,
+    site_id: Option<String>,
+}
+
+impl TraceContext {
+    /// The context of a JSON message, from its meta when it tells one, from its fields otherwise
+    pub fn of_json(payload: &[u8]) -> Self {
+        let traced: Traced = match serde_json::from_slice(payload) {
+            Ok(traced) => traced,
+            Err(_) => return Self::default(),
+        };
+        traced.meta.filter(|it| !it.is_empty()).unwrap_or(Self {
+            session_id: traced.session_id,
+            site_id: traced.site_id,
+        })
+    }
+
+    /// The context of a message serialized to a JSON value, from its fields
+    pub fn of_value(value: &serde_json::Value) -> Self {
+        <Self as serde::Deserialize>::deserialize(value).unwrap_or_default()
+    }
+
+    /// The context of the message being delivered on this thread, empty outside of a callback
+    pub fn current() -> Self {
+        CURRENT.with(|it| it.borrow().clone())
+    }
+
+    /// Whether this context tells neither the session nor the site
+    pub fn is_empty(&self) -> bool {
+        self.session_id.is_none() && self.site_id.is_none()
+    }
+
+    /// This context, completed with what `other` tells and it doesn't
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            session_id: self.session_id.or(other.session_id),
+            site_id: self.site_id.or(other.site_id),
+        }
+    }
+
+    /// Run `f` with this context as the current one of the thread
+    pub fn scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        let _restore = Restore(CURRENT.with(|it| it.replace(self.clone())));
+        f()
+    }
+}
+
+/// Puts the previous context back when a scope ends, even by a panic
+struct Restore(TraceContext);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = mem::take(&mut self.0);
+        let _ = CURRENT.try_with(|it| *it.borrow_mut() = previous);
+    }
+}
+
+/// The span of a message published on `topic`, its context is only computed if the span is enabled
+pub fn publish_span<F: FnOnce() -> TraceContext>(topic: &str, context: F) -> Span {
+    record(
+        info_span!("publish", topic, session_id = field::Empty, site_id = field::Empty),
+        context,
+    )
+}
+
+/// The span of a message delivered from `topic`, its context is only computed if the span is
+/// enabled
+pub fn deliver_span<F: FnOnce() -> TraceContext>(topic: &str, context: F) -> Span {
+    record(
+        info_span!("deliver", topic, session_id = field::Empty, site_id = field::Empty),
+        context,
+    )
+}
+
+fn record<F: FnOnce() -> TraceContext>(span: Span, context: F) -> Span {
+    if !span.is_disabled() {
+        let context = context();
+        if let Some(session_id) = &context.session_id {
+            span.record("session_id", &session_id.as_str());
+        }
+        if let Some(site_id) = &context.site_id {
+            span.record("site_id", &site_id.as_str());
+        }
+    }
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(session_id: &str, site_id: &str) -> TraceContext {
+        TraceContext {
+            session_id: Some(session_id.into()),
+            site_id: Some(site_id.into()),
+        }
+    }
+
+    #[test]
+    fn context_is_read_from_the_meta_then_the_fields() {
+        let payload = br#"{"text": "hello", "sessionId": "turn", "siteId": "kitchen"}"#;
+        assert_eq!(TraceContext::of_json(payload), context("turn", "kitchen"));
+
+        let payload = br#"{"id": "1", "_meta": {"id": "m", "sessionId": "turn", "siteId": "default"}}"#;
+        assert_eq!(TraceContext::of_json(payload), context("turn", "default"));
+
+        assert_eq!(TraceContext::of_json(b""), TraceContext::default());
+        assert_eq!(TraceContext::of_json(b"[1, 2]"), TraceContext::default());
+    }
+
+    #[test]
+    fn scopes_set_the_current_context_of_the_thread() {
+        let outer = context("turn", "kitchen");
+        let inner = TraceContext {
+            session_id: None,
+            site_id: Some("default".into()),
+        };
+
+        outer.scope(|| {
+            assert_eq!(TraceContext::current(), outer);
+            inner.scope(|| assert_eq!(TraceContext::current().or(outer.clone()), context("turn", "default")));
+            assert_eq!(TraceContext::current(), outer);
+        });
+        assert!(TraceContext::current().is_empty());
+    }
+}
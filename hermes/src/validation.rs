@@ -0,0 +1,652 @@
+//! Semantic checks of the messages of the ontology, beyond what their types enforce: confidence
+//! scores within [0, 1], ranges that fit their input...
+
+use std::collections::HashMap;
+use std::fmt;
+
+use log::warn;
+
+use crate::errors::{HermesError, HermesResult};
+use crate::ontology::*;
+
+/// A field of a message holding an invalid value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The path of the field in the message, `tokens[0].confidence` for example
+    pub field: String,
+    /// What is wrong with its value
+    pub reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// Collects the violations of a message while walking through its fields
+pub struct Checker<'a> {
+    path: String,
+    violations: &'a mut Vec<Violation>,
+}
+
+impl<'a> Checker<'a> {
+    fn path_of(&self, field: &str) -> String {
+        if self.path.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", self.path, field)
+        }
+    }
+
+    pub fn violation<R: Into<String>>(&mut self, field: &str, reason: R) {
+        let field = self.path_of(field);
+        self.violations.push(Violation {
+            field,
+            reason: reason.into(),
+        })
+    }
+
+    /// Check a score, which must be within [0, 1]
+    pub fn unit_interval(&mut self, field: &str, value: f32) {
+        if !(0. ..=1.).contains(&value) {
+            self.violation(field, format!("{} is not within [0, 1]", value))
+        }
+    }
+
+    /// Check a range, which must not end before it starts nor, if given, after `len`
+    pub fn range(&mut self, field: &str, start: usize, end: usize, len: Option<usize>) {
+        if start > end {
+            self.violation(field, format!("starts at {} after its end {}", start, end))
+        } else if let Some(len) = len.filter(|len| end > *len) {
+            self.violation(field, format!("ends at {} past the end of the input ({})", end, len))
+        }
+    }
+
+    /// Check a field holding a value with its own rules
+    pub fn nested<V: Validate + ?Sized>(&mut self, field: &str, value: &V) {
+        let path = self.path_of(field);
+        value.check(&mut Checker {
+            path,
+            violations: &mut *self.violations,
+        })
+    }
+
+    fn index<V: Validate>(&mut self, index: usize, value: &V) {
+        let path = format!("{}[{}]", self.path, index);
+        value.check(&mut Checker {
+            path,
+            violations: &mut *self.violations,
+        })
+    }
+}
+
+/// A message, or part of a message, which can be checked
+pub trait Validate {
+    /// Report the violations of this value to `checker`, there's nothing to check by default
+    fn check(&self, _checker: &mut Checker) {}
+
+    /// All the violations of this value
+    fn violations(&self) -> Vec<Violation> {
+        let mut violations = vec![];
+        self.check(&mut Checker {
+            path: String::new(),
+            violations: &mut violations,
+        });
+        violations
+    }
+
+    /// Fail with a `HermesError::Validation` describing the violations, if there are some
+    fn validate(&self) -> HermesResult<()> {
+        to_result(self.violations())
+    }
+}
+
+fn to_result(violations: Vec<Violation>) -> HermesResult<()> {
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(HermesError::Validation(
+            violations
+                .iter()
+                .map(|it| it.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(value) = self {
+            value.check(checker)
+        }
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn check(&self, checker: &mut Checker) {
+        for (index, value) in self.iter().enumerate() {
+            checker.index(index, value)
+        }
+    }
+}
+
+/// What the protocol handlers do with the invalid messages they publish or receive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationMode {
+    /// Messages aren't checked
+    Off,
+    /// Invalid messages are logged, but still published and delivered
+    Log,
+    /// Invalid messages are logged, and neither published (failing with a
+    /// `HermesError::Validation`) nor delivered
+    Reject,
+}
+
+impl Default for ValidationMode {
+    fn default() -> Self {
+        ValidationMode::Off
+    }
+}
+
+impl ValidationMode {
+    /// Check a message about to be published
+    pub fn check_outgoing<M: Validate + fmt::Debug>(self, message: &M) -> HermesResult<()> {
+        if self == ValidationMode::Off {
+            return Ok(());
+        }
+        self.outgoing(message, message.validate())
+    }
+
+    /// Check a received message, returns whether it should be delivered
+    pub fn accepts_incoming<M: Validate + fmt::Debug>(self, message: &M) -> bool {
+        if self == ValidationMode::Off {
+            return true;
+        }
+        self.incoming(message, message.validate())
+    }
+
+    /// Check a chunk of an audio stream about to be published, in order with the chunks of its
+    /// stream checked before by `sequence`
+    pub fn check_outgoing_chunk(
+        self,
+        sequence: &mut StreamBytesSequence,
+        message: &StreamBytesMessage,
+    ) -> HermesResult<()> {
+        if self == ValidationMode::Off {
+            return Ok(());
+        }
+        self.outgoing(message, to_result(sequence.check(message)))
+    }
+
+    /// Check a received chunk of an audio stream, in order with the chunks of its stream checked
+    /// before by `sequence`, returns whether it should be delivered
+    pub fn accepts_incoming_chunk(self, sequence: &mut StreamBytesSequence, message: &StreamBytesMessage) -> bool {
+        if self == ValidationMode::Off {
+            return true;
+        }
+        self.incoming(message, to_result(sequence.check(message)))
+    }
+
+    fn outgoing<M: fmt::Debug>(self, message: &M, validated: HermesResult<()>) -> HermesResult<()> {
+        match validated {
+            Err(e) if self == ValidationMode::Reject => {
+                warn!("Not publishing invalid message {:?}: {}", message, e);
+                Err(e)
+            }
+            Err(e) => {
+                warn!("Publishing invalid message {:?}: {}", message, e);
+                Ok(())
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    fn incoming<M: fmt::Debug>(self, message: &M, validated: HermesResult<()>) -> bool {
+        match validated {
+            Err(e) if self == ValidationMode::Reject => {
+                warn!("Dropping invalid message {:?}: {}", message, e);
+                false
+            }
+            Err(e) => {
+                warn!("Received invalid message {:?}: {}", message, e);
+                true
+            }
+            Ok(()) => true,
+        }
+    }
+}
+
+/// How many streams a `StreamBytesSequence` follows at once, the ones not heard of for the longest
+/// are forgotten past this, as the streams that never send their last chunk would be kept forever
+const MAX_STREAMS: usize = 256;
+
+/// Checks that the chunks of each audio stream come in order, which can't be done looking at a
+/// `StreamBytesMessage` alone
+#[derive(Debug, Default)]
+pub struct StreamBytesSequence {
+    /// The last chunk in order of each stream, with when it came
+    last_chunks: HashMap<(String, String), (u32, u64)>,
+    /// How many chunks were checked
+    checked: u64,
+}
+
+impl StreamBytesSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check the next chunk of a stream, the stream is forgotten after its last chunk. A chunk out
+    /// of order doesn't move the stream back, the chunks after it are checked against the last one
+    /// in order
+    pub fn check(&mut self, message: &StreamBytesMessage) -> Vec<Violation> {
+        let mut violations = message.violations();
+        let key = (message.site_id.clone(), message.stream_id.clone());
+        self.checked += 1;
+        if let Some((last, _)) = self
+            .last_chunks
+            .get(&key)
+            .filter(|(last, _)| message.chunk_number <= *last)
+        {
+            violations.push(Violation {
+                field: "chunk_number".into(),
+                reason: format!(
+                    "chunk {} of stream {} comes after chunk {}",
+                    message.chunk_number, message.stream_id, last
+                ),
+            });
+            return violations;
+        }
+        if message.is_last_chunk {
+            self.last_chunks.remove(&key);
+            return violations;
+        }
+        if self.last_chunks.len() >= MAX_STREAMS && !self.last_chunks.contains_key(&key) {
+            self.forget_oldest_stream();
+        }
+        self.last_chunks.insert(key, (message.chunk_number, self.checked));
+        violations
+    }
+
+    fn forget_oldest_stream(&mut self) {
+        let oldest = self
+            .last_chunks
+            .iter()
+            .min_by_key(|(_, (_, checked))| *checked)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.last_chunks.remove(&oldest);
+        }
+    }
+}
+
+fn input_len(input: &str) -> Option<usize> {
+    Some(input.chars().count())
+}
+
+/// Check tokens whose ranges are within `input`
+fn check_tokens(checker: &mut Checker, field: &str, tokens: &[AsrToken], input: &str) {
+    for (index, token) in tokens.iter().enumerate() {
+        let field = format!("{}[{}]", field, index);
+        checker.nested(&field, token);
+        if token.range_start <= token.range_end {
+            checker.range(
+                &format!("{}.range_end", field),
+                token.range_start,
+                token.range_end,
+                input_len(input),
+            );
+        }
+    }
+}
+
+/// Check a slot whose range is within `input`
+fn check_slot(checker: &mut Checker, field: &str, slot: &NluSlot, input: &str) {
+    checker.nested(field, slot);
+    let range = &slot.nlu_slot.range;
+    if range.start <= range.end {
+        checker.range(&format!("{}.range", field), range.start, range.end, input_len(input));
+    }
+}
+
+fn check_slots(checker: &mut Checker, field: &str, slots: &[NluSlot], input: &str) {
+    for (index, slot) in slots.iter().enumerate() {
+        check_slot(checker, &format!("{}[{}]", field, index), slot, input);
+    }
+}
+
+fn check_alternatives(checker: &mut Checker, alternatives: &Option<Vec<NluIntentAlternative>>, input: &str) {
+    for (index, alternative) in alternatives.iter().flatten().enumerate() {
+        let field = format!("alternatives[{}]", index);
+        checker.unit_interval(&format!("{}.confidence_score", field), alternative.confidence_score);
+        check_slots(checker, &format!("{}.slots", field), &alternative.slots, input);
+    }
+}
+
+fn check_signals(checker: &mut Checker, start_field: &str, start: Option<i64>, end: Option<i64>) {
+    if let (Some(start), Some(end)) = (start, end) {
+        if start > end {
+            checker.violation(
+                start_field,
+                format!("signal at {} comes after the end signal {}", start, end),
+            )
+        }
+    }
+}
+
+macro_rules! nothing_to_check {
+    ($($t:ty),* $(,)*) => {
+        $(impl Validate for $t {})*
+    };
+}
+
+nothing_to_check!(
+    SiteMessage,
+    VersionMessage,
+    ErrorMessage,
+    SiteErrorMessage,
+    ComponentLoadedOnSiteMessage,
+    RequestComponentReloadMessage,
+    ComponentLoadedMessage,
+    AsrStartListeningMessage,
+    SayMessage,
+    SayFinishedMessage,
+    RegisterSoundMessage,
+    VadUpMessage,
+    VadDownMessage,
+    PlayBytesMessage,
+    StreamBytesMessage,
+    AudioFrameMessage,
+    ReplayRequestMessage,
+    PlayFinishedMessage,
+    StreamFinishedMessage,
+    SessionInit,
+    StartSessionMessage,
+    SessionStartedMessage,
+    SessionQueuedMessage,
+    EndSessionMessage,
+    SessionEndedMessage,
+    DialogueConfigureMessage,
+    DialogueConfigureIntent,
+    EntityValue,
+    InjectionRequestMessage,
+    InjectionStatusMessage,
+    InjectionCompleteMessage,
+    InjectionResetRequestMessage,
+    InjectionResetCompleteMessage,
+);
+
+impl Validate for AsrDecodingDuration {
+    fn check(&self, checker: &mut Checker) {
+        if self.start < 0. {
+            checker.violation("start", format!("{} is negative", self.start))
+        }
+        if self.start > self.end {
+            checker.violation("end", format!("{} is before the start {}", self.end, self.start))
+        }
+    }
+}
+
+impl Validate for AsrToken {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence", self.confidence);
+        checker.range("range_start", self.range_start, self.range_end, None);
+        checker.nested("time", &self.time);
+    }
+}
+
+impl Validate for SpeakerId {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence", self.confidence)
+    }
+}
+
+impl Validate for TextCapturedMessage {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("likelihood", self.likelihood);
+        if self.seconds < 0. {
+            checker.violation("seconds", format!("{} is negative", self.seconds))
+        }
+        if let Some(tokens) = &self.tokens {
+            check_tokens(checker, "tokens", tokens, &self.text);
+        }
+        checker.nested("speaker_hypotheses", &self.speaker_hypotheses);
+    }
+}
+
+impl Validate for HotwordDetectedMessage {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(sensitivity) = self.current_sensitivity {
+            checker.unit_interval("current_sensitivity", sensitivity)
+        }
+        check_signals(
+            checker,
+            "detection_signal_ms",
+            self.detection_signal_ms,
+            self.end_signal_ms,
+        );
+    }
+}
+
+impl Validate for NluSlot {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(confidence) = self.nlu_slot.confidence_score {
+            checker.unit_interval("confidence_score", confidence)
+        }
+        checker.range("range", self.nlu_slot.range.start, self.nlu_slot.range.end, None);
+    }
+}
+
+impl Validate for NluIntentClassifierResult {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence_score", self.confidence_score)
+    }
+}
+
+impl Validate for NluIntentAlternative {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence_score", self.confidence_score);
+        checker.nested("slots", &self.slots);
+    }
+}
+
+impl Validate for NluQueryMessage {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(tokens) = &self.asr_tokens {
+            check_tokens(checker, "asr_tokens", tokens, &self.input);
+        }
+    }
+}
+
+impl Validate for NluSlotQueryMessage {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(tokens) = &self.asr_tokens {
+            check_tokens(checker, "asr_tokens", tokens, &self.input);
+        }
+    }
+}
+
+impl Validate for NluSlotMessage {
+    fn check(&self, checker: &mut Checker) {
+        if let Some(slot) = &self.slot {
+            check_slot(checker, "slot", slot, &self.input);
+        }
+    }
+}
+
+impl Validate for NluIntentMessage {
+    fn check(&self, checker: &mut Checker) {
+        checker.nested("intent", &self.intent);
+        check_slots(checker, "slots", &self.slots, &self.input);
+        check_alternatives(checker, &self.alternatives, &self.input);
+    }
+}
+
+impl Validate for NluIntentNotRecognizedMessage {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence_score", self.confidence_score);
+        check_alternatives(checker, &self.alternatives, &self.input);
+    }
+}
+
+impl Validate for IntentMessage {
+    fn check(&self, checker: &mut Checker) {
+        // the ranges of the tokens refer to what the ASR captured, which may differ from the input
+        checker.nested("asr_tokens", &self.asr_tokens);
+        if let Some(confidence) = self.asr_confidence {
+            checker.unit_interval("asr_confidence", confidence)
+        }
+        checker.nested("speaker_hypotheses", &self.speaker_hypotheses);
+        checker.nested("intent", &self.intent);
+        check_slots(checker, "slots", &self.slots, &self.input);
+        check_alternatives(checker, &self.alternatives, &self.input);
+    }
+}
+
+impl Validate for IntentNotRecognizedMessage {
+    fn check(&self, checker: &mut Checker) {
+        checker.unit_interval("confidence_score", self.confidence_score);
+        checker.nested("speaker_hypotheses", &self.speaker_hypotheses);
+        match &self.input {
+            Some(input) => check_alternatives(checker, &self.alternatives, input),
+            None => checker.nested("alternatives", &self.alternatives),
+        }
+    }
+}
+
+impl Validate for ContinueSessionMessage {
+    fn check(&self, checker: &mut Checker) {
+        if self.slot.is_some() && self.intent_filter.as_ref().map(Vec::len) != Some(1) {
+            checker.violation("slot", "requires the intent filter to hold exactly one intent")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hermes_utils::Example;
+
+    fn token(confidence: f32, range_start: usize, range_end: usize) -> AsrToken {
+        AsrToken {
+            value: "hello".into(),
+            confidence,
+            range_start,
+            range_end,
+            time: AsrDecodingDuration { start: 0., end: 1. },
+        }
+    }
+
+    #[test]
+    fn violations_point_at_the_offending_fields() {
+        let message = TextCapturedMessage {
+            text: "hello".into(),
+            likelihood: 1.5,
+            tokens: Some(vec![token(0.5, 0, 5), token(-0.1, 3, 2), token(0.5, 2, 8)]),
+            seconds: 1.,
+            site_id: "default".into(),
+            session_id: None,
+            speaker_hypotheses: None,
+        };
+
+        let fields = message.violations().into_iter().map(|it| it.field).collect::<Vec<_>>();
+
+        assert_eq!(
+            fields,
+            vec![
+                "likelihood",
+                "tokens[1].confidence",
+                "tokens[1].range_start",
+                "tokens[2].range_end",
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_messages_have_no_violations() {
+        let message = HotwordDetectedMessage {
+            current_sensitivity: Some(0.5),
+            ..HotwordDetectedMessage::minimal_example()
+        };
+
+        assert_eq!(message.violations(), vec![]);
+        assert!(message.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_reports_a_validation_error() {
+        let message = HotwordDetectedMessage {
+            current_sensitivity: Some(2.),
+            ..HotwordDetectedMessage::minimal_example()
+        };
+
+        match message.validate() {
+            Err(HermesError::Validation(reason)) => assert!(reason.starts_with("current_sensitivity")),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_mode_refuses_invalid_messages() {
+        let message = HotwordDetectedMessage {
+            current_sensitivity: Some(2.),
+            ..HotwordDetectedMessage::minimal_example()
+        };
+
+        assert!(ValidationMode::Reject.check_outgoing(&message).is_err());
+        assert!(!ValidationMode::Reject.accepts_incoming(&message));
+        assert!(ValidationMode::Log.check_outgoing(&message).is_ok());
+        assert!(ValidationMode::Log.accepts_incoming(&message));
+    }
+
+    #[test]
+    fn stream_chunks_must_come_in_order() {
+        let chunk = |chunk_number, is_last_chunk| StreamBytesMessage {
+            chunk_number,
+            is_last_chunk,
+            ..StreamBytesMessage::minimal_example()
+        };
+        let mut sequence = StreamBytesSequence::new();
+
+        assert_eq!(sequence.check(&chunk(0, false)), vec![]);
+        assert_eq!(sequence.check(&chunk(1, false)), vec![]);
+        assert_eq!(sequence.check(&chunk(1, false)).len(), 1);
+        assert_eq!(sequence.check(&chunk(2, true)), vec![]);
+        // the stream is over, a new one can start with the same id
+        assert_eq!(sequence.check(&chunk(0, false)), vec![]);
+
+        let mut sequence = StreamBytesSequence::new();
+        assert!(ValidationMode::Reject
+            .check_outgoing_chunk(&mut sequence, &chunk(3, false))
+            .is_ok());
+        assert!(ValidationMode::Reject
+            .check_outgoing_chunk(&mut sequence, &chunk(2, false))
+            .is_err());
+        assert!(ValidationMode::Log.accepts_incoming_chunk(&mut sequence, &chunk(1, false)));
+        assert!(!ValidationMode::Reject.accepts_incoming_chunk(&mut sequence, &chunk(0, false)));
+        // the chunks out of order didn't move the stream back
+        assert!(!ValidationMode::Reject.accepts_incoming_chunk(&mut sequence, &chunk(2, false)));
+        assert!(ValidationMode::Reject.accepts_incoming_chunk(&mut sequence, &chunk(4, false)));
+    }
+
+    #[test]
+    fn streams_never_ending_are_forgotten() {
+        let chunk = |stream_id: usize, chunk_number| StreamBytesMessage {
+            stream_id: stream_id.to_string(),
+            chunk_number,
+            is_last_chunk: false,
+            ..StreamBytesMessage::minimal_example()
+        };
+        let mut sequence = StreamBytesSequence::new();
+
+        for stream_id in 0..=MAX_STREAMS {
+            assert_eq!(sequence.check(&chunk(stream_id, 1)), vec![]);
+        }
+
+        assert_eq!(sequence.last_chunks.len(), MAX_STREAMS);
+        // the first stream was forgotten, the last ones weren't
+        assert_eq!(sequence.check(&chunk(0, 0)), vec![]);
+        assert_eq!(sequence.check(&chunk(MAX_STREAMS, 0)).len(), 1);
+    }
+}
@@ -56,6 +56,53 @@ typedef enum {
   SNIPS_HERMES_COMPONENT_CLIENT_APP = 8,
 } SNIPS_HERMES_COMPONENT;
 
+/**
+ * The kind of an error reported by a function returning `SNIPS_RESULT_KO`, it can be retrieved
+ * with `hermes_get_last_error_kind`
+ */
+typedef enum {
+  /**
+   * The last call made from this thread succeeded, or none was made yet
+   */
+  SNIPS_HERMES_ERROR_KIND_NONE = 0,
+  /**
+   * An error that isn't one of the kinds below, a null pointer argument for example
+   */
+  SNIPS_HERMES_ERROR_KIND_OTHER = 1,
+  /**
+   * The transport failed to carry a message, for example the MQTT broker couldn't be reached
+   */
+  SNIPS_HERMES_ERROR_KIND_TRANSPORT = 2,
+  /**
+   * A message couldn't be encoded to, or decoded from, its wire format
+   */
+  SNIPS_HERMES_ERROR_KIND_CODEC = 3,
+  /**
+   * The protocol handler was shut down
+   */
+  SNIPS_HERMES_ERROR_KIND_CLOSED = 4,
+  /**
+   * A message was rejected because it is not valid
+   */
+  SNIPS_HERMES_ERROR_KIND_VALIDATION = 5,
+  /**
+   * No response to a request was received in time
+   */
+  SNIPS_HERMES_ERROR_KIND_TIMEOUT = 6,
+  /**
+   * A lock was poisoned, which means a thread panicked while holding it
+   */
+  SNIPS_HERMES_ERROR_KIND_POISON_LOCK = 7,
+  /**
+   * Reading or writing a file failed
+   */
+  SNIPS_HERMES_ERROR_KIND_IO = 8,
+  /**
+   * The configuration of the protocol handler is invalid
+   */
+  SNIPS_HERMES_ERROR_KIND_CONFIG = 9,
+} SNIPS_HERMES_ERROR_KIND;
+
 typedef enum {
   SNIPS_INJECTION_KIND_ADD = 1,
   SNIPS_INJECTION_KIND_ADD_FROM_VANILLA = 2,
@@ -653,6 +700,15 @@ typedef struct {
   uint64_t major;
   uint64_t minor;
   uint64_t patch;
+  /**
+   * Nullable, the version of the hermes protocol the component speaks, components predating
+   * this field don't send it
+   */
+  const char *protocol_version;
+  /**
+   * The optional features of the protocol the component supports
+   */
+  const CStringArray *capabilities;
 } CVersionMessage;
 
 typedef struct {
@@ -961,6 +1017,12 @@ SNIPS_RESULT hermes_enable_debug_logs(void);
  */
 SNIPS_RESULT hermes_get_last_error(const char **error);
 
+/**
+ * Used to retrieve the kind of the error of the last call made from this thread, it tells
+ * apart the errors `hermes_get_last_error` describes
+ */
+SNIPS_RESULT hermes_get_last_error_kind(SNIPS_HERMES_ERROR_KIND *kind);
+
 SNIPS_RESULT hermes_injection_publish_injection_request(const CInjectionFacade *facade,
                                                         const CInjectionRequestMessage *message);
 